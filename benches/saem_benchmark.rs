@@ -1,7 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nmodes::data::{Dataset, DosingRecord, DosingType, Individual, Observation, ObservationType};
 use nmodes::models::{CompartmentModel, ModelType, ModelState};
+use nmodes::estimation::{EstimationConfig, EstimationMethod, FoceEstimator};
+use nmodes::prediction;
 use nmodes::solver::{RungeKuttaSolver, OdeSolver, SolverConfig};
 use nalgebra::DVector;
+use std::collections::HashMap;
 
 struct BenchmarkSystem {
     model: CompartmentModel,
@@ -61,5 +65,80 @@ fn benchmark_model_derivatives(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_ode_solving, benchmark_model_derivatives);
+fn benchmark_one_compartment_prediction(c: &mut Criterion) {
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let params = model.default_parameters();
+    let solver = RungeKuttaSolver::new();
+
+    let observations = [0.5, 1.0, 2.0, 4.0, 8.0, 12.0, 18.0, 24.0].iter()
+        .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+        .collect();
+    let doses = vec![
+        DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus),
+        DosingRecord::new(12.0, 100.0, 1, DosingType::Bolus),
+    ];
+    let individual = Individual::new(1, observations, doses, HashMap::new());
+
+    let analytic = SolverConfig::default();
+    let numerical = SolverConfig { analytic_solutions: false, ..SolverConfig::default() };
+
+    let mut group = c.benchmark_group("predict_one_compartment");
+    group.bench_function("analytic", |b| {
+        b.iter(|| {
+            prediction::predict_individual(&model, &solver, black_box(&individual), black_box(&params), &analytic).unwrap()
+        })
+    });
+    group.bench_function("numerical", |b| {
+        b.iter(|| {
+            prediction::predict_individual(&model, &solver, black_box(&individual), black_box(&params), &numerical).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn benchmark_fisher_matrix(c: &mut Criterion) {
+    // Two-compartment data, so the Fisher matrix has 16 entries to spread
+    // across threads. A single FOCE iteration keeps the covariance step the
+    // bulk of each fit
+    let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+    for id in 1..=3 {
+        csv.push_str(&format!("{},0,,100,1,1\n", id));
+        for t in [0.5_f64, 2.0, 8.0, 24.0] {
+            let conc = 20.0 * (-0.8 * t).exp() + 8.0 * (-0.2 * t).exp() + 2.0 * (-0.02 * t).exp();
+            csv.push_str(&format!("{},{},{},,0,1\n", id, t, conc * (1.0 + 0.02 * id as f64)));
+        }
+    }
+    let path = std::env::temp_dir().join("fisher_benchmark.csv");
+    std::fs::write(&path, csv).unwrap();
+    let dataset = Dataset::from_csv(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    let config = EstimationConfig::default()
+        .with_method(EstimationMethod::Foce)
+        .with_foce_iterations(1);
+    let max_threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut thread_counts = vec![1];
+    let mut threads = 2;
+    while threads <= max_threads {
+        thread_counts.push(threads);
+        threads *= 2;
+    }
+
+    let mut group = c.benchmark_group("fisher_matrix_two_compartment");
+    group.sample_size(10);
+    for threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_function(format!("threads_{}", threads), |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    let model = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+                    FoceEstimator::new(model, config.clone()).fit(black_box(&dataset)).unwrap()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_ode_solving, benchmark_model_derivatives, benchmark_one_compartment_prediction, benchmark_fisher_matrix);
 criterion_main!(benches);
\ No newline at end of file
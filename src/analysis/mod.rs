@@ -0,0 +1,191 @@
+//! One entry point for fitting a model from library code: assemble the
+//! dataset, structural model, method and configuration with
+//! [`Analysis::builder`] and call `run`, instead of constructing the model
+//! and estimator by hand.
+//!
+//! ```
+//! use nmodes::{Analysis, Dataset, EstimationConfig, EstimationMethod, ModelType};
+//!
+//! let mut csv = String::from("ID,TIME,DV,AMT,EVID\n");
+//! for id in 1..=4 {
+//!     csv.push_str(&format!("{},0,,100,1\n", id));
+//!     for t in [1.0_f64, 2.0, 4.0, 8.0] {
+//!         csv.push_str(&format!("{},{},{},,0\n", id, t, 10.0 * (-0.1 * t).exp()));
+//!     }
+//! }
+//! let path = std::env::temp_dir().join("nmodes_analysis_doctest.csv");
+//! std::fs::write(&path, csv)?;
+//! let dataset = Dataset::from_csv(&path)?;
+//! std::fs::remove_file(&path).ok();
+//!
+//! let results = Analysis::builder()
+//!     .dataset(dataset)
+//!     .model(ModelType::OneCompartment)
+//!     .method(EstimationMethod::Foce)
+//!     .config(EstimationConfig::default().with_foce_iterations(2))
+//!     .run()?;
+//!
+//! assert_eq!(results.parameter_names(), ["CL", "V"]);
+//! assert!(results.objective_function_value().is_finite());
+//! assert_eq!(results.fitted_model.parameters.fixed_effects, results.fixed_effects());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::data::Dataset;
+use crate::estimation::{EstimationConfig, EstimationMethod, FoceEstimator, FoceResults};
+use crate::models::{CompartmentModel, FittedModel, ModelType};
+use crate::saem::{SaemEstimator, SaemResults};
+use crate::validation;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Namespace for [`Analysis::builder`].
+pub struct Analysis;
+
+impl Analysis {
+    pub fn builder() -> AnalysisBuilder {
+        AnalysisBuilder::default()
+    }
+}
+
+enum ModelChoice {
+    Type(ModelType),
+    Custom(CompartmentModel),
+}
+
+/// Collects the pieces of one fit. A dataset and a model are required; the
+/// configuration defaults to `EstimationConfig::default()` and the method
+/// to the configuration's.
+#[derive(Default)]
+pub struct AnalysisBuilder {
+    dataset: Option<Dataset>,
+    model: Option<ModelChoice>,
+    method: Option<EstimationMethod>,
+    config: Option<EstimationConfig>,
+}
+
+impl AnalysisBuilder {
+    pub fn dataset(mut self, dataset: Dataset) -> Self {
+        self.dataset = Some(dataset);
+        self
+    }
+
+    /// One of the built-in structural models.
+    pub fn model(mut self, model_type: ModelType) -> Self {
+        self.model = Some(ModelChoice::Type(model_type));
+        self
+    }
+
+    /// A model built with `CompartmentModel::from_custom`.
+    pub fn custom_model(mut self, model: CompartmentModel) -> Self {
+        self.model = Some(ModelChoice::Custom(model));
+        self
+    }
+
+    /// Overrides the method of the configuration.
+    pub fn method(mut self, method: EstimationMethod) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    pub fn config(mut self, config: EstimationConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Validates the dataset and fits the model with the chosen method.
+    pub fn run(self) -> Result<AnalysisResults> {
+        let dataset = self.dataset.ok_or_else(|| anyhow!("Analysis needs a dataset"))?;
+        let model = match self.model.ok_or_else(|| anyhow!("Analysis needs a model"))? {
+            ModelChoice::Type(model_type) => CompartmentModel::new(model_type)?,
+            ModelChoice::Custom(model) => model,
+        };
+        let mut config = self.config.unwrap_or_default();
+        if let Some(method) = self.method {
+            config.method = method;
+        }
+        config.foce_interaction |= matches!(config.method, EstimationMethod::FoceI);
+        config.validate().map_err(|message| anyhow!("Invalid estimation configuration: {}", message))?;
+        validation::validate_dataset(&dataset)?;
+        validation::check_dose_units(&dataset, &model);
+
+        let method = config.method.clone();
+        let (fit, fitted_model) = match method {
+            EstimationMethod::Saem => {
+                let mut estimator = SaemEstimator::new(model, config);
+                let results = estimator.fit(&dataset)?;
+                let fitted_model = FittedModel::from_saem(estimator.model(), &results);
+                (Fit::Saem(results), fitted_model)
+            }
+            EstimationMethod::Foce | EstimationMethod::FoceI | EstimationMethod::Agq => {
+                let mut estimator = FoceEstimator::new(model, config);
+                let results = estimator.fit(&dataset)?;
+                let fitted_model = FittedModel::from_foce(estimator.model(), &results);
+                (Fit::Foce(results), fitted_model)
+            }
+        };
+        Ok(AnalysisResults { method, fit, fitted_model, dataset })
+    }
+}
+
+/// The estimator's own results.
+#[derive(Debug, Clone)]
+pub enum Fit {
+    Saem(SaemResults),
+    Foce(FoceResults),
+}
+
+/// Results of [`AnalysisBuilder::run`]: the method's full results, the
+/// estimates as a reloadable [`FittedModel`], and the dataset they came
+/// from, with accessors for what every method reports.
+#[derive(Debug, Clone)]
+pub struct AnalysisResults {
+    pub method: EstimationMethod,
+    pub fit: Fit,
+    pub fitted_model: FittedModel,
+    pub dataset: Dataset,
+}
+
+impl AnalysisResults {
+    pub fn fixed_effects(&self) -> &[f64] {
+        match &self.fit {
+            Fit::Saem(results) => &results.fixed_effects,
+            Fit::Foce(results) => &results.fixed_effects,
+        }
+    }
+
+    pub fn random_effects_variance(&self) -> &[Vec<f64>] {
+        match &self.fit {
+            Fit::Saem(results) => &results.random_effects_variance,
+            Fit::Foce(results) => &results.random_effects_variance,
+        }
+    }
+
+    pub fn parameter_names(&self) -> &[String] {
+        match &self.fit {
+            Fit::Saem(results) => &results.parameter_names,
+            Fit::Foce(results) => &results.parameter_names,
+        }
+    }
+
+    pub fn individual_parameters(&self) -> &HashMap<i32, Vec<f64>> {
+        match &self.fit {
+            Fit::Saem(results) => &results.individual_parameters,
+            Fit::Foce(results) => &results.individual_parameters,
+        }
+    }
+
+    pub fn objective_function_value(&self) -> f64 {
+        match &self.fit {
+            Fit::Saem(results) => results.objective_function_value,
+            Fit::Foce(results) => results.objective_function_value,
+        }
+    }
+
+    pub fn converged(&self) -> bool {
+        match &self.fit {
+            Fit::Saem(results) => results.converged,
+            Fit::Foce(results) => results.converged,
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use super::{DataError, Individual, Observation, DosingRecord, ObservationType, DosingType};
+use super::{Censoring, CovariateInterpolation, DataError, Individual, Observation, DosingRecord, ObservationType, DosingType, PriorRegimen};
+use log::warn;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
 
 unsafe impl Send for Dataset {}
@@ -8,8 +10,9 @@ unsafe impl Sync for Dataset {}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NonmemRecord {
+    /// Subject identifier as written in the file; may be non-numeric.
     #[serde(rename = "ID")]
-    pub id: i32,
+    pub id: String,
     #[serde(rename = "TIME")]
     pub time: f64,
     #[serde(rename = "DV")]
@@ -28,33 +31,167 @@ pub struct NonmemRecord {
     pub addl: Option<i32>,
     #[serde(rename = "SS")]
     pub ss: Option<i32>,
+    #[serde(rename = "PREDOSE")]
+    pub predose: Option<i32>,
+    /// 1 when DV is the LLOQ of a sample below it, -1 when DV is the ULOQ
+    /// of a sample above it.
+    #[serde(rename = "CENS")]
+    pub cens: Option<i32>,
+    /// Number of identical subjects the individual represents in pooled
+    /// data; read from the individual's first row that has it.
+    #[serde(rename = "FREQ")]
+    pub freq: Option<f64>,
+    /// Numeric values of the non-standard columns present on this row.
+    #[serde(skip)]
+    pub covariates: HashMap<String, f64>,
+    /// Non-blank cells of the columns read as categorical, such as `M` or
+    /// `F`; coded into `covariates` once the file is read.
+    #[serde(skip)]
+    pub categories: HashMap<String, String>,
+    /// Type of the observation in `dv`, set for rows expanded from a
+    /// wide-format DV column; concentrations otherwise.
+    #[serde(skip)]
+    pub observation_type: Option<ObservationType>,
+}
+
+/// A wide-format DV column, e.g. `DV2` holding PD responses, and the
+/// observations its values become.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DvColumn {
+    pub column: String,
+    pub compartment: i32,
+    pub observation_type: ObservationType,
+}
+
+impl DvColumn {
+    pub fn new(column: impl Into<String>, compartment: i32, observation_type: ObservationType) -> Self {
+        Self {
+            column: column.into(),
+            compartment,
+            observation_type,
+        }
+    }
+}
+
+/// Counts describing a loaded dataset, for checking it was read as intended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetSummary {
+    pub n_individuals: usize,
+    pub n_observations: usize,
+    pub n_censored: usize,
+    pub n_effect_observations: usize,
+    pub n_doses: usize,
+    /// Earliest and latest observation times; `None` without observations.
+    pub time_range: Option<(f64, f64)>,
+    pub covariate_names: Vec<String>,
+}
+
+impl std::fmt::Display for DatasetSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Individuals: {}", self.n_individuals)?;
+        writeln!(f, "Observations: {} ({} censored, {} effect)",
+                 self.n_observations, self.n_censored, self.n_effect_observations)?;
+        writeln!(f, "Dose records: {}", self.n_doses)?;
+        if let Some((first, last)) = self.time_range {
+            writeln!(f, "Observation times: {} to {}", first, last)?;
+        }
+        if self.covariate_names.is_empty() {
+            write!(f, "Covariates: none")
+        } else {
+            write!(f, "Covariates: {}", self.covariate_names.join(", "))
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Dataset {
-    individuals: HashMap<i32, Individual>,
+    individuals: BTreeMap<i32, Individual>,
     covariate_names: Vec<String>,
+    /// Original subject identifiers of datasets whose IDs are not all
+    /// integers, keyed by the internal integer ID.
+    id_labels: HashMap<i32, String>,
+    /// Categories of the columns read as categorical, sorted; each
+    /// individual's value is the index of its category.
+    covariate_levels: BTreeMap<String, Vec<String>>,
 }
 
 impl Dataset {
     pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Self, DataError> {
+        Self::load(path, &[], &[])
+    }
+
+    /// Loads a file whose `categorical` columns hold categories, such as
+    /// `SEX` with `M` and `F`: each cell, text or number, is a category
+    /// label, and individuals carry the position of theirs among the
+    /// column's sorted categories. Other columns are read as numbers.
+    pub fn from_csv_with_categorical<P: AsRef<Path>>(path: P, categorical: &[&str]) -> Result<Self, DataError> {
+        Self::load(path, &[], categorical)
+    }
+
+    /// Loads a file with several DV columns. Each observation row (EVID 0)
+    /// becomes one observation per listed column with a value on that row,
+    /// in the column's compartment and of its type; a `DV` column is
+    /// ignored unless listed.
+    pub fn from_wide_csv<P: AsRef<Path>>(path: P, dv_columns: &[DvColumn]) -> Result<Self, DataError> {
+        if dv_columns.is_empty() {
+            return Err(DataError::InvalidFormat("No DV columns given for a wide-format dataset".to_string()));
+        }
+        Self::load(path, dv_columns, &[])
+    }
+
+    fn load<P: AsRef<Path>>(path: P, dv_columns: &[DvColumn], categorical: &[&str]) -> Result<Self, DataError> {
         let mut reader = csv::Reader::from_path(path)?;
         let headers = reader.headers()?.clone();
         
-        // Validate required columns
-        let required_cols = ["ID", "TIME", "DV", "AMT", "EVID"];
+        // Validate required columns; wide files need their DV columns instead of DV
+        let mut required_cols: Vec<&str> = vec!["ID", "TIME", "AMT", "EVID"];
+        if dv_columns.is_empty() {
+            required_cols.insert(2, "DV");
+        }
+        required_cols.extend(dv_columns.iter().map(|dv| dv.column.as_str()));
+        required_cols.extend(categorical);
         for col in required_cols.iter() {
             if !headers.iter().any(|h| h == *col) {
                 return Err(DataError::MissingColumn(col.to_string()));
             }
         }
+        let dv_indices: Vec<(usize, &DvColumn)> = dv_columns.iter()
+            .map(|dv| (headers.iter().position(|h| h == dv.column).unwrap(), dv))
+            .collect();
+
+        // Extract covariate names (columns not in standard NONMEM set)
+        let standard_cols = ["ID", "TIME", "DV", "AMT", "EVID", "CMT", "RATE", "II", "ADDL", "SS", "PREDOSE", "CENS", "FREQ"];
+        let covariate_columns: Vec<(usize, String)> = headers.iter()
+            .enumerate()
+            .filter(|(_, h)| !standard_cols.contains(h) && !dv_columns.iter().any(|dv| dv.column == *h))
+            .map(|(idx, h)| (idx, h.to_string()))
+            .collect();
 
-        let mut individuals: HashMap<i32, Individual> = HashMap::new();
-        let mut records_by_id: HashMap<i32, Vec<NonmemRecord>> = HashMap::new();
+        let mut individuals: BTreeMap<i32, Individual> = BTreeMap::new();
+        let mut records_by_label: HashMap<String, Vec<NonmemRecord>> = HashMap::new();
+        let mut labels_in_order: Vec<String> = Vec::new();
+        let mut ignored_text: BTreeSet<String> = BTreeSet::new();
 
         // Parse all records
-        for result in reader.deserialize() {
-            let record: NonmemRecord = result?;
+        for result in reader.records() {
+            let row = result?;
+            let mut record: NonmemRecord = row.deserialize(Some(&headers))?;
+            
+            // Blank covariate cells, and text in a column not read as
+            // categorical, carry no value on this row
+            for (idx, name) in &covariate_columns {
+                let cell = row.get(*idx).map(str::trim).unwrap_or_default();
+                if cell.is_empty() {
+                    continue;
+                }
+                if categorical.contains(&name.as_str()) {
+                    record.categories.insert(name.clone(), cell.to_string());
+                } else if let Ok(value) = cell.parse::<f64>() {
+                    record.covariates.insert(name.clone(), value);
+                } else {
+                    ignored_text.insert(name.clone());
+                }
+            }
             
             // Validate basic constraints
             if record.time < 0.0 {
@@ -67,13 +204,76 @@ impl Dataset {
                 }
             }
 
-            records_by_id.entry(record.id).or_default().push(record);
+            let label = record.id.trim().to_string();
+            if !records_by_label.contains_key(&label) {
+                labels_in_order.push(label.clone());
+            }
+            let records = records_by_label.entry(label).or_default();
+            if dv_indices.is_empty() || record.evid != 0 {
+                records.push(record);
+                continue;
+            }
+            // Wide observation row: one record per DV column with a value
+            for (idx, dv) in &dv_indices {
+                if let Some(value) = row.get(*idx).and_then(|v| v.trim().parse::<f64>().ok()) {
+                    let mut expanded = record.clone();
+                    expanded.dv = Some(value);
+                    expanded.cmt = Some(dv.compartment);
+                    expanded.observation_type = Some(dv.observation_type.clone());
+                    records.push(expanded);
+                }
+            }
+        }
+
+        for name in &ignored_text {
+            warn!("Covariate column {} has text cells, which carry no value; \
+                   load it with Dataset::from_csv_with_categorical if it holds categories", name);
+        }
+
+        // Each categorical value is coded by its category's position among
+        // the column's sorted categories
+        let mut covariate_levels: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for &name in categorical {
+            let mut levels: Vec<String> = records_by_label.values().flatten()
+                .filter_map(|record| record.categories.get(name).cloned())
+                .collect::<BTreeSet<String>>()
+                .into_iter()
+                .collect();
+            levels.sort_by(|a, b| compare_category_labels(a, b));
+            covariate_levels.insert(name.to_string(), levels);
+        }
+        for record in records_by_label.values_mut().flatten() {
+            for (name, levels) in &covariate_levels {
+                if let Some(label) = record.categories.remove(name) {
+                    let code = levels.iter().position(|level| *level == label).unwrap();
+                    record.covariates.insert(name.clone(), code as f64);
+                }
+            }
         }
 
+        // Integer IDs are used as they are; otherwise every subject is
+        // numbered 1..n in order of first appearance and keeps its label.
+        // Labels such as "01" and "1" parse to the same integer but are
+        // different subjects, so they are renumbered too
+        let mut id_labels: HashMap<i32, String> = HashMap::new();
+        let numeric_ids: Option<Vec<i32>> = labels_in_order.iter()
+            .map(|label| label.parse::<i32>().ok())
+            .collect::<Option<Vec<i32>>>()
+            .filter(|ids| ids.iter().collect::<HashSet<_>>().len() == ids.len());
+        let ids = match numeric_ids {
+            Some(ids) => ids,
+            None => {
+                let ids: Vec<i32> = (1..=labels_in_order.len() as i32).collect();
+                id_labels = ids.iter().copied().zip(labels_in_order.iter().cloned()).collect();
+                ids
+            }
+        };
+
         // Process records into individuals
-        for (id, mut records) in records_by_id {
+        for (id, label) in ids.into_iter().zip(labels_in_order) {
+            let mut records = records_by_label.remove(&label).unwrap_or_default();
             // Sort by time
-            records.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+            records.sort_by(|a, b| a.time.total_cmp(&b.time));
             
             let individual = Self::process_individual_records(id, records)?;
             individuals.insert(id, individual);
@@ -83,16 +283,15 @@ impl Dataset {
             return Err(DataError::InvalidFormat("No valid individuals found".to_string()));
         }
 
-        // Extract covariate names (columns not in standard NONMEM set)
-        let standard_cols = ["ID", "TIME", "DV", "AMT", "EVID", "CMT", "RATE", "II", "ADDL", "SS"];
-        let covariate_names: Vec<String> = headers.iter()
-            .filter(|h| !standard_cols.contains(h))
-            .map(|h| h.to_string())
+        let covariate_names: Vec<String> = covariate_columns.into_iter()
+            .map(|(_, name)| name)
             .collect();
 
         Ok(Dataset {
             individuals,
             covariate_names,
+            id_labels,
+            covariate_levels,
         })
     }
 
@@ -102,8 +301,47 @@ impl Dataset {
     ) -> Result<Individual, DataError> {
         let mut observations = Vec::new();
         let mut dosing_records = Vec::new();
+        let mut baseline_covariates: HashMap<String, f64> = HashMap::new();
+        let mut covariate_changes: Vec<(String, f64, f64)> = Vec::new();
+        let mut current_covariates: HashMap<String, f64> = HashMap::new();
+        let mut first_measurements: HashMap<String, f64> = HashMap::new();
+        let mut last_measurements: HashMap<String, f64> = HashMap::new();
+        let mut varying: HashSet<String> = HashSet::new();
+        let mut weight = None;
 
         for record in records {
+            if let (None, Some(freq)) = (weight, record.freq) {
+                if !(freq > 0.0 && freq.is_finite()) {
+                    return Err(DataError::InvalidFormat(format!("FREQ of individual {} must be positive, got {}", id, freq)));
+                }
+                weight = Some(freq);
+            }
+
+            // The first value of a covariate is its baseline. Once a later
+            // value differs, every value from then on is kept as a
+            // measurement, along with the first and last constant ones, so
+            // the covariate can be interpolated as well as carried forward
+            for (name, &value) in &record.covariates {
+                match current_covariates.get(name) {
+                    None => {
+                        baseline_covariates.insert(name.clone(), value);
+                        first_measurements.insert(name.clone(), record.time);
+                    }
+                    Some(&previous) if previous != value || varying.contains(name) => {
+                        if varying.insert(name.clone()) {
+                            covariate_changes.push((name.clone(), first_measurements[name], previous));
+                            if last_measurements[name] > first_measurements[name] {
+                                covariate_changes.push((name.clone(), last_measurements[name], previous));
+                            }
+                        }
+                        covariate_changes.push((name.clone(), record.time, value));
+                    }
+                    Some(_) => {}
+                }
+                current_covariates.insert(name.clone(), value);
+                last_measurements.insert(name.clone(), record.time);
+            }
+
             match record.evid {
                 0 => {
                     // Observation record
@@ -113,10 +351,16 @@ impl Dataset {
                             value: dv,
                             compartment: record.cmt.unwrap_or(1),
                             observation_type: if dv > 0.0 { 
-                                ObservationType::Concentration 
+                                record.observation_type.clone().unwrap_or(ObservationType::Concentration)
                             } else { 
                                 ObservationType::Missing 
                             },
+                            pre_dose: record.predose.unwrap_or(0) == 1,
+                            censoring: match record.cens {
+                                Some(1) => Censoring::BelowLimit,
+                                Some(-1) => Censoring::AboveLimit,
+                                _ => Censoring::None,
+                            },
                         };
                         observations.push(obs);
                     }
@@ -141,6 +385,11 @@ impl Dataset {
                         dosing_records.push(dose);
                     }
                 }
+                2 => {
+                    // Other-event marker: no dose or observation, only the
+                    // covariate values recorded above
+                    continue;
+                }
                 _ => {
                     // Other event types (reset, etc.)
                     continue;
@@ -148,9 +397,8 @@ impl Dataset {
             }
         }
 
-        if observations.is_empty() {
-            return Err(DataError::NoObservations(id));
-        }
+        // Pre-dose samples come first among observations at the same time
+        observations.sort_by(|a, b| a.time.total_cmp(&b.time).then(b.pre_dose.cmp(&a.pre_dose)));
 
         // Validate time sequence
         for i in 1..observations.len() {
@@ -159,13 +407,62 @@ impl Dataset {
             }
         }
 
-        Ok(Individual::new(id, observations, dosing_records, HashMap::new()))
+        let mut individual = Individual::new(id, observations, dosing_records, baseline_covariates)
+            .with_weight(weight.unwrap_or(1.0));
+        for (name, time, value) in covariate_changes {
+            individual.add_covariate_change(&name, time, value);
+        }
+
+        Ok(individual)
     }
 
-    pub fn individuals(&self) -> &HashMap<i32, Individual> {
+    /// Individuals in ascending ID order, so anything iterating them (output
+    /// files, per-individual seeds) is the same from run to run.
+    pub fn individuals(&self) -> &BTreeMap<i32, Individual> {
         &self.individuals
     }
 
+    /// Reads the time-varying covariate `name` of every individual with
+    /// `interpolation` between its measured values.
+    pub fn set_covariate_interpolation(&mut self, name: &str, interpolation: CovariateInterpolation) {
+        for individual in self.individuals.values_mut() {
+            individual.set_covariate_interpolation(name, interpolation);
+        }
+    }
+
+    /// Gives individual `id` the dosing `regimen` before its first record
+    /// (see `PriorRegimen`), or removes it with `None`. Returns whether the
+    /// individual exists.
+    pub fn set_prior_regimen(&mut self, id: i32, regimen: Option<PriorRegimen>) -> bool {
+        match self.individuals.get_mut(&id) {
+            Some(individual) => {
+                individual.set_prior_regimen(regimen);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every observation with its individual's ID, in ascending ID then
+    /// time order.
+    pub fn iter_observations(&self) -> impl Iterator<Item = (i32, &Observation)> + '_ {
+        self.individuals.iter()
+            .flat_map(|(&id, individual)| individual.observations().iter().map(move |obs| (id, obs)))
+    }
+
+    /// Every observation paired with the entry at the same position in its
+    /// individual's list in `predictions`, such as the (TIME, IPRED, PRED)
+    /// of `prediction::individual_predictions`, in the order of
+    /// `iter_observations`. Individuals without predictions are skipped.
+    pub fn iter_observations_with<'a, P>(
+        &'a self,
+        predictions: &'a HashMap<i32, Vec<P>>,
+    ) -> impl Iterator<Item = (i32, &'a Observation, &'a P)> + 'a {
+        self.individuals.iter()
+            .filter_map(move |(&id, individual)| predictions.get(&id).map(|p| (id, individual, p)))
+            .flat_map(|(id, individual, p)| individual.observations().iter().zip(p).map(move |(obs, p)| (id, obs, p)))
+    }
+
     pub fn n_individuals(&self) -> usize {
         self.individuals.len()
     }
@@ -180,26 +477,143 @@ impl Dataset {
         &self.covariate_names
     }
 
+    /// A dataset with only the individuals in `ids`, keeping the covariate
+    /// names and original subject identifiers. IDs not in the dataset are
+    /// ignored.
+    pub fn subset(&self, ids: &[i32]) -> Dataset {
+        let individuals: BTreeMap<i32, Individual> = ids.iter()
+            .filter_map(|id| self.individuals.get(id).map(|individual| (*id, individual.clone())))
+            .collect();
+        let id_labels = self.id_labels.iter()
+            .filter(|(id, _)| individuals.contains_key(id))
+            .map(|(&id, label)| (id, label.clone()))
+            .collect();
+
+        Dataset {
+            individuals,
+            covariate_names: self.covariate_names.clone(),
+            id_labels,
+            covariate_levels: self.covariate_levels.clone(),
+        }
+    }
+
+    /// The sorted categories of a column read as categorical, whose
+    /// positions are the values individuals carry; `None` for numeric
+    /// columns.
+    pub fn covariate_levels(&self, name: &str) -> Option<&[String]> {
+        self.covariate_levels.get(name).map(Vec::as_slice)
+    }
+
+    /// The value individuals in category `label` of covariate `name` carry:
+    /// its position for a column read as categorical, the number itself for
+    /// a numeric one. `None` when the column has no such category.
+    pub fn covariate_category_value(&self, name: &str, label: &str) -> Option<f64> {
+        match self.covariate_levels.get(name) {
+            Some(levels) => levels.iter().position(|level| level == label).map(|code| code as f64),
+            None => {
+                let value = label.trim().parse::<f64>().ok()?;
+                self.individuals.values()
+                    .any(|individual| individual.get_covariate(name) == Some(value))
+                    .then_some(value)
+            }
+        }
+    }
+
+    /// The subject identifier of `id` as written in the source file.
+    pub fn id_label(&self, id: i32) -> String {
+        self.id_labels.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// The ID of the subject written as `label` in the source file.
+    pub fn id_for_label(&self, label: &str) -> Option<i32> {
+        let label = label.trim();
+        if self.id_labels.is_empty() {
+            label.parse().ok().filter(|id| self.individuals.contains_key(id))
+        } else {
+            self.id_labels.iter().find(|(_, known)| known.as_str() == label).map(|(&id, _)| id)
+        }
+    }
+
+    /// Replaces the observations of individual `id`, keeping its doses and
+    /// covariates. Does nothing if `id` is not in the dataset.
+    pub fn replace_observations(&mut self, id: i32, observations: Vec<Observation>) {
+        if let Some(individual) = self.individuals.get_mut(&id) {
+            *individual = individual.with_observations(observations);
+        }
+    }
+
+    /// Marks the concentration observations in `compartment` as effect (PD)
+    /// observations, for long-format files where CMT alone tells PK from PD
+    /// rows. Returns the number of observations retyped.
+    pub fn set_effect_compartment(&mut self, compartment: i32) -> usize {
+        let mut retyped = 0;
+        for individual in self.individuals.values_mut() {
+            let mut observations = individual.observations().to_vec();
+            for obs in observations.iter_mut()
+                .filter(|obs| obs.compartment == compartment && obs.observation_type == ObservationType::Concentration)
+            {
+                obs.observation_type = ObservationType::Effect;
+                retyped += 1;
+            }
+            *individual = individual.with_observations(observations);
+        }
+        retyped
+    }
+
+    /// Whether any observation is an effect (PD) observation.
+    pub fn has_effect_observations(&self) -> bool {
+        self.individuals.values()
+            .flat_map(|ind| ind.observations())
+            .any(|obs| obs.observation_type == ObservationType::Effect)
+    }
+
+    pub fn summary(&self) -> DatasetSummary {
+        let observations = || self.iter_observations().map(|(_, obs)| obs);
+        let time_range = observations().map(|obs| obs.time).fold(None, |range: Option<(f64, f64)>, t| {
+            Some(range.map_or((t, t), |(first, last)| (first.min(t), last.max(t))))
+        });
+        DatasetSummary {
+            n_individuals: self.n_individuals(),
+            n_observations: self.n_observations(),
+            n_censored: observations().filter(|obs| obs.is_censored()).count(),
+            n_effect_observations: observations().filter(|obs| obs.observation_type == ObservationType::Effect).count(),
+            n_doses: self.individuals.values().map(|ind| ind.dosing_records().len()).sum(),
+            time_range,
+            covariate_names: self.covariate_names.clone(),
+        }
+    }
+
+    /// Each individual's FREQ weight, keyed by ID.
+    pub fn individual_weights(&self) -> HashMap<i32, f64> {
+        self.individuals.iter().map(|(&id, individual)| (id, individual.weight())).collect()
+    }
+
     pub fn get_individual(&self, id: i32) -> Option<&Individual> {
         self.individuals.get(&id)
     }
 
     pub fn get_all_times(&self) -> Vec<f64> {
-    let mut times: Vec<f64> = self.individuals.values()
-        .flat_map(|ind| ind.observations().iter().map(|obs| obs.time))
-        .collect();
-    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut times: Vec<f64> = self.iter_observations().map(|(_, obs)| obs.time).collect();
+    times.sort_by(|a, b| a.total_cmp(b));
     times.dedup_by(|a, b| (*a - *b).abs() < f64::EPSILON);
     times
 }
 
     pub fn get_concentration_data(&self) -> Vec<(f64, f64)> {
-        self.individuals.values()
-            .flat_map(|ind| {
-                ind.observations().iter()
-                    .filter(|obs| matches!(obs.observation_type, ObservationType::Concentration))
-                    .map(|obs| (obs.time, obs.value))
-            })
+        self.iter_observations()
+            .filter(|(_, obs)| matches!(obs.observation_type, ObservationType::Concentration))
+            .map(|(_, obs)| (obs.time, obs.value))
             .collect()
     }
-}
\ No newline at end of file
+}
+
+/// Order of categorical labels: numbers by value, before text, which is in
+/// lexicographic order, so `2` precedes `10`.
+fn compare_category_labels(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.total_cmp(&y),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
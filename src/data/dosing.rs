@@ -7,6 +7,10 @@ pub enum DosingType {
     Oral,
 }
 
+/// RATE value marking a dose whose duration is estimated by the model (D1),
+/// as in NONMEM.
+pub const MODELED_DURATION_RATE: f64 = -2.0;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DosingRecord {
     pub time: f64,
@@ -45,6 +49,11 @@ impl DosingRecord {
         self.additional_doses >= 0
     }
 
+    /// Whether RATE=-2 asks for the model's estimated duration (D1).
+    pub fn has_modeled_duration(&self) -> bool {
+        self.rate == Some(MODELED_DURATION_RATE)
+    }
+
     pub fn infusion_duration(&self) -> Option<f64> {
         if matches!(self.dosing_type, DosingType::Infusion) {
             self.rate.map(|r| self.amount / r)
@@ -71,6 +80,43 @@ impl DosingRecord {
     }
 }
 
+/// Dosing before the first record of an individual's data: `n_doses` of
+/// `amount` every `interval`, the last one `interval` before time zero.
+/// For chronic-dosing studies sampled on one day, where the regimen that
+/// brought the subject to steady state is known but not in the dataset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriorRegimen {
+    pub amount: f64,
+    pub interval: f64,
+    pub n_doses: usize,
+    pub compartment: i32,
+    pub dosing_type: DosingType,
+}
+
+impl PriorRegimen {
+    pub fn new(amount: f64, interval: f64, n_doses: usize, dosing_type: DosingType) -> Self {
+        Self {
+            amount,
+            interval,
+            n_doses,
+            compartment: 1,
+            dosing_type,
+        }
+    }
+
+    pub fn with_compartment(mut self, compartment: i32) -> Self {
+        self.compartment = compartment;
+        self
+    }
+
+    /// The regimen's doses in time order, at negative times.
+    pub fn doses(&self) -> Vec<DosingRecord> {
+        (1..=self.n_doses).rev()
+            .map(|k| DosingRecord::new(-(k as f64) * self.interval, self.amount, self.compartment, self.dosing_type.clone()))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,4 +141,12 @@ mod tests {
         assert_eq!(expanded[1].time, 12.0);
         assert_eq!(expanded[2].time, 24.0);
     }
+
+    #[test]
+    fn test_prior_regimen_doses_end_one_interval_before_zero() {
+        let regimen = PriorRegimen::new(100.0, 12.0, 3, DosingType::Oral);
+        let times: Vec<f64> = regimen.doses().iter().map(|dose| dose.time).collect();
+        assert_eq!(times, vec![-36.0, -24.0, -12.0]);
+        assert!(PriorRegimen::new(100.0, 12.0, 0, DosingType::Oral).doses().is_empty());
+    }
 }
\ No newline at end of file
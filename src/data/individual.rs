@@ -1,13 +1,40 @@
-use super::{Observation, DosingRecord};
+use super::{Observation, DosingRecord, PriorRegimen};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// How a time-varying covariate is read between its measured values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CovariateInterpolation {
+    /// Last observation carried forward: each value holds until the next.
+    #[default]
+    Locf,
+    /// Linear between neighbouring measurements, constant outside them.
+    Linear,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Individual {
     pub id: i32,
     observations: Vec<Observation>,
     dosing_records: Vec<DosingRecord>,
     covariates: HashMap<String, f64>,
+    /// Measured covariate values as (time, value), sorted by time.
+    time_varying_covariates: HashMap<String, Vec<(f64, f64)>>,
+    /// Interpolation of covariates read between measurements; LOCF when absent.
+    #[serde(default)]
+    covariate_interpolation: HashMap<String, CovariateInterpolation>,
+    /// Number of identical subjects this individual stands for, from the
+    /// FREQ column of pooled data; 1 otherwise.
+    #[serde(default = "default_weight")]
+    weight: f64,
+    /// Dosing before the first record, simulated to set the state at time
+    /// zero.
+    #[serde(default)]
+    prior_regimen: Option<PriorRegimen>,
+}
+
+fn default_weight() -> f64 {
+    1.0
 }
 
 impl Individual {
@@ -22,9 +49,39 @@ impl Individual {
             observations,
             dosing_records,
             covariates,
+            time_varying_covariates: HashMap::new(),
+            covariate_interpolation: HashMap::new(),
+            weight: 1.0,
+            prior_regimen: None,
         }
     }
 
+    /// This individual standing for `weight` identical subjects: its
+    /// likelihood and its share of the population moments count `weight`
+    /// times.
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// This individual with `regimen` given before its first record.
+    pub fn with_prior_regimen(mut self, regimen: PriorRegimen) -> Self {
+        self.prior_regimen = Some(regimen);
+        self
+    }
+
+    pub fn set_prior_regimen(&mut self, regimen: Option<PriorRegimen>) {
+        self.prior_regimen = regimen;
+    }
+
+    pub fn prior_regimen(&self) -> Option<&PriorRegimen> {
+        self.prior_regimen.as_ref()
+    }
+
     pub fn observations(&self) -> &[Observation] {
         &self.observations
     }
@@ -37,6 +94,35 @@ impl Individual {
         &self.covariates
     }
 
+    /// A copy of this individual, with the same doses and covariates,
+    /// observed at `observations` instead.
+    pub fn with_observations(&self, observations: Vec<Observation>) -> Self {
+        Self {
+            observations,
+            ..self.clone()
+        }
+    }
+
+    /// A copy of this individual with every dose, including any prior
+    /// regimen, `factor` times larger. Infusion rates scale with them, so
+    /// each dose keeps its duration.
+    pub fn with_dose_scale(&self, factor: f64) -> Self {
+        let dosing_records = self.dosing_records.iter()
+            .map(|dose| DosingRecord {
+                amount: dose.amount * factor,
+                rate: dose.rate.map(|rate| if rate > 0.0 { rate * factor } else { rate }),
+                ..dose.clone()
+            })
+            .collect();
+        let prior_regimen = self.prior_regimen.as_ref()
+            .map(|regimen| PriorRegimen { amount: regimen.amount * factor, ..regimen.clone() });
+        Self {
+            dosing_records,
+            prior_regimen,
+            ..self.clone()
+        }
+    }
+
     pub fn n_observations(&self) -> usize {
         self.observations.len()
     }
@@ -65,6 +151,81 @@ impl Individual {
         self.covariates.insert(name, value);
     }
 
+    /// Records that `name` was measured as `value` at `time`. Under LOCF it
+    /// takes that value from `time` onwards.
+    pub fn add_covariate_change(&mut self, name: &str, time: f64, value: f64) {
+        let changes = self.time_varying_covariates.entry(name.to_string()).or_default();
+        let position = changes.iter().position(|&(t, _)| t > time).unwrap_or(changes.len());
+        changes.insert(position, (time, value));
+    }
+
+    pub fn set_covariate_interpolation(&mut self, name: &str, interpolation: CovariateInterpolation) {
+        self.covariate_interpolation.insert(name.to_string(), interpolation);
+    }
+
+    pub fn covariate_interpolation(&self, name: &str) -> CovariateInterpolation {
+        self.covariate_interpolation.get(name).copied().unwrap_or_default()
+    }
+
+    /// Covariate value in effect at `time`. Under LOCF this is the last
+    /// measurement at or before `time`; linear interpolation draws a line
+    /// between the measurements either side. Before the first measurement,
+    /// or without any, the baseline value applies.
+    pub fn covariate_at(&self, name: &str, time: f64) -> Option<f64> {
+        let Some(changes) = self.time_varying_covariates.get(name) else {
+            return self.get_covariate(name);
+        };
+        let next = changes.iter().position(|&(t, _)| t > time).unwrap_or(changes.len());
+        if next == 0 {
+            return self.get_covariate(name);
+        }
+        let (t0, v0) = changes[next - 1];
+        match (self.covariate_interpolation(name), changes.get(next)) {
+            (CovariateInterpolation::Linear, Some(&(t1, v1))) => Some(v0 + (v1 - v0) * (time - t0) / (t1 - t0)),
+            _ => Some(v0),
+        }
+    }
+
+    /// All covariate values in effect at `time`.
+    pub fn covariates_at(&self, time: f64) -> HashMap<String, f64> {
+        let mut values = self.covariates.clone();
+        for name in self.time_varying_covariates.keys() {
+            if let Some(value) = self.covariate_at(name, time) {
+                values.insert(name.clone(), value);
+            }
+        }
+        values
+    }
+
+    /// Sorted, de-duplicated times at which any covariate changes value.
+    /// A linearly interpolated covariate changes at each observation time
+    /// between measurements, so predictions see it interpolated there.
+    pub fn covariate_change_times(&self) -> Vec<f64> {
+        let mut times = Vec::new();
+        for (name, changes) in &self.time_varying_covariates {
+            let mut candidates: Vec<f64> = changes.iter().map(|&(t, _)| t).collect();
+            if self.covariate_interpolation(name) == CovariateInterpolation::Linear {
+                candidates.extend(self.observations.iter().map(|obs| obs.time));
+                candidates.sort_by(|a, b| a.total_cmp(b));
+            }
+            let mut current = self.get_covariate(name);
+            for t in candidates {
+                let value = self.covariate_at(name, t);
+                if value != current {
+                    times.push(t);
+                    current = value;
+                }
+            }
+        }
+        times.sort_by(|a, b| a.total_cmp(b));
+        times.dedup();
+        times
+    }
+
+    pub fn has_time_varying_covariates(&self) -> bool {
+        !self.covariate_change_times().is_empty()
+    }
+
     pub fn first_dose_time(&self) -> Option<f64> {
         self.dosing_records.first().map(|dose| dose.time)
     }
@@ -107,4 +268,40 @@ mod tests {
         assert_eq!(individual.n_observations(), 2);
         assert_eq!(individual.total_dose(), 100.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_covariate_changes_carry_forward() {
+        let mut covariates = HashMap::new();
+        covariates.insert("WT".to_string(), 70.0);
+        let mut individual = Individual::new(1, Vec::new(), Vec::new(), covariates);
+        individual.add_covariate_change("WT", 5.0, 90.0);
+
+        assert_eq!(individual.covariate_at("WT", 0.0), Some(70.0));
+        assert_eq!(individual.covariate_at("WT", 5.0), Some(90.0));
+        assert_eq!(individual.covariate_at("WT", 12.0), Some(90.0));
+        assert_eq!(individual.covariate_change_times(), vec![5.0]);
+    }
+
+    #[test]
+    fn test_locf_and_linear_interpolation_between_measurements() {
+        let covariates = HashMap::from([("CRCL".to_string(), 100.0)]);
+        let observations = [6.0, 12.0, 30.0].iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let mut individual = Individual::new(1, observations, Vec::new(), covariates);
+        individual.add_covariate_change("CRCL", 0.0, 100.0);
+        individual.add_covariate_change("CRCL", 24.0, 40.0);
+
+        // LOCF holds 100 until the next measurement
+        assert_eq!(individual.covariate_at("CRCL", 12.0), Some(100.0));
+        assert_eq!(individual.covariate_change_times(), vec![24.0]);
+
+        individual.set_covariate_interpolation("CRCL", CovariateInterpolation::Linear);
+        assert_eq!(individual.covariate_at("CRCL", 12.0), Some(70.0));
+        assert_eq!(individual.covariate_at("CRCL", 6.0), Some(85.0));
+        // Constant outside the measurements
+        assert_eq!(individual.covariate_at("CRCL", -1.0), Some(100.0));
+        assert_eq!(individual.covariate_at("CRCL", 30.0), Some(40.0));
+        assert_eq!(individual.covariate_change_times(), vec![6.0, 12.0, 24.0]);
+    }
+}
@@ -3,10 +3,10 @@ pub mod observation;
 pub mod dosing;
 pub mod individual;
 
-pub use dataset::Dataset;
-pub use observation::{Observation, ObservationType};
-pub use dosing::{DosingRecord, DosingType};
-pub use individual::Individual;
+pub use dataset::{Dataset, DatasetSummary, DvColumn};
+pub use observation::{Censoring, Observation, ObservationType};
+pub use dosing::{DosingRecord, DosingType, PriorRegimen, MODELED_DURATION_RATE};
+pub use individual::{CovariateInterpolation, Individual};
 
 use thiserror::Error;
 
@@ -35,4 +35,13 @@ pub enum DataError {
     
     #[error("Negative time value: {0}")]
     NegativeTime(f64),
+    
+    #[error("Dose compartment {compartment} for individual {id} is outside the model's {n_compartments} compartment(s)")]
+    InvalidDoseCompartment { id: i32, compartment: i32, n_compartments: usize },
+    
+    #[error("Individual {id} has more than one observation at time {time} in compartment {compartment}")]
+    DuplicateObservation { id: i32, time: f64, compartment: i32 },
+    
+    #[error("Individual {0} has observations but no doses, and the model has no initial conditions, so all its predictions would be zero; add its dosing records, remove it, or use a model with baseline amounts")]
+    NoDoses(i32),
 }
\ No newline at end of file
@@ -7,12 +7,29 @@ pub enum ObservationType {
     Missing,
 }
 
+/// Whether an observation is a quantification limit rather than a measured
+/// value; `value` then holds the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Censoring {
+    #[default]
+    None,
+    /// Below the lower limit of quantification (BLQ), CENS = 1.
+    BelowLimit,
+    /// Above the upper limit of quantification (ULOQ), CENS = -1.
+    AboveLimit,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Observation {
     pub time: f64,
     pub value: f64,
     pub compartment: i32,
     pub observation_type: ObservationType,
+    /// A sample at a dose time taken before the dose (e.g. a trough).
+    #[serde(default)]
+    pub pre_dose: bool,
+    #[serde(default)]
+    pub censoring: Censoring,
 }
 
 impl Observation {
@@ -22,9 +39,25 @@ impl Observation {
             value,
             compartment,
             observation_type,
+            pre_dose: false,
+            censoring: Censoring::None,
         }
     }
 
+    pub fn with_pre_dose(mut self, pre_dose: bool) -> Self {
+        self.pre_dose = pre_dose;
+        self
+    }
+
+    pub fn with_censoring(mut self, censoring: Censoring) -> Self {
+        self.censoring = censoring;
+        self
+    }
+
+    pub fn is_censored(&self) -> bool {
+        self.censoring != Censoring::None
+    }
+
     pub fn is_valid(&self) -> bool {
         self.time >= 0.0 && 
         self.value.is_finite() && 
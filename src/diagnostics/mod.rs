@@ -1,6 +1,11 @@
-use crate::data::Dataset;
+use crate::data::{Dataset, Individual, Observation};
+use crate::estimation::objective;
+use crate::models::{CompartmentModel, CovariateModel, FittedModel};
+use crate::prediction;
 use crate::saem::SaemResults;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiagnosticResults {
@@ -13,6 +18,13 @@ pub struct DiagnosticResults {
 pub struct GoodnessOfFitMetrics {
     pub aic: f64,
     pub bic: f64,
+    /// AIC with the small-sample correction 2k(k+1)/(n-k-1), n observations.
+    #[serde(default)]
+    pub aicc: f64,
+    /// BIC penalizing the k fixed effects, all of which carry random
+    /// effects, by ln(number of individuals) instead of ln(observations).
+    #[serde(default)]
+    pub bicc: f64,
     pub log_likelihood: f64,
     pub rmse: f64,
     pub mae: f64,
@@ -21,10 +33,52 @@ pub struct GoodnessOfFitMetrics {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResidualAnalysis {
+    /// Individual ID of each residual, in ascending ID then time order.
+    pub ids: Vec<i32>,
+    /// DV - PRED.
     pub residuals: Vec<f64>,
+    /// Population-level: (DV - PRED) / sigma.
     pub standardized_residuals: Vec<f64>,
     pub weighted_residuals: Vec<f64>,
+    /// Individual-level: (DV - IPRED) / sigma.
+    pub iwres: Vec<f64>,
     pub residual_statistics: ResidualStatistics,
+    /// Correlation of the normal Q-Q plot of the standardized residuals;
+    /// well below 1 when they are not normal.
+    #[serde(default)]
+    pub qq_correlation: f64,
+    /// Lag-1 autocorrelation of each individual's time-ordered IWRES.
+    #[serde(default)]
+    pub iwres_autocorrelation: Vec<ResidualAutocorrelation>,
+    /// Distribution of `iwres_autocorrelation` over the individuals with
+    /// enough observations; `None` when none have.
+    #[serde(default)]
+    pub iwres_autocorrelation_summary: Option<AutocorrelationSummary>,
+}
+
+/// Lag-1 autocorrelation of one individual's residuals. Runs of same-signed
+/// residuals, as a structural model missing a phase of the profile leaves,
+/// push it towards 1; independent residuals keep it near 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResidualAutocorrelation {
+    pub id: i32,
+    pub n_residuals: usize,
+    /// NaN with fewer than `MIN_AUTOCORRELATION_RESIDUALS` residuals or
+    /// when they are all equal.
+    pub lag1: f64,
+}
+
+/// Fewest residuals an individual needs for a lag-1 autocorrelation.
+pub const MIN_AUTOCORRELATION_RESIDUALS: usize = 3;
+
+/// Spread of the per-individual lag-1 autocorrelations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutocorrelationSummary {
+    pub n_individuals: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub percentile_5: f64,
+    pub percentile_95: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,9 +100,11 @@ pub struct ConvergenceDiagnostics {
 pub fn generate_diagnostics(
     dataset: &Dataset,
     results: &SaemResults,
+    model: &CompartmentModel,
 ) -> Result<DiagnosticResults, anyhow::Error> {
-    let gof_metrics = calculate_goodness_of_fit(dataset, results)?;
-    let residual_analysis = analyze_residuals(dataset, results)?;
+    let fits = observation_fits(dataset, results, model)?;
+    let gof_metrics = calculate_goodness_of_fit(&fits, results);
+    let residual_analysis = analyze_residuals(&fits, results, model);
     let convergence_diagnostics = assess_convergence(results);
     
     Ok(DiagnosticResults {
@@ -58,50 +114,543 @@ pub fn generate_diagnostics(
     })
 }
 
+/// Eta and epsilon shrinkage for one parameter, in percent. Values above
+/// about 30% mean the individual estimates say little about that parameter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParameterShrinkage {
+    pub parameter: String,
+    pub omega: f64,
+    /// Standard deviation of the individual etas.
+    pub eta_sd: f64,
+    pub eta_shrinkage_percent: Option<f64>,
+    /// Shared by every parameter; one residual error per model.
+    pub epsilon_shrinkage_percent: Option<f64>,
+}
+
+/// SD-based eta shrinkage per parameter, 100 * (1 - SD(eta) / sqrt(omega)),
+/// with etas the individual estimates minus the fixed effects. `None` for
+/// fewer than two individuals or a non-positive omega. Clamped to [0, 100].
+pub fn eta_shrinkage(
+    individual_parameters: &HashMap<i32, Vec<f64>>,
+    fixed_effects: &[f64],
+    omega: &[Vec<f64>],
+) -> Vec<Option<f64>> {
+    (0..fixed_effects.len())
+        .map(|k| {
+            let etas: Vec<f64> = individual_parameters.values()
+                .map(|params| params[k] - fixed_effects[k])
+                .collect();
+            let omega_kk = omega[k][k];
+            if etas.len() < 2 || omega_kk <= 0.0 {
+                return None;
+            }
+            Some(shrinkage_percent(sample_sd(&etas), omega_kk.sqrt()))
+        })
+        .collect()
+}
+
+/// Epsilon shrinkage, 100 * (1 - SD(IWRES)), clamped to [0, 100].
+pub fn epsilon_shrinkage(iwres: &[f64]) -> Option<f64> {
+    let finite: Vec<f64> = iwres.iter().copied().filter(|r| r.is_finite()).collect();
+    if finite.len() < 2 {
+        return None;
+    }
+    Some(shrinkage_percent(sample_sd(&finite), 1.0))
+}
+
+/// Per-parameter shrinkage table for `shrinkage.csv`.
+pub fn parameter_shrinkage(results: &SaemResults, iwres: &[f64]) -> Vec<ParameterShrinkage> {
+    let eta = eta_shrinkage(&results.individual_parameters, &results.fixed_effects, &results.random_effects_variance);
+    let epsilon = epsilon_shrinkage(iwres);
+
+    results.parameter_names.iter().enumerate()
+        .map(|(k, name)| {
+            let etas: Vec<f64> = results.individual_parameters.values()
+                .map(|params| params[k] - results.fixed_effects[k])
+                .collect();
+            ParameterShrinkage {
+                parameter: name.clone(),
+                omega: results.random_effects_variance[k][k],
+                eta_sd: if etas.len() < 2 { f64::NAN } else { sample_sd(&etas) },
+                eta_shrinkage_percent: eta[k],
+                epsilon_shrinkage_percent: epsilon,
+            }
+        })
+        .collect()
+}
+
+fn sample_sd(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    (values.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+}
+
+fn shrinkage_percent(sd: f64, reference_sd: f64) -> f64 {
+    ((1.0 - sd / reference_sd) * 100.0).clamp(0.0, 100.0)
+}
+
+/// |correlation| above which a pair of estimates is flagged as poorly identified.
+pub const HIGH_CORRELATION_THRESHOLD: f64 = 0.95;
+
+/// Correlation matrix of the estimates from their covariance matrix. The
+/// diagonal is 1; entries involving a non-positive variance are NaN.
+pub fn correlation_matrix(covariance: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = covariance.len();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    let (var_i, var_j) = (covariance[i][i], covariance[j][j]);
+                    if i == j {
+                        1.0
+                    } else if var_i > 0.0 && var_j > 0.0 {
+                        (covariance[i][j] / (var_i * var_j).sqrt()).clamp(-1.0, 1.0)
+                    } else {
+                        f64::NAN
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Parameter pairs whose |correlation| exceeds `HIGH_CORRELATION_THRESHOLD`.
+pub fn high_correlations(correlation: &[Vec<f64>], parameter_names: &[String]) -> Vec<(String, String, f64)> {
+    let mut pairs = Vec::new();
+    for i in 0..correlation.len() {
+        for j in (i + 1)..correlation.len() {
+            if correlation[i][j].abs() > HIGH_CORRELATION_THRESHOLD {
+                pairs.push((parameter_names[i].clone(), parameter_names[j].clone(), correlation[i][j]));
+            }
+        }
+    }
+    pairs
+}
+
+/// %RSE above which an estimate is reported as poorly identified.
+pub const HIGH_RSE_THRESHOLD: f64 = 50.0;
+
+/// Parameters whose %RSE exceeds `HIGH_RSE_THRESHOLD`, with their %RSE.
+/// NaN (no estimate) is never flagged.
+pub fn poorly_identified_parameters(parameter_names: &[String], rse_percent: &[f64]) -> Vec<(String, f64)> {
+    parameter_names.iter().zip(rse_percent)
+        .filter(|(_, &rse)| rse > HIGH_RSE_THRESHOLD)
+        .map(|(name, &rse)| (name.clone(), rse))
+        .collect()
+}
+
+/// Inter-individual variability as a %CV from the Omega variance of a
+/// parameter, `sqrt(exp(ω²) − 1)·100`: every parameter is log-normal,
+/// `P = exp(θ + η)`.
+pub fn omega_cv_percent(variance: f64) -> f64 {
+    variance.exp_m1().sqrt() * 100.0
+}
+
+/// %CV of each diagonal Omega term, in parameter order.
+pub fn iiv_cv_percent(omega: &[Vec<f64>]) -> Vec<f64> {
+    omega.iter().enumerate().map(|(i, row)| omega_cv_percent(row[i])).collect()
+}
+
+/// Criterion by which fitted models are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InformationCriterion {
+    #[default]
+    Aic,
+    Aicc,
+    Bic,
+    Bicc,
+}
+
+impl std::fmt::Display for InformationCriterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InformationCriterion::Aic => write!(f, "AIC"),
+            InformationCriterion::Aicc => write!(f, "AICc"),
+            InformationCriterion::Bic => write!(f, "BIC"),
+            InformationCriterion::Bicc => write!(f, "BICc"),
+        }
+    }
+}
+
+/// AICc = AIC + 2k(k+1)/(n-k-1) for k parameters and n observations;
+/// infinite when n ≤ k + 1, where the correction is undefined.
+pub fn corrected_aic(aic: f64, n_params: usize, n_obs: usize) -> f64 {
+    if n_obs <= n_params + 1 {
+        return f64::INFINITY;
+    }
+    let k = n_params as f64;
+    aic + 2.0 * k * (k + 1.0) / (n_obs as f64 - k - 1.0)
+}
+
+/// Orders information criteria (AIC, BIC) from best to worst without
+/// panicking on diverged fits: finite values ascend, and non-finite values
+/// (NaN, ±Inf) sort after all finite ones.
+pub fn compare_information_criteria(a: f64, b: f64) -> Ordering {
+    match (a.is_finite(), b.is_finite()) {
+        (true, true) => a.total_cmp(&b),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.total_cmp(&b),
+    }
+}
+
+/// Goodness of fit within one stratum of individuals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StratumGoodnessOfFit {
+    pub stratum: String,
+    pub n_individuals: usize,
+    pub n_observations: usize,
+    pub rmse: f64,
+    pub mae: f64,
+    pub r_squared: f64,
+}
+
+/// Normal quantile of the two-sided 95% confidence intervals.
+const Z_95: f64 = 1.959964;
+
+/// Percentiles of the baseline covariate distribution shown for each effect.
+const FOREST_PERCENTILES: [(f64, &str); 2] = [(0.05, "P5"), (0.95, "P95")];
+
+/// One row of a covariate forest plot: the parameter at a covariate value
+/// relative to its value at the effect's reference, with a 95% CI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CovariateForestEffect {
+    pub parameter: String,
+    pub covariate: String,
+    /// "REF", or the percentile of the covariate across individuals ("P5", "P95").
+    pub label: String,
+    pub value: f64,
+    /// P(value) / P(reference) = (value / reference)^exponent.
+    pub ratio: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Forest-plot rows for every effect of `covariate_model`: the reference and
+/// the 5th and 95th percentiles of the baseline covariate in `dataset`.
+/// `covariance` is the sampling covariance of the effect exponents, in
+/// effect order, from the fit that estimated them; the estimators here hold
+/// the exponents fixed, so their covariance step does not cover them. The CI comes from the delta method on the log ratio,
+/// SE = |ln(value / reference)| · SE(exponent), so it is asymmetric about
+/// the ratio; it is NaN for effects without a variance.
+pub fn covariate_effects(
+    covariate_model: &CovariateModel,
+    dataset: &Dataset,
+    covariance: &[Vec<f64>],
+) -> Vec<CovariateForestEffect> {
+    let mut rows = Vec::new();
+    for (k, effect) in covariate_model.effects.iter().enumerate() {
+        let variance = covariance.get(k).and_then(|row| row.get(k)).copied().unwrap_or(f64::NAN);
+        let mut values: Vec<f64> = dataset.individuals().values()
+            .filter_map(|individual| individual.get_covariate(&effect.covariate))
+            .filter(|value| *value > 0.0)
+            .collect();
+
+        let mut points = vec![("REF".to_string(), effect.reference)];
+        for (p, label) in FOREST_PERCENTILES {
+            if let Some(value) = crate::vpc::percentile(&mut values, p) {
+                points.push((label.to_string(), value));
+            }
+        }
+        for (label, value) in points {
+            let log_ratio = (value / effect.reference).ln();
+            let se = log_ratio.abs() * variance.sqrt();
+            rows.push(CovariateForestEffect {
+                parameter: effect.parameter.clone(),
+                covariate: effect.covariate.clone(),
+                label,
+                value,
+                ratio: (effect.exponent * log_ratio).exp(),
+                lower: (effect.exponent * log_ratio - Z_95 * se).exp(),
+                upper: (effect.exponent * log_ratio + Z_95 * se).exp(),
+            });
+        }
+    }
+    rows
+}
+
+/// One observation with its population (PRED) and individual (IPRED)
+/// predictions.
+struct ObservationFit {
+    id: i32,
+    obs: Observation,
+    pred: f64,
+    ipred: f64,
+}
+
+/// Predictions for every observation, in ascending ID then time order.
+fn observation_fits(
+    dataset: &Dataset,
+    results: &SaemResults,
+    model: &CompartmentModel,
+) -> Result<Vec<ObservationFit>, anyhow::Error> {
+    let predictions = prediction::individual_predictions(results, dataset, model)?;
+
+    Ok(dataset.iter_observations_with(&predictions)
+        .map(|(id, obs, &(_, ipred, pred))| ObservationFit { id, obs: obs.clone(), pred, ipred })
+        .collect())
+}
+
+/// RMSE, MAE and R² of DV against IPRED. R² is NaN when DV does not vary.
+fn fit_statistics<'a>(fits: impl Iterator<Item = &'a ObservationFit>) -> (f64, f64, f64) {
+    let pairs: Vec<(f64, f64)> = fits.map(|fit| (fit.obs.value, fit.ipred)).collect();
+    if pairs.is_empty() {
+        return (f64::NAN, f64::NAN, f64::NAN);
+    }
+    let n = pairs.len() as f64;
+    let ss_res: f64 = pairs.iter().map(|(dv, ipred)| (dv - ipred).powi(2)).sum();
+    let mae = pairs.iter().map(|(dv, ipred)| (dv - ipred).abs()).sum::<f64>() / n;
+    let mean_dv = pairs.iter().map(|(dv, _)| dv).sum::<f64>() / n;
+    let ss_tot: f64 = pairs.iter().map(|(dv, _)| (dv - mean_dv).powi(2)).sum();
+    let r_squared = if ss_tot > 0.0 { 1.0 - ss_res / ss_tot } else { f64::NAN };
+    ((ss_res / n).sqrt(), mae, r_squared)
+}
+
 fn calculate_goodness_of_fit(
-    _dataset: &Dataset,
+    fits: &[ObservationFit],
     results: &SaemResults,
-) -> Result<GoodnessOfFitMetrics, anyhow::Error> {
-    // Simplified implementation
+) -> GoodnessOfFitMetrics {
     let n_params = results.fixed_effects.len();
-    let n_obs = 100; // Placeholder
-    
-    Ok(GoodnessOfFitMetrics {
-        aic: -2.0 * results.final_log_likelihood + 2.0 * n_params as f64,
+    let n_obs = fits.len();
+    let (rmse, mae, r_squared) = fit_statistics(fits.iter());
+
+    let mut ids: Vec<i32> = fits.iter().map(|fit| fit.id).collect();
+    ids.dedup();
+    let aic = -2.0 * results.final_log_likelihood + 2.0 * n_params as f64;
+
+    GoodnessOfFitMetrics {
+        aic,
         bic: -2.0 * results.final_log_likelihood + (n_params as f64) * (n_obs as f64).ln(),
+        aicc: corrected_aic(aic, n_params, n_obs),
+        bicc: -2.0 * results.final_log_likelihood + (n_params as f64) * (ids.len() as f64).ln(),
         log_likelihood: results.final_log_likelihood,
-        rmse: 1.0, // Placeholder
-        mae: 0.8,  // Placeholder
-        r_squared: 0.95, // Placeholder
-    })
+        rmse,
+        mae,
+        r_squared,
+    }
+}
+
+/// Label of the stratum an individual falls in: the baseline value of the
+/// covariate `stratify_by`, or the first dose amount for `AMT`. "NA" when
+/// the individual has no such value.
+fn stratum_label(individual: &Individual, stratify_by: &str) -> String {
+    let value = if stratify_by == "AMT" {
+        individual.dosing_records().first().map(|dose| dose.amount)
+    } else {
+        individual.get_covariate(stratify_by)
+    };
+    value.map(|v| v.to_string()).unwrap_or_else(|| "NA".to_string())
+}
+
+/// The goodness-of-fit metrics of `generate_diagnostics` computed within each
+/// stratum, in first-seen order of ascending ID.
+pub fn goodness_of_fit_by_stratum(
+    dataset: &Dataset,
+    results: &SaemResults,
+    model: &CompartmentModel,
+    stratify_by: &str,
+) -> Result<Vec<StratumGoodnessOfFit>, anyhow::Error> {
+    let fits = observation_fits(dataset, results, model)?;
+
+    let mut strata: Vec<(String, Vec<i32>)> = Vec::new();
+    let mut ids: Vec<i32> = dataset.individuals().keys().copied().collect();
+    ids.sort_unstable();
+    for id in ids {
+        let label = stratum_label(&dataset.individuals()[&id], stratify_by);
+        match strata.iter_mut().find(|(existing, _)| *existing == label) {
+            Some((_, members)) => members.push(id),
+            None => strata.push((label, vec![id])),
+        }
+    }
+
+    Ok(strata.into_iter()
+        .map(|(stratum, members)| {
+            let in_stratum = || fits.iter().filter(|fit| members.contains(&fit.id));
+            let (rmse, mae, r_squared) = fit_statistics(in_stratum());
+            StratumGoodnessOfFit {
+                stratum,
+                n_individuals: members.len(),
+                n_observations: in_stratum().count(),
+                rmse,
+                mae,
+                r_squared,
+            }
+        })
+        .collect())
 }
 
 fn analyze_residuals(
-    _dataset: &Dataset,
-    _results: &SaemResults,
-) -> Result<ResidualAnalysis, anyhow::Error> {
-    // Placeholder implementation
-    let residuals = vec![0.1, -0.2, 0.05, -0.1, 0.15]; // Placeholder data
-    
-    let mean = residuals.iter().sum::<f64>() / residuals.len() as f64;
-    let variance = residuals.iter()
-        .map(|&x| (x - mean).powi(2))
-        .sum::<f64>() / (residuals.len() - 1) as f64;
-    let std_dev = variance.sqrt();
-    
-    Ok(ResidualAnalysis {
+    fits: &[ObservationFit],
+    results: &SaemResults,
+    model: &CompartmentModel,
+) -> ResidualAnalysis {
+    // Each residual over the SD of its observation's error model at the
+    // prediction it is taken from; on the log scale under the exponential
+    // model
+    let params = FittedModel::from_saem(model, results).parameters;
+    let weighted = |obs: &Observation, prediction: f64| {
+        let sd = objective::observation_variance(&params, obs, prediction).sqrt();
+        if objective::on_log_scale(&params, obs) {
+            objective::log_scale_residual(obs, prediction) / sd
+        } else {
+            (obs.value - prediction) / sd
+        }
+    };
+
+    let residuals: Vec<f64> = fits.iter().map(|fit| fit.obs.value - fit.pred).collect();
+    let iwres: Vec<f64> = fits.iter().map(|fit| weighted(&fit.obs, fit.ipred)).collect();
+    let standardized_residuals: Vec<f64> = fits.iter().map(|fit| weighted(&fit.obs, fit.pred)).collect();
+
+    let ids: Vec<i32> = fits.iter().map(|fit| fit.id).collect();
+    let iwres_autocorrelation = residual_autocorrelation(&ids, &iwres);
+
+    ResidualAnalysis {
         residuals: residuals.clone(),
-        standardized_residuals: residuals.iter().map(|&x| x / std_dev).collect(),
-        weighted_residuals: residuals.clone(), // Simplified
-        residual_statistics: ResidualStatistics {
-            mean,
-            std_dev,
-            skewness: 0.0, // Placeholder
-            kurtosis: 3.0, // Placeholder
-        },
+        residual_statistics: residual_statistics(&standardized_residuals),
+        qq_correlation: qq_data(&standardized_residuals).correlation,
+        standardized_residuals,
+        weighted_residuals: residuals, // Simplified
+        iwres_autocorrelation_summary: autocorrelation_summary(&iwres_autocorrelation),
+        iwres_autocorrelation,
+        ids,
+        iwres,
+    }
+}
+
+/// Sample lag-1 autocorrelation of time-ordered `residuals`:
+/// Σ (r_t − r̄)(r_{t+1} − r̄) / Σ (r_t − r̄)².
+pub fn lag1_autocorrelation(residuals: &[f64]) -> f64 {
+    if residuals.len() < MIN_AUTOCORRELATION_RESIDUALS {
+        return f64::NAN;
+    }
+    let mean = residuals.iter().sum::<f64>() / residuals.len() as f64;
+    let variance: f64 = residuals.iter().map(|r| (r - mean).powi(2)).sum();
+    if variance == 0.0 {
+        return f64::NAN;
+    }
+    let covariance: f64 = residuals.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum();
+    covariance / variance
+}
+
+/// Lag-1 autocorrelation per individual of residuals in ascending ID then
+/// time order, with `ids` the individual of each.
+pub fn residual_autocorrelation(ids: &[i32], residuals: &[f64]) -> Vec<ResidualAutocorrelation> {
+    let mut autocorrelations = Vec::new();
+    let mut start = 0;
+    while start < ids.len() {
+        let id = ids[start];
+        let end = start + ids[start..].iter().take_while(|&&other| other == id).count();
+        let individual = &residuals[start..end];
+        autocorrelations.push(ResidualAutocorrelation {
+            id,
+            n_residuals: individual.len(),
+            lag1: lag1_autocorrelation(individual),
+        });
+        start = end;
+    }
+    autocorrelations
+}
+
+/// Mean, median and 5th/95th percentiles of the finite autocorrelations.
+pub fn autocorrelation_summary(autocorrelations: &[ResidualAutocorrelation]) -> Option<AutocorrelationSummary> {
+    let mut values: Vec<f64> = autocorrelations.iter()
+        .map(|a| a.lag1)
+        .filter(|lag1| lag1.is_finite())
+        .collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    Some(AutocorrelationSummary {
+        n_individuals: values.len(),
+        mean,
+        median: crate::vpc::percentile(&mut values, 0.5)?,
+        percentile_5: crate::vpc::percentile(&mut values, 0.05)?,
+        percentile_95: crate::vpc::percentile(&mut values, 0.95)?,
     })
 }
 
+/// Points of a normal Q-Q plot: the residuals sorted, each against the
+/// standard normal quantile at its Blom plotting position
+/// (i − 3/8)/(n + 1/4), with the correlation of the pairs (the plot's R).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QqData {
+    pub theoretical_quantiles: Vec<f64>,
+    pub sorted_residuals: Vec<f64>,
+    /// NaN with fewer than two residuals or when they are all equal.
+    pub correlation: f64,
+}
+
+pub fn qq_data(residuals: &[f64]) -> QqData {
+    let mut sorted_residuals = residuals.to_vec();
+    sorted_residuals.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted_residuals.len() as f64;
+    let theoretical_quantiles: Vec<f64> = (1..=sorted_residuals.len())
+        .map(|i| normal_quantile((i as f64 - 0.375) / (n + 0.25)))
+        .collect();
+    let correlation = pearson_correlation(&theoretical_quantiles, &sorted_residuals);
+    QqData { theoretical_quantiles, sorted_residuals, correlation }
+}
+
+fn pearson_correlation(x: &[f64], y: &[f64]) -> f64 {
+    if x.len() < 2 {
+        return f64::NAN;
+    }
+    let n = x.len() as f64;
+    let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let covariance: f64 = x.iter().zip(y).map(|(a, b)| (a - mean_x) * (b - mean_y)).sum();
+    let ss_x: f64 = x.iter().map(|a| (a - mean_x).powi(2)).sum();
+    let ss_y: f64 = y.iter().map(|b| (b - mean_y).powi(2)).sum();
+    covariance / (ss_x * ss_y).sqrt()
+}
+
+/// Standard normal quantile Φ⁻¹(p) for 0 < p < 1, by Acklam's rational
+/// approximation (relative error below 1.2e-9).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2,
+                         1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2,
+                         6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838,
+                         -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996,
+                         3.754408661907416];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    }
+}
+
+fn residual_statistics(values: &[f64]) -> ResidualStatistics {
+    let n = values.len() as f64;
+    if values.len() < 2 {
+        return ResidualStatistics { mean: 0.0, std_dev: 0.0, skewness: 0.0, kurtosis: 0.0 };
+    }
+
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let std_dev = variance.sqrt();
+
+    let (skewness, kurtosis) = if std_dev > 0.0 {
+        let m3 = values.iter().map(|&x| ((x - mean) / std_dev).powi(3)).sum::<f64>() / n;
+        let m4 = values.iter().map(|&x| ((x - mean) / std_dev).powi(4)).sum::<f64>() / n;
+        (m3, m4)
+    } else {
+        (0.0, 0.0)
+    };
+
+    ResidualStatistics { mean, std_dev, skewness, kurtosis }
+}
+
 fn assess_convergence(results: &SaemResults) -> ConvergenceDiagnostics {
     let n_recent = 100.min(results.log_likelihood_trajectory.len());
     
@@ -124,4 +673,304 @@ fn assess_convergence(results: &SaemResults) -> ConvergenceDiagnostics {
         parameter_stability: vec![0.01; results.fixed_effects.len()], // Placeholder
         log_likelihood_stability: stability,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelType;
+    use crate::test_support::dataset_from_csv;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+
+    #[test]
+    fn test_nan_aic_ranks_last() {
+        let mut aics = [f64::NAN, 120.5, f64::INFINITY, 98.2, f64::NEG_INFINITY];
+        aics.sort_by(|a, b| compare_information_criteria(*a, *b));
+
+        assert_eq!(&aics[..2], &[98.2, 120.5]);
+        assert!(aics[2..].iter().all(|aic| !aic.is_finite()));
+        assert!(aics[4].is_nan());
+
+        let best = [f64::NAN, 250.0].into_iter()
+            .min_by(|a, b| compare_information_criteria(*a, *b));
+        assert_eq!(best, Some(250.0));
+    }
+
+    #[test]
+    fn test_omega_cv_percent_of_log_normal_parameters() {
+        // ω² = 0.09 is the conventional "30% CV" of a log-normal parameter
+        let cv = omega_cv_percent(0.09);
+        assert!((cv - 30.69).abs() < 0.01, "{}", cv);
+
+        let omega = vec![vec![0.09, 0.01], vec![0.01, 0.04]];
+        let cv = iiv_cv_percent(&omega);
+        assert_eq!(cv, vec![omega_cv_percent(0.09), 0.04_f64.exp_m1().sqrt() * 100.0]);
+    }
+
+    #[test]
+    fn test_correlation_matrix_unit_diagonal_and_bounded() {
+        let covariance = vec![
+            vec![0.04, 0.019, -0.01],
+            vec![0.019, 0.01, 0.002],
+            vec![-0.01, 0.002, 0.25],
+        ];
+        let correlation = correlation_matrix(&covariance);
+
+        for (i, row) in correlation.iter().enumerate() {
+            assert_eq!(row[i], 1.0);
+            for (j, &r) in row.iter().enumerate() {
+                assert!((-1.0..=1.0).contains(&r));
+                assert_eq!(r, correlation[j][i]);
+            }
+        }
+        assert!((correlation[0][1] - 0.95).abs() < 1e-12);
+
+        let names = vec!["CL".to_string(), "V".to_string(), "KA".to_string()];
+        assert!(high_correlations(&correlation, &names).is_empty());
+        let mut collinear = covariance.clone();
+        collinear[0][1] = 0.0199;
+        collinear[1][0] = 0.0199;
+        let flagged = high_correlations(&correlation_matrix(&collinear), &names);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!((flagged[0].0.as_str(), flagged[0].1.as_str()), ("CL", "V"));
+    }
+
+    #[test]
+    fn test_iwres_unit_variance_on_simulated_data() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let sigma: f64 = 0.2;
+        let times = [0.5, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0];
+        let mut rng = StdRng::seed_from_u64(7);
+        let eta = Normal::new(0.0, 0.3).unwrap();
+        let noise = Normal::new(0.0, sigma).unwrap();
+
+        let mut results = SaemResults::new(2, model.parameter_names());
+        results.fixed_effects = params.fixed_effects.clone();
+        results.residual_variance = sigma * sigma;
+
+        // Simulate each individual from known parameters, then add noise
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=40 {
+            let mut ind_params = params.clone();
+            for theta in ind_params.fixed_effects.iter_mut() {
+                *theta += eta.sample(&mut rng);
+            }
+            results.individual_parameters.insert(id, ind_params.fixed_effects.clone());
+
+            let cl = ind_params.fixed_effects[0].exp();
+            let v = ind_params.fixed_effects[1].exp();
+            csv.push_str(&format!("{},0,,100,1,1\n", id));
+            for &t in &times {
+                let conc = 100.0 / v * (-cl / v * t).exp() + noise.sample(&mut rng);
+                csv.push_str(&format!("{},{},{},,0,1\n", id, t, conc));
+            }
+        }
+
+        let dataset = dataset_from_csv("iwres_test.csv", &csv);
+
+        let fits = observation_fits(&dataset, &results, &model).unwrap();
+        let analysis = analyze_residuals(&fits, &results, &model);
+        assert_eq!(analysis.iwres.len(), 40 * times.len());
+        assert_eq!(analysis.ids.len(), analysis.iwres.len());
+        assert_eq!(analysis.ids[0], 1);
+
+        let stats = residual_statistics(&analysis.iwres);
+        assert!(stats.mean.abs() < 0.15, "IWRES mean {}", stats.mean);
+        assert!((stats.std_dev - 1.0).abs() < 0.1, "IWRES sd {}", stats.std_dev);
+    }
+
+    #[test]
+    fn test_iwres_scaled_by_proportional_error_of_converted_foce_fit() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let cv: f64 = 0.15;
+        let mut rng = StdRng::seed_from_u64(126);
+        let noise = Normal::new(0.0, cv).unwrap();
+
+        // A FOCE proportional fit: no additive part to divide by
+        let mut results = SaemResults::new(2, model.parameter_names());
+        results.fixed_effects = params.fixed_effects.clone();
+        results.error_model = crate::estimation::ErrorModel::Proportional;
+        results.residual_variance = 0.0;
+        results.proportional_variance = cv * cv;
+
+        let (cl, v) = (params.fixed_effects[0].exp(), params.fixed_effects[1].exp());
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=30 {
+            results.individual_parameters.insert(id, params.fixed_effects.clone());
+            csv.push_str(&format!("{},0,,100,1,1\n", id));
+            for t in [0.5_f64, 1.0, 2.0, 4.0, 8.0, 12.0, 24.0] {
+                let conc = 100.0 / v * (-cl / v * t).exp();
+                csv.push_str(&format!("{},{},{},,0,1\n", id, t, conc * (1.0 + noise.sample(&mut rng))));
+            }
+        }
+        let dataset = dataset_from_csv("iwres_proportional_test.csv", &csv);
+
+        let fits = observation_fits(&dataset, &results, &model).unwrap();
+        let analysis = analyze_residuals(&fits, &results, &model);
+        let stats = residual_statistics(&analysis.iwres);
+        assert!(stats.mean.abs() < 0.15, "IWRES mean {}", stats.mean);
+        assert!((stats.std_dev - 1.0).abs() < 0.1, "IWRES sd {}", stats.std_dev);
+        assert!(analysis.qq_correlation > 0.98, "Q-Q correlation {}", analysis.qq_correlation);
+        assert!(epsilon_shrinkage(&analysis.iwres).is_some());
+    }
+
+    #[test]
+    fn test_qq_correlation_near_one_for_gaussian_residuals() {
+        assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((normal_quantile(0.001) + 3.090232).abs() < 1e-6);
+        assert_eq!(normal_quantile(0.5), 0.0);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let gaussian: Vec<f64> = (0..500).map(|_| normal.sample(&mut rng)).collect();
+        let qq = qq_data(&gaussian);
+        assert!(qq.sorted_residuals.windows(2).all(|w| w[0] <= w[1]));
+        assert!(qq.theoretical_quantiles.windows(2).all(|w| w[0] < w[1]));
+        assert!(qq.correlation > 0.995, "Q-Q correlation {}", qq.correlation);
+
+        // Squared normals are strongly right-skewed
+        let skewed: Vec<f64> = gaussian.iter().map(|x| x * x).collect();
+        assert!(qq_data(&skewed).correlation < 0.9);
+    }
+
+    #[test]
+    fn test_serially_correlated_residuals_have_high_lag1_autocorrelation() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let n_individuals = 30;
+        let n_times = 12;
+
+        // AR(1) with coefficient 0.8 per individual, then white noise
+        let mut ids = Vec::new();
+        let mut correlated = Vec::new();
+        let mut independent = Vec::new();
+        for id in 1..=n_individuals {
+            let mut previous: f64 = normal.sample(&mut rng);
+            for _ in 0..n_times {
+                previous = 0.8 * previous + 0.6 * normal.sample(&mut rng);
+                ids.push(id);
+                correlated.push(previous);
+                independent.push(normal.sample(&mut rng));
+            }
+        }
+
+        let autocorrelations = residual_autocorrelation(&ids, &correlated);
+        assert_eq!(autocorrelations.len(), n_individuals as usize);
+        assert!(autocorrelations.iter().all(|a| a.n_residuals == n_times));
+        let summary = autocorrelation_summary(&autocorrelations).unwrap();
+        assert_eq!(summary.n_individuals, n_individuals as usize);
+        assert!(summary.median > 0.4, "AR(1) median lag-1 {}", summary.median);
+
+        let summary = autocorrelation_summary(&residual_autocorrelation(&ids, &independent)).unwrap();
+        assert!(summary.median.abs() < 0.2, "white-noise median lag-1 {}", summary.median);
+        assert!(summary.percentile_5 < summary.median && summary.median < summary.percentile_95);
+
+        // Too few residuals, or no variation, gives no estimate
+        assert!(lag1_autocorrelation(&[1.0, -1.0]).is_nan());
+        assert!(lag1_autocorrelation(&[0.5; 5]).is_nan());
+        assert!(autocorrelation_summary(&residual_autocorrelation(&[1, 1, 2], &[0.1, 0.2, 0.3])).is_none());
+    }
+
+    #[test]
+    fn test_gof_by_stratum_separates_well_and_poorly_fit_dose_groups() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+
+        let mut results = SaemResults::new(2, model.parameter_names());
+        results.fixed_effects = params.fixed_effects.clone();
+        results.residual_variance = 0.01;
+
+        // The 100 mg group follows the model; the 300 mg group has twice the
+        // clearance the fit assumes
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT,DOSE\n");
+        for id in 1..=6 {
+            let (dose, true_cl) = if id <= 3 { (100.0, cl) } else { (300.0, 2.0 * cl) };
+            csv.push_str(&format!("{},0,,{},1,1,{}\n", id, dose, dose));
+            for t in [1.0, 2.0, 4.0, 8.0, 12.0] {
+                let conc = dose / v * (-true_cl / v * t).exp();
+                csv.push_str(&format!("{},{},{},,0,1,{}\n", id, t, conc, dose));
+            }
+        }
+        let dataset = dataset_from_csv("gof_strata_test.csv", &csv);
+
+        let strata = goodness_of_fit_by_stratum(&dataset, &results, &model, "DOSE").unwrap();
+        assert_eq!(strata.len(), 2);
+        assert_eq!((strata[0].stratum.as_str(), strata[1].stratum.as_str()), ("100", "300"));
+        assert_eq!(strata[0].n_individuals, 3);
+        assert_eq!(strata[1].n_observations, 15);
+        assert!(strata[0].rmse < 0.05, "well-fit RMSE {}", strata[0].rmse);
+        assert!(strata[1].rmse > 1.0, "poorly fit RMSE {}", strata[1].rmse);
+        assert!(strata[0].r_squared > strata[1].r_squared);
+
+        // The overall RMSE pools both groups
+        let overall = generate_diagnostics(&dataset, &results, &model).unwrap().goodness_of_fit;
+        assert!(overall.rmse > strata[0].rmse && overall.rmse < strata[1].rmse);
+    }
+
+    #[test]
+    fn test_shrinkage_bounds_and_extremes() {
+        let fixed_effects = vec![0.0, 1.0];
+        let omega = vec![vec![0.04, 0.0], vec![0.0, 0.09]];
+
+        // CL etas spread as widely as omega says; V etas all at zero
+        let mut individual_parameters = HashMap::new();
+        for (id, z) in [-1.5, -0.5, 0.5, 1.5].iter().enumerate() {
+            individual_parameters.insert(id as i32, vec![z * 0.2 / 1.2910, 1.0]);
+        }
+        let shrinkage = eta_shrinkage(&individual_parameters, &fixed_effects, &omega);
+        assert!(shrinkage[0].unwrap() < 0.1);
+        assert_eq!(shrinkage[1], Some(100.0));
+
+        assert_eq!(epsilon_shrinkage(&[0.0; 5]), Some(100.0));
+        assert_eq!(epsilon_shrinkage(&[1.0]), None);
+    }
+
+    #[test]
+    fn test_weight_effect_on_cl_forest_rows() {
+        // Weights 50, 52, ..., 90: 5th percentile 52, 95th percentile 88
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,WT\n");
+        for id in 1..=21 {
+            csv.push_str(&format!("{},0,,100,1,{}\n{},1,5.0,,0,\n", id, 48 + 2 * id, id));
+        }
+        let dataset = dataset_from_csv("nmodes_forest_weights.csv", &csv);
+
+        let covariate_model = CovariateModel::new().with_effect(crate::models::CovariateEffect {
+            parameter: "CL".to_string(),
+            covariate: "WT".to_string(),
+            reference: 70.0,
+            exponent: 0.75,
+        });
+        let rows = covariate_effects(&covariate_model, &dataset, &[vec![0.01]]);
+        let labels: Vec<&str> = rows.iter().map(|row| row.label.as_str()).collect();
+        assert_eq!(labels, ["REF", "P5", "P95"]);
+
+        // No change, and no uncertainty, at the reference weight
+        assert_eq!((rows[0].ratio, rows[0].lower, rows[0].upper), (1.0, 1.0, 1.0));
+
+        let heavy = &rows[2];
+        assert!((heavy.value - 88.0).abs() < 1e-9);
+        assert!((heavy.ratio - (88.0_f64 / 70.0).powf(0.75)).abs() < 1e-12);
+        let half_width = Z_95 * 0.1 * (88.0_f64 / 70.0).ln();
+        assert!((heavy.lower - (heavy.ratio.ln() - half_width).exp()).abs() < 1e-12);
+        assert!((heavy.upper - (heavy.ratio.ln() + half_width).exp()).abs() < 1e-12);
+        assert!(rows[1].ratio < 1.0 && rows[1].lower < rows[1].ratio && rows[1].ratio < rows[1].upper);
+    }
+
+    #[test]
+    fn test_aicc_exceeds_aic_and_approaches_it_with_more_observations() {
+        let aic = 100.0;
+        let corrections: Vec<f64> = [10, 50, 1000, 100_000].iter()
+            .map(|&n| corrected_aic(aic, 4, n) - aic)
+            .collect();
+        assert!(corrections.iter().all(|c| *c > 0.0));
+        assert!(corrections.windows(2).all(|w| w[1] < w[0]));
+        assert!((corrections[0] - 40.0 / 5.0).abs() < 1e-12);
+        assert!(corrections[3] < 1e-3);
+        assert_eq!(corrected_aic(aic, 4, 5), f64::INFINITY);
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Floor on log-scale parameters without an explicit lower bound, so that
+/// exp(theta) stays away from zero (exp(-10) ≈ 4.5e-5).
+pub const LOG_PARAMETER_FLOOR: f64 = -10.0;
+
+/// Distance on the log scale within which a parameter counts as at its bound.
+const AT_BOUND_TOLERANCE: f64 = 1e-3;
+
+/// Natural-scale limits on a parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParameterBounds {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ParameterBounds {
+    pub fn new(lower: f64, upper: f64) -> Self {
+        Self { lower, upper }
+    }
+}
+
+/// Log-scale (lower, upper) for each named parameter. Parameters without
+/// bounds are only floored at `LOG_PARAMETER_FLOOR`. Lower bounds must be
+/// positive, as `EstimationConfig::validate` checks.
+pub fn log_bounds(bounds: &HashMap<String, ParameterBounds>, parameter_names: &[String]) -> Vec<(f64, f64)> {
+    parameter_names.iter()
+        .map(|name| match bounds.get(name) {
+            Some(b) => (b.lower.ln(), b.upper.ln()),
+            None => (LOG_PARAMETER_FLOOR, f64::INFINITY),
+        })
+        .collect()
+}
+
+/// Reflects `value` back into `[lower, upper]` off whichever bound it
+/// crossed, for proposals and inner-optimizer steps. Values too far out to
+/// land inside after one reflection are clamped to the bound they crossed.
+pub fn reflect(value: f64, (lower, upper): (f64, f64)) -> f64 {
+    if value < lower {
+        let reflected = 2.0 * lower - value;
+        if reflected <= upper { reflected } else { lower }
+    } else if value > upper {
+        let reflected = 2.0 * upper - value;
+        if reflected >= lower { reflected } else { upper }
+    } else {
+        value
+    }
+}
+
+/// Clamps a population update to `[lower, upper]`. Unlike `reflect`, an
+/// update past a bound ends on it, where `parameters_at_bounds` flags it.
+pub fn clamp(value: f64, (lower, upper): (f64, f64)) -> f64 {
+    value.clamp(lower, upper)
+}
+
+/// Names of the parameters whose log-scale value lies on one of its bounds.
+pub fn parameters_at_bounds(values: &[f64], bounds: &[(f64, f64)], parameter_names: &[String]) -> Vec<String> {
+    values.iter().zip(bounds).zip(parameter_names)
+        .filter(|((&value, &(lower, upper)), _)| {
+            (value - lower).abs() < AT_BOUND_TOLERANCE || (upper - value).abs() < AT_BOUND_TOLERANCE
+        })
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reflect_stays_within_bounds() {
+        let bounds = (0.0, 1.0);
+        assert_eq!(reflect(0.5, bounds), 0.5);
+        assert!((reflect(1.25, bounds) - 0.75).abs() < 1e-12);
+        assert!((reflect(-0.25, bounds) - 0.25).abs() < 1e-12);
+        assert_eq!(reflect(5.0, bounds), 1.0);
+        assert_eq!(reflect(-5.0, bounds), 0.0);
+        assert!((reflect(-10.5, (LOG_PARAMETER_FLOOR, f64::INFINITY)) + 9.5).abs() < 1e-12);
+
+        assert_eq!(clamp(1.25, bounds), 1.0);
+        assert_eq!(clamp(-0.25, bounds), 0.0);
+        assert_eq!(clamp(0.5, bounds), 0.5);
+    }
+
+    #[test]
+    fn test_parameters_at_bounds_flagged() {
+        let names = vec!["CL".to_string(), "V".to_string()];
+        let bounds = HashMap::from([("CL".to_string(), ParameterBounds::new(0.1, 2.0))]);
+        let limits = log_bounds(&bounds, &names);
+
+        assert_eq!(parameters_at_bounds(&[2.0_f64.ln(), 1.0], &limits, &names), vec!["CL".to_string()]);
+        assert!(parameters_at_bounds(&[1.0_f64.ln(), 1.0], &limits, &names).is_empty());
+        assert_eq!(parameters_at_bounds(&[0.0, LOG_PARAMETER_FLOOR], &limits, &names), vec!["V".to_string()]);
+    }
+}
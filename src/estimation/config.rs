@@ -1,10 +1,18 @@
+use super::ParameterBounds;
+use crate::models::EtaPrior;
+use crate::solver::SolverConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EstimationMethod {
     Saem,
     Foce,
     FoceI, // FOCE with interaction
+    /// FOCE's conditional modes with the marginal likelihood from adaptive
+    /// Gauss–Hermite quadrature instead of the Laplace approximation.
+    Agq,
 }
 
 impl std::fmt::Display for EstimationMethod {
@@ -13,6 +21,56 @@ impl std::fmt::Display for EstimationMethod {
             EstimationMethod::Saem => write!(f, "SAEM"),
             EstimationMethod::Foce => write!(f, "FOCE"),
             EstimationMethod::FoceI => write!(f, "FOCE-I"),
+            EstimationMethod::Agq => write!(f, "AGQ"),
+        }
+    }
+}
+
+/// Residual error model: Var(DV) = additive + proportional * |PRED|^(2·power),
+/// with power 1 except for `Power`, which estimates it alongside a purely
+/// multiplicative variance. `Exponential` is additive on the log scale,
+/// ln(DV) = ln(PRED) + ε, with the residual variance that of ε.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ErrorModel {
+    #[default]
+    Additive,
+    Proportional,
+    Combined,
+    Power,
+    Exponential,
+}
+
+impl std::fmt::Display for ErrorModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorModel::Additive => write!(f, "additive"),
+            ErrorModel::Proportional => write!(f, "proportional"),
+            ErrorModel::Combined => write!(f, "combined"),
+            ErrorModel::Power => write!(f, "power"),
+            ErrorModel::Exponential => write!(f, "exponential"),
+        }
+    }
+}
+
+/// How SAEM's Metropolis sampler proposes new individual parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum McmcUpdate {
+    /// All parameters move together with one step size and are accepted or
+    /// rejected jointly.
+    #[default]
+    Block,
+    /// One parameter at a time, each accepted or rejected on its own with
+    /// its own step size, tuned during burn-in towards `target_acceptance`.
+    /// Mixes better when the posterior scales of the parameters differ
+    /// widely, at one prediction per parameter per sample.
+    Componentwise,
+}
+
+impl std::fmt::Display for McmcUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            McmcUpdate::Block => write!(f, "block"),
+            McmcUpdate::Componentwise => write!(f, "componentwise"),
         }
     }
 }
@@ -22,19 +80,98 @@ pub struct EstimationConfig {
     pub method: EstimationMethod,
     pub n_iterations: usize,
     pub n_burnin: usize,
+    /// SAEM iterations in the exploratory phase, with gain 1 and annealed
+    /// variances, before the decreasing-gain phase; `n_burnin` when `None`.
+    #[serde(default)]
+    pub n_exploratory: Option<usize>,
     pub n_chains: usize,
     pub mcmc_samples_per_iteration: usize,
     pub step_size: f64,
     pub target_acceptance: f64,
+    /// Block or componentwise Metropolis updates in SAEM.
+    #[serde(default)]
+    pub mcmc_update: McmcUpdate,
     pub adaptation_interval: usize,
     pub convergence_tolerance: f64,
+    pub convergence_window: usize,
+    pub statistics_window: usize,
     pub max_retries: usize,
     pub seed: Option<u64>,
+    /// Keep every post-burn-in SAEM MCMC draw for `mcmc_samples.csv`.
+    #[serde(default)]
+    pub save_mcmc_samples: bool,
+    /// Starting fixed effects (log scale); the model defaults when `None`.
+    pub initial_fixed_effects: Option<Vec<f64>>,
+    /// Starting Omega in parameter order; the model default when `None`.
+    /// Must be symmetric and positive definite.
+    #[serde(default)]
+    pub initial_omega: Option<Vec<Vec<f64>>>,
+    /// Keep the fixed effects at their initial values during estimation.
+    pub fix_theta: bool,
+    /// Fixed effects held at their initial values by name, as `fix_theta`
+    /// holds all of them; the others are estimated as usual.
+    #[serde(default)]
+    pub fixed_parameters: Vec<String>,
+    /// Keep Omega at its initial value during estimation.
+    pub fix_omega: bool,
+    /// Starting residual variance; the model default when `None`. FOCE with a
+    /// proportional or combined error model starts the proportional
+    /// component from it as well.
+    #[serde(default)]
+    pub initial_residual_variance: Option<f64>,
+    /// Keep the residual variance components at their initial values during
+    /// estimation, e.g. when the assay variance is known.
+    #[serde(default)]
+    pub fix_residual_variance: bool,
+    /// Natural-scale bounds by parameter name, applied to population and
+    /// individual values by every estimator.
+    #[serde(default)]
+    pub parameter_bounds: HashMap<String, ParameterBounds>,
+    /// Eta priors by parameter name, normal for parameters not listed.
+    /// Honored by SAEM's sampler and M-step.
+    #[serde(default)]
+    pub eta_priors: HashMap<String, EtaPrior>,
     // FOCE-specific parameters
     pub foce_max_iterations: usize,
     pub foce_tolerance: f64,
     pub foce_step_size: f64,
     pub foce_interaction: bool,
+    pub foce_inner_max_iterations: usize,
+    pub foce_inner_tolerance: f64,
+    /// Gauss–Hermite nodes per random effect for `EstimationMethod::Agq`;
+    /// one node is the Laplace approximation.
+    #[serde(default = "default_agq_nodes")]
+    pub agq_nodes: usize,
+    pub error_model: ErrorModel,
+    /// Record each iteration's wall-clock time in the results and log an
+    /// estimated time to completion every this many iterations.
+    #[serde(default)]
+    pub timing_interval: Option<usize>,
+    /// Individuals whose etas and likelihood are logged every outer
+    /// iteration at trace level (see `estimation::trace`); all when empty.
+    #[serde(default)]
+    pub trace_individuals: Vec<i32>,
+    /// Include the ln(2π) normalization constants in the reported OFV. Off
+    /// gives the OFV NONMEM reports; see `objective::likelihood_constant`.
+    #[serde(default = "default_include_likelihood_constants")]
+    pub include_likelihood_constants: bool,
+    /// ODE solver settings for every prediction made during the fit and
+    /// from its results.
+    #[serde(default)]
+    pub solver_config: SolverConfig,
+    /// Wall-clock limit on the fit. Checked after every iteration; once it
+    /// is spent the estimator stops, leaves `converged` false and returns
+    /// its current estimates.
+    #[serde(default)]
+    pub time_budget: Option<Duration>,
+}
+
+fn default_agq_nodes() -> usize {
+    5
+}
+
+fn default_include_likelihood_constants() -> bool {
+    true
 }
 
 impl Default for EstimationConfig {
@@ -43,18 +180,41 @@ impl Default for EstimationConfig {
             method: EstimationMethod::Saem,
             n_iterations: 1000,
             n_burnin: 200,
+            n_exploratory: None,
             n_chains: 4,
             mcmc_samples_per_iteration: 10,
             step_size: 0.1,
             target_acceptance: 0.44,
+            mcmc_update: McmcUpdate::Block,
             adaptation_interval: 50,
             convergence_tolerance: 0.001,
+            convergence_window: 50,
+            statistics_window: 100,
             max_retries: 3,
             seed: Some(12345), // Default seed for reproducibility
+            save_mcmc_samples: false,
+            initial_fixed_effects: None,
+            initial_omega: None,
+            fix_theta: false,
+            fixed_parameters: Vec::new(),
+            fix_omega: false,
+            initial_residual_variance: None,
+            fix_residual_variance: false,
+            parameter_bounds: HashMap::new(),
+            eta_priors: HashMap::new(),
             foce_max_iterations: 100,
             foce_tolerance: 1e-6,
             foce_step_size: 1e-4,
             foce_interaction: false,
+            foce_inner_max_iterations: 20,
+            foce_inner_tolerance: 1e-6,
+            agq_nodes: default_agq_nodes(),
+            error_model: ErrorModel::Additive,
+            timing_interval: None,
+            trace_individuals: Vec::new(),
+            include_likelihood_constants: default_include_likelihood_constants(),
+            solver_config: SolverConfig::default(),
+            time_budget: None,
         }
     }
 }
@@ -79,6 +239,16 @@ impl EstimationConfig {
         self
     }
 
+    pub fn with_exploratory_iterations(mut self, n_exploratory: usize) -> Self {
+        self.n_exploratory = Some(n_exploratory);
+        self
+    }
+
+    pub fn with_saved_mcmc_samples(mut self, save_mcmc_samples: bool) -> Self {
+        self.save_mcmc_samples = save_mcmc_samples;
+        self
+    }
+
     pub fn with_chains(mut self, n_chains: usize) -> Self {
         self.n_chains = n_chains;
         self
@@ -89,11 +259,81 @@ impl EstimationConfig {
         self
     }
 
+    pub fn with_mcmc_update(mut self, mcmc_update: McmcUpdate) -> Self {
+        self.mcmc_update = mcmc_update;
+        self
+    }
+
+    pub fn with_solver_config(mut self, solver_config: SolverConfig) -> Self {
+        self.solver_config = solver_config;
+        self
+    }
+
+    pub fn with_convergence_window(mut self, convergence_window: usize) -> Self {
+        self.convergence_window = convergence_window;
+        self
+    }
+
+    pub fn with_statistics_window(mut self, statistics_window: usize) -> Self {
+        self.statistics_window = statistics_window;
+        self
+    }
+
     pub fn with_seed(mut self, seed: Option<u64>) -> Self {
         self.seed = seed;
         self
     }
 
+    pub fn with_initial_fixed_effects(mut self, initial_fixed_effects: Option<Vec<f64>>) -> Self {
+        self.initial_fixed_effects = initial_fixed_effects;
+        self
+    }
+
+    pub fn with_initial_omega(mut self, initial_omega: Option<Vec<Vec<f64>>>) -> Self {
+        self.initial_omega = initial_omega;
+        self
+    }
+
+    pub fn with_fixed_theta(mut self, fix_theta: bool) -> Self {
+        self.fix_theta = fix_theta;
+        self
+    }
+
+    pub fn with_fixed_parameter(mut self, name: impl Into<String>) -> Self {
+        self.fixed_parameters.push(name.into());
+        self
+    }
+
+    /// Whether the fixed effect `name` keeps its initial value.
+    pub fn theta_fixed(&self, name: &str) -> bool {
+        self.fix_theta || self.fixed_parameters.iter().any(|fixed| fixed == name)
+    }
+
+    pub fn with_fixed_omega(mut self, fix_omega: bool) -> Self {
+        self.fix_omega = fix_omega;
+        self
+    }
+
+    pub fn with_initial_residual_variance(mut self, initial_residual_variance: Option<f64>) -> Self {
+        self.initial_residual_variance = initial_residual_variance;
+        self
+    }
+
+    pub fn with_fixed_residual_variance(mut self, fix_residual_variance: bool) -> Self {
+        self.fix_residual_variance = fix_residual_variance;
+        self
+    }
+
+    pub fn with_parameter_bounds(mut self, name: impl Into<String>, lower: f64, upper: f64) -> Self {
+        self.parameter_bounds.insert(name.into(), ParameterBounds::new(lower, upper));
+        self
+    }
+
+    pub fn with_eta_prior(mut self, name: impl Into<String>, prior: EtaPrior) -> Self {
+        self.eta_priors.insert(name.into(), prior);
+        self
+    }
+
     pub fn with_foce_iterations(mut self, foce_max_iterations: usize) -> Self {
         self.foce_max_iterations = foce_max_iterations;
         self
@@ -109,6 +349,62 @@ impl EstimationConfig {
         self
     }
 
+    pub fn with_foce_inner_iterations(mut self, foce_inner_max_iterations: usize) -> Self {
+        self.foce_inner_max_iterations = foce_inner_max_iterations;
+        self
+    }
+
+    pub fn with_foce_inner_tolerance(mut self, foce_inner_tolerance: f64) -> Self {
+        self.foce_inner_tolerance = foce_inner_tolerance;
+        self
+    }
+
+    pub fn with_agq_nodes(mut self, agq_nodes: usize) -> Self {
+        self.agq_nodes = agq_nodes;
+        self
+    }
+
+    pub fn with_error_model(mut self, error_model: ErrorModel) -> Self {
+        self.error_model = error_model;
+        self
+    }
+
+    pub fn with_timing_interval(mut self, timing_interval: Option<usize>) -> Self {
+        self.timing_interval = timing_interval;
+        self
+    }
+
+    pub fn with_time_budget(mut self, time_budget: Option<Duration>) -> Self {
+        self.time_budget = time_budget;
+        self
+    }
+
+    /// Whether a fit started at `started` has spent its time budget.
+    pub fn time_budget_spent(&self, started: Instant) -> bool {
+        self.time_budget.is_some_and(|budget| started.elapsed() >= budget)
+    }
+
+    pub fn with_traced_individuals(mut self, trace_individuals: Vec<i32>) -> Self {
+        self.trace_individuals = trace_individuals;
+        self
+    }
+
+    pub fn with_likelihood_constants(mut self, include_likelihood_constants: bool) -> Self {
+        self.include_likelihood_constants = include_likelihood_constants;
+        self
+    }
+
+    /// Iterations left after burn-in, the span the convergence check and
+    /// parameter statistics draw from.
+    pub fn post_burnin_iterations(&self) -> usize {
+        self.n_iterations.saturating_sub(self.n_burnin)
+    }
+
+    /// Length of the SAEM exploratory phase.
+    pub fn exploratory_iterations(&self) -> usize {
+        self.n_exploratory.unwrap_or(self.n_burnin)
+    }
+
     pub fn validate(&self) -> Result<(), String> {
         if self.n_iterations == 0 {
             return Err("Number of iterations must be positive".to_string());
@@ -118,18 +414,71 @@ impl EstimationConfig {
             return Err("Burn-in period must be less than total iterations".to_string());
         }
         
+        if self.exploratory_iterations() >= self.n_iterations {
+            return Err("Exploratory phase must be shorter than total iterations".to_string());
+        }
+        
         if self.n_chains == 0 {
             return Err("Number of chains must be positive".to_string());
         }
         
+        if self.agq_nodes == 0 {
+            return Err("Number of quadrature nodes must be positive".to_string());
+        }
+        
         if self.step_size <= 0.0 {
             return Err("Step size must be positive".to_string());
         }
         
+        if self.solver_config.max_step_size <= 0.0 {
+            return Err("Solver maximum step size must be positive".to_string());
+        }
+        
         if !(0.0..=1.0).contains(&self.target_acceptance) {
             return Err("Target acceptance rate must be between 0 and 1".to_string());
         }
         
+        if self.convergence_window < 2 {
+            return Err("Convergence window must be at least 2 iterations".to_string());
+        }
+        
+        if self.statistics_window < 2 {
+            return Err("Statistics window must be at least 2 iterations".to_string());
+        }
+        
+        // Only SAEM averages its statistics over the post-burn-in iterations
+        if matches!(self.method, EstimationMethod::Saem) && self.post_burnin_iterations() < self.statistics_window {
+            return Err(format!(
+                "Only {} post-burn-in iterations for a statistics window of {}",
+                self.post_burnin_iterations(), self.statistics_window
+            ));
+        }
+        
+        for (name, bounds) in &self.parameter_bounds {
+            // The parameters are estimated on the log scale
+            if !(bounds.lower > 0.0 && bounds.lower < bounds.upper) {
+                return Err(format!(
+                    "Bounds for {} must satisfy 0 < lower < upper, got ({}, {})",
+                    name, bounds.lower, bounds.upper
+                ));
+            }
+        }
+        
+        for (name, prior) in &self.eta_priors {
+            if let EtaPrior::Truncated { lower, upper } = *prior {
+                if !(lower <= 0.0 && upper >= 0.0 && lower < upper) {
+                    return Err(format!(
+                        "Eta prior for {} must be truncated to an interval containing 0, got [{}, {}]",
+                        name, lower, upper
+                    ));
+                }
+            }
+        }
+        
+        if let Some(omega) = &self.initial_omega {
+            super::omega::validate_omega(omega, omega.len()).map_err(|message| format!("Invalid initial Omega: {}", message))?;
+        }
+        
         if self.foce_max_iterations == 0 {
             return Err("FOCE max iterations must be positive".to_string());
         }
@@ -142,6 +491,14 @@ impl EstimationConfig {
             return Err("FOCE step size must be positive".to_string());
         }
         
+        if self.foce_inner_max_iterations == 0 {
+            return Err("FOCE inner max iterations must be positive".to_string());
+        }
+        
+        if self.foce_inner_tolerance <= 0.0 {
+            return Err("FOCE inner tolerance must be positive".to_string());
+        }
+        
         Ok(())
     }
 }
@@ -168,4 +525,45 @@ mod tests {
         config.n_burnin = 150;
         assert!(config.validate().is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_non_symmetric_initial_omega_rejected() {
+        let config = EstimationConfig::default()
+            .with_initial_omega(Some(vec![vec![0.09, 0.01], vec![0.02, 0.04]]));
+        let message = config.validate().unwrap_err();
+        assert!(message.starts_with("Invalid initial Omega: Omega is not symmetric"), "{}", message);
+
+        let config = config.with_initial_omega(Some(vec![vec![0.09, 0.01], vec![0.01, 0.04]]));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_inverted_or_non_positive_bounds_rejected() {
+        let config = EstimationConfig::default().with_parameter_bounds("CL", 5.0, 1.0);
+        assert!(config.validate().is_err());
+
+        let config = EstimationConfig::default().with_parameter_bounds("CL", 0.0, 5.0);
+        assert!(config.validate().is_err());
+
+        let config = EstimationConfig::default().with_parameter_bounds("CL", 0.5, 5.0);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_short_run_flagged() {
+        let config = EstimationConfig::default()
+            .with_iterations(10)
+            .with_burnin(2);
+        assert!(config.validate().is_err());
+
+        let config = config.with_statistics_window(8);
+        assert!(config.validate().is_ok());
+
+        // FOCE has no statistics window to fill
+        let config = EstimationConfig::default()
+            .with_method(EstimationMethod::Foce)
+            .with_iterations(10)
+            .with_burnin(2);
+        assert!(config.validate().is_ok());
+    }
+}
@@ -1,12 +1,23 @@
-use crate::data::{Dataset, Individual};
-use crate::models::{CompartmentModel, ModelParameters, ModelState};
-use crate::solver::{OdeSolver, OdeSystem, RungeKuttaSolver, SolverConfig};
-use super::EstimationConfig;
-use anyhow::{Context, Result};
-use log::{info, debug, warn};
+use crate::data::{Dataset, Individual, ObservationType};
+use crate::diagnostics;
+use crate::models::{CompartmentModel, ModelParameters};
+use crate::prediction::{self, StepSizeCheck};
+use crate::validation;
+use crate::solver::{CountingSolver, EvaluationCounter, EvaluationCounts, OdeSolver, RungeKuttaSolver, SolverConfig};
+use super::bounds;
+use super::timing::IterationTimer;
+use super::trace::{self, IndividualTrace};
+use super::objective;
+use super::omega;
+use super::profile::{self, LikelihoodProfile};
+use super::{ErrorModel, EstimationConfig, EstimationMethod};
+use anyhow::{anyhow, Context, Result};
+use log::{info, debug, trace, warn};
 use nalgebra::{DVector, DMatrix};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FoceResults {
@@ -19,10 +30,149 @@ pub struct FoceResults {
     pub n_iterations: usize,
     pub individual_parameters: HashMap<i32, Vec<f64>>,
     pub parameter_names: Vec<String>,
+    /// Unit of each parameter, in `parameter_names` order; `None` when
+    /// dimensionless.
+    #[serde(default)]
+    pub parameter_units: Vec<Option<String>>,
+    #[serde(deserialize_with = "infinite_if_null")]
     pub gradient_norm: f64,
+    #[serde(deserialize_with = "infinite_if_null")]
     pub hessian_condition_number: f64,
+    /// Effective rank of the Fisher information matrix; below the number of
+    /// parameters when some combinations are not identifiable.
+    #[serde(default)]
+    pub fisher_rank: usize,
     pub covariance_matrix: Vec<Vec<f64>>,
     pub standard_errors: Vec<f64>,
+    pub evaluation_counts: EvaluationCounts,
+    /// Mean Newton steps per individual-eta optimization.
+    pub mean_inner_iterations: f64,
+    pub error_model: ErrorModel,
+    /// Proportional residual variance; `residual_variance` holds the additive part.
+    pub proportional_variance: f64,
+    /// Exponent on |PRED| of the proportional component; estimated by the
+    /// power error model, 1 otherwise.
+    #[serde(default = "crate::models::compartment::default_error_power")]
+    pub error_power: f64,
+    /// Additive residual variance of effect (PD) observations, estimated
+    /// separately when the dataset has any.
+    #[serde(default)]
+    pub effect_residual_variance: Option<f64>,
+    /// Residual variance of each output compartment the model observes
+    /// separately, by CMT: its proportional component under a proportional,
+    /// combined or power error model, its additive one otherwise.
+    #[serde(default)]
+    pub output_residual_variances: BTreeMap<usize, f64>,
+    /// The residual components the error model estimates, with their SEs.
+    pub residual_components: Vec<ResidualComponent>,
+    /// Individuals with fewer observations than parameters. Only the etas
+    /// their data inform best were estimated; the rest were held fixed.
+    #[serde(default)]
+    pub underdetermined_individuals: Vec<i32>,
+    /// Parameters whose final estimate lies on a bound.
+    #[serde(default)]
+    pub parameters_at_bounds: Vec<String>,
+    /// Wall-clock time of each outer iteration, recorded only when
+    /// `EstimationConfig::timing_interval` is set.
+    #[serde(default)]
+    pub iteration_times: Vec<Duration>,
+    /// Whether the fit stopped because `EstimationConfig::time_budget` ran
+    /// out.
+    #[serde(default)]
+    pub time_budget_exceeded: bool,
+    /// Objective after each outer iteration's M-step: -2 × joint log
+    /// density of the data and the etas at their modes.
+    #[serde(default)]
+    pub objective_trajectory: Vec<f64>,
+    /// Each individual's joint objective at its final etas, split into data
+    /// and prior terms.
+    #[serde(default)]
+    pub individual_objectives: Vec<IndividualObjective>,
+    /// Solver settings of the fit, reused for predictions from the results.
+    #[serde(default)]
+    pub solver_config: SolverConfig,
+}
+
+/// An estimated residual error component and its standard error: a
+/// variance, or the exponent of the power error model's "Power" row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResidualComponent {
+    pub name: String,
+    /// `None` on the "Power" row.
+    #[serde(default)]
+    pub variance: Option<f64>,
+    /// Exponent on |PRED|, on the "Power" row only.
+    #[serde(default)]
+    pub power: Option<f64>,
+    #[serde(deserialize_with = "nan_if_null")]
+    pub standard_error: f64,
+}
+
+impl ResidualComponent {
+    /// The component's estimate, variance or exponent.
+    pub fn estimate(&self) -> f64 {
+        self.variance.or(self.power).unwrap_or(f64::NAN)
+    }
+}
+
+/// Reads a value that is infinite until computed, which serde_json saves
+/// as `null`.
+fn infinite_if_null<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+}
+
+/// Reads a value that is NaN when not computed, which serde_json saves as
+/// `null`.
+fn nan_if_null<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+}
+
+/// One individual's -2 log p(y, eta) at its etas, split into the data
+/// likelihood and the eta prior. A prior term large relative to the data
+/// term means the individual's etas are heavily shrunk toward zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndividualObjective {
+    pub id: i32,
+    pub data: f64,
+    pub prior: f64,
+}
+
+impl IndividualObjective {
+    pub fn total(&self) -> f64 {
+        self.data + self.prior
+    }
+}
+
+/// An individual prediction, the residual of its observation and the
+/// individual's weight.
+type PredictionResidual = (f64, f64, f64);
+
+/// The residual of an effect observation and its individual's weight.
+type WeightedResidual = (f64, f64);
+
+/// The weighted residuals of each separately observed output, by CMT,
+/// rescaled by `objective::output_variance_residual`.
+type OutputResiduals = BTreeMap<usize, Vec<WeightedResidual>>;
+
+/// The variance estimate Σ w·r² / Σ w.
+fn weighted_mean_square(residuals: &[WeightedResidual]) -> f64 {
+    let total_weight: f64 = residuals.iter().map(|(_, w)| w).sum();
+    residuals.iter().map(|(r, w)| w * r * r).sum::<f64>() / total_weight
+}
+
+/// Derivative of an observation's residual variance in one component, as
+/// a function of the prediction.
+type VarianceDerivative = Box<dyn Fn(f64) -> f64>;
+
+/// Deviations of log-scale individual parameters from the population values.
+fn etas_from(individual: &[f64], population_params: &ModelParameters) -> Vec<f64> {
+    individual.iter().zip(&population_params.fixed_effects).map(|(p, theta)| p - theta).collect()
+}
+
+fn etas_by_id(individual_params: &HashMap<i32, Vec<f64>>, population_params: &ModelParameters) -> HashMap<i32, Vec<f64>> {
+    individual_params.iter()
+        .map(|(&id, params)| (id, etas_from(params, population_params)))
+        .collect()
 }
 
 impl FoceResults {
@@ -37,18 +187,42 @@ impl FoceResults {
             n_iterations: 0,
             individual_parameters: HashMap::new(),
             parameter_names,
+            parameter_units: Vec::new(),
             gradient_norm: f64::INFINITY,
             hessian_condition_number: f64::INFINITY,
+            fisher_rank: 0,
             covariance_matrix: vec![vec![0.0; n_params]; n_params],
             standard_errors: vec![0.0; n_params],
+            evaluation_counts: EvaluationCounts::default(),
+            mean_inner_iterations: 0.0,
+            error_model: ErrorModel::Additive,
+            proportional_variance: 0.0,
+            error_power: 1.0,
+            effect_residual_variance: None,
+            output_residual_variances: BTreeMap::new(),
+            residual_components: Vec::new(),
+            underdetermined_individuals: Vec::new(),
+            parameters_at_bounds: Vec::new(),
+            iteration_times: Vec::new(),
+            time_budget_exceeded: false,
+            objective_trajectory: Vec::new(),
+            individual_objectives: Vec::new(),
+            solver_config: SolverConfig::default(),
         }
     }
+
+    /// %RSE of each fixed effect on the natural scale. The estimates are on
+    /// the log scale, where SE(exp θ) / exp θ ≈ SE(θ).
+    pub fn rse_percent(&self) -> Vec<f64> {
+        self.standard_errors.iter().map(|se| 100.0 * se).collect()
+    }
 }
 
 pub struct FoceEstimator {
     model: CompartmentModel,
     config: EstimationConfig,
     solver: Box<dyn OdeSolver + Send + Sync>,
+    evaluations: EvaluationCounter,
 }
 
 impl FoceEstimator {
@@ -59,6 +233,7 @@ impl FoceEstimator {
             model,
             config,
             solver,
+            evaluations: EvaluationCounter::new(),
         }
     }
 
@@ -66,29 +241,101 @@ impl FoceEstimator {
         &self.model
     }
 
+    /// Log-scale bounds for each model parameter.
+    fn log_bounds(&self) -> Vec<(f64, f64)> {
+        bounds::log_bounds(&self.config.parameter_bounds, &self.model.parameter_names())
+    }
+
     pub fn fit(&mut self, dataset: &Dataset) -> Result<FoceResults> {
         info!("Starting FOCE estimation for {} individuals", dataset.n_individuals());
+        let started = Instant::now();
+        self.evaluations.reset();
+        let negative_corrections_before = self.solver.negative_state_corrections();
+        validation::validate_dose_compartments(dataset, &self.model)?;
+        validation::validate_dosed_individuals(dataset, &self.model)?;
         
         let n_params = self.model.parameter_names().len();
         let parameter_names = self.model.parameter_names();
         let mut results = FoceResults::new(n_params, parameter_names);
+        results.parameter_units = self.model.parameter_units();
         
         // Initialize parameters
+        let log_bounds = self.log_bounds();
         let mut current_params = self.model.default_parameters();
+        if let Some(initial) = &self.config.initial_fixed_effects {
+            current_params.fixed_effects = initial.clone();
+        }
+        if let Some(initial) = &self.config.initial_omega {
+            omega::validate_omega(initial, current_params.n_parameters())
+                .map_err(|message| anyhow!("Invalid initial Omega: {}", message))?;
+            current_params.random_effects_variance = initial.clone();
+        }
+        if let Some(initial) = self.config.initial_residual_variance {
+            current_params.residual_variance = initial;
+        }
+        for (theta, &limits) in current_params.fixed_effects.iter_mut().zip(&log_bounds) {
+            *theta = theta.clamp(limits.0, limits.1);
+        }
+        if self.config.eta_priors.values().chain(&current_params.eta_priors).any(|prior| prior.is_truncated()) {
+            warn!("FOCE treats every eta as normal; truncated eta priors are honored by SAEM only");
+        }
+        // Effect observations get their own additive variance, starting
+        // where the concentrations' does
+        if dataset.has_effect_observations() && current_params.effect_residual_variance.is_none() {
+            current_params.effect_residual_variance = Some(current_params.residual_variance);
+        }
+        objective::initialize_output_variances(&self.model, dataset, &mut current_params);
+        if !self.config.fix_omega && dataset.n_individuals() < omega::MIN_INDIVIDUALS_FOR_OMEGA {
+            warn!("FOCE fit to {} individual(s): Omega is not estimable and is held at its initial value",
+                  dataset.n_individuals());
+        }
+        match self.config.error_model {
+            ErrorModel::Additive => {}
+            ErrorModel::Proportional | ErrorModel::Power => {
+                current_params.proportional_variance = current_params.residual_variance;
+                current_params.residual_variance = 0.0;
+            }
+            ErrorModel::Combined => current_params.proportional_variance = current_params.residual_variance,
+            ErrorModel::Exponential => current_params.log_scale_residuals = true,
+        }
+        if matches!(self.config.method, EstimationMethod::Agq) {
+            objective::check_agq_grid(self.config.agq_nodes, current_params.n_parameters())?;
+        }
+        let mut step_size_check = StepSizeCheck::new();
+        step_size_check.check(&self.model, &current_params, &self.config.solver_config);
         let mut individual_params: HashMap<i32, Vec<f64>> = HashMap::new();
         
+        let mut underdetermined: Vec<i32> = Vec::new();
+        for (&id, individual) in dataset.individuals() {
+            let n_obs = individual.observations().len();
+            if n_obs < n_params {
+                warn!("Individual {} has {} observation(s) for {} parameters; estimating only the {} best-informed eta(s)",
+                      id, n_obs, n_params, n_obs);
+                underdetermined.push(id);
+            }
+        }
+        underdetermined.sort_unstable();
+        
         // Initialize individual parameters to population means
         for (&id, _) in dataset.individuals() {
             individual_params.insert(id, current_params.fixed_effects.clone());
         }
 
         let mut previous_objective = f64::INFINITY;
+        let mut inner_iterations = 0;
+        let mut inner_optimizations = 0;
+        let mut n_iterations = 0;
+        let mut timer = self.config.timing_interval
+            .map(|interval| IterationTimer::new(interval, self.config.foce_max_iterations));
+        let tracer = IndividualTrace::new(&self.config.trace_individuals);
         
         for iteration in 0..self.config.foce_max_iterations {
             debug!("FOCE iteration {}/{}", iteration + 1, self.config.foce_max_iterations);
             
             // E-step: Estimate individual parameters using first-order approximation
-            self.estimate_individual_parameters(dataset, &current_params, &mut individual_params)?;
+            let individual_inner_iterations = self.estimate_individual_parameters(dataset, &current_params, &mut individual_params)?;
+            inner_iterations += individual_inner_iterations.values().sum::<usize>();
+            inner_optimizations += dataset.n_individuals();
             
             // M-step: Update population parameters
             let objective = self.update_population_parameters(
@@ -96,6 +343,24 @@ impl FoceEstimator {
                 &individual_params,
                 &mut current_params,
             )?;
+            results.objective_trajectory.push(objective);
+            
+            if tracer.active() {
+                let etas = etas_by_id(&individual_params, &current_params);
+                for individual_objective in self.individual_objectives(dataset, &etas, &current_params)? {
+                    let id = individual_objective.id;
+                    if tracer.traces(id) {
+                        trace!(target: trace::TARGET, "FOCE iteration={} id={} eta={} inner_iterations={} ofv_data={} ofv_prior={}",
+                               iteration + 1, id, trace::format_values(&etas[&id]), individual_inner_iterations[&id],
+                               individual_objective.data, individual_objective.prior);
+                    }
+                }
+            }
+            
+            n_iterations += 1;
+            if let Some(timer) = timer.as_mut() {
+                timer.lap("FOCE");
+            }
             
             // Check convergence
             let objective_change = (previous_objective - objective).abs();
@@ -114,26 +379,69 @@ impl FoceEstimator {
             }
             
             previous_objective = objective;
+            
+            if self.config.time_budget_spent(started) {
+                warn!("FOCE stopped after {} iteration(s): time budget spent before convergence", iteration + 1);
+                results.time_budget_exceeded = true;
+                break;
+            }
         }
 
-        // Calculate final statistics
-        let final_objective = self.calculate_objective_function(dataset, &individual_params, &current_params)?;
+        // Final OFV on the convention shared with every method
+        let etas = etas_by_id(&individual_params, &current_params);
+        if matches!(self.config.method, EstimationMethod::Agq) {
+            info!("Integrating the marginal likelihood with {} Gauss-Hermite node(s) per eta", self.config.agq_nodes);
+        }
+        let final_objective = self.reported_objective(dataset, &current_params, &etas)?;
         
         // Estimate covariance matrix and standard errors
-        let (covariance_matrix, standard_errors) = self.estimate_covariance_matrix(
+        let (covariance_matrix, standard_errors, fisher_rank) = self.estimate_covariance_matrix(
             dataset, &individual_params, &current_params
         )?;
 
+        let residual_components = self.residual_components(dataset, &individual_params, &current_params)?;
+        let individual_objectives = self.individual_objectives(dataset, &etas, &current_params)?;
+
         // Populate results
+        results.error_model = self.config.error_model;
+        results.proportional_variance = current_params.proportional_variance;
+        results.error_power = current_params.error_power;
+        results.effect_residual_variance = current_params.effect_residual_variance;
+        results.output_residual_variances = current_params.output_residual_variances.clone();
+        results.residual_components = residual_components;
+        results.underdetermined_individuals = underdetermined;
+        results.parameters_at_bounds = bounds::parameters_at_bounds(&current_params.fixed_effects, &log_bounds, &results.parameter_names);
+        for name in &results.parameters_at_bounds {
+            warn!("Parameter {} finished at a bound", name);
+        }
+        step_size_check.check(&self.model, &current_params, &self.config.solver_config);
         results.fixed_effects = current_params.fixed_effects;
         results.random_effects_variance = current_params.random_effects_variance;
         results.residual_variance = current_params.residual_variance;
         results.objective_function_value = final_objective;
         results.final_log_likelihood = -final_objective / 2.0;
-        results.n_iterations = self.config.foce_max_iterations;
+        results.n_iterations = n_iterations;
+        results.iteration_times = timer.map(IterationTimer::into_times).unwrap_or_default();
+        results.solver_config = self.config.solver_config.clone();
         results.individual_parameters = individual_params;
+        results.individual_objectives = individual_objectives;
         results.covariance_matrix = covariance_matrix;
         results.standard_errors = standard_errors;
+        results.fisher_rank = fisher_rank;
+        results.evaluation_counts = self.evaluations.counts();
+        results.evaluation_counts.negative_state_corrections =
+            self.solver.negative_state_corrections() - negative_corrections_before;
+        if results.evaluation_counts.negative_state_corrections > 0 {
+            warn!("ODE integration produced negative amounts in {} steps; {}",
+                  results.evaluation_counts.negative_state_corrections,
+                  self.config.solver_config.negative_states.outcome());
+        }
+        if inner_optimizations > 0 {
+            results.mean_inner_iterations = inner_iterations as f64 / inner_optimizations as f64;
+        }
+        for (name, rse) in diagnostics::poorly_identified_parameters(&results.parameter_names, &results.rse_percent()) {
+            warn!("Parameter {} is poorly identified (%RSE {:.1})", name, rse);
+        }
 
         info!("FOCE estimation completed. Objective function: {:.3}, Converged: {}", 
               results.objective_function_value, results.converged);
@@ -141,51 +449,154 @@ impl FoceEstimator {
         Ok(results)
     }
 
+    /// -2·log-likelihood at the population parameters `params` on the
+    /// convention of `fit`'s OFV, without fitting. Each individual's etas are
+    /// optimized to their conditional modes under `params`, and the marginal
+    /// likelihood is integrated around them by Laplace (AGQ for that
+    /// method); the residual error model is the one `params` describes. For
+    /// likelihood profiles and sensitivity analyses.
+    pub fn evaluate_objective(&self, dataset: &Dataset, params: &ModelParameters) -> Result<f64> {
+        let n_params = self.model.parameter_names().len();
+        if params.n_parameters() != n_params {
+            return Err(anyhow!("Expected {} parameters for the model, got {}", n_params, params.n_parameters()));
+        }
+        let mut individual_params: HashMap<i32, Vec<f64>> = dataset.individuals().keys()
+            .map(|&id| (id, params.fixed_effects.clone()))
+            .collect();
+        self.estimate_individual_parameters(dataset, params, &mut individual_params)?;
+        self.reported_objective(dataset, params, &etas_by_id(&individual_params, params))
+    }
+
+    /// Profile likelihood of fixed effect `param_index` over the
+    /// natural-scale `values`: at each value the parameter is held fixed and
+    /// the rest refitted from `estimate`, typically the fit's own
+    /// `FittedModel::parameters`. See `profile::LikelihoodProfile` for the
+    /// confidence interval read from it.
+    pub fn profile_likelihood(
+        &mut self,
+        dataset: &Dataset,
+        estimate: &ModelParameters,
+        param_index: usize,
+        values: &[f64],
+    ) -> Result<LikelihoodProfile> {
+        let parameter_names = self.model.parameter_names();
+        let original = self.config.clone();
+        let profile = profile::profile_likelihood(&parameter_names, &original, estimate, param_index, values, |config| {
+            self.config = config;
+            self.fit(dataset).map(|results| (results.objective_function_value, results.converged))
+        });
+        self.config = original;
+        profile
+    }
+
+    /// The marginal OFV at `etas`, by AGQ for that method and Laplace
+    /// otherwise, less the likelihood constants when the configuration
+    /// leaves them out.
+    fn reported_objective(&self, dataset: &Dataset, params: &ModelParameters, etas: &HashMap<i32, Vec<f64>>) -> Result<f64> {
+        let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
+        let objective = if matches!(self.config.method, EstimationMethod::Agq) {
+            objective::agq_objective_function_value(
+                &self.model, &solver, &self.config.solver_config, dataset, params, etas, self.config.agq_nodes,
+            )?
+        } else {
+            objective::objective_function_value(&self.model, &solver, &self.config.solver_config, dataset, params, etas)?
+        };
+        Ok(if self.config.include_likelihood_constants {
+            objective
+        } else {
+            objective - objective::likelihood_constant(dataset)
+        })
+    }
+
     fn estimate_individual_parameters(
         &self,
         dataset: &Dataset,
         population_params: &ModelParameters,
         individual_params: &mut HashMap<i32, Vec<f64>>,
-    ) -> Result<()> {
+    ) -> Result<HashMap<i32, usize>> {
+        let mut inner_iterations = HashMap::new();
+        
         for (&id, individual) in dataset.individuals() {
-            let current_eta = individual_params.get(&id).unwrap().clone();
+            // Individual parameters are stored on the log scale; the inner
+            // optimization works on their deviations from the population
+            let current_eta = etas_from(individual_params.get(&id).unwrap(), population_params);
             
             // Newton-Raphson optimization for individual parameters
-            let optimized_eta = self.optimize_individual_eta(
+            let (optimized_eta, iterations) = self.optimize_individual_eta(
                 individual,
                 population_params,
                 &current_eta,
             )?;
             
-            individual_params.insert(id, optimized_eta);
+            inner_iterations.insert(id, iterations);
+            let optimized = population_params.fixed_effects.iter().zip(&optimized_eta)
+                .map(|(theta, eta)| theta + eta)
+                .collect();
+            individual_params.insert(id, optimized);
         }
         
-        Ok(())
+        Ok(inner_iterations)
     }
 
+    /// Returns the optimized eta and the number of Newton steps taken.
     fn optimize_individual_eta(
         &self,
         individual: &Individual,
         population_params: &ModelParameters,
         initial_eta: &[f64],
-    ) -> Result<Vec<f64>> {
+    ) -> Result<(Vec<f64>, usize)> {
         let mut eta = initial_eta.to_vec();
-        let max_inner_iterations = 20;
+        let mut iterations = 0;
+        let log_bounds = self.log_bounds();
+        // With fewer observations than etas the data cannot identify them all;
+        // only the best-informed ones move and the rest keep their values
+        let n_free = individual.observations().len().min(eta.len());
+        let mut frozen: Option<Vec<bool>> = None;
         
-        for _iter in 0..max_inner_iterations {
+        while iterations < self.config.foce_inner_max_iterations {
             // Calculate gradient and Hessian of individual objective function
-            let (gradient, hessian) = self.calculate_individual_derivatives(
+            let (mut gradient, mut hessian) = self.calculate_individual_derivatives(
                 individual,
                 population_params,
                 &eta,
             )?;
             
-            // Newton-Raphson step: eta_new = eta - H^(-1) * g
-            let hessian_matrix = DMatrix::from_vec(eta.len(), eta.len(), hessian);
+            if n_free < eta.len() {
+                let n = eta.len();
+                let frozen = frozen.get_or_insert_with(|| {
+                    // Data curvature is the Hessian diagonal less the prior term
+                    let curvature = |i: usize| -hessian[i * n + i] - 1.0 / population_params.random_effects_variance[i][i];
+                    let mut order: Vec<usize> = (0..n).collect();
+                    order.sort_by(|&a, &b| curvature(b).total_cmp(&curvature(a)));
+                    let mut frozen = vec![true; n];
+                    for &i in &order[..n_free] {
+                        frozen[i] = false;
+                    }
+                    frozen
+                });
+                for i in (0..n).filter(|&i| frozen[i]) {
+                    gradient[i] = 0.0;
+                    for j in 0..n {
+                        hessian[i * n + j] = 0.0;
+                        hessian[j * n + i] = 0.0;
+                    }
+                    hessian[i * n + i] = -1.0;
+                }
+            }
+            
             let gradient_vector = DVector::from_vec(gradient);
             
-            // Check if Hessian is positive definite (add regularization if needed)
-            let regularized_hessian = self.regularize_hessian(&hessian_matrix);
+            // Check convergence
+            if gradient_vector.norm() < self.config.foce_inner_tolerance {
+                break;
+            }
+            
+            // Newton-Raphson step: eta_new = eta - H^(-1) * g. H is the Hessian
+            // of the log-likelihood and negative definite, so factor -H.
+            let hessian_matrix = DMatrix::from_vec(eta.len(), eta.len(), hessian);
+            
+            // Check if -H is positive definite (add regularization if needed)
+            let regularized_hessian = regularize_hessian(&(-hessian_matrix));
             
             if let Some(chol) = regularized_hessian.cholesky() {
                 let step = chol.solve(&gradient_vector);
@@ -193,23 +604,23 @@ impl FoceEstimator {
                 // Update eta with step size control
                 let step_size = 1.0; // Could be adaptive
                 for i in 0..eta.len() {
-                    eta[i] -= step_size * step[i];
+                    eta[i] += step_size * step[i];
                     
                     // Apply bounds: keep individual deviations reasonable
                     eta[i] = eta[i].max(-5.0).min(5.0);
+                    
+                    // Reflect the individual value into the parameter's bounds
+                    let theta = population_params.fixed_effects[i];
+                    eta[i] = bounds::reflect(theta + eta[i], log_bounds[i]) - theta;
                 }
-                
-                // Check convergence
-                if gradient_vector.norm() < 1e-6 {
-                    break;
-                }
+                iterations += 1;
             } else {
                 warn!("Hessian not positive definite for individual optimization");
                 break;
             }
         }
         
-        Ok(eta)
+        Ok((eta, iterations))
     }
 
     fn calculate_individual_derivatives(
@@ -257,9 +668,8 @@ impl FoceEstimator {
                 .zip(predictions.iter().zip(predictions_plus.iter()))
                 .enumerate()
             {
-                let residual = obs.value - pred;
                 let dpred_deta = (pred_plus - pred) / h;
-                grad_data += residual * dpred_deta / population_params.residual_variance;
+                grad_data += objective::observation_score(population_params, obs, *pred) * dpred_deta;
             }
             
             // Gradient contribution from prior (eta ~ N(0, Omega))
@@ -269,9 +679,9 @@ impl FoceEstimator {
             
             // Diagonal Hessian approximation
             let mut hess_data = 0.0;
-            for (pred, pred_plus) in predictions.iter().zip(predictions_plus.iter()) {
+            for (obs, (pred, pred_plus)) in individual.observations().iter().zip(predictions.iter().zip(predictions_plus.iter())) {
                 let dpred_deta = (pred_plus - pred) / h;
-                hess_data -= (dpred_deta * dpred_deta) / population_params.residual_variance;
+                hess_data -= dpred_deta * dpred_deta * objective::observation_weight(population_params, obs, *pred);
             }
             
             let hess_prior = -1.0 / population_params.random_effects_variance[i][i];
@@ -281,98 +691,89 @@ impl FoceEstimator {
         Ok((gradient, hessian))
     }
 
-    fn regularize_hessian(&self, hessian: &DMatrix<f64>) -> DMatrix<f64> {
-        let mut regularized = hessian.clone();
-        let regularization = 1e-6;
-        
-        // Add regularization to diagonal
-        for i in 0..regularized.nrows() {
-            regularized[(i, i)] += regularization;
-        }
-        
-        regularized
-    }
-
     fn update_population_parameters(
         &self,
         dataset: &Dataset,
         individual_params: &HashMap<i32, Vec<f64>>,
         current_params: &mut ModelParameters,
     ) -> Result<f64> {
-        let n_individuals = individual_params.len() as f64;
-        let n_params = current_params.n_parameters();
-        
         // Update fixed effects (population means)
-        let mut new_fixed_effects = vec![0.0; n_params];
-        for params in individual_params.values() {
-            for i in 0..n_params {
-                new_fixed_effects[i] += params[i];
+        if !self.config.fix_theta {
+            let log_bounds = self.log_bounds();
+            let means = observation_weighted_mean(dataset, individual_params);
+            for ((theta, name), (mean, &limits)) in current_params.fixed_effects.iter_mut()
+                .zip(&self.model.parameter_names())
+                .zip(means.into_iter().zip(&log_bounds))
+            {
+                if !self.config.theta_fixed(name) {
+                    *theta = bounds::clamp(mean, limits);
+                }
             }
         }
-        for i in 0..n_params {
-            new_fixed_effects[i] /= n_individuals;
-            // Apply bounds to population parameters
-            new_fixed_effects[i] = new_fixed_effects[i].max(-10.0);
-        }
-        current_params.fixed_effects = new_fixed_effects;
         
         // Update random effects variance (Omega matrix)
-        let mut new_omega = vec![vec![0.0; n_params]; n_params];
-        for params in individual_params.values() {
-            for i in 0..n_params {
-                for j in 0..n_params {
-                    let eta_i = params[i] - current_params.fixed_effects[i];
-                    let eta_j = params[j] - current_params.fixed_effects[j];
-                    new_omega[i][j] += eta_i * eta_j;
-                }
-            }
-        }
-        for i in 0..n_params {
-            for j in 0..n_params {
-                new_omega[i][j] /= n_individuals;
+        if !self.config.fix_omega && dataset.n_individuals() >= omega::MIN_INDIVIDUALS_FOR_OMEGA {
+            let new_omega = observation_weighted_covariance(dataset, individual_params, &current_params.fixed_effects);
+            // Keep Omega positive definite, including when the etas barely vary
+            let (projected, indefinite) = omega::nearest_positive_definite(&new_omega, omega::MIN_OMEGA_EIGENVALUE);
+            if indefinite {
+                warn!("FOCE Omega update was not positive definite; projected to the nearest positive-definite matrix");
             }
+            current_params.random_effects_variance = projected;
         }
-        current_params.random_effects_variance = new_omega;
         
-        // Update residual variance
-        let mut residual_sum = 0.0;
-        let mut total_observations = 0;
-        
-        for (&id, individual) in dataset.individuals() {
-            if let Some(ind_params) = individual_params.get(&id) {
-                let mut temp_params = current_params.clone();
-                for i in 0..n_params {
-                    temp_params.fixed_effects[i] = current_params.fixed_effects[i] + ind_params[i];
-                }
-                
-                let predictions = self.predict_individual(individual, &temp_params)?;
-                
-                for (obs, pred) in individual.observations().iter().zip(predictions.iter()) {
-                    let residual = (obs.value - pred).powi(2);
-                    residual_sum += residual;
-                    total_observations += 1;
+        // Update residual variance components
+        if !self.config.fix_residual_variance {
+            let (pairs, effect_residuals, output_residuals) = self.prediction_residual_pairs(dataset, individual_params, current_params)?;
+            if !pairs.is_empty() {
+                if self.config.error_model == ErrorModel::Power {
+                    let (proportional, power) = estimate_power_error(&pairs);
+                    current_params.proportional_variance = proportional;
+                    current_params.error_power = power;
+                } else {
+                    let (additive, proportional) = estimate_residual_variances(self.config.error_model, &pairs);
+                    current_params.residual_variance = additive;
+                    current_params.proportional_variance = proportional;
                 }
             }
-        }
-        
-        if total_observations > 0 {
-            current_params.residual_variance = residual_sum / total_observations as f64;
+            if !effect_residuals.is_empty() {
+                current_params.effect_residual_variance = Some(weighted_mean_square(&effect_residuals));
+            }
+            for (compartment, residuals) in &output_residuals {
+                current_params.output_residual_variances.insert(*compartment, weighted_mean_square(residuals));
+            }
         }
         
         // Calculate objective function
-        self.calculate_objective_function(dataset, individual_params, current_params)
+        self.calculate_objective_function(dataset, &etas_by_id(individual_params, current_params), current_params)
     }
 
+    /// -2 × joint log density of the data and the etas at the given etas,
+    /// each individual counted as often as its weight.
     fn calculate_objective_function(
         &self,
         dataset: &Dataset,
-        individual_params: &HashMap<i32, Vec<f64>>,
+        etas: &HashMap<i32, Vec<f64>>,
         population_params: &ModelParameters,
     ) -> Result<f64> {
-        let mut objective = 0.0;
+        Ok(self.individual_objectives(dataset, etas, population_params)?
+            .iter()
+            .map(|objective| individual_weight(dataset, objective.id) * objective.total())
+            .sum())
+    }
+
+    /// Data and prior terms of the joint objective for every individual with
+    /// etas, in ID order.
+    fn individual_objectives(
+        &self,
+        dataset: &Dataset,
+        etas: &HashMap<i32, Vec<f64>>,
+        population_params: &ModelParameters,
+    ) -> Result<Vec<IndividualObjective>> {
+        let mut objectives = Vec::new();
         
         for (&id, individual) in dataset.individuals() {
-            if let Some(eta) = individual_params.get(&id) {
+            if let Some(eta) = etas.get(&id) {
                 // Individual parameters: theta_i = theta + eta_i
                 let mut ind_params = population_params.clone();
                 for i in 0..eta.len() {
@@ -381,81 +782,156 @@ impl FoceEstimator {
                 
                 // Data likelihood contribution
                 let predictions = self.predict_individual(individual, &ind_params)?;
-                for (obs, pred) in individual.observations().iter().zip(predictions.iter()) {
-                    let residual = obs.value - pred;
-                    objective += (residual * residual) / population_params.residual_variance;
-                    objective += (2.0 * std::f64::consts::PI * population_params.residual_variance).ln();
-                }
+                let data = individual.observations().iter().zip(predictions.iter())
+                    .map(|(obs, pred)| objective::observation_objective(population_params, obs, *pred))
+                    .sum();
                 
                 // Prior likelihood contribution (eta ~ N(0, Omega))
-                for i in 0..eta.len() {
-                    objective += (eta[i] * eta[i]) / population_params.random_effects_variance[i][i];
-                    objective += (2.0 * std::f64::consts::PI * population_params.random_effects_variance[i][i]).ln();
+                let prior = (0..eta.len())
+                    .map(|i| {
+                        let omega = population_params.random_effects_variance[i][i];
+                        eta[i] * eta[i] / omega + (2.0 * std::f64::consts::PI * omega).ln()
+                    })
+                    .sum();
+                
+                objectives.push(IndividualObjective { id, data, prior });
+            }
+        }
+        
+        Ok(objectives)
+    }
+
+    /// (individual prediction, residual, weight) for every measured
+    /// observation under the concentration error model, the residual on the
+    /// log scale under the exponential one, and the (residual,
+    /// weight) of the effect observations when they have their own variance
+    /// and of each output compartment's; censored ones have no residual.
+    fn prediction_residual_pairs(
+        &self,
+        dataset: &Dataset,
+        individual_params: &HashMap<i32, Vec<f64>>,
+        population_params: &ModelParameters,
+    ) -> Result<(Vec<PredictionResidual>, Vec<WeightedResidual>, OutputResiduals)> {
+        let mut pairs = Vec::new();
+        let mut effect_residuals = Vec::new();
+        let mut output_residuals = OutputResiduals::new();
+        let separate_effects = population_params.effect_residual_variance.is_some();
+        
+        for (&id, individual) in dataset.individuals() {
+            if let Some(params) = individual_params.get(&id) {
+                let mut ind_params = population_params.clone();
+                ind_params.fixed_effects = params.clone();
+                
+                let predictions = self.predict_individual(individual, &ind_params)?;
+                let weight = individual.weight();
+                for (obs, pred) in individual.observations().iter().zip(predictions).filter(|(obs, _)| !obs.is_censored()) {
+                    if separate_effects && obs.observation_type == ObservationType::Effect {
+                        effect_residuals.push((obs.value - pred, weight));
+                    } else if let Some(compartment) = objective::separate_output(population_params, obs) {
+                        let residual = if objective::on_log_scale(population_params, obs) {
+                            objective::log_scale_residual(obs, pred)
+                        } else {
+                            obs.value - pred
+                        };
+                        let residual = objective::output_variance_residual(population_params, residual, pred);
+                        output_residuals.entry(compartment).or_default().push((residual, weight));
+                    } else if objective::on_log_scale(population_params, obs) {
+                        pairs.push((pred, objective::log_scale_residual(obs, pred), weight));
+                    } else {
+                        pairs.push((pred, obs.value - pred, weight));
+                    }
                 }
             }
         }
         
-        Ok(objective)
+        Ok((pairs, effect_residuals, output_residuals))
     }
 
-    fn predict_individual(
+    /// Estimated residual components with SEs from the expected Fisher
+    /// information of the Gaussian likelihood in the component variances.
+    fn residual_components(
         &self,
-        individual: &Individual,
-        params: &ModelParameters,
-    ) -> Result<Vec<f64>> {
-        let mut predictions = Vec::new();
-        let solver_config = SolverConfig::default();
+        dataset: &Dataset,
+        individual_params: &HashMap<i32, Vec<f64>>,
+        population_params: &ModelParameters,
+    ) -> Result<Vec<ResidualComponent>> {
+        let (pairs, effect_residuals, output_residuals) = self.prediction_residual_pairs(dataset, individual_params, population_params)?;
         
-        let system = CompartmentSystem {
-            model: &self.model,
-            params,
-        };
+        // d Var / d component: 1 for additive, |PRED|^(2·power) for
+        // proportional, and proportional · |PRED|^(2·power) · 2 ln|PRED| for
+        // the power itself
+        let (proportional, power) = (population_params.proportional_variance, population_params.error_power);
+        let scale = move |pred: f64| pred.abs().powf(2.0 * power);
+        let mut components: Vec<(&str, f64, VarianceDerivative)> = Vec::new();
+        if self.config.error_model == ErrorModel::Exponential {
+            components.push(("Exponential", population_params.residual_variance, Box::new(|_: f64| 1.0)));
+        }
+        if matches!(self.config.error_model, ErrorModel::Additive | ErrorModel::Combined) {
+            components.push(("Additive", population_params.residual_variance, Box::new(|_: f64| 1.0)));
+        }
+        if matches!(self.config.error_model, ErrorModel::Proportional | ErrorModel::Combined | ErrorModel::Power) {
+            components.push(("Proportional", proportional, Box::new(scale)));
+        }
+        if self.config.error_model == ErrorModel::Power {
+            components.push(("Power", power, Box::new(move |pred: f64| {
+                if pred == 0.0 { 0.0 } else { 2.0 * proportional * scale(pred) * pred.abs().ln() }
+            })));
+        }
         
-        let mut current_state = ModelState::new(self.model.n_compartments());
-        let mut last_time = 0.0;
-        
-        // Apply dosing events
-        for dose in individual.dosing_records() {
-            if dose.time > last_time {
-                let final_state = self.solver.solve_to_time(
-                    &system,
-                    last_time,
-                    dose.time,
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = final_state;
-                current_state.time = dose.time;
-            }
-            
-            current_state.add_dose(dose.compartment as usize, dose.amount);
-            last_time = dose.time;
-        }
-        
-        // Predict concentrations at observation times
-        for obs in individual.observations() {
-            if obs.time > last_time {
-                let final_state = self.solver.solve_to_time(
-                    &system,
-                    last_time,
-                    obs.time,
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = final_state;
-                current_state.time = obs.time;
-                last_time = obs.time;
+        let n = components.len();
+        let mut information = DMatrix::<f64>::zeros(n, n);
+        for &(pred, _, weight) in &pairs {
+            let variance = objective::concentration_variance(population_params, pred);
+            for (j, (_, _, dj)) in components.iter().enumerate() {
+                for (k, (_, _, dk)) in components.iter().enumerate() {
+                    information[(j, k)] += 0.5 * weight * dj(pred) * dk(pred) / (variance * variance);
+                }
             }
-            
-            let concentration = self.model.observation_function(
-                &current_state,
-                params,
-                obs.compartment as usize,
-            );
-            predictions.push(concentration);
         }
+        let covariance = information.try_inverse();
         
-        Ok(predictions)
+        let mut estimated: Vec<ResidualComponent> = components.iter().enumerate()
+            .map(|(j, &(name, estimate, _))| ResidualComponent {
+                name: name.to_string(),
+                variance: (name != "Power").then_some(estimate),
+                power: (name == "Power").then_some(estimate),
+                standard_error: covariance.as_ref().map_or(f64::NAN, |cov| cov[(j, j)].max(0.0).sqrt()),
+            })
+            .collect();
+        // One variance, additive or scaling the proportional shape: information n / (2σ⁴)
+        if let Some(variance) = population_params.effect_residual_variance.filter(|_| !effect_residuals.is_empty()) {
+            estimated.push(ResidualComponent {
+                name: "Effect".to_string(),
+                variance: Some(variance),
+                power: None,
+                standard_error: variance * (2.0 / effect_residuals.iter().map(|(_, w)| w).sum::<f64>()).sqrt(),
+            });
+        }
+        for (compartment, residuals) in &output_residuals {
+            let variance = population_params.output_residual_variances[compartment];
+            estimated.push(ResidualComponent {
+                name: format!("CMT {}", compartment),
+                variance: Some(variance),
+                power: None,
+                standard_error: variance * (2.0 / residuals.iter().map(|(_, w)| w).sum::<f64>()).sqrt(),
+            });
+        }
+        Ok(estimated)
+    }
+
+    fn predict_individual(
+        &self,
+        individual: &Individual,
+        params: &ModelParameters,
+    ) -> Result<Vec<f64>> {
+        let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
+        prediction::predict_individual(
+            &self.model,
+            &solver,
+            individual,
+            params,
+            &self.config.solver_config,
+        )
     }
 
     fn estimate_covariance_matrix(
@@ -463,80 +939,286 @@ impl FoceEstimator {
         dataset: &Dataset,
         individual_params: &HashMap<i32, Vec<f64>>,
         population_params: &ModelParameters,
-    ) -> Result<(Vec<Vec<f64>>, Vec<f64>)> {
+    ) -> Result<(Vec<Vec<f64>>, Vec<f64>, usize)> {
         let n_params = population_params.n_parameters();
+        // Etas stay fixed while the population parameters are perturbed
+        let etas = etas_by_id(individual_params, population_params);
         
-        // Calculate Fisher Information Matrix using finite differences
-        let mut fisher_matrix = vec![vec![0.0; n_params]; n_params];
+        // Fisher Information Matrix by finite differences. Every entry is
+        // independent, so they are computed in parallel; each lands at its
+        // own (i, j), so the matrix does not depend on the thread count
         let h = 1e-6;
-        
-        for i in 0..n_params {
-            for j in 0..n_params {
-                // Calculate second derivatives
-                let mut params_ij = population_params.clone();
-                let mut params_i = population_params.clone();
-                let mut params_j = population_params.clone();
-                let mut params_base = population_params.clone();
-                
-                params_ij.fixed_effects[i] += h;
-                params_ij.fixed_effects[j] += h;
-                params_i.fixed_effects[i] += h;
-                params_j.fixed_effects[j] += h;
-                
-                let obj_ij = self.calculate_objective_function(dataset, individual_params, &params_ij)?;
-                let obj_i = self.calculate_objective_function(dataset, individual_params, &params_i)?;
-                let obj_j = self.calculate_objective_function(dataset, individual_params, &params_j)?;
-                let obj_base = self.calculate_objective_function(dataset, individual_params, &params_base)?;
-                
-                // Second derivative approximation
-                let second_deriv = (obj_ij - obj_i - obj_j + obj_base) / (h * h);
-                fisher_matrix[i][j] = second_deriv;
-            }
-        }
+        let second_derivative = |i: usize, j: usize| -> Result<f64> {
+            let mut params_ij = population_params.clone();
+            let mut params_i = population_params.clone();
+            let mut params_j = population_params.clone();
+            
+            params_ij.fixed_effects[i] += h;
+            params_ij.fixed_effects[j] += h;
+            params_i.fixed_effects[i] += h;
+            params_j.fixed_effects[j] += h;
+            
+            let obj_ij = self.calculate_objective_function(dataset, &etas, &params_ij)?;
+            let obj_i = self.calculate_objective_function(dataset, &etas, &params_i)?;
+            let obj_j = self.calculate_objective_function(dataset, &etas, &params_j)?;
+            let obj_base = self.calculate_objective_function(dataset, &etas, population_params)?;
+            
+            Ok((obj_ij - obj_i - obj_j + obj_base) / (h * h))
+        };
+        let entries = (0..n_params * n_params)
+            .into_par_iter()
+            .map(|k| second_derivative(k / n_params, k % n_params))
+            .collect::<Result<Vec<f64>>>()?;
+        let fisher_matrix: Vec<Vec<f64>> = entries.chunks(n_params).map(|row| row.to_vec()).collect();
         
         // Invert Fisher matrix to get covariance matrix
         let fisher_dmatrix = DMatrix::from_vec(n_params, n_params, 
             fisher_matrix.iter().flatten().cloned().collect());
         
-        let covariance_dmatrix = if let Some(inv) = fisher_dmatrix.clone().try_inverse() {
-            inv
-        } else {
-            warn!("Fisher matrix not invertible, using regularized version");
-            let regularized = &fisher_dmatrix + DMatrix::identity(n_params, n_params) * 1e-6;
-            regularized.try_inverse().unwrap_or_else(|| DMatrix::identity(n_params, n_params))
-        };
+        let (covariance_dmatrix, rank) = pseudo_inverse(&fisher_dmatrix);
+        if rank < n_params {
+            warn!("Fisher information matrix has effective rank {} of {}; SEs of the unidentifiable combinations are omitted",
+                  rank, n_params);
+        }
         
         // Convert back to Vec<Vec<f64>>
         let mut covariance_matrix = vec![vec![0.0; n_params]; n_params];
         let mut standard_errors = vec![0.0; n_params];
         
+        // A parameter the objective does not depend on has no information
+        // at all; the pseudo-inverse would give it a zero SE
+        let largest = fisher_dmatrix.abs().max();
         for i in 0..n_params {
             for j in 0..n_params {
                 covariance_matrix[i][j] = covariance_dmatrix[(i, j)];
             }
-            standard_errors[i] = covariance_dmatrix[(i, i)].sqrt();
+            let uninformed = fisher_dmatrix.row(i).abs().max() <= largest * PSEUDO_INVERSE_TOLERANCE;
+            standard_errors[i] = if uninformed { f64::INFINITY } else { covariance_dmatrix[(i, i)].sqrt() };
         }
         
-        Ok((covariance_matrix, standard_errors))
+        Ok((covariance_matrix, standard_errors, rank))
     }
 }
 
-struct CompartmentSystem<'a> {
-    model: &'a CompartmentModel,
-    params: &'a ModelParameters,
+/// Mean of the individual parameters, in ID order, weighted by each
+/// individual's observation count times its FREQ. Sparse conditional modes
+/// sit near the value they were shrunk towards and would otherwise pull the
+/// estimate as hard as data-rich individuals.
+fn observation_weighted_mean(dataset: &Dataset, individual_params: &HashMap<i32, Vec<f64>>) -> Vec<f64> {
+    let mut ids: Vec<i32> = individual_params.keys().copied().collect();
+    ids.sort();
+    let n_params = ids.first().map_or(0, |id| individual_params[id].len());
+    let mut mean = vec![0.0; n_params];
+    let mut total_weight = 0.0;
+    for id in ids {
+        let weight = observation_weight(dataset, id);
+        for (m, p) in mean.iter_mut().zip(&individual_params[&id]) {
+            *m += weight * p;
+        }
+        total_weight += weight;
+    }
+    if total_weight > 0.0 {
+        for m in &mut mean {
+            *m /= total_weight;
+        }
+    }
+    mean
 }
 
-impl<'a> OdeSystem for CompartmentSystem<'a> {
-    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
-        let state = ModelState {
-            compartments: y.clone(),
-            time: t,
-        };
-        self.model.derivatives(&state, self.params)
+/// Mean outer product of the etas about `fixed_effects`, with the weights
+/// of `observation_weighted_mean`. Weighting both alike keeps Omega the
+/// spread about the mean it was centred on: an unweighted second moment
+/// about a weighted mean would add the squared gap between the two means
+/// to every variance. Summed in ID order so the estimate does not depend
+/// on hash order.
+fn observation_weighted_covariance(
+    dataset: &Dataset,
+    individual_params: &HashMap<i32, Vec<f64>>,
+    fixed_effects: &[f64],
+) -> Vec<Vec<f64>> {
+    let mut ids: Vec<i32> = individual_params.keys().copied().collect();
+    ids.sort();
+    let n_params = fixed_effects.len();
+    let mut covariance = vec![vec![0.0; n_params]; n_params];
+    let mut total_weight = 0.0;
+    for id in ids {
+        let weight = observation_weight(dataset, id);
+        let eta: Vec<f64> = individual_params[&id].iter().zip(fixed_effects).map(|(p, theta)| p - theta).collect();
+        for (row, eta_i) in covariance.iter_mut().zip(&eta) {
+            for (value, eta_j) in row.iter_mut().zip(&eta) {
+                *value += weight * eta_i * eta_j;
+            }
+        }
+        total_weight += weight;
     }
+    if total_weight > 0.0 {
+        for value in covariance.iter_mut().flatten() {
+            *value /= total_weight;
+        }
+    }
+    covariance
+}
+
+/// FREQ weight of individual `id`; 1 when it is not in the dataset.
+fn individual_weight(dataset: &Dataset, id: i32) -> f64 {
+    dataset.get_individual(id).map_or(1.0, Individual::weight)
+}
 
-    fn dimension(&self) -> usize {
-        self.model.n_compartments()
+/// Weight of individual `id` in the population update: its FREQ times its
+/// number of observations; 0 when it is not in the dataset.
+fn observation_weight(dataset: &Dataset, id: i32) -> f64 {
+    dataset.get_individual(id)
+        .map_or(0.0, |individual| individual.weight() * individual.observations().len() as f64)
+}
+
+/// Largest condition number of the Newton system in the eta optimization.
+const MAX_INNER_HESSIAN_CONDITION: f64 = 1e8;
+
+/// `hessian` (the negated log-likelihood Hessian of the etas) shifted along
+/// its diagonal just enough that it is positive definite with condition
+/// number at most `MAX_INNER_HESSIAN_CONDITION`. The shift is relative to
+/// the Hessian's own eigenvalues, so it is negligible for a large Hessian
+/// and not swamping for a tiny one; a well-conditioned Hessian is returned
+/// unchanged.
+fn regularize_hessian(hessian: &DMatrix<f64>) -> DMatrix<f64> {
+    let n = hessian.nrows();
+    if n == 0 {
+        return hessian.clone();
+    }
+    let eigenvalues = (0.5 * (hessian + hessian.transpose())).symmetric_eigenvalues();
+    let largest = eigenvalues.max();
+    let smallest = eigenvalues.min();
+    let scale = eigenvalues.amax().max(f64::EPSILON);
+    let kappa = MAX_INNER_HESSIAN_CONDITION;
+    // (largest + shift) / (smallest + shift) ≤ κ, with the smallest
+    // eigenvalue kept at least scale/κ above zero
+    let shift = ((largest - kappa * smallest) / (kappa - 1.0))
+        .max(scale / kappa - smallest)
+        .max(0.0);
+    hessian + DMatrix::identity(n, n) * shift
+}
+
+/// Singular values below this fraction of the largest are treated as zero
+/// when inverting the Fisher information matrix.
+const PSEUDO_INVERSE_TOLERANCE: f64 = 1e-8;
+
+/// Moore–Penrose pseudo-inverse by SVD, with near-zero singular values
+/// dropped, and the number kept (the effective rank). On a full-rank matrix
+/// this is the ordinary inverse; on a near-singular Fisher matrix it inverts
+/// only the identifiable directions instead of blowing up.
+pub(crate) fn pseudo_inverse(matrix: &DMatrix<f64>) -> (DMatrix<f64>, usize) {
+    let (rows, cols) = matrix.shape();
+    let svd = matrix.clone().svd(true, true);
+    let (Some(u), Some(v_t)) = (svd.u, svd.v_t) else {
+        return (DMatrix::zeros(cols, rows), 0);
+    };
+    let largest = svd.singular_values.iter().copied().fold(0.0, f64::max);
+    let cutoff = largest * PSEUDO_INVERSE_TOLERANCE;
+    let mut rank = 0;
+    let inverted = DMatrix::from_diagonal(&svd.singular_values.map(|sigma| {
+        if sigma > cutoff && sigma.is_finite() {
+            rank += 1;
+            1.0 / sigma
+        } else {
+            0.0
+        }
+    }));
+    (v_t.transpose() * inverted * u.transpose(), rank)
+}
+
+/// Range searched for the power of the power error model.
+const ERROR_POWER_RANGE: (f64, f64) = (0.0, 2.0);
+
+/// Golden-section steps of the power search, narrowing the range to
+/// about 1e-8 of its width.
+const ERROR_POWER_ITERATIONS: usize = 40;
+
+/// Maximum-likelihood (proportional variance, power) of the power error
+/// model from weighted (prediction, residual) pairs with nonzero
+/// predictions. For a given power the variance is the weighted mean of
+/// r²/|PRED|^(2·power), which leaves -2 log-likelihood
+/// n·ln σ² + 2·power·Σ ln|PRED| + n to minimize over the power alone.
+fn estimate_power_error(pairs: &[PredictionResidual]) -> (f64, f64) {
+    let pairs: Vec<(f64, f64, f64)> = pairs.iter()
+        .filter(|(p, _, _)| p.abs() > 0.0)
+        .map(|&(p, r, w)| (p.abs().ln(), r * r, w))
+        .collect();
+    if pairs.is_empty() {
+        return (0.0, 1.0);
+    }
+    let n: f64 = pairs.iter().map(|(_, _, w)| w).sum();
+    let sum_log_pred: f64 = pairs.iter().map(|(log_pred, _, w)| w * log_pred).sum();
+    let variance = |power: f64| {
+        pairs.iter().map(|(log_pred, r2, w)| w * r2 * (-2.0 * power * log_pred).exp()).sum::<f64>() / n
+    };
+    let profile = |power: f64| n * variance(power).ln() + 2.0 * power * sum_log_pred;
+
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = ERROR_POWER_RANGE;
+    let mut left = high - ratio * (high - low);
+    let mut right = low + ratio * (high - low);
+    let (mut f_left, mut f_right) = (profile(left), profile(right));
+    for _ in 0..ERROR_POWER_ITERATIONS {
+        if f_left <= f_right {
+            high = right;
+            right = left;
+            f_right = f_left;
+            left = high - ratio * (high - low);
+            f_left = profile(left);
+        } else {
+            low = left;
+            left = right;
+            f_left = f_right;
+            right = low + ratio * (high - low);
+            f_right = profile(right);
+        }
+    }
+    let power = 0.5 * (low + high);
+    (variance(power), power)
+}
+
+/// Moment estimates of the (additive, proportional) residual variances from
+/// weighted (prediction, residual) pairs. The combined model regresses
+/// squared residuals on squared predictions, dropping a component whose
+/// estimate would be negative. The exponential model's variance is the
+/// mean square of its log-scale residuals.
+fn estimate_residual_variances(error_model: ErrorModel, pairs: &[PredictionResidual]) -> (f64, f64) {
+    let n: f64 = pairs.iter().map(|(_, _, w)| w).sum();
+    let mean_squared = pairs.iter().map(|(_, r, w)| w * r * r).sum::<f64>() / n;
+    let through_origin = || {
+        let sxy: f64 = pairs.iter().map(|(p, r, w)| w * p * p * r * r).sum();
+        let sxx: f64 = pairs.iter().map(|(p, _, w)| w * p.powi(4)).sum();
+        if sxx > 0.0 { sxy / sxx } else { 0.0 }
+    };
+    
+    match error_model {
+        ErrorModel::Additive | ErrorModel::Exponential => (mean_squared, 0.0),
+        ErrorModel::Proportional | ErrorModel::Power => {
+            let scaled: Vec<(f64, f64)> = pairs.iter()
+                .filter(|(p, _, _)| p.abs() > 0.0)
+                .map(|(p, r, w)| ((r / p).powi(2), *w))
+                .collect();
+            if scaled.is_empty() {
+                (0.0, through_origin())
+            } else {
+                let total_weight: f64 = scaled.iter().map(|(_, w)| w).sum();
+                (0.0, scaled.iter().map(|(s, w)| w * s).sum::<f64>() / total_weight)
+            }
+        }
+        ErrorModel::Combined => {
+            let mean_x = pairs.iter().map(|(p, _, w)| w * p * p).sum::<f64>() / n;
+            let sxx: f64 = pairs.iter().map(|(p, _, w)| w * (p * p - mean_x).powi(2)).sum();
+            let sxy: f64 = pairs.iter().map(|(p, r, w)| w * (p * p - mean_x) * (r * r - mean_squared)).sum();
+            let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+            let intercept = mean_squared - slope * mean_x;
+            if slope <= 0.0 {
+                (mean_squared, 0.0)
+            } else if intercept <= 0.0 {
+                (0.0, through_origin())
+            } else {
+                (intercept, slope)
+            }
+        }
     }
 }
 
@@ -545,8 +1227,39 @@ mod tests {
     use super::*;
     use crate::models::{CompartmentModel, ModelType};
     use crate::data::*;
+    use crate::test_support::{
+        dataset_from_csv, one_compartment_concentration, simulated_one_compartment, simulated_one_compartment_csv,
+    };
     use std::collections::HashMap;
 
+    #[test]
+    fn test_badly_scaled_hessian_regularized_to_bounded_condition() {
+        let condition = |matrix: &DMatrix<f64>| {
+            let eigenvalues = matrix.clone().symmetric_eigenvalues();
+            eigenvalues.max() / eigenvalues.min()
+        };
+
+        // Condition number 1e14 whatever the overall scale
+        for scale in [1e-9, 1.0, 1e9] {
+            let hessian = DMatrix::from_row_slice(2, 2, &[1e6, 0.0, 0.0, 1e-8]) * scale;
+            let regularized = regularize_hessian(&hessian);
+            let kappa = condition(&regularized);
+            assert!(kappa <= MAX_INNER_HESSIAN_CONDITION * (1.0 + 1e-6) && kappa > 0.0, "{}", kappa);
+            assert!(regularized.clone().cholesky().is_some());
+            assert!((regularized[(0, 0)] - hessian[(0, 0)]).abs() < 1e-6 * hessian[(0, 0)]);
+        }
+
+        // Indefinite: shifted to positive definite
+        let indefinite = DMatrix::from_row_slice(2, 2, &[2.0, 0.0, 0.0, -1.0]);
+        let regularized = regularize_hessian(&indefinite);
+        assert!(regularized.clone().cholesky().is_some());
+        assert!(condition(&regularized) <= MAX_INNER_HESSIAN_CONDITION * (1.0 + 1e-6));
+
+        // Well-conditioned: unchanged
+        let hessian = DMatrix::from_row_slice(2, 2, &[4.0, 1.0, 1.0, 3.0]);
+        assert_eq!(regularize_hessian(&hessian), hessian);
+    }
+
     #[test]
     fn test_foce_estimator_creation() {
         let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
@@ -566,4 +1279,404 @@ mod tests {
         assert_eq!(results.parameter_names.len(), 2);
         assert!(!results.converged);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_more_inner_iterations_improve_eta_convergence() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut population_params = model.default_parameters();
+        population_params.residual_variance = 1e-4;
+
+        // Dense, precise data from parameters well away from the population
+        // values makes the individual objective sharply curved
+        let true_eta = [0.5, -0.3];
+        let cl = (population_params.fixed_effects[0] + true_eta[0]).exp();
+        let v = (population_params.fixed_effects[1] + true_eta[1]).exp();
+        let observations = (1..=24)
+            .map(|t| {
+                let t = t as f64;
+                Observation::new(t, 100.0 / v * (-cl / v * t).exp(), 1, ObservationType::Concentration)
+            })
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+
+        let gradient_norm = |max_iterations: usize| {
+            let config = EstimationConfig::default().with_foce_inner_iterations(max_iterations);
+            let estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+            let (eta, iterations) = estimator
+                .optimize_individual_eta(&individual, &population_params, &[0.0, 0.0])
+                .unwrap();
+            assert!(iterations <= max_iterations);
+            let (gradient, _) = estimator
+                .calculate_individual_derivatives(&individual, &population_params, &eta)
+                .unwrap();
+            DVector::from_vec(gradient).norm()
+        };
+
+        assert!(gradient_norm(30) < gradient_norm(1));
+    }
+
+    #[test]
+    fn test_individual_objective_components_sum_to_total() {
+        let dataset = simulated_one_compartment(
+            "foce_individual_ofv_test.csv", 4, &[1.0, 4.0, 12.0], |id, _, pred| pred * (1.0 + 0.05 * id as f64),
+        );
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(1);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+        assert_eq!(results.individual_objectives.len(), 4);
+
+        let mut population_params = estimator.model().default_parameters();
+        population_params.fixed_effects = results.fixed_effects.clone();
+        population_params.random_effects_variance = results.random_effects_variance.clone();
+        population_params.residual_variance = results.residual_variance;
+        population_params.proportional_variance = results.proportional_variance;
+        for objective in &results.individual_objectives {
+            let eta = etas_from(&results.individual_parameters[&objective.id], &population_params);
+            assert!(eta.iter().any(|e| e.abs() > 0.0));
+            // The objective restricted to this individual
+            let total = estimator
+                .calculate_objective_function(&dataset, &HashMap::from([(objective.id, eta)]), &population_params)
+                .unwrap();
+            assert!((objective.data + objective.prior - total).abs() < 1e-9 * (1.0 + total.abs()));
+        }
+    }
+
+    #[test]
+    fn test_combined_error_fit_reports_two_residual_components() {
+        let times = [0.5, 1.0, 2.0, 4.0, 8.0, 12.0, 24.0];
+        let dataset = simulated_one_compartment("foce_combined_error_test.csv", 6, &times, |id, k, pred| {
+            // Deterministic +/- noise with additive and proportional parts
+            pred + (-1.0_f64).powi(id + k as i32) * (0.05 + 0.1 * pred)
+        });
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(1)
+            .with_error_model(ErrorModel::Combined);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+
+        let names: Vec<&str> = results.residual_components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Additive", "Proportional"]);
+        assert_eq!(results.residual_components[0].variance, Some(results.residual_variance));
+        assert_eq!(results.residual_components[1].variance, Some(results.proportional_variance));
+        assert!(results.proportional_variance > 0.0);
+        assert!(results.residual_components.iter().all(|c| c.estimate() >= 0.0 && c.standard_error.is_finite()));
+    }
+
+    /// One-compartment PK with an effect compartment: CMT 1 reads the
+    /// plasma concentration, CMT 2 a response proportional to the effect-site
+    /// concentration.
+    struct EffectCompartmentModel;
+
+    impl crate::models::CompartmentModelTrait for EffectCompartmentModel {
+        fn n_compartments(&self) -> usize {
+            2
+        }
+
+        fn parameter_names(&self) -> Vec<String> {
+            vec!["CL".to_string(), "V".to_string(), "KE0".to_string()]
+        }
+
+        fn default_parameters(&self) -> ModelParameters {
+            let mut params = ModelParameters::new(3, self.parameter_names());
+            params.fixed_effects = vec![1.0_f64.ln(), 3.0_f64.ln(), 0.5_f64.ln()];
+            for i in 0..3 {
+                params.random_effects_variance[i][i] = 0.04;
+            }
+            params
+        }
+
+        fn derivatives(&self, state: &crate::models::ModelState, params: &ModelParameters) -> DVector<f64> {
+            let (cl, v, ke0) = (params.fixed_effects[0].exp(), params.fixed_effects[1].exp(), params.fixed_effects[2].exp());
+            let central = state.compartments[0];
+            DVector::from_vec(vec![-cl / v * central, ke0 * (central / v - state.compartments[1])])
+        }
+
+        fn observation_function(&self, state: &crate::models::ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+            match compartment {
+                1 => state.compartments[0] / params.fixed_effects[1].exp(),
+                _ => 10.0 * state.compartments[1],
+            }
+        }
+
+        fn validate_parameters(&self, _params: &ModelParameters) -> Result<(), crate::models::ModelError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_joint_pkpd_fit_estimates_separate_residual_variances() {
+        let model = CompartmentModel::from_custom("effect", Box::new(EffectCompartmentModel));
+        let params = model.default_parameters();
+        let solver = RungeKuttaSolver::new();
+        let times = [0.5_f64, 1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0];
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        // PK and PD rows interleaved at each time, as in a long-format file
+        let design: Vec<Observation> = times.iter()
+            .flat_map(|&t| [1, 2].map(|cmt| Observation::new(t, 1.0, cmt, ObservationType::Concentration)))
+            .collect();
+        let truth = prediction::predict_individual(
+            &model, &solver, &Individual::new(1, design.clone(), doses, HashMap::new()), &params, &SolverConfig::default(),
+        ).unwrap();
+
+        // Residual SD 0.1 on the concentrations and 2 on the responses
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=8 {
+            csv.push_str(&format!("{},0,,100,1,1\n", id));
+            for (k, (obs, pred)) in design.iter().zip(&truth).enumerate() {
+                let sign = if (id + k / 2) % 2 == 0 { 1.0 } else { -1.0 };
+                let sd = if obs.compartment == 1 { 0.1 } else { 2.0 };
+                csv.push_str(&format!("{},{},{},,0,{}\n", id, obs.time, pred + sign * sd, obs.compartment));
+            }
+        }
+        let mut dataset = dataset_from_csv("foce_joint_pkpd_test.csv", &csv);
+        assert_eq!(dataset.set_effect_compartment(2), 8 * times.len());
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(5);
+        let results = FoceEstimator::new(model, config).fit(&dataset).unwrap();
+
+        let effect_variance = results.effect_residual_variance.unwrap();
+        assert!((2.0..8.0).contains(&effect_variance), "effect variance {}", effect_variance);
+        assert!(results.residual_variance < 0.1, "concentration variance {}", results.residual_variance);
+        let names: Vec<&str> = results.residual_components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Additive", "Effect"]);
+        assert!(results.objective_function_value.is_finite());
+    }
+
+    #[test]
+    fn test_metabolite_output_variance_follows_proportional_error_model() {
+        let model = CompartmentModel::new(ModelType::ParentMetabolite).unwrap();
+        let params = model.default_parameters();
+        let solver = RungeKuttaSolver::new();
+        let times = [0.5_f64, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0];
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let design: Vec<Observation> = times.iter()
+            .flat_map(|&t| [1, 2].map(|cmt| Observation::new(t, 1.0, cmt, ObservationType::Concentration)))
+            .collect();
+        let truth = prediction::predict_individual(
+            &model, &solver, &Individual::new(1, design.clone(), doses, HashMap::new()), &params, &SolverConfig::default(),
+        ).unwrap();
+
+        // 10% CV on the parent and 30% on the metabolite
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=8 {
+            csv.push_str(&format!("{},0,,100,1,1\n", id));
+            for (k, (obs, pred)) in design.iter().zip(&truth).enumerate() {
+                let sign = if (id + k / 2) % 2 == 0 { 1.0 } else { -1.0 };
+                let cv = if obs.compartment == 1 { 0.1 } else { 0.3 };
+                csv.push_str(&format!("{},{},{},,0,{}\n", id, obs.time, pred * (1.0 + sign * cv), obs.compartment));
+            }
+        }
+        let dataset = dataset_from_csv("foce_metabolite_proportional_test.csv", &csv);
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_error_model(ErrorModel::Proportional);
+        let estimator = FoceEstimator::new(model, config);
+        let mut population = params.clone();
+        population.proportional_variance = 0.01;
+        population.residual_variance = 0.0;
+        population.output_residual_variances.insert(2, 0.01);
+        let individual_params: HashMap<i32, Vec<f64>> = dataset.individuals().keys()
+            .map(|&id| (id, params.fixed_effects.clone()))
+            .collect();
+        let (pairs, _, output_residuals) = estimator.prediction_residual_pairs(&dataset, &individual_params, &population).unwrap();
+
+        // At the true parameters both M-step estimates are proportional
+        // variances, the CVs squared
+        let (_, parent) = estimate_residual_variances(ErrorModel::Proportional, &pairs);
+        let metabolite = weighted_mean_square(&output_residuals[&2]);
+        assert!((parent - 0.01).abs() < 1e-9, "parent variance {}", parent);
+        assert!((metabolite - 0.09).abs() < 1e-9, "metabolite variance {}", metabolite);
+    }
+
+    #[test]
+    fn test_underdetermined_individual_flagged_with_finite_result() {
+        let mut csv = simulated_one_compartment_csv(3, &[0.5, 1.0, 2.0, 4.0, 8.0], |_, _, pred| pred);
+        csv.push_str(&format!("4,0,,100,1,1\n4,2,{},,0,1\n", one_compartment_concentration(2.0)));
+        let dataset = dataset_from_csv("foce_underdetermined_test.csv", &csv);
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(2);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+
+        assert_eq!(results.underdetermined_individuals, vec![4]);
+        assert!(results.individual_parameters[&4].iter().all(|p| p.is_finite()));
+        assert!(results.fixed_effects.iter().all(|p| p.is_finite()));
+        assert!(results.objective_function_value.is_finite());
+    }
+
+    #[test]
+    fn test_power_error_fit_recovers_injected_power() {
+        use rand::{SeedableRng, rngs::StdRng};
+        use rand_distr::{Distribution, Normal};
+
+        // SD of 0.2·PRED^0.5: between additive and proportional
+        let power = 0.5_f64;
+        let mut rng = StdRng::seed_from_u64(177);
+        let noise = Normal::new(0.0, 1.0).unwrap();
+        let times = [0.5, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0];
+        let dataset = simulated_one_compartment("foce_power_error_test.csv", 20, &times, |_, _, pred| {
+            pred + 0.2 * pred.powf(power) * noise.sample(&mut rng)
+        });
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(5)
+            .with_error_model(ErrorModel::Power);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+
+        assert!((results.error_power - power).abs() < 0.15, "power {}", results.error_power);
+        assert!((results.proportional_variance / 0.04 - 1.0).abs() < 0.5, "variance {}", results.proportional_variance);
+        assert_eq!(results.residual_variance, 0.0);
+        let names: Vec<&str> = results.residual_components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Proportional", "Power"]);
+        assert_eq!(results.residual_components[1].power, Some(results.error_power));
+        assert_eq!(results.residual_components[1].variance, None);
+        assert!(results.residual_components.iter().all(|c| c.standard_error.is_finite()));
+
+        // The M-step alone: exact pairs with unit-size residuals recover
+        // the power exactly
+        let pairs: Vec<PredictionResidual> = (1..=30)
+            .map(|k| {
+                let pred = k as f64;
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                (pred, sign * 0.3 * pred.powf(1.4), 1.0)
+            })
+            .collect();
+        let (variance, estimated) = estimate_power_error(&pairs);
+        assert!((estimated - 1.4).abs() < 1e-6 && (variance - 0.09).abs() < 1e-6, "{} {}", variance, estimated);
+    }
+
+    #[test]
+    fn test_objective_trajectory_non_increasing_on_well_posed_fit() {
+        // Dense, nearly exact monoexponential data: the objective is
+        // close to quadratic around its minimum
+        let times = [0.5, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0];
+        let dataset = simulated_one_compartment("foce_objective_trajectory_test.csv", 6, &times, |id, _, pred| {
+            (1.0 + 0.05 * (id as f64 - 3.5)) * pred
+        });
+
+        let config = EstimationConfig::default()
+            .with_method(super::super::EstimationMethod::Foce)
+            .with_foce_iterations(8);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+
+        assert_eq!(results.objective_trajectory.len(), results.n_iterations);
+        for pair in results.objective_trajectory.windows(2) {
+            assert!(pair[1] <= pair[0] + 1e-8 * pair[0].abs(), "{:?}", results.objective_trajectory);
+        }
+    }
+
+    #[test]
+    fn test_individual_with_freq_three_counts_as_three_copies() {
+        let rows = |id: i32, scale: f64| {
+            let mut rows = format!("{},0,,100,1\n", id);
+            for (k, t) in [0.5_f64, 1.0, 2.0, 4.0, 8.0, 12.0].iter().enumerate() {
+                let noise = if k % 2 == 0 { 1.03 } else { 0.97 };
+                rows.push_str(&format!("{},{},{},,0\n", id, t, noise * scale * one_compartment_concentration(*t)));
+            }
+            rows
+        };
+        let with_freq = |csv: String, freq: &str| csv.lines()
+            .map(|line| format!("{},{}\n", line, freq))
+            .collect::<String>();
+
+        // Individual 1 with FREQ 3 versus three copies of it
+        let weighted = dataset_from_csv("foce_freq_weighted_test.csv", &format!(
+            "ID,TIME,DV,AMT,EVID,FREQ\n{}{}{}",
+            with_freq(rows(1, 1.4), "3"), with_freq(rows(2, 0.8), "1"), with_freq(rows(3, 1.0), ""),
+        ));
+        assert_eq!(weighted.individual_weights(), HashMap::from([(1, 3.0), (2, 1.0), (3, 1.0)]));
+        assert!(weighted.covariate_names().is_empty());
+        let copies = dataset_from_csv("foce_freq_copies_test.csv", &format!(
+            "ID,TIME,DV,AMT,EVID\n{}{}{}{}{}",
+            rows(1, 1.4), rows(4, 1.4), rows(5, 1.4), rows(2, 0.8), rows(3, 1.0),
+        ));
+
+        let fit = |dataset: &Dataset| {
+            let config = EstimationConfig::default()
+                .with_method(super::super::EstimationMethod::Foce)
+                .with_foce_iterations(3);
+            FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config)
+                .fit(dataset)
+                .unwrap()
+        };
+        let (weighted, copies) = (fit(&weighted), fit(&copies));
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * (1.0 + b.abs());
+        for (a, b) in weighted.fixed_effects.iter().zip(&copies.fixed_effects) {
+            assert!(close(*a, *b), "{:?} vs {:?}", weighted.fixed_effects, copies.fixed_effects);
+        }
+        for (a, b) in weighted.random_effects_variance.iter().flatten().zip(copies.random_effects_variance.iter().flatten()) {
+            assert!(close(*a, *b), "{:?} vs {:?}", weighted.random_effects_variance, copies.random_effects_variance);
+        }
+        assert!(close(weighted.residual_variance, copies.residual_variance));
+        assert!(close(weighted.objective_function_value, copies.objective_function_value),
+                "{} vs {}", weighted.objective_function_value, copies.objective_function_value);
+    }
+
+    #[test]
+    fn test_combined_residual_variances_recover_both_parts() {
+        let pairs: Vec<PredictionResidual> = (1..=40)
+            .map(|k| {
+                let pred = k as f64 * 0.5;
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                (pred, sign * (0.04 + 0.01 * pred * pred).sqrt(), 1.0)
+            })
+            .collect();
+        let (additive, proportional) = estimate_residual_variances(ErrorModel::Combined, &pairs);
+        assert!((additive - 0.04).abs() < 1e-9);
+        assert!((proportional - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pseudo_inverse_of_rank_deficient_fisher_matrix() {
+        // CL and V enter only through CL/V: the Fisher matrix is v v' with
+        // v = (2, 1), whose pseudo-inverse is v v' / |v|⁴
+        let fisher = DMatrix::from_row_slice(2, 2, &[4.0, 2.0, 2.0, 1.0 + 1e-14]);
+        let (covariance, rank) = pseudo_inverse(&fisher);
+        assert_eq!(rank, 1);
+        let expected = &fisher / 25.0;
+        assert!((&covariance - expected).abs().max() < 1e-9, "{}", covariance);
+        assert!((&fisher * &covariance * &fisher - &fisher).abs().max() < 1e-9);
+
+        // Full rank: the ordinary inverse
+        let fisher = DMatrix::from_row_slice(2, 2, &[4.0, 1.0, 1.0, 3.0]);
+        let (covariance, rank) = pseudo_inverse(&fisher);
+        assert_eq!(rank, 2);
+        assert!((covariance - fisher.try_inverse().unwrap()).abs().max() < 1e-12);
+    }
+
+    #[test]
+    fn test_data_rich_individual_dominates_population_mean() {
+        // Individual 1 has twenty observations, individual 2 only two
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID\n1,0,,100,1\n");
+        for t in 1..=20 {
+            csv.push_str(&format!("1,{},5.0,,0\n", t));
+        }
+        csv.push_str("2,0,,100,1\n2,1,5.0,,0\n2,2,5.0,,0\n");
+        let dataset = dataset_from_csv("foce_weighted_mean_test.csv", &csv);
+
+        let individual_params = HashMap::from([(1, vec![0.0, 1.0]), (2, vec![2.2, -1.2])]);
+        let mean = observation_weighted_mean(&dataset, &individual_params);
+        assert!((mean[0] - 0.2).abs() < 1e-12 && (mean[1] - 0.8).abs() < 1e-12, "{:?}", mean);
+
+        // Omega about that mean carries the same weights: etas (−0.2, 0.2)
+        // with weight 20/22 and (2, −2) with weight 2/22
+        let omega = observation_weighted_covariance(&dataset, &individual_params, &mean);
+        let variance = (20.0 * 0.04 + 2.0 * 4.0) / 22.0;
+        assert!((omega[0][0] - variance).abs() < 1e-12, "{:?}", omega);
+        assert!((omega[0][1] + variance).abs() < 1e-12 && (omega[1][1] - variance).abs() < 1e-12, "{:?}", omega);
+    }
+}
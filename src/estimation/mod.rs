@@ -1,5 +1,15 @@
+pub mod bounds;
+pub mod censoring;
 pub mod config;
 pub mod foce;
+pub mod nca;
+pub mod objective;
+pub mod omega;
+pub mod profile;
+pub mod timing;
+pub mod trace;
 
-pub use config::{EstimationConfig, EstimationMethod};
-pub use foce::{FoceEstimator, FoceResults};
\ No newline at end of file
+pub use bounds::ParameterBounds;
+pub use config::{ErrorModel, EstimationConfig, EstimationMethod, McmcUpdate};
+pub use foce::{FoceEstimator, FoceResults, IndividualObjective};
+pub use profile::{LikelihoodProfile, ProfilePoint};
\ No newline at end of file
@@ -0,0 +1,146 @@
+use crate::data::{Dataset, Individual};
+use crate::models::CompartmentModel;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+/// Number of samples used for each log-linear regression.
+const REGRESSION_POINTS: usize = 3;
+
+/// Non-compartmental estimates for one individual.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndividualNca {
+    /// Terminal elimination rate constant from the log-linear terminal slope.
+    pub ke: f64,
+    /// Concentration back-extrapolated to the first dose time.
+    pub c0: f64,
+    /// AUC from the first dose to infinity.
+    pub auc: f64,
+    /// Total dose / AUC.
+    pub cl: f64,
+    /// First dose / C0.
+    pub v: f64,
+}
+
+/// Runs NCA on an individual's positive post-dose samples. Returns `None`
+/// when there are too few samples or the terminal phase is not declining.
+pub fn individual_nca(individual: &Individual) -> Option<IndividualNca> {
+    let first_dose = individual.dosing_records().first()?;
+    let dose_time = first_dose.time;
+    let samples: Vec<(f64, f64)> = individual.observations().iter()
+        .filter(|obs| obs.time > dose_time && obs.value > 0.0)
+        .map(|obs| (obs.time, obs.value))
+        .collect();
+    if samples.len() < REGRESSION_POINTS {
+        return None;
+    }
+
+    let (terminal_slope, _) = log_linear_fit(&samples[samples.len() - REGRESSION_POINTS..])?;
+    let ke = -terminal_slope;
+    if ke <= 0.0 {
+        return None;
+    }
+
+    // Initial phase: the first samples before any later dose
+    let next_dose_time = individual.dosing_records().iter()
+        .flat_map(|dose| dose.expand_multiple_doses())
+        .map(|dose| dose.time)
+        .filter(|&t| t > dose_time)
+        .fold(f64::INFINITY, f64::min);
+    let initial: Vec<(f64, f64)> = samples.iter()
+        .copied()
+        .take_while(|&(t, _)| t <= next_dose_time)
+        .take(REGRESSION_POINTS)
+        .collect();
+    let (initial_slope, initial_intercept) = if initial.len() >= 2 {
+        log_linear_fit(&initial)?
+    } else {
+        log_linear_fit(&samples[samples.len() - REGRESSION_POINTS..])?
+    };
+    let c0 = (initial_intercept + initial_slope * dose_time).exp();
+
+    let mut auc = 0.0;
+    let mut previous = (dose_time, c0);
+    for &(t, c) in &samples {
+        auc += 0.5 * (c + previous.1) * (t - previous.0);
+        previous = (t, c);
+    }
+    auc += previous.1 / ke;
+
+    let cl = individual.total_dose() / auc;
+    let v = first_dose.amount / c0;
+    if !(cl.is_finite() && v.is_finite() && cl > 0.0 && v > 0.0) {
+        return None;
+    }
+
+    Some(IndividualNca { ke, c0, auc, cl, v })
+}
+
+/// Initial fixed effects (log scale) with CL and the central volume set to the
+/// geometric means of the individual NCA estimates. Other parameters keep the
+/// model defaults. Returns `None` if no individual yields usable NCA.
+pub fn initial_estimates(dataset: &Dataset, model: &CompartmentModel) -> Option<Vec<f64>> {
+    let estimates: Vec<IndividualNca> = dataset.individuals().values()
+        .filter_map(individual_nca)
+        .collect();
+    if estimates.is_empty() {
+        return None;
+    }
+    debug!("NCA succeeded for {}/{} individuals", estimates.len(), dataset.n_individuals());
+
+    let log_mean = |values: Vec<f64>| values.iter().map(|v| v.ln()).sum::<f64>() / values.len() as f64;
+    let log_cl = log_mean(estimates.iter().map(|e| e.cl).collect());
+    let log_v = log_mean(estimates.iter().map(|e| e.v).collect());
+
+    let mut fixed_effects = model.default_parameters().fixed_effects;
+    for (i, name) in model.parameter_names().iter().enumerate() {
+        match name.as_str() {
+            "CL" => fixed_effects[i] = log_cl,
+            "V" | "V1" => fixed_effects[i] = log_v,
+            _ => {}
+        }
+    }
+    info!("NCA initial estimates: CL = {:.4}, V = {:.4}", log_cl.exp(), log_v.exp());
+
+    Some(fixed_effects)
+}
+
+/// Least-squares fit of ln(concentration) against time, as (slope, intercept).
+fn log_linear_fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|&(t, _)| t).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, c)| c.ln()).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|&(t, _)| (t - mean_t).powi(2)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    let sxy: f64 = points.iter().map(|&(t, c)| (t - mean_t) * (c.ln() - mean_y)).sum();
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelType;
+    use crate::test_support::dataset_from_csv;
+
+    #[test]
+    fn test_initial_estimates_near_true_one_compartment_parameters() {
+        let (cl, v) = (2.0_f64, 10.0_f64);
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=4 {
+            let dose = 50.0 * id as f64;
+            csv.push_str(&format!("{},0,,{},1,1\n", id, dose));
+            for t in [0.5_f64, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 24.0] {
+                csv.push_str(&format!("{},{},{},,0,1\n", id, t, dose / v * (-cl / v * t).exp()));
+            }
+        }
+        let dataset = dataset_from_csv("nca_init_test.csv", &csv);
+
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let thetas = initial_estimates(&dataset, &model).unwrap();
+
+        assert!((thetas[0].exp() - cl).abs() / cl < 0.1, "CL {}", thetas[0].exp());
+        assert!((thetas[1].exp() - v).abs() / v < 0.01, "V {}", thetas[1].exp());
+    }
+}
@@ -0,0 +1,534 @@
+//! The objective function value (OFV) reported by every estimation method.
+//!
+//! OFV = -2 × marginal log-likelihood, with all 2π constants included. The
+//! marginal likelihood of each individual integrates over its etas with a
+//! Laplace approximation at the supplied etas, using the first-order
+//! (Gauss-Newton) curvature J'WJ + Ω⁻¹. `final_log_likelihood` in the results
+//! of every method is -OFV / 2.
+//!
+//! With `EstimationConfig::include_likelihood_constants` off, the methods
+//! report OFV − `likelihood_constant`, NONMEM's convention. The difference
+//! is fixed by the data, so it changes neither the estimates nor OFV
+//! differences between models fitted to the same dataset.
+
+use crate::data::{Dataset, Individual, Observation, ObservationType};
+use crate::models::{CompartmentModel, ModelParameters};
+use crate::prediction;
+use crate::solver::{OdeSolver, SolverConfig};
+use super::censoring;
+use anyhow::{anyhow, Result};
+use nalgebra::{DMatrix, DVector};
+use std::collections::HashMap;
+
+/// Floor on an observation's residual variance, so a proportional model
+/// stays defined where the prediction is zero.
+const MIN_OBSERVATION_VARIANCE: f64 = 1e-12;
+
+/// Smallest value taken to the log scale, so the exponential error model
+/// stays defined for zero predictions and non-positive observations.
+const MIN_LOG_SCALE_VALUE: f64 = 1e-8;
+
+/// Finite-difference step for the prediction sensitivities.
+const SENSITIVITY_STEP: f64 = 1e-6;
+
+/// Most AGQ grid points, `n_nodes^n_eta`, per individual: 5 nodes for 5
+/// etas, or 10 for 4, each point a full prediction of the individual.
+pub const MAX_AGQ_GRID_POINTS: usize = 10_000;
+
+/// |PRED|^(2·error_power), the prediction dependence of the proportional
+/// component.
+fn proportional_scale(params: &ModelParameters, prediction: f64) -> f64 {
+    if params.error_power == 1.0 {
+        prediction * prediction
+    } else {
+        prediction.abs().powf(2.0 * params.error_power)
+    }
+}
+
+/// Residual variance of a concentration with the given prediction.
+pub(crate) fn concentration_variance(params: &ModelParameters, prediction: f64) -> f64 {
+    (params.residual_variance + params.proportional_variance * proportional_scale(params, prediction))
+        .max(MIN_OBSERVATION_VARIANCE)
+}
+
+/// Residual variance of a concentration in a separately observed output:
+/// the concentration error model with the output's own variance in place of
+/// its proportional component when it has one, of its additive component
+/// otherwise. Outputs share the error model's shape, not its magnitude.
+pub(crate) fn output_variance(params: &ModelParameters, compartment: usize, prediction: f64) -> f64 {
+    let variance = params.output_residual_variances[&compartment];
+    if params.proportional_variance > 0.0 {
+        (params.residual_variance + variance * proportional_scale(params, prediction)).max(MIN_OBSERVATION_VARIANCE)
+    } else {
+        variance.max(MIN_OBSERVATION_VARIANCE)
+    }
+}
+
+/// An output's residual (on the log scale under the exponential model)
+/// rescaled so that its mean square estimates the output's variance: less
+/// the shared additive component and over the proportional scale when the
+/// error model has a proportional component.
+pub(crate) fn output_variance_residual(params: &ModelParameters, residual: f64, prediction: f64) -> f64 {
+    if params.proportional_variance > 0.0 {
+        let scale = proportional_scale(params, prediction).max(MIN_OBSERVATION_VARIANCE);
+        ((residual * residual - params.residual_variance).max(0.0) / scale).sqrt()
+    } else {
+        residual
+    }
+}
+
+/// Residual variance of an observation with the given prediction: the
+/// effect variance for an effect observation when one is set, its output's
+/// variance for a concentration in a separately observed output, the
+/// concentration error model otherwise.
+pub(crate) fn observation_variance(params: &ModelParameters, obs: &Observation, prediction: f64) -> f64 {
+    match (&obs.observation_type, params.effect_residual_variance) {
+        (ObservationType::Effect, Some(variance)) => variance.max(MIN_OBSERVATION_VARIANCE),
+        _ => match separate_output(params, obs) {
+            Some(compartment) => output_variance(params, compartment, prediction),
+            None => concentration_variance(params, prediction),
+        },
+    }
+}
+
+/// Whether the observation's residual is taken on the log scale: a
+/// concentration, in any output, under the exponential error model.
+pub(crate) fn on_log_scale(params: &ModelParameters, obs: &Observation) -> bool {
+    params.log_scale_residuals && obs.observation_type == ObservationType::Concentration
+}
+
+/// ln of `value`, floored so zero and negative values stay finite.
+pub(crate) fn log_scale_value(value: f64) -> f64 {
+    value.max(MIN_LOG_SCALE_VALUE).ln()
+}
+
+/// ln(DV) − ln(PRED), the residual of the exponential error model.
+pub(crate) fn log_scale_residual(obs: &Observation, prediction: f64) -> f64 {
+    log_scale_value(obs.value) - log_scale_value(prediction)
+}
+
+fn log_scale_observation(obs: &Observation) -> Observation {
+    Observation { value: log_scale_value(obs.value), ..obs.clone() }
+}
+
+/// -2 log-likelihood of one observation under the error model of `params`.
+/// On the log scale this is the density of ln(DV) plus the Jacobian
+/// 2·ln(DV) of a measured value, so it stays a density of DV and the OFV is
+/// comparable with the other error models'.
+pub(crate) fn observation_objective(params: &ModelParameters, obs: &Observation, prediction: f64) -> f64 {
+    let variance = observation_variance(params, obs, prediction);
+    if !on_log_scale(params, obs) {
+        return censoring::observation_objective(obs, prediction, variance);
+    }
+    let jacobian = if obs.is_censored() { 0.0 } else { 2.0 * log_scale_value(obs.value) };
+    censoring::observation_objective(&log_scale_observation(obs), log_scale_value(prediction), variance) + jacobian
+}
+
+/// d log-likelihood / d prediction of one observation under the error model
+/// of `params`.
+pub(crate) fn observation_score(params: &ModelParameters, obs: &Observation, prediction: f64) -> f64 {
+    let variance = observation_variance(params, obs, prediction);
+    if !on_log_scale(params, obs) {
+        return censoring::observation_score(obs, prediction, variance);
+    }
+    censoring::observation_score(&log_scale_observation(obs), log_scale_value(prediction), variance)
+        / prediction.max(MIN_LOG_SCALE_VALUE)
+}
+
+/// Gauss–Newton weight of one observation on the prediction under the error
+/// model of `params`; on the log scale, the log-scale weight over PRED².
+pub(crate) fn observation_weight(params: &ModelParameters, obs: &Observation, prediction: f64) -> f64 {
+    let variance = observation_variance(params, obs, prediction);
+    if !on_log_scale(params, obs) {
+        return censoring::observation_weight(obs, prediction, variance);
+    }
+    censoring::observation_weight(&log_scale_observation(obs), log_scale_value(prediction), variance)
+        / prediction.max(MIN_LOG_SCALE_VALUE).powi(2)
+}
+
+/// The output compartment of a concentration observed in one with its own
+/// residual variance.
+pub(crate) fn separate_output(params: &ModelParameters, obs: &Observation) -> Option<usize> {
+    let compartment = obs.compartment as usize;
+    (obs.observation_type == ObservationType::Concentration && params.output_residual_variances.contains_key(&compartment))
+        .then_some(compartment)
+}
+
+/// Gives each of the model's output compartments observed in the dataset
+/// its own residual variance, starting where the concentrations' does.
+pub(crate) fn initialize_output_variances(model: &CompartmentModel, dataset: &Dataset, params: &mut ModelParameters) {
+    for compartment in model.output_compartments() {
+        let observed = dataset.individuals().values()
+            .flat_map(|individual| individual.observations())
+            .any(|obs| obs.observation_type == ObservationType::Concentration && obs.compartment as usize == compartment);
+        if observed {
+            params.output_residual_variances.entry(compartment).or_insert(params.residual_variance);
+        }
+    }
+}
+
+/// The joint density p(y, eta) of one individual and its Gauss-Newton
+/// curvature, shared by the Laplace and quadrature integrals over eta.
+struct JointDensity<'a> {
+    model: &'a CompartmentModel,
+    solver: &'a dyn OdeSolver,
+    solver_config: &'a SolverConfig,
+    individual: &'a Individual,
+    population: &'a ModelParameters,
+    omega_inv: DMatrix<f64>,
+    omega_log_det: f64,
+}
+
+impl<'a> JointDensity<'a> {
+    /// `None` when Omega is not positive definite.
+    fn new(
+        model: &'a CompartmentModel,
+        solver: &'a dyn OdeSolver,
+        solver_config: &'a SolverConfig,
+        individual: &'a Individual,
+        population: &'a ModelParameters,
+    ) -> Option<Self> {
+        let n = population.n_parameters();
+        let omega = DMatrix::from_fn(n, n, |i, j| population.random_effects_variance[i][j]);
+        let omega_chol = omega.cholesky()?;
+        Some(Self {
+            model,
+            solver,
+            solver_config,
+            individual,
+            population,
+            omega_inv: omega_chol.inverse(),
+            omega_log_det: 2.0 * omega_chol.l().diagonal().iter().map(|d| d.ln()).sum::<f64>(),
+        })
+    }
+
+    fn predict(&self, eta: &[f64]) -> Result<Vec<f64>> {
+        let mut params = self.population.clone();
+        for (theta, eta) in params.fixed_effects.iter_mut().zip(eta) {
+            *theta += eta;
+        }
+        prediction::predict_individual(self.model, self.solver, self.individual, &params, self.solver_config)
+    }
+
+    /// -2 log p(y, eta) and the predictions at `eta`.
+    fn objective(&self, eta: &[f64]) -> Result<(f64, Vec<f64>)> {
+        let predictions = self.predict(eta)?;
+        let mut objective = 0.0;
+        for (obs, pred) in self.individual.observations().iter().zip(&predictions) {
+            objective += observation_objective(self.population, obs, *pred);
+        }
+        let eta_vector = DVector::from_column_slice(eta);
+        objective += (eta_vector.transpose() * &self.omega_inv * &eta_vector)[(0, 0)];
+        objective += eta.len() as f64 * (2.0 * std::f64::consts::PI).ln() + self.omega_log_det;
+        Ok((objective, predictions))
+    }
+
+    /// Curvature of -log p(y, eta): J' W J + Omega^-1.
+    fn curvature(&self, eta: &[f64], predictions: &[f64]) -> Result<DMatrix<f64>> {
+        let n = eta.len();
+        let mut sensitivities = DMatrix::zeros(predictions.len(), n);
+        for k in 0..n {
+            let mut eta_plus = eta.to_vec();
+            eta_plus[k] += SENSITIVITY_STEP;
+            for (row, (plus, pred)) in self.predict(&eta_plus)?.iter().zip(predictions).enumerate() {
+                sensitivities[(row, k)] = (plus - pred) / SENSITIVITY_STEP;
+            }
+        }
+        let weights = DMatrix::from_diagonal(&DVector::from_iterator(
+            predictions.len(),
+            self.individual.observations().iter().zip(predictions)
+                .map(|(obs, pred)| observation_weight(self.population, obs, *pred)),
+        ));
+        Ok(sensitivities.transpose() * weights * &sensitivities + &self.omega_inv)
+    }
+}
+
+/// -2 × Laplace-approximated marginal log-likelihood of one individual, with
+/// `eta` the deviations of its log-scale parameters from the population
+/// values. Infinite when Omega or the curvature is not positive definite.
+pub fn individual_objective(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    solver_config: &SolverConfig,
+    individual: &Individual,
+    population: &ModelParameters,
+    eta: &[f64],
+) -> Result<f64> {
+    let Some(density) = JointDensity::new(model, solver, solver_config, individual, population) else {
+        return Ok(f64::INFINITY);
+    };
+    let (objective, predictions) = density.objective(eta)?;
+    let Some(curvature_chol) = density.curvature(eta, &predictions)?.cholesky() else {
+        return Ok(f64::INFINITY);
+    };
+
+    // log det(curvature / 2π) from the Laplace integral over eta
+    let log_det = 2.0 * curvature_chol.l().diagonal().iter().map(|d| d.ln()).sum::<f64>();
+    Ok(objective + log_det - eta.len() as f64 * (2.0 * std::f64::consts::PI).ln())
+}
+
+/// Rejects an AGQ grid of `n_nodes` per eta over `n_eta` etas with more than
+/// `MAX_AGQ_GRID_POINTS` points.
+pub fn check_agq_grid(n_nodes: usize, n_eta: usize) -> Result<()> {
+    match n_nodes.checked_pow(n_eta as u32) {
+        Some(points) if points <= MAX_AGQ_GRID_POINTS => Ok(()),
+        _ => Err(anyhow!(
+            "AGQ with {} nodes for each of {} etas needs {}^{} predictions per individual, more than {}; use fewer nodes",
+            n_nodes, n_eta, n_nodes, n_eta, MAX_AGQ_GRID_POINTS
+        )),
+    }
+}
+
+/// Nodes and weights of the `n`-point Gauss–Hermite rule for ∫ f(z) e^(-z²) dz,
+/// from the eigen-decomposition of the Hermite Jacobi matrix (Golub–Welsch).
+pub fn gauss_hermite(n: usize) -> (Vec<f64>, Vec<f64>) {
+    let jacobi = DMatrix::from_fn(n, n, |i, j| {
+        if i + 1 == j || j + 1 == i {
+            (i.max(j) as f64 / 2.0).sqrt()
+        } else {
+            0.0
+        }
+    });
+    let eigen = jacobi.symmetric_eigen();
+    let mut rule: Vec<(f64, f64)> = (0..n)
+        .map(|k| (eigen.eigenvalues[k], std::f64::consts::PI.sqrt() * eigen.eigenvectors[(0, k)].powi(2)))
+        .collect();
+    rule.sort_by(|a, b| a.0.total_cmp(&b.0));
+    rule.into_iter().unzip()
+}
+
+/// -2 × marginal log-likelihood of one individual by adaptive Gauss–Hermite
+/// quadrature with `n_nodes` nodes per eta. The grid is centred on `eta`,
+/// ideally the conditional mode, and scaled by the inverse curvature there;
+/// one node reproduces `individual_objective`. Costs `n_nodes^n_eta`
+/// predictions.
+pub fn individual_objective_agq(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    solver_config: &SolverConfig,
+    individual: &Individual,
+    population: &ModelParameters,
+    eta: &[f64],
+    n_nodes: usize,
+) -> Result<f64> {
+    let Some(density) = JointDensity::new(model, solver, solver_config, individual, population) else {
+        return Ok(f64::INFINITY);
+    };
+    let (_, predictions) = density.objective(eta)?;
+    let Some(curvature_chol) = density.curvature(eta, &predictions)?.cholesky() else {
+        return Ok(f64::INFINITY);
+    };
+    // eta = mode + √2 R z with R R' = curvature⁻¹, i.e. R = L'⁻¹
+    let n = eta.len();
+    let Some(scale) = curvature_chol.l().transpose().try_inverse() else {
+        return Ok(f64::INFINITY);
+    };
+    let log_det_scale = -curvature_chol.l().diagonal().iter().map(|d| d.ln()).sum::<f64>();
+    let (nodes, weights) = gauss_hermite(n_nodes);
+
+    // log of each grid point's term w·e^(z'z)·p(y, eta), combined by log-sum-exp
+    let mut log_terms = Vec::with_capacity(n_nodes.pow(n as u32));
+    let mut index = vec![0; n];
+    loop {
+        let z = DVector::from_iterator(n, index.iter().map(|&k| nodes[k]));
+        let point = DVector::from_column_slice(eta) + std::f64::consts::SQRT_2 * &scale * &z;
+        let (objective, _) = density.objective(point.as_slice())?;
+        let log_weight: f64 = index.iter().map(|&k| weights[k].ln()).sum();
+        log_terms.push(log_weight + z.norm_squared() - objective / 2.0);
+
+        // Advance the multi-index over the tensor grid
+        let Some(k) = (0..n).find(|&k| index[k] + 1 < n_nodes) else {
+            break;
+        };
+        index[k] += 1;
+        for slot in index.iter_mut().take(k) {
+            *slot = 0;
+        }
+    }
+    let max = log_terms.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if !max.is_finite() {
+        return Ok(f64::INFINITY);
+    }
+    let log_sum = max + log_terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln();
+    let log_integral = n as f64 / 2.0 * 2.0_f64.ln() + log_det_scale + log_sum;
+    Ok(-2.0 * log_integral)
+}
+
+/// The n·ln(2π) the OFV includes for the n measured observations, each
+/// counted as often as its individual's weight; the 2π constants of the eta
+/// prior cancel against the Laplace correction, and a censored observation
+/// contributes a probability with no constant.
+pub fn likelihood_constant(dataset: &Dataset) -> f64 {
+    let n_measured: f64 = dataset.individuals().values()
+        .map(|individual| {
+            let measured = individual.observations().iter().filter(|obs| !obs.is_censored()).count();
+            individual.weight() * measured as f64
+        })
+        .sum();
+    n_measured * (2.0 * std::f64::consts::PI).ln()
+}
+
+/// OFV summed over individuals, each weighted by its FREQ. Individuals
+/// without etas are evaluated at the population values.
+pub fn objective_function_value(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    solver_config: &SolverConfig,
+    dataset: &Dataset,
+    population: &ModelParameters,
+    etas: &HashMap<i32, Vec<f64>>,
+) -> Result<f64> {
+    let zero = vec![0.0; population.n_parameters()];
+    let mut objective = 0.0;
+    for (id, individual) in dataset.individuals() {
+        let eta = etas.get(id).unwrap_or(&zero);
+        objective += individual.weight() * individual_objective(model, solver, solver_config, individual, population, eta)?;
+    }
+    Ok(objective)
+}
+
+/// OFV summed over individuals, each weighted by its FREQ, by adaptive
+/// Gauss–Hermite quadrature around each individual's etas. Individuals without etas are centred on the
+/// population values. Fails when the grid exceeds `MAX_AGQ_GRID_POINTS`.
+pub fn agq_objective_function_value(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    solver_config: &SolverConfig,
+    dataset: &Dataset,
+    population: &ModelParameters,
+    etas: &HashMap<i32, Vec<f64>>,
+    n_nodes: usize,
+) -> Result<f64> {
+    check_agq_grid(n_nodes, population.n_parameters())?;
+    let zero = vec![0.0; population.n_parameters()];
+    let mut objective = 0.0;
+    for (id, individual) in dataset.individuals() {
+        let eta = etas.get(id).unwrap_or(&zero);
+        objective += individual.weight() * individual_objective_agq(model, solver, solver_config, individual, population, eta, n_nodes)?;
+    }
+    Ok(objective)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingRecord, DosingType, Observation, ObservationType};
+    use crate::models::ModelType;
+    use crate::solver::RungeKuttaSolver;
+
+    #[test]
+    fn test_negligible_omega_gives_residual_density() {
+        // With negligible Omega the marginal likelihood is the residual
+        // density at the population prediction
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let mut population = model.default_parameters();
+        population.residual_variance = 0.5;
+        population.random_effects_variance = vec![vec![1e-8, 0.0], vec![0.0, 1e-8]];
+
+        let observation = Observation::new(2.0, 7.0, 1, ObservationType::Concentration);
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, vec![observation], doses, HashMap::new());
+
+        let objective = individual_objective(&model, &solver, &SolverConfig::default(), &individual, &population, &[0.0, 0.0]).unwrap();
+        let pred = prediction::predict_individual(&model, &solver, &individual, &population, &SolverConfig::default())
+            .unwrap()[0];
+        let expected = (7.0 - pred).powi(2) / 0.5 + (2.0 * std::f64::consts::PI * 0.5).ln();
+        assert!((objective - expected).abs() < 1e-3, "{} vs {}", objective, expected);
+    }
+
+    #[test]
+    fn test_output_compartment_observations_use_their_own_variance() {
+        let model = CompartmentModel::new(ModelType::ParentMetabolite).unwrap();
+        let mut params = model.default_parameters();
+        params.proportional_variance = 0.04;
+        params.output_residual_variances.insert(2, 0.25);
+
+        let parent = Observation::new(1.0, 5.0, 1, ObservationType::Concentration);
+        let metabolite = Observation::new(1.0, 5.0, 2, ObservationType::Concentration);
+        assert_eq!(separate_output(&params, &parent), None);
+        assert_eq!(separate_output(&params, &metabolite), Some(2));
+        // The output's variance takes the place of the proportional component
+        assert!((observation_variance(&params, &metabolite, 10.0) - (0.01 + 0.25 * 100.0)).abs() < 1e-12);
+        assert!((observation_variance(&params, &parent, 10.0) - (0.01 + 0.04 * 100.0)).abs() < 1e-12);
+        assert_eq!(output_variance_residual(&params, 5.0, 10.0), ((25.0 - 0.01) / 100.0_f64).sqrt());
+
+        // and of the additive one without a proportional component
+        params.proportional_variance = 0.0;
+        assert_eq!(observation_variance(&params, &metabolite, 10.0), 0.25);
+        assert_eq!(output_variance_residual(&params, -5.0, 10.0), -5.0);
+
+        // Under the exponential model outputs are on the log scale as well
+        params.log_scale_residuals = true;
+        assert!(on_log_scale(&params, &metabolite));
+    }
+
+    #[test]
+    fn test_log_scale_objective_is_lognormal_density_with_matching_score() {
+        let mut params = CompartmentModel::new(ModelType::OneCompartment).unwrap().default_parameters();
+        params.residual_variance = 0.04;
+        params.log_scale_residuals = true;
+        let obs = Observation::new(1.0, 8.0, 1, ObservationType::Concentration);
+
+        // -2 ln of the log-normal density of DV
+        let pred = 10.0_f64;
+        let expected = (8.0_f64.ln() - pred.ln()).powi(2) / 0.04
+            + (2.0 * std::f64::consts::PI * 0.04).ln() + 2.0 * 8.0_f64.ln();
+        assert!((observation_objective(&params, &obs, pred) - expected).abs() < 1e-12);
+
+        let h = 1e-5;
+        let numeric = -0.5 * (observation_objective(&params, &obs, pred + h) - observation_objective(&params, &obs, pred - h)) / (2.0 * h);
+        assert!((observation_score(&params, &obs, pred) - numeric).abs() < 1e-6);
+        assert!((observation_weight(&params, &obs, pred) - 1.0 / (0.04 * pred * pred)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_gauss_hermite_rule_is_exact_for_low_degree_polynomials() {
+        let (nodes, weights) = gauss_hermite(3);
+        assert!((nodes[0] + 1.5_f64.sqrt()).abs() < 1e-12 && nodes[1].abs() < 1e-12);
+        // ∫ e^(-z²) = √π, ∫ z² e^(-z²) = √π/2, ∫ z⁴ e^(-z²) = 3√π/4
+        let moment = |p: i32| nodes.iter().zip(&weights).map(|(z, w)| w * z.powi(p)).sum::<f64>();
+        let sqrt_pi = std::f64::consts::PI.sqrt();
+        assert!((moment(0) - sqrt_pi).abs() < 1e-12);
+        assert!((moment(2) - sqrt_pi / 2.0).abs() < 1e-12);
+        assert!((moment(4) - 3.0 * sqrt_pi / 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_agq_starts_at_laplace_and_converges_with_nodes() {
+        // Two sparse observations and a wide Omega make the posterior of the
+        // etas clearly non-Gaussian
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let mut population = model.default_parameters();
+        population.residual_variance = 0.5;
+        population.random_effects_variance = vec![vec![0.5, 0.0], vec![0.0, 0.5]];
+        let observations = vec![
+            Observation::new(1.0, 6.0, 1, ObservationType::Concentration),
+            Observation::new(12.0, 1.5, 1, ObservationType::Concentration),
+        ];
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+        let eta = [0.1, -0.1];
+
+        let laplace = individual_objective(&model, &solver, &SolverConfig::default(), &individual, &population, &eta).unwrap();
+        let agq = |n_nodes| individual_objective_agq(&model, &solver, &SolverConfig::default(), &individual, &population, &eta, n_nodes).unwrap();
+        assert!((agq(1) - laplace).abs() < 1e-9, "{} vs {}", agq(1), laplace);
+
+        let objectives: Vec<f64> = [1, 3, 5, 7, 9].iter().map(|&n| agq(n)).collect();
+        let changes: Vec<f64> = objectives.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+        assert!(changes[0] > 1e-3, "quadrature should correct the Laplace OFV: {:?}", objectives);
+        assert!(changes.windows(2).all(|c| c[1] < c[0]), "{:?}", objectives);
+        assert!(changes[changes.len() - 1] < 0.1 * changes[0], "{:?}", objectives);
+    }
+
+    #[test]
+    fn test_agq_grid_bounded() {
+        assert!(check_agq_grid(5, 5).is_ok());
+        assert!(check_agq_grid(10, 4).is_ok());
+        let message = check_agq_grid(7, 6).unwrap_err().to_string();
+        assert!(message.contains("7^6"), "{}", message);
+        // Overflowing the count is rejected too
+        assert!(check_agq_grid(1000, 20).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use nalgebra::DMatrix;
+
+/// Smallest eigenvalue Omega may have after an update, which also floors
+/// the variance of an eta that barely varies.
+pub const MIN_OMEGA_EIGENVALUE: f64 = 1e-6;
+
+/// Fewest individuals from which Omega is estimated. A single subject's
+/// etas say nothing about the spread between subjects, and the update would
+/// collapse Omega onto them, so below this it is held at its initial value.
+pub const MIN_INDIVIDUALS_FOR_OMEGA: usize = 2;
+
+/// Relative tolerance on the asymmetry and negative eigenvalues a supplied
+/// Omega may have from rounding in the file it came from.
+const OMEGA_VALIDATION_TOLERANCE: f64 = 1e-9;
+
+/// Checks a user-supplied Omega for `n_parameters` parameters: square of
+/// that size, finite, symmetric and positive definite, as the eta prior
+/// needs its inverse and log-determinant. The error names the offending
+/// entries, or for an indefinite matrix its negative eigenvalue and the
+/// parameters (by index) that dominate its eigenvector.
+pub fn validate_omega(omega: &[Vec<f64>], n_parameters: usize) -> Result<(), String> {
+    if omega.len() != n_parameters {
+        return Err(format!("Omega has {} rows for {} parameters", omega.len(), n_parameters));
+    }
+    for (i, row) in omega.iter().enumerate() {
+        if row.len() != n_parameters {
+            return Err(format!("Omega row {} has {} entries for {} parameters", i, row.len(), n_parameters));
+        }
+        if let Some(j) = row.iter().position(|value| !value.is_finite()) {
+            return Err(format!("Omega[{}][{}] is {}", i, j, row[j]));
+        }
+    }
+
+    let scale = omega.iter().flatten().fold(0.0_f64, |m, value| m.max(value.abs()));
+    let tolerance = OMEGA_VALIDATION_TOLERANCE * scale.max(f64::MIN_POSITIVE);
+    for (i, row) in omega.iter().enumerate() {
+        for (j, &upper) in row.iter().enumerate().skip(i + 1) {
+            let lower = omega[j][i];
+            if (upper - lower).abs() > tolerance {
+                return Err(format!("Omega is not symmetric: Omega[{}][{}] = {} but Omega[{}][{}] = {}",
+                                   i, j, upper, j, i, lower));
+            }
+        }
+    }
+    if let Some(i) = (0..n_parameters).find(|&i| omega[i][i] <= 0.0) {
+        return Err(format!("Omega[{}][{}] = {} is not a positive variance", i, i, omega[i][i]));
+    }
+
+    let matrix = DMatrix::from_fn(n_parameters, n_parameters, |i, j| omega[i][j]);
+    let eigen = matrix.clone().symmetric_eigen();
+    let (k, &smallest) = eigen.eigenvalues.iter().enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap_or((0, &0.0));
+    if smallest < -tolerance {
+        let vector = eigen.eigenvectors.column(k);
+        let involved: Vec<String> = (0..n_parameters)
+            .filter(|&i| vector[i].abs() > 0.1)
+            .map(|i| i.to_string())
+            .collect();
+        return Err(format!(
+            "Omega is not positive definite: eigenvalue {} along parameters {}; the covariances among them exceed what their variances allow",
+            smallest, involved.join(", ")
+        ));
+    }
+    if matrix.cholesky().is_none() {
+        return Err(format!(
+            "Omega is singular (smallest eigenvalue {}): some etas are perfectly correlated; it must be positive definite",
+            smallest
+        ));
+    }
+    Ok(())
+}
+
+/// Nearest positive-definite matrix to `omega` (symmetrized) in the
+/// Frobenius norm with eigenvalues of at least `floor`: the eigenvalues
+/// below `floor` are raised to it and the eigenvectors kept. Also returns
+/// whether `omega` was indefinite, i.e. had a negative eigenvalue beyond
+/// rounding, as moment matching on a finite sample can give; raising small
+/// positive eigenvalues is not reported.
+pub fn nearest_positive_definite(omega: &[Vec<f64>], floor: f64) -> (Vec<Vec<f64>>, bool) {
+    let n = omega.len();
+    let symmetric = DMatrix::from_fn(n, n, |i, j| 0.5 * (omega[i][j] + omega[j][i]));
+    let eigen = symmetric.clone().symmetric_eigen();
+    // Eigenvalues of a singular matrix can come out slightly negative from
+    // rounding alone
+    let scale = eigen.eigenvalues.iter().fold(0.0_f64, |m, lambda| m.max(lambda.abs()));
+    let tolerance = n as f64 * f64::EPSILON * scale;
+    let indefinite = eigen.eigenvalues.iter().any(|&lambda| lambda < -tolerance);
+    if eigen.eigenvalues.iter().all(|&lambda| lambda >= floor) {
+        let projected = (0..n).map(|i| (0..n).map(|j| symmetric[(i, j)]).collect()).collect();
+        return (projected, indefinite);
+    }
+
+    let floored = eigen.eigenvalues.map(|lambda| lambda.max(floor));
+    let vectors = &eigen.eigenvectors;
+    let projected = vectors * DMatrix::from_diagonal(&floored) * vectors.transpose();
+    // Symmetric up to rounding; average so the result is exactly symmetric
+    let projected = (0..n)
+        .map(|i| (0..n).map(|j| 0.5 * (projected[(i, j)] + projected[(j, i)])).collect())
+        .collect();
+    (projected, indefinite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_initial_omega_rejected() {
+        let asymmetric = vec![vec![0.09, 0.01], vec![0.02, 0.04]];
+        let message = validate_omega(&asymmetric, 2).unwrap_err();
+        assert!(message.contains("Omega[0][1] = 0.01 but Omega[1][0] = 0.02"), "{}", message);
+
+        // Correlation above one
+        let indefinite = vec![vec![0.09, 0.12, 0.0], vec![0.12, 0.09, 0.0], vec![0.0, 0.0, 0.04]];
+        let message = validate_omega(&indefinite, 3).unwrap_err();
+        assert!(message.contains("not positive definite") && message.contains("parameters 0, 1"), "{}", message);
+
+        assert!(validate_omega(&[vec![0.09]], 2).is_err());
+        assert!(validate_omega(&[vec![-0.09]], 1).is_err());
+
+        // Singular matrices have no inverse for the eta prior: a zero
+        // variance, or a correlation of exactly one
+        let message = validate_omega(&[vec![0.09, 0.0], vec![0.0, 0.0]], 2).unwrap_err();
+        assert!(message.contains("Omega[1][1] = 0 is not a positive variance"), "{}", message);
+        let message = validate_omega(&[vec![0.09, 0.06], vec![0.06, 0.04]], 2).unwrap_err();
+        assert!(message.contains("singular"), "{}", message);
+        assert_eq!(validate_omega(&[vec![0.09, 0.01], vec![0.01, 0.04]], 2), Ok(()));
+    }
+
+    #[test]
+    fn test_indefinite_update_projected_to_positive_definite() {
+        // Correlation above one makes the matrix indefinite: eigenvalues
+        // 0.09 ± 0.12
+        let omega = vec![vec![0.09, 0.12], vec![0.12, 0.09]];
+        let (projected, indefinite) = nearest_positive_definite(&omega, MIN_OMEGA_EIGENVALUE);
+        assert!(indefinite);
+
+        let matrix = DMatrix::from_fn(2, 2, |i, j| projected[i][j]);
+        assert_eq!(matrix, matrix.transpose());
+        assert!(matrix.clone().cholesky().is_some());
+        let eigenvalues = matrix.symmetric_eigen().eigenvalues;
+        assert!(eigenvalues.iter().all(|&lambda| lambda >= MIN_OMEGA_EIGENVALUE * (1.0 - 1e-9)));
+        // The positive direction is kept: (1, 1)/√2 with eigenvalue 0.21
+        assert!((projected[0][0] - (0.21 + MIN_OMEGA_EIGENVALUE) / 2.0).abs() < 1e-12);
+        assert!((projected[0][1] - (0.21 - MIN_OMEGA_EIGENVALUE) / 2.0).abs() < 1e-12);
+
+        // A positive-definite matrix is left alone
+        let omega = vec![vec![0.09, 0.01], vec![0.01, 0.04]];
+        assert_eq!(nearest_positive_definite(&omega, MIN_OMEGA_EIGENVALUE), (omega, false));
+    }
+}
@@ -0,0 +1,166 @@
+use super::{ErrorModel, EstimationConfig};
+use crate::models::ModelParameters;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Rise in OFV above the profile's minimum that bounds a 95% confidence
+/// interval: the 95th percentile of χ² with one degree of freedom.
+pub const PROFILE_DELTA_OFV_95: f64 = 3.84;
+
+/// The refit with one fixed effect held at a value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfilePoint {
+    /// Natural-scale value the parameter was held at.
+    pub value: f64,
+    pub objective_function_value: f64,
+    pub converged: bool,
+}
+
+/// OFV of a fit as a function of one fixed effect, with the remaining
+/// parameters re-estimated at each value, and the confidence interval read
+/// from where it rises `PROFILE_DELTA_OFV_95` above its minimum.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LikelihoodProfile {
+    pub parameter: String,
+    /// In ascending order of value.
+    pub points: Vec<ProfilePoint>,
+    /// Lower 95% bound, interpolated between the two profile points either
+    /// side of the crossing; `None` when the profile stays below the
+    /// threshold down to its lowest value.
+    pub lower: Option<f64>,
+    /// Upper 95% bound; `None` when the profile does not reach the
+    /// threshold above the minimum.
+    pub upper: Option<f64>,
+}
+
+impl LikelihoodProfile {
+    pub fn new(parameter: impl Into<String>, mut points: Vec<ProfilePoint>) -> Self {
+        points.sort_by(|a, b| a.value.total_cmp(&b.value));
+        let mut profile = Self { parameter: parameter.into(), points, lower: None, upper: None };
+        if let Some(minimum) = profile.minimum_index() {
+            let threshold = profile.points[minimum].objective_function_value + PROFILE_DELTA_OFV_95;
+            let crossing = |inside: &ProfilePoint, outside: &ProfilePoint| {
+                if outside.objective_function_value < threshold {
+                    return None;
+                }
+                let fraction = (threshold - inside.objective_function_value)
+                    / (outside.objective_function_value - inside.objective_function_value);
+                Some(inside.value + fraction * (outside.value - inside.value))
+            };
+            profile.lower = (1..=minimum).rev()
+                .find_map(|k| crossing(&profile.points[k], &profile.points[k - 1]));
+            profile.upper = (minimum..profile.points.len() - 1)
+                .find_map(|k| crossing(&profile.points[k], &profile.points[k + 1]));
+        }
+        profile
+    }
+
+    /// Index of the point with the lowest finite OFV.
+    pub fn minimum_index(&self) -> Option<usize> {
+        self.points.iter().enumerate()
+            .filter(|(_, point)| point.objective_function_value.is_finite())
+            .min_by(|a, b| a.1.objective_function_value.total_cmp(&b.1.objective_function_value))
+            .map(|(k, _)| k)
+    }
+
+    /// OFV of each point above the profile's minimum.
+    pub fn delta_objective(&self) -> Vec<f64> {
+        let minimum = self.minimum_index()
+            .map_or(f64::NAN, |k| self.points[k].objective_function_value);
+        self.points.iter().map(|point| point.objective_function_value - minimum).collect()
+    }
+}
+
+/// `n_points` natural-scale values evenly spaced on the log scale within
+/// `log_span` either side of `estimate` (a log-scale fixed effect), the
+/// middle one the estimate itself when `n_points` is odd.
+pub fn profile_grid(estimate: f64, log_span: f64, n_points: usize) -> Vec<f64> {
+    if n_points < 2 {
+        return vec![estimate.exp(); n_points];
+    }
+    (0..n_points)
+        .map(|k| (estimate - log_span + 2.0 * log_span * k as f64 / (n_points - 1) as f64).exp())
+        .collect()
+}
+
+/// Profiles fixed effect `param_index` of `estimate` over the natural-scale
+/// `values`: each value is refitted by `fit` under `config` started from
+/// `estimate`, with the parameter held at the value, and `fit` returns the
+/// refit's OFV and whether it converged. Shared by the estimators'
+/// `profile_likelihood`.
+pub(crate) fn profile_likelihood(
+    parameter_names: &[String],
+    config: &EstimationConfig,
+    estimate: &ModelParameters,
+    param_index: usize,
+    values: &[f64],
+    mut fit: impl FnMut(EstimationConfig) -> Result<(f64, bool)>,
+) -> Result<LikelihoodProfile> {
+    let name = parameter_names.get(param_index)
+        .ok_or_else(|| anyhow!("Parameter index {} out of range for {} parameters", param_index, parameter_names.len()))?;
+    if estimate.n_parameters() != parameter_names.len() {
+        return Err(anyhow!("Expected {} parameters for the model, got {}", parameter_names.len(), estimate.n_parameters()));
+    }
+    if let Some(&value) = values.iter().find(|&&value| !(value > 0.0 && value.is_finite())) {
+        return Err(anyhow!("Profile values of {} must be positive and finite, got {}", name, value));
+    }
+
+    let mut points = Vec::with_capacity(values.len());
+    for &value in values {
+        let mut initial = estimate.fixed_effects.clone();
+        initial[param_index] = value.ln();
+        let initial_residual_variance = match config.error_model {
+            ErrorModel::Proportional | ErrorModel::Power => estimate.proportional_variance,
+            _ => estimate.residual_variance,
+        };
+        let point_config = config.clone()
+            .with_initial_fixed_effects(Some(initial))
+            .with_initial_omega(Some(estimate.random_effects_variance.clone()))
+            .with_initial_residual_variance(Some(initial_residual_variance))
+            .with_fixed_parameter(name.clone());
+        let (objective_function_value, converged) = fit(point_config)?;
+        points.push(ProfilePoint { value, objective_function_value, converged });
+    }
+    Ok(LikelihoodProfile::new(name.clone(), points))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(value: f64, objective_function_value: f64) -> ProfilePoint {
+        ProfilePoint { value, objective_function_value, converged: true }
+    }
+
+    #[test]
+    fn test_interval_interpolated_at_threshold_and_open_when_not_crossed() {
+        // Quadratic in the value, minimum 10 at 2: ΔOFV = 3.84 at 2 ± 1.96
+        let points = (0..=8).map(|k| {
+            let value = 0.5 * k as f64;
+            point(value, 10.0 + (value - 2.0).powi(2))
+        }).rev().collect();
+        let profile = LikelihoodProfile::new("CL", points);
+        assert_eq!(profile.minimum_index(), Some(4));
+        assert!(profile.points.windows(2).all(|w| w[0].value < w[1].value));
+        assert_eq!(profile.delta_objective()[4], 0.0);
+        let (lower, upper) = (profile.lower.unwrap(), profile.upper.unwrap());
+        // The chord between grid points lies above the convex profile, so
+        // the interpolated interval is slightly narrow
+        assert!(lower < 2.0 - 1.9 && lower > 2.0 - 2.0, "lower {}", lower);
+        assert!(upper > 2.0 + 1.9 && upper < 2.0 + 2.0, "upper {}", upper);
+
+        // A flat side leaves that bound open
+        let profile = LikelihoodProfile::new("V", vec![point(1.0, 11.0), point(2.0, 10.0), point(3.0, 20.0)]);
+        assert_eq!(profile.lower, None);
+        assert!((profile.upper.unwrap() - (2.0 + 3.84 / 10.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_profile_grid_centered_on_estimate() {
+        let grid = profile_grid(2.0_f64.ln(), 0.5, 5);
+        assert_eq!(grid.len(), 5);
+        assert!((grid[2] - 2.0).abs() < 1e-12);
+        assert!((grid[0] - 2.0 * (-0.5_f64).exp()).abs() < 1e-12);
+        assert!((grid[4] - 2.0 * 0.5_f64.exp()).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,69 @@
+use log::info;
+use std::time::{Duration, Instant};
+
+/// Wall-clock time of each estimation iteration, with an estimated time to
+/// completion logged every `interval` iterations. The ETA assumes the
+/// remaining iterations take as long as the mean so far, so it is an upper
+/// bound when the run converges early.
+pub struct IterationTimer {
+    interval: usize,
+    max_iterations: usize,
+    lap_start: Instant,
+    times: Vec<Duration>,
+}
+
+impl IterationTimer {
+    pub fn new(interval: usize, max_iterations: usize) -> Self {
+        Self {
+            interval,
+            max_iterations,
+            lap_start: Instant::now(),
+            times: Vec::with_capacity(max_iterations),
+        }
+    }
+
+    /// Records the iteration that just finished and restarts the clock.
+    pub fn lap(&mut self, label: &str) {
+        let now = Instant::now();
+        self.times.push(now - self.lap_start);
+        self.lap_start = now;
+
+        let completed = self.times.len();
+        if self.interval > 0 && completed.is_multiple_of(self.interval) {
+            let elapsed: Duration = self.times.iter().sum();
+            let remaining = self.max_iterations.saturating_sub(completed) as u32;
+            let eta = elapsed / completed as u32 * remaining;
+            info!("{} iteration {}/{}: {:.3}s per iteration, elapsed {}, ETA {}",
+                  label, completed, self.max_iterations,
+                  elapsed.as_secs_f64() / completed as f64,
+                  format_duration(elapsed), format_duration(eta));
+        }
+    }
+
+    pub fn into_times(self) -> Vec<Duration> {
+        self.times
+    }
+}
+
+/// `h:mm:ss`, or `m:ss` under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(5)), "0:05");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2:05");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 62)), "3:01:02");
+    }
+}
@@ -0,0 +1,124 @@
+//! Per-individual trace logging for debugging one subject's fit. Each outer
+//! iteration logs one `key=value` line per traced individual under the
+//! `nmodes::individual` target at trace level, so
+//! `RUST_LOG=nmodes::individual=trace` enables it without the rest of the
+//! crate's debug output.
+
+use log::{log_enabled, Level};
+
+/// Log target of the per-individual trace lines.
+pub const TARGET: &str = "nmodes::individual";
+
+/// Which individuals to trace: those in `ids`, or all when `ids` is empty.
+pub struct IndividualTrace<'a> {
+    ids: &'a [i32],
+}
+
+impl<'a> IndividualTrace<'a> {
+    pub fn new(ids: &'a [i32]) -> Self {
+        Self { ids }
+    }
+
+    /// Whether trace logging is on for the target at all, so callers can
+    /// skip computing values only the trace would use.
+    pub fn active(&self) -> bool {
+        log_enabled!(target: TARGET, Level::Trace)
+    }
+
+    pub fn traces(&self, id: i32) -> bool {
+        self.active() && (self.ids.is_empty() || self.ids.contains(&id))
+    }
+}
+
+/// `[a,b,...]` at full precision, without spaces so a line splits on them.
+pub fn format_values(values: &[f64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimation::{EstimationConfig, EstimationMethod, FoceEstimator};
+    use crate::models::{CompartmentModel, ModelType};
+    use crate::test_support::dataset_from_csv;
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+
+    /// Keeps the messages logged under `TARGET` by the one thread that
+    /// asked for them, so other tests running concurrently neither add
+    /// lines nor see trace logging enabled.
+    struct CapturingLogger {
+        thread: Mutex<Option<ThreadId>>,
+        records: Mutex<Vec<String>>,
+    }
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == TARGET && *self.thread.lock().unwrap() == Some(std::thread::current().id())
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                self.records.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger { thread: Mutex::new(None), records: Mutex::new(Vec::new()) };
+
+    /// Captures the current thread's trace lines until dropped, then turns
+    /// logging back off, also when the test fails.
+    struct Capture;
+
+    impl Capture {
+        fn start() -> Self {
+            log::set_logger(&LOGGER).expect("the trace test must be the only test installing a logger");
+            *LOGGER.thread.lock().unwrap() = Some(std::thread::current().id());
+            log::set_max_level(LevelFilter::Trace);
+            Capture
+        }
+
+        fn lines(&self) -> Vec<String> {
+            LOGGER.records.lock().unwrap().clone()
+        }
+    }
+
+    impl Drop for Capture {
+        fn drop(&mut self) {
+            log::set_max_level(LevelFilter::Off);
+            *LOGGER.thread.lock().unwrap() = None;
+        }
+    }
+
+    #[test]
+    fn test_trace_lines_logged_for_targeted_individual() {
+        let capture = Capture::start();
+
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=3 {
+            csv.push_str(&format!("{},0,,100,1,1\n", id));
+            for (t, dv) in [(1.0, 25.0), (4.0, 10.0), (12.0, 1.5)] {
+                csv.push_str(&format!("{},{},{},,0,1\n", id, t, dv * (1.0 + 0.1 * id as f64)));
+            }
+        }
+        let dataset = dataset_from_csv("individual_trace_test.csv", &csv);
+
+        let config = EstimationConfig::default()
+            .with_method(EstimationMethod::Foce)
+            .with_foce_iterations(2)
+            .with_traced_individuals(vec![2]);
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(&dataset).unwrap();
+
+        let lines = capture.lines();
+        assert_eq!(lines.len(), results.n_iterations);
+        for (iteration, line) in lines.iter().enumerate() {
+            assert!(line.starts_with(&format!("FOCE iteration={} id=2 eta=[", iteration + 1)), "{}", line);
+            assert!(line.contains("inner_iterations=") && line.contains("ofv_data=") && line.contains("ofv_prior="));
+        }
+    }
+}
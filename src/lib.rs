@@ -1,3 +1,4 @@
+pub mod analysis;
 pub mod data;
 pub mod models;
 pub mod saem;
@@ -5,10 +6,18 @@ pub mod solver;
 pub mod estimation;
 pub mod diagnostics;
 pub mod output;
+pub mod prediction;
 pub mod validation;
+pub mod vpc;
+pub mod pipeline;
 
+#[cfg(test)]
+mod test_support;
+
+pub use analysis::{Analysis, AnalysisResults};
 pub use data::Dataset;
 pub use models::{CompartmentModel, ModelType};
 pub use saem::{SaemEstimator, SaemResults};
 pub use estimation::{EstimationConfig, EstimationMethod, FoceEstimator, FoceResults};
-pub use solver::{RungeKuttaSolver, SolverConfig};
\ No newline at end of file
+pub use solver::{RungeKuttaSolver, SolverConfig};
+pub use pipeline::{run_pipeline, AnalysisResult, PipelineConfig};
\ No newline at end of file
@@ -1,7 +1,8 @@
-use super::{ModelError, OneCompartmentModel, TwoCompartmentModel, ThreeCompartmentModel};
+use super::{EtaPrior, ModelError, OneCompartmentModel, ParentMetaboliteModel, TwoCompartmentModel, ThreeCompartmentModel, WeibullAbsorptionModel, ZeroOrderAbsorptionModel};
+use crate::data::Individual;
 use serde::{Deserialize, Serialize};
 use nalgebra::{DVector, DMatrix};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // CORRECTED: Removed `Send` and `Sync` from derive macro
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -9,14 +10,58 @@ pub enum ModelType {
     OneCompartment,
     TwoCompartment,
     ThreeCompartment,
+    /// One compartment with zero-order absorption over an estimated D1.
+    OneCompartmentZeroOrder,
+    /// One compartment with Weibull absorption (estimated TAU and BETA).
+    OneCompartmentWeibull,
+    /// One-compartment parent whose elimination partly forms a
+    /// one-compartment metabolite (estimated FM), observed in CMT 1 and 2.
+    ParentMetabolite,
+    Custom(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelParameters {
     pub fixed_effects: Vec<f64>,
     pub random_effects_variance: Vec<Vec<f64>>,
     pub residual_variance: f64,
+    /// Variance of the proportional residual component; zero for a purely
+    /// additive error model.
+    #[serde(default)]
+    pub proportional_variance: f64,
+    /// Exponent on |PRED| in the proportional component, whose variance is
+    /// `proportional_variance · |PRED|^(2·error_power)`: 1 is the usual
+    /// proportional error, 0 a second additive term.
+    #[serde(default = "default_error_power")]
+    pub error_power: f64,
+    /// Additive residual variance of effect (PD) observations; `None` when
+    /// they share the error model of the concentrations.
+    #[serde(default)]
+    pub effect_residual_variance: Option<f64>,
+    /// Residual variance of concentrations observed in each of the model's
+    /// output compartments (CMT), e.g. a metabolite's, under the central
+    /// compartment's error model (see `objective::output_variance`); outputs
+    /// not listed share the central compartment's variance as well.
+    #[serde(default)]
+    pub output_residual_variances: BTreeMap<usize, f64>,
+    /// Concentration residuals are ln(DV) − ln(PRED), the exponential error
+    /// model, with `residual_variance` their variance.
+    #[serde(default)]
+    pub log_scale_residuals: bool,
     pub parameter_names: Vec<String>,
+    /// Compartment amounts at time zero, before any dose, in compartment
+    /// order; missing entries are zero. For endogenous substances or
+    /// pre-loaded compartments.
+    #[serde(default)]
+    pub initial_conditions: Vec<f64>,
+    /// Prior of each parameter's eta, in parameter order; missing entries
+    /// are normal.
+    #[serde(default)]
+    pub eta_priors: Vec<EtaPrior>,
+}
+
+pub(crate) fn default_error_power() -> f64 {
+    1.0
 }
 
 impl ModelParameters {
@@ -31,10 +76,21 @@ impl ModelParameters {
                 matrix
             },
             residual_variance: 1.0,
+            proportional_variance: 0.0,
+            error_power: 1.0,
+            effect_residual_variance: None,
+            output_residual_variances: BTreeMap::new(),
+            log_scale_residuals: false,
             parameter_names: param_names,
+            initial_conditions: Vec::new(),
+            eta_priors: Vec::new(),
         }
     }
 
+    pub fn eta_prior(&self, index: usize) -> EtaPrior {
+        self.eta_priors.get(index).copied().unwrap_or_default()
+    }
+
     pub fn n_parameters(&self) -> usize {
         self.fixed_effects.len()
     }
@@ -110,6 +166,16 @@ impl ModelState {
         }
     }
 
+    /// State at time zero with the given amounts; compartments beyond
+    /// `initial_conditions` start empty.
+    pub fn with_initial_conditions(n_compartments: usize, initial_conditions: &[f64]) -> Self {
+        let mut state = Self::new(n_compartments);
+        for (amount, &initial) in state.compartments.iter_mut().zip(initial_conditions) {
+            *amount = initial;
+        }
+        state
+    }
+
     pub fn with_time(mut self, time: f64) -> Self {
         self.time = time;
         self
@@ -130,6 +196,18 @@ impl ModelState {
     }
 }
 
+/// Unit of a built-in parameter name with doses in mg, volumes in L and
+/// times in h; `None` for dimensionless parameters and unknown names.
+pub fn conventional_unit(name: &str) -> Option<&'static str> {
+    match name {
+        "CL" | "Q" | "Q2" | "Q3" | "CLM" => Some("L/h"),
+        "V" | "V1" | "V2" | "V3" | "VM" => Some("L"),
+        "KA" | "KE" => Some("1/h"),
+        "D1" | "TAU" | "ALAG" => Some("h"),
+        _ => None,
+    }
+}
+
 pub trait CompartmentModelTrait {
     fn n_compartments(&self) -> usize;
     fn parameter_names(&self) -> Vec<String>;
@@ -137,6 +215,70 @@ pub trait CompartmentModelTrait {
     fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64>;
     fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64;
     fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError>;
+
+    /// Individual parameters given the covariate values in effect. Called at
+    /// the start of every integration segment, so time-varying covariates
+    /// take effect from the time they change.
+    fn individual_parameters(&self, params: &ModelParameters, _covariates: &HashMap<String, f64>) -> ModelParameters {
+        params.clone()
+    }
+
+    /// Compartment (1-based) IV doses and observations refer to by default.
+    fn central_compartment(&self) -> usize {
+        1
+    }
+
+    /// Absorption compartment (1-based) for oral doses, if the model has one.
+    /// Without a depot, oral doses enter the central compartment directly.
+    fn depot_compartment(&self) -> Option<usize> {
+        None
+    }
+
+    /// Fraction of an oral dose that reaches the depot (F).
+    fn bioavailability(&self, _params: &ModelParameters) -> f64 {
+        1.0
+    }
+
+    /// Delay between an oral dose and its arrival in the depot (ALAG).
+    fn absorption_lag(&self, _params: &ModelParameters) -> f64 {
+        0.0
+    }
+
+    /// Duration (D1) over which oral doses, and doses with RATE=-2, enter
+    /// at a constant rate, if the model estimates one. Without a depot such
+    /// doses then go into the central compartment as a zero-order input.
+    fn absorption_duration(&self, _params: &ModelParameters) -> Option<f64> {
+        None
+    }
+
+    /// Scale τ and shape β of a Weibull absorption, if the model has one:
+    /// oral doses then enter the central compartment with cumulative
+    /// fraction absorbed `1 − exp(−(t/τ)^β)` at time `t` after the dose.
+    fn weibull_absorption(&self, _params: &ModelParameters) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Unit of each parameter in `parameter_names` order, shown next to the
+    /// estimates in the reports; `None` for a dimensionless parameter.
+    fn parameter_units(&self) -> Vec<Option<String>> {
+        self.parameter_names().iter()
+            .map(|name| conventional_unit(name).map(str::to_string))
+            .collect()
+    }
+
+    /// Observation compartments (1-based CMT) other than the central one
+    /// that are separate outputs, such as a metabolite. Concentrations
+    /// observed in them get their own residual variance.
+    fn output_compartments(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Closed-form predictions at the individual's observations, used in
+    /// place of numerical integration when available. `None` when the model
+    /// has no closed form or the regimen is outside what it covers.
+    fn analytic_prediction(&self, _individual: &Individual, _params: &ModelParameters) -> Option<Vec<f64>> {
+        None
+    }
 }
 
 pub struct CompartmentModel {
@@ -150,6 +292,14 @@ impl CompartmentModel {
             ModelType::OneCompartment => Box::new(OneCompartmentModel::new()),
             ModelType::TwoCompartment => Box::new(TwoCompartmentModel::new()),
             ModelType::ThreeCompartment => Box::new(ThreeCompartmentModel::new()),
+            ModelType::OneCompartmentZeroOrder => Box::new(ZeroOrderAbsorptionModel::new()),
+            ModelType::OneCompartmentWeibull => Box::new(WeibullAbsorptionModel::new()),
+            ModelType::ParentMetabolite => Box::new(ParentMetaboliteModel::new()),
+            ModelType::Custom(name) => {
+                return Err(ModelError::UnsupportedModel(format!(
+                    "custom model '{}' must be registered with CompartmentModel::from_custom", name
+                )));
+            }
         };
 
         Ok(Self {
@@ -158,6 +308,17 @@ impl CompartmentModel {
         })
     }
 
+    /// Wraps a user-supplied structural model so it can be fitted by any estimator.
+    pub fn from_custom(
+        name: impl Into<String>,
+        inner: Box<dyn CompartmentModelTrait + Send + Sync>,
+    ) -> Self {
+        Self {
+            model_type: ModelType::Custom(name.into()),
+            inner,
+        }
+    }
+
     pub fn model_type(&self) -> &ModelType {
         &self.model_type
     }
@@ -170,6 +331,10 @@ impl CompartmentModel {
         self.inner.parameter_names()
     }
 
+    pub fn parameter_units(&self) -> Vec<Option<String>> {
+        self.inner.parameter_units()
+    }
+
     pub fn default_parameters(&self) -> ModelParameters {
         self.inner.default_parameters()
     }
@@ -185,6 +350,49 @@ impl CompartmentModel {
     pub fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError> {
         self.inner.validate_parameters(params)
     }
+
+    pub fn individual_parameters(&self, params: &ModelParameters, covariates: &HashMap<String, f64>) -> ModelParameters {
+        self.inner.individual_parameters(params, covariates)
+    }
+
+    pub fn central_compartment(&self) -> usize {
+        self.inner.central_compartment()
+    }
+
+    pub fn depot_compartment(&self) -> Option<usize> {
+        self.inner.depot_compartment()
+    }
+
+    pub fn bioavailability(&self, params: &ModelParameters) -> f64 {
+        self.inner.bioavailability(params)
+    }
+
+    pub fn absorption_lag(&self, params: &ModelParameters) -> f64 {
+        self.inner.absorption_lag(params)
+    }
+
+    pub fn absorption_duration(&self, params: &ModelParameters) -> Option<f64> {
+        self.inner.absorption_duration(params)
+    }
+
+    pub fn weibull_absorption(&self, params: &ModelParameters) -> Option<(f64, f64)> {
+        self.inner.weibull_absorption(params)
+    }
+
+    pub fn output_compartments(&self) -> Vec<usize> {
+        self.inner.output_compartments()
+    }
+
+    /// Closed-form predictions, when the model has them for this individual.
+    /// The closed forms start from empty compartments at time zero, so
+    /// nonzero initial conditions and prior regimens always take the
+    /// numerical path.
+    pub fn analytic_prediction(&self, individual: &Individual, params: &ModelParameters) -> Option<Vec<f64>> {
+        if params.initial_conditions.iter().any(|&amount| amount != 0.0) || individual.prior_regimen().is_some() {
+            return None;
+        }
+        self.inner.analytic_prediction(individual, params)
+    }
 }
 
 // Note: These unsafe impls are likely here because of the trait object `inner`.
@@ -0,0 +1,153 @@
+use super::ModelParameters;
+use crate::data::{DataError, Dataset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Power effect of a continuous covariate on a log-scale parameter:
+/// P = TVP * (COV / reference)^exponent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CovariateEffect {
+    pub parameter: String,
+    pub covariate: String,
+    pub reference: f64,
+    pub exponent: f64,
+}
+
+/// A non-reference category of a categorical covariate effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CovariateCategory {
+    pub label: String,
+    /// Covariate value of individuals in the category, see
+    /// `Dataset::covariate_category_value`.
+    pub value: f64,
+    /// Change of the parameter relative to the reference category, e.g.
+    /// -0.2 for 20% lower; above -1.
+    pub fractional_change: f64,
+}
+
+/// Indicator-coded effect of a categorical covariate (sex, genotype) on a
+/// log-scale parameter: P = TVP * (1 + fractional_change) for individuals in
+/// one of `categories`, TVP in the reference category.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoricalCovariateEffect {
+    pub parameter: String,
+    pub covariate: String,
+    /// Label of the category the typical value refers to.
+    pub reference: String,
+    pub categories: Vec<CovariateCategory>,
+}
+
+impl CategoricalCovariateEffect {
+    /// The effect on `parameter` of `covariate`'s categories in `dataset`,
+    /// with `changes` the fractional change of each non-reference category
+    /// by label. Labels are a text column's cells, or the numbers of a
+    /// numerically coded one (`"1"` for SEX = 1).
+    pub fn from_dataset(
+        dataset: &Dataset,
+        parameter: impl Into<String>,
+        covariate: impl Into<String>,
+        reference: impl Into<String>,
+        changes: &[(&str, f64)],
+    ) -> Result<Self, DataError> {
+        let (covariate, reference) = (covariate.into(), reference.into());
+        let value_of = |label: &str| dataset.covariate_category_value(&covariate, label)
+            .ok_or_else(|| DataError::InvalidFormat(format!("Covariate {} has no category {}", covariate, label)));
+        value_of(&reference)?;
+        let categories = changes.iter()
+            .map(|&(label, fractional_change)| {
+                if !(fractional_change > -1.0 && fractional_change.is_finite()) {
+                    return Err(DataError::InvalidFormat(format!(
+                        "Fractional change of {} category {} must be finite and above -1, got {}",
+                        covariate, label, fractional_change
+                    )));
+                }
+                Ok(CovariateCategory { label: label.to_string(), value: value_of(label)?, fractional_change })
+            })
+            .collect::<Result<Vec<_>, DataError>>()?;
+        Ok(Self { parameter: parameter.into(), covariate, reference, categories })
+    }
+}
+
+/// Covariate effects applied on top of the typical parameter values.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CovariateModel {
+    pub effects: Vec<CovariateEffect>,
+    #[serde(default)]
+    pub categorical_effects: Vec<CategoricalCovariateEffect>,
+}
+
+impl CovariateModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_effect(mut self, effect: CovariateEffect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn with_categorical_effect(mut self, effect: CategoricalCovariateEffect) -> Self {
+        self.categorical_effects.push(effect);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty() && self.categorical_effects.is_empty()
+    }
+
+    /// Parameters adjusted for the covariate values in effect. Effects whose
+    /// parameter or covariate is missing, or whose value is not positive,
+    /// leave the parameter unchanged, as does a categorical effect for a
+    /// value in none of its categories.
+    pub fn apply(&self, params: &ModelParameters, covariates: &HashMap<String, f64>) -> ModelParameters {
+        let mut adjusted = params.clone();
+        for effect in &self.effects {
+            let Some(idx) = params.parameter_names.iter().position(|n| *n == effect.parameter) else {
+                continue;
+            };
+            match covariates.get(&effect.covariate) {
+                Some(&value) if value > 0.0 && effect.reference > 0.0 => {
+                    adjusted.fixed_effects[idx] += effect.exponent * (value / effect.reference).ln();
+                }
+                _ => {}
+            }
+        }
+        for effect in &self.categorical_effects {
+            let Some(idx) = params.parameter_names.iter().position(|n| *n == effect.parameter) else {
+                continue;
+            };
+            let Some(&value) = covariates.get(&effect.covariate) else {
+                continue;
+            };
+            if let Some(category) = effect.categories.iter().find(|category| category.value == value) {
+                adjusted.fixed_effects[idx] += category.fractional_change.ln_1p();
+            }
+        }
+        adjusted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_effect_scales_parameter() {
+        let mut params = ModelParameters::new(2, vec!["CL".to_string(), "V".to_string()]);
+        params.fixed_effects = vec![2.0_f64.ln(), 10.0_f64.ln()];
+        let model = CovariateModel::new().with_effect(CovariateEffect {
+            parameter: "CL".to_string(),
+            covariate: "WT".to_string(),
+            reference: 70.0,
+            exponent: 0.75,
+        });
+
+        let covariates = HashMap::from([("WT".to_string(), 140.0)]);
+        let adjusted = model.apply(&params, &covariates);
+        assert!((adjusted.fixed_effects[0].exp() - 2.0 * 2.0_f64.powf(0.75)).abs() < 1e-12);
+        assert_eq!(adjusted.fixed_effects[1], params.fixed_effects[1]);
+
+        // Missing covariate leaves the typical value
+        assert_eq!(model.apply(&params, &HashMap::new()).fixed_effects, params.fixed_effects);
+    }
+}
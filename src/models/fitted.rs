@@ -0,0 +1,247 @@
+use super::{CompartmentModel, CompartmentModelTrait, CovariateModel, ModelError, ModelParameters, ModelState, ModelType};
+use crate::data::{DosingRecord, Individual, Observation, ObservationType};
+use crate::estimation::{ErrorModel, FoceResults};
+use crate::prediction;
+use crate::saem::SaemResults;
+use crate::solver::{RungeKuttaSolver, SolverConfig};
+use anyhow::Result;
+use nalgebra::DVector;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Everything needed to predict for new subjects without refitting: the
+/// structural model, its estimates, the residual error model and any
+/// covariate effects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FittedModel {
+    pub model_type: ModelType,
+    pub parameters: ModelParameters,
+    pub error_model: ErrorModel,
+    #[serde(default)]
+    pub covariate_model: CovariateModel,
+    /// Solver settings of the fit, used by `predict`.
+    #[serde(default)]
+    pub solver_config: SolverConfig,
+}
+
+impl FittedModel {
+    pub fn new(model_type: ModelType, parameters: ModelParameters, error_model: ErrorModel) -> Self {
+        Self {
+            model_type,
+            parameters,
+            error_model,
+            covariate_model: CovariateModel::default(),
+            solver_config: SolverConfig::default(),
+        }
+    }
+
+    pub fn with_covariate_model(mut self, covariate_model: CovariateModel) -> Self {
+        self.covariate_model = covariate_model;
+        self
+    }
+
+    /// SAEM estimates, or FOCE estimates converted to `SaemResults`.
+    pub fn from_saem(model: &CompartmentModel, results: &SaemResults) -> Self {
+        let mut parameters = model.default_parameters();
+        parameters.fixed_effects = results.fixed_effects.clone();
+        parameters.random_effects_variance = results.random_effects_variance.clone();
+        parameters.residual_variance = results.residual_variance;
+        parameters.proportional_variance = results.proportional_variance;
+        parameters.error_power = results.error_power;
+        parameters.log_scale_residuals = results.error_model == ErrorModel::Exponential;
+        parameters.effect_residual_variance = results.effect_residual_variance;
+        parameters.output_residual_variances = results.output_residual_variances.clone();
+        Self {
+            solver_config: results.solver_config.clone(),
+            ..Self::new(model.model_type().clone(), parameters, results.error_model)
+        }
+    }
+
+    pub fn from_foce(model: &CompartmentModel, results: &FoceResults) -> Self {
+        Self::from_saem(model, &crate::pipeline::convert_foce_to_saem_results(results))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Typical-value predictions at the individual's observation times, with
+    /// covariate effects evaluated from the individual's covariates.
+    pub fn predict(&self, individual: &Individual) -> Result<Vec<f64>> {
+        prediction::predict_individual(
+            &self.model()?,
+            &RungeKuttaSolver::new(),
+            individual,
+            &self.parameters,
+            &self.solver_config,
+        )
+    }
+
+    /// Typical concentration in the central compartment at `time` after the
+    /// doses of `dose_schedule`, for a subject at the reference covariate
+    /// values. A quick what-if query that needs no dataset.
+    pub fn predict_single(&self, dose_schedule: &[DosingRecord], time: f64) -> Result<f64> {
+        let model = self.model()?;
+        let observation = Observation::new(time, 0.0, model.central_compartment() as i32, ObservationType::Concentration);
+        let subject = Individual::new(0, vec![observation], dose_schedule.to_vec(), HashMap::new());
+        let predictions = prediction::predict_individual(
+            &model,
+            &RungeKuttaSolver::new(),
+            &subject,
+            &self.parameters,
+            &self.solver_config,
+        )?;
+        Ok(predictions[0])
+    }
+
+    fn model(&self) -> Result<CompartmentModel> {
+        let structural = CompartmentModel::new(self.model_type.clone())?;
+        Ok(if self.covariate_model.is_empty() {
+            structural
+        } else {
+            CompartmentModel::from_custom(
+                self.model_type.to_string(),
+                Box::new(CovariateAdjusted { structural, covariate_model: self.covariate_model.clone() }),
+            )
+        })
+    }
+}
+
+/// A structural model with covariate effects layered on its individual parameters.
+struct CovariateAdjusted {
+    structural: CompartmentModel,
+    covariate_model: CovariateModel,
+}
+
+impl CompartmentModelTrait for CovariateAdjusted {
+    fn n_compartments(&self) -> usize {
+        self.structural.n_compartments()
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        self.structural.parameter_names()
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        self.structural.default_parameters()
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        self.structural.derivatives(state, params)
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        self.structural.observation_function(state, params, compartment)
+    }
+
+    fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError> {
+        self.structural.validate_parameters(params)
+    }
+
+    fn individual_parameters(&self, params: &ModelParameters, covariates: &HashMap<String, f64>) -> ModelParameters {
+        let typical = self.structural.individual_parameters(params, covariates);
+        self.covariate_model.apply(&typical, covariates)
+    }
+
+    fn central_compartment(&self) -> usize {
+        self.structural.central_compartment()
+    }
+
+    fn depot_compartment(&self) -> Option<usize> {
+        self.structural.depot_compartment()
+    }
+
+    fn bioavailability(&self, params: &ModelParameters) -> f64 {
+        self.structural.bioavailability(params)
+    }
+
+    fn absorption_lag(&self, params: &ModelParameters) -> f64 {
+        self.structural.absorption_lag(params)
+    }
+
+    fn weibull_absorption(&self, params: &ModelParameters) -> Option<(f64, f64)> {
+        self.structural.weibull_absorption(params)
+    }
+
+    fn output_compartments(&self) -> Vec<usize> {
+        self.structural.output_compartments()
+    }
+
+    fn parameter_units(&self) -> Vec<Option<String>> {
+        self.structural.parameter_units()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DosingType;
+    use crate::estimation::EstimationConfig;
+    use crate::models::CovariateEffect;
+    use crate::test_support::simulated_one_compartment;
+
+    #[test]
+    fn test_round_trip_predicts_identically() {
+        let dataset = simulated_one_compartment(
+            "fitted_model_test.csv", 4, &[0.5, 1.0, 2.0, 4.0, 8.0], |_, _, pred| pred,
+        );
+
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let config = EstimationConfig::default().with_iterations(20).with_burnin(5).with_statistics_window(10);
+        let mut estimator = crate::saem::SaemEstimator::new(model, config);
+        let results = estimator.fit(&dataset).unwrap();
+        let fitted = FittedModel::from_saem(estimator.model(), &results).with_covariate_model(
+            CovariateModel::new().with_effect(CovariateEffect {
+                parameter: "CL".to_string(),
+                covariate: "WT".to_string(),
+                reference: 70.0,
+                exponent: 0.75,
+            }),
+        );
+
+        // A new subject, not in the fitted dataset
+        let observations = [1.0_f64, 6.0, 12.0].iter()
+            .map(|&t| Observation::new(t, 0.0, 1, ObservationType::Concentration))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 50.0, 1, DosingType::Bolus)];
+        let covariates = HashMap::from([("WT".to_string(), 90.0)]);
+        let subject = Individual::new(99, observations, doses, covariates);
+
+        let path = std::env::temp_dir().join("fitted_model_round_trip.json");
+        fitted.save(&path).unwrap();
+        let reloaded = FittedModel::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let before = fitted.predict(&subject).unwrap();
+        assert_eq!(before, reloaded.predict(&subject).unwrap());
+        assert_eq!(reloaded.covariate_model, fitted.covariate_model);
+        assert!(before.iter().all(|c| c.is_finite() && *c > 0.0));
+    }
+
+    #[test]
+    fn test_predict_single_matches_analytic_one_compartment() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut parameters = model.default_parameters();
+        let (cl, v) = (2.0_f64, 20.0_f64);
+        parameters.fixed_effects = vec![cl.ln(), v.ln()];
+        let mut fitted = FittedModel::new(ModelType::OneCompartment, parameters, ErrorModel::Additive);
+        fitted.solver_config = SolverConfig { max_step_size: 0.1, ..SolverConfig::default() };
+
+        let dose = DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus);
+        let expected = 100.0 / v * (-cl / v * 8.0).exp();
+        let concentration = fitted.predict_single(std::slice::from_ref(&dose), 8.0).unwrap();
+        assert!((concentration - expected).abs() / expected < 1e-6, "{} vs {}", concentration, expected);
+
+        // A second dose at 4 h adds its own decayed contribution
+        let doses = [dose, DosingRecord::new(4.0, 100.0, 1, DosingType::Bolus)];
+        let expected = expected + 100.0 / v * (-cl / v * 4.0).exp();
+        let concentration = fitted.predict_single(&doses, 8.0).unwrap();
+        assert!((concentration - expected).abs() / expected < 1e-6, "{} vs {}", concentration, expected);
+    }
+}
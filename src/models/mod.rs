@@ -2,11 +2,25 @@ pub mod compartment;
 pub mod one_compartment;
 pub mod two_compartment;
 pub mod three_compartment;
+pub mod zero_order;
+pub mod weibull;
+pub mod parent_metabolite;
+pub mod secondary;
+pub mod covariate;
+pub mod fitted;
+pub mod prior;
 
-pub use compartment::{CompartmentModel, ModelType, ModelParameters, ModelState};
+pub use compartment::{conventional_unit, CompartmentModel, CompartmentModelTrait, ModelType, ModelParameters, ModelState};
 pub use one_compartment::OneCompartmentModel;
 pub use two_compartment::TwoCompartmentModel;
 pub use three_compartment::ThreeCompartmentModel;
+pub use zero_order::ZeroOrderAbsorptionModel;
+pub use weibull::{weibull_fraction_absorbed, WeibullAbsorptionModel};
+pub use parent_metabolite::ParentMetaboliteModel;
+pub use covariate::{CategoricalCovariateEffect, CovariateCategory, CovariateEffect, CovariateModel};
+pub use fitted::FittedModel;
+pub use prior::EtaPrior;
+pub use secondary::{secondary_parameter_estimates, secondary_parameters, SecondaryParameter, SecondaryParameterEstimate};
 
 use thiserror::Error;
 
@@ -16,6 +30,10 @@ impl std::fmt::Display for ModelType {
             ModelType::OneCompartment => write!(f, "one-compartment"),
             ModelType::TwoCompartment => write!(f, "two-compartment"),
             ModelType::ThreeCompartment => write!(f, "three-compartment"),
+            ModelType::OneCompartmentZeroOrder => write!(f, "one-compartment-zero-order"),
+            ModelType::OneCompartmentWeibull => write!(f, "one-compartment-weibull"),
+            ModelType::ParentMetabolite => write!(f, "parent-metabolite"),
+            ModelType::Custom(name) => write!(f, "{}", name),
         }
     }
 }
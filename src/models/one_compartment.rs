@@ -1,5 +1,6 @@
 use super::compartment::{CompartmentModelTrait, ModelParameters, ModelState};
 use super::ModelError;
+use crate::data::{DosingType, Individual};
 use nalgebra::DVector;
 
 pub struct OneCompartmentModel {
@@ -99,6 +100,42 @@ impl CompartmentModelTrait for OneCompartmentModel {
 
         Ok(())
     }
+
+    /// Superposition of `(Dose/V)·exp(-(CL/V)·(t - t_dose))` over bolus
+    /// doses into the central compartment. Infusions, RATE=-2 doses, doses
+    /// into other compartments, ADDL and steady state fall back to
+    /// integration.
+    fn analytic_prediction(&self, individual: &Individual, params: &ModelParameters) -> Option<Vec<f64>> {
+        let doses = individual.dosing_records();
+        let closed_form = doses.iter().all(|dose| {
+            dose.dosing_type != DosingType::Infusion
+                && !dose.has_modeled_duration()
+                && (dose.dosing_type == DosingType::Oral || dose.compartment == 1)
+                && dose.additional_doses == 0
+                && !dose.steady_state
+        });
+        if !closed_form {
+            return None;
+        }
+
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+        let ke = cl / v;
+
+        let predictions = individual.observations().iter()
+            .map(|obs| {
+                if obs.compartment != 1 {
+                    return 0.0;
+                }
+                // Pre-dose samples do not see a dose given at their own time
+                doses.iter()
+                    .filter(|dose| dose.time < obs.time || (dose.time == obs.time && !obs.pre_dose))
+                    .map(|dose| dose.amount / v * (-ke * (obs.time - dose.time)).exp())
+                    .sum()
+            })
+            .collect();
+        Some(predictions)
+    }
 }
 
 #[cfg(test)]
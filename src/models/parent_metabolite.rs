@@ -0,0 +1,182 @@
+use super::compartment::{CompartmentModelTrait, ModelParameters, ModelState};
+use super::ModelError;
+use nalgebra::DVector;
+
+/// Largest fraction metabolized used in the derivatives. The formation
+/// clearance grows without bound as FM approaches 1, and an exponential eta
+/// on a typical FM near 1 can take an individual past it.
+pub const MAX_FRACTION_METABOLIZED: f64 = 0.999;
+
+#[derive(Default)]
+pub struct ParentMetaboliteModel {
+    // Model: dA1/dt = -(CL + CLF)/V * A1
+    //        dA2/dt = CLF/V * A1 - CLM/VM * A2
+    // Where CLF = CL * FM/(1 - FM) is the formation clearance, so FM is the
+    // fraction of the parent's elimination that forms the metabolite and CL
+    // the clearance by all other routes
+}
+
+impl ParentMetaboliteModel {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Clearance of the parent into the metabolite, CL · FM/(1 − FM).
+    pub fn formation_clearance(params: &ModelParameters) -> f64 {
+        let fm = params.fixed_effects[4].exp().min(MAX_FRACTION_METABOLIZED);
+        params.fixed_effects[0].exp() * fm / (1.0 - fm)
+    }
+}
+
+impl CompartmentModelTrait for ParentMetaboliteModel {
+    fn n_compartments(&self) -> usize {
+        2
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        vec!["CL".to_string(), "V".to_string(), "CLM".to_string(), "VM".to_string(), "FM".to_string()]
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        let param_names = self.parameter_names();
+        let mut params = ModelParameters::new(5, param_names);
+
+        // Typical values for a parent eliminated half by metabolism
+        params.fixed_effects[0] = 1.0_f64.ln(); // ln(CL) = ln(1.0 L/h) = 0.0
+        params.fixed_effects[1] = 3.0_f64.ln(); // ln(V) = ln(3.0 L)
+        params.fixed_effects[2] = 2.0_f64.ln(); // ln(CLM) = ln(2.0 L/h)
+        params.fixed_effects[3] = 5.0_f64.ln(); // ln(VM) = ln(5.0 L)
+        params.fixed_effects[4] = 0.5_f64.ln(); // ln(FM) = ln(0.5)
+
+        // Inter-individual variability (diagonal omega matrix)
+        params.random_effects_variance[0][0] = 0.09; // 30% CV for CL
+        params.random_effects_variance[1][1] = 0.04; // 20% CV for V
+        params.random_effects_variance[2][2] = 0.09; // 30% CV for CLM
+        params.random_effects_variance[3][3] = 0.04; // 20% CV for VM
+        params.random_effects_variance[4][4] = 0.01; // 10% CV for FM
+
+        // Starting residual variance: the proportional component's (10% CV)
+        // under a proportional error model, the additive one's otherwise;
+        // the metabolite output starts from the same value
+        params.residual_variance = 0.01;
+
+        params
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+        let clm = params.fixed_effects[2].exp();
+        let vm = params.fixed_effects[3].exp();
+        let clf = Self::formation_clearance(params);
+
+        let mut derivatives = DVector::<f64>::zeros(2);
+        derivatives[0] = -((cl + clf) / v) * state.compartments[0];
+        derivatives[1] = (clf / v) * state.compartments[0] - (clm / vm) * state.compartments[1];
+
+        derivatives
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        match compartment {
+            1 => state.compartments[0] / params.fixed_effects[1].exp(),
+            2 => state.compartments[1] / params.fixed_effects[3].exp(),
+            _ => 0.0,
+        }
+    }
+
+    fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError> {
+        if params.n_parameters() != 5 {
+            return Err(ModelError::InvalidParameter {
+                parameter: "n_parameters".to_string(),
+                value: params.n_parameters() as f64,
+            });
+        }
+
+        for (name, &theta) in params.parameter_names.iter().zip(&params.fixed_effects) {
+            let value = theta.exp();
+            if value <= 0.0 || !value.is_finite() {
+                return Err(ModelError::InvalidParameter {
+                    parameter: name.clone(),
+                    value,
+                });
+            }
+        }
+
+        let fm = params.fixed_effects[4].exp();
+        if fm >= 1.0 {
+            return Err(ModelError::InvalidParameter {
+                parameter: "FM".to_string(),
+                value: fm,
+            });
+        }
+
+        if params.residual_variance <= 0.0 {
+            return Err(ModelError::InvalidParameter {
+                parameter: "residual_variance".to_string(),
+                value: params.residual_variance,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn output_compartments(&self) -> Vec<usize> {
+        vec![2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingRecord, DosingType, Individual, Observation, ObservationType};
+    use crate::models::{CompartmentModel, ModelType};
+    use crate::prediction::predict_individual;
+    use crate::solver::{RungeKuttaSolver, SolverConfig};
+    use std::collections::HashMap;
+
+    /// Trapezoidal AUC over 0–48 h of the parent and the metabolite.
+    fn exposures(model: &CompartmentModel, fm: f64) -> (f64, f64) {
+        let times: Vec<f64> = (0..=480).map(|k| 0.1 * k as f64).collect();
+        let observations = times.iter()
+            .flat_map(|&t| [1, 2].map(|cmt| Observation::new(t, 1.0, cmt, ObservationType::Concentration)))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+
+        let mut params = model.default_parameters();
+        params.fixed_effects[4] = fm.ln();
+        // At FM = 0.8 the parent's rate constant is 5/3 per hour, too fast
+        // for the default 1 h steps to resolve
+        let config = SolverConfig { max_step_size: 0.1, ..SolverConfig::default() };
+        let predictions = predict_individual(model, &RungeKuttaSolver::new(), &individual, &params, &config).unwrap();
+        let auc = |offset: usize| {
+            let profile: Vec<f64> = predictions.iter().skip(offset).step_by(2).copied().collect();
+            profile.windows(2).map(|w| 0.05 * (w[0] + w[1])).sum::<f64>()
+        };
+        (auc(0), auc(1))
+    }
+
+    #[test]
+    fn test_fraction_metabolized_shifts_exposure_from_parent_to_metabolite() {
+        let model = CompartmentModel::new(ModelType::ParentMetabolite).unwrap();
+        assert_eq!(model.parameter_names(), vec!["CL", "V", "CLM", "VM", "FM"]);
+        let params = model.default_parameters();
+        assert!(model.validate_parameters(&params).is_ok());
+        assert!((ParentMetaboliteModel::formation_clearance(&params) - 1.0).abs() < 1e-12);
+
+        let (parent_low, metabolite_low) = exposures(&model, 0.2);
+        let (parent_high, metabolite_high) = exposures(&model, 0.8);
+        assert!(parent_high < parent_low, "parent AUC {} vs {}", parent_high, parent_low);
+        assert!(metabolite_high > metabolite_low, "metabolite AUC {} vs {}", metabolite_high, metabolite_low);
+
+        // Parent AUC is dose/(CL + CLF) = dose·(1 − FM)/CL, metabolite AUC
+        // the formed amount dose·FM over CLM; both nearly complete by 48 h
+        assert!((parent_low - 100.0 * 0.8).abs() / 80.0 < 0.01, "parent AUC {}", parent_low);
+        assert!((metabolite_high - 100.0 * 0.8 / 2.0).abs() / 40.0 < 0.01, "metabolite AUC {}", metabolite_high);
+
+        let mut invalid = params.clone();
+        invalid.fixed_effects[4] = 1.2_f64.ln();
+        assert!(model.validate_parameters(&invalid).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::estimation::censoring::log_normal_cdf;
+use serde::{Deserialize, Serialize};
+
+/// Fixed-point steps when matching a truncated prior's variance to a sample.
+const MOMENT_ITERATIONS: usize = 50;
+
+/// Distribution of one parameter's eta. `Normal` is the usual N(0, ω²);
+/// `Truncated` is N(0, ω²) restricted to `[lower, upper]` on the eta (log)
+/// scale, for deviations that are physically one-sided. ω² is then the
+/// variance of the untruncated normal, not of the etas themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum EtaPrior {
+    #[default]
+    Normal,
+    Truncated { lower: f64, upper: f64 },
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    if z.is_infinite() {
+        if z > 0.0 { 1.0 } else { 0.0 }
+    } else {
+        log_normal_cdf(z).exp()
+    }
+}
+
+fn normal_pdf(z: f64) -> f64 {
+    if z.is_infinite() {
+        0.0
+    } else {
+        (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt()
+    }
+}
+
+impl EtaPrior {
+    /// Individual values never below the typical value: eta ≥ 0.
+    pub fn half_normal() -> Self {
+        EtaPrior::Truncated { lower: 0.0, upper: f64::INFINITY }
+    }
+
+    /// Individual values never above the typical value: eta ≤ 0.
+    pub fn negative_half_normal() -> Self {
+        EtaPrior::Truncated { lower: f64::NEG_INFINITY, upper: 0.0 }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, EtaPrior::Truncated { .. })
+    }
+
+    /// Log density of `eta` given the variance of the untruncated normal;
+    /// −∞ outside the support.
+    pub fn log_density(&self, eta: f64, variance: f64) -> f64 {
+        let normal = -0.5 * eta * eta / variance - 0.5 * (2.0 * std::f64::consts::PI * variance).ln();
+        match *self {
+            EtaPrior::Normal => normal,
+            EtaPrior::Truncated { lower, upper } => {
+                if eta < lower || eta > upper {
+                    return f64::NEG_INFINITY;
+                }
+                let sd = variance.sqrt();
+                normal - (normal_cdf(upper / sd) - normal_cdf(lower / sd)).ln()
+            }
+        }
+    }
+
+    /// Mean and variance of eta given the variance of the untruncated normal.
+    pub fn moments(&self, variance: f64) -> (f64, f64) {
+        match *self {
+            EtaPrior::Normal => (0.0, variance),
+            EtaPrior::Truncated { lower, upper } => {
+                let sd = variance.sqrt();
+                let (alpha, beta) = (lower / sd, upper / sd);
+                let mass = normal_cdf(beta) - normal_cdf(alpha);
+                let shift = (normal_pdf(alpha) - normal_pdf(beta)) / mass;
+                // z·φ(z) vanishes at an infinite limit
+                let edge = |z: f64| if z.is_infinite() { 0.0 } else { z * normal_pdf(z) };
+                let spread = 1.0 + (edge(alpha) - edge(beta)) / mass - shift * shift;
+                (sd * shift, variance * spread)
+            }
+        }
+    }
+
+    /// Typical value and ω² whose prior reproduces the sample mean and
+    /// variance of the individual (log-scale) parameter values: the M-step
+    /// for this parameter.
+    pub fn match_moments(&self, mean: f64, sample_variance: f64) -> (f64, f64) {
+        let mut variance = sample_variance;
+        if self.is_truncated() {
+            for _ in 0..MOMENT_ITERATIONS {
+                let (_, eta_variance) = self.moments(variance);
+                let updated = variance * sample_variance / eta_variance;
+                let converged = (updated - variance).abs() <= 1e-12 * variance;
+                variance = updated;
+                if converged {
+                    break;
+                }
+            }
+        }
+        let (eta_mean, _) = self.moments(variance);
+        (mean - eta_mean, variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_normal_moments_match_closed_form() {
+        let prior = EtaPrior::half_normal();
+        let (mean, variance) = prior.moments(0.09);
+        assert!((mean - 0.3 * (2.0 / std::f64::consts::PI).sqrt()).abs() < 1e-6);
+        assert!((variance - 0.09 * (1.0 - 2.0 / std::f64::consts::PI)).abs() < 1e-6);
+
+        // Twice the normal density on the support, nothing outside it
+        let normal = EtaPrior::Normal.log_density(0.2, 0.09);
+        assert!((prior.log_density(0.2, 0.09) - normal - 2.0_f64.ln()).abs() < 1e-6);
+        assert_eq!(prior.log_density(-0.01, 0.09), f64::NEG_INFINITY);
+
+        // Moment matching recovers the typical value and ω²
+        let (theta, omega) = prior.match_moments(1.0 + mean, variance);
+        assert!((theta - 1.0).abs() < 1e-6 && (omega - 0.09).abs() < 1e-6);
+        let two_sided = EtaPrior::Truncated { lower: -0.2, upper: 0.5 };
+        let (mean, variance) = two_sided.moments(0.09);
+        let (theta, omega) = two_sided.match_moments(2.0 + mean, variance);
+        assert!((theta - 2.0).abs() < 1e-6 && (omega - 0.09).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,238 @@
+use super::parent_metabolite::MAX_FRACTION_METABOLIZED;
+use super::{ModelError, ModelType};
+use nalgebra::DMatrix;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecondaryParameter {
+    pub name: String,
+    pub value: f64,
+}
+
+/// A secondary parameter with its delta-method standard error and 95% CI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecondaryParameterEstimate {
+    pub name: String,
+    pub value: f64,
+    pub standard_error: f64,
+    pub ci_lower: f64,
+    pub ci_upper: f64,
+}
+
+/// Derives KE, terminal half-life and Vss from log-scale fixed effects.
+///
+/// For multi-compartment models the terminal slope is the smallest-magnitude
+/// eigenvalue of the compartment rate matrix.
+pub fn secondary_parameters(
+    model_type: &ModelType,
+    fixed_effects: &[f64],
+) -> Result<Vec<SecondaryParameter>, ModelError> {
+    let Disposition { cl, v1, peripherals } = disposition_parameters(model_type, fixed_effects)?;
+
+    let ke = cl / v1;
+    let terminal_slope = if peripherals.is_empty() {
+        ke
+    } else {
+        terminal_rate_constant(cl, v1, &peripherals)
+    };
+    let vss = v1 + peripherals.iter().map(|&(_, v)| v).sum::<f64>();
+
+    Ok(vec![
+        SecondaryParameter { name: "KE".to_string(), value: ke },
+        SecondaryParameter { name: "T_HALF".to_string(), value: std::f64::consts::LN_2 / terminal_slope },
+        SecondaryParameter { name: "VSS".to_string(), value: vss },
+    ])
+}
+
+/// Propagates the covariance of the log-scale fixed effects to the secondary
+/// parameters with the delta method, `Var(g) = J C J^T`, where the Jacobian
+/// `J` is taken by central differences.
+pub fn secondary_parameter_estimates(
+    model_type: &ModelType,
+    fixed_effects: &[f64],
+    covariance: &[Vec<f64>],
+) -> Result<Vec<SecondaryParameterEstimate>, ModelError> {
+    let base = secondary_parameters(model_type, fixed_effects)?;
+    let n = fixed_effects.len();
+
+    if covariance.len() != n || covariance.iter().any(|row| row.len() != n) {
+        return Err(ModelError::InvalidParameter {
+            parameter: "covariance_dimension".to_string(),
+            value: covariance.len() as f64,
+        });
+    }
+
+    // jacobian[k][i] = d secondary_k / d theta_i
+    let h = 1e-6;
+    let mut jacobian = vec![vec![0.0; n]; base.len()];
+    for i in 0..n {
+        let mut plus = fixed_effects.to_vec();
+        let mut minus = fixed_effects.to_vec();
+        plus[i] += h;
+        minus[i] -= h;
+        let upper = secondary_parameters(model_type, &plus)?;
+        let lower = secondary_parameters(model_type, &minus)?;
+        for (k, (u, l)) in upper.iter().zip(lower.iter()).enumerate() {
+            jacobian[k][i] = (u.value - l.value) / (2.0 * h);
+        }
+    }
+
+    let z = 1.959964;
+    Ok(base.into_iter().zip(jacobian).map(|(param, grad)| {
+        let variance: f64 = (0..n)
+            .map(|i| (0..n).map(|j| grad[i] * covariance[i][j] * grad[j]).sum::<f64>())
+            .sum();
+        let standard_error = variance.max(0.0).sqrt();
+        SecondaryParameterEstimate {
+            ci_lower: param.value - z * standard_error,
+            ci_upper: param.value + z * standard_error,
+            name: param.name,
+            value: param.value,
+            standard_error,
+        }
+    }).collect())
+}
+
+/// Clearance, central volume and (Q_i, V_i) pairs on the natural scale.
+struct Disposition {
+    cl: f64,
+    v1: f64,
+    peripherals: Vec<(f64, f64)>,
+}
+
+fn disposition_parameters(
+    model_type: &ModelType,
+    fixed_effects: &[f64],
+) -> Result<Disposition, ModelError> {
+    // (total parameters, leading disposition parameters)
+    let (expected, n_disposition) = match model_type {
+        ModelType::OneCompartment => (2, 2),
+        ModelType::TwoCompartment => (4, 4),
+        ModelType::ThreeCompartment => (6, 6),
+        ModelType::OneCompartmentZeroOrder => (3, 2),
+        ModelType::OneCompartmentWeibull => (4, 2),
+        ModelType::ParentMetabolite => (5, 2),
+        ModelType::Custom(name) => {
+            return Err(ModelError::UnsupportedModel(format!(
+                "secondary parameters are not defined for custom model '{}'", name
+            )));
+        }
+    };
+
+    if fixed_effects.len() != expected {
+        return Err(ModelError::InvalidParameter {
+            parameter: "n_parameters".to_string(),
+            value: fixed_effects.len() as f64,
+        });
+    }
+
+    let natural: Vec<f64> = fixed_effects.iter().map(|theta| theta.exp()).collect();
+    let peripherals = natural[2..n_disposition].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+
+    // The parent's CL excludes metabolite formation; its total clearance is
+    // CL/(1 − FM)
+    let cl = match model_type {
+        ModelType::ParentMetabolite => natural[0] / (1.0 - natural[4].min(MAX_FRACTION_METABOLIZED)),
+        _ => natural[0],
+    };
+
+    Ok(Disposition {
+        cl,
+        v1: natural[1],
+        peripherals,
+    })
+}
+
+fn terminal_rate_constant(cl: f64, v1: f64, peripherals: &[(f64, f64)]) -> f64 {
+    // The amount rate matrix K is similar to a symmetric matrix under
+    // diag(V)^(1/2) scaling, so its eigenvalues are real and can be taken
+    // from the symmetric form S_ij = Q_ij / sqrt(V_i * V_j).
+    let n = peripherals.len() + 1;
+    let mut s = DMatrix::<f64>::zeros(n, n);
+
+    let total_q: f64 = peripherals.iter().map(|&(q, _)| q).sum();
+    s[(0, 0)] = -(cl + total_q) / v1;
+
+    for (i, &(q, v)) in peripherals.iter().enumerate() {
+        let idx = i + 1;
+        s[(idx, idx)] = -q / v;
+        s[(0, idx)] = q / (v1 * v).sqrt();
+        s[(idx, 0)] = s[(0, idx)];
+    }
+
+    s.symmetric_eigen()
+        .eigenvalues
+        .iter()
+        .map(|lambda| lambda.abs())
+        .fold(f64::INFINITY, f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+
+    fn value(params: &[SecondaryParameter], name: &str) -> f64 {
+        params.iter().find(|p| p.name == name).unwrap().value
+    }
+
+    #[test]
+    fn test_one_compartment_half_life() {
+        let cl: f64 = 2.0;
+        let v: f64 = 25.0;
+        let params = secondary_parameters(&ModelType::OneCompartment, &[cl.ln(), v.ln()]).unwrap();
+
+        assert!((value(&params, "T_HALF") - std::f64::consts::LN_2 * v / cl).abs() < 1e-10);
+        assert!((value(&params, "KE") - cl / v).abs() < 1e-12);
+        assert!((value(&params, "VSS") - v).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_two_compartment_terminal_slope() {
+        let (cl, v1, q, v2): (f64, f64, f64, f64) = (1.0, 10.0, 2.0, 40.0);
+        let params = secondary_parameters(
+            &ModelType::TwoCompartment,
+            &[cl.ln(), v1.ln(), q.ln(), v2.ln()],
+        ).unwrap();
+
+        // Closed-form beta from the micro-constants
+        let (k10, k12, k21) = (cl / v1, q / v1, q / v2);
+        let sum = k10 + k12 + k21;
+        let beta = (sum - (sum * sum - 4.0 * k10 * k21).sqrt()) / 2.0;
+
+        assert!((value(&params, "T_HALF") - std::f64::consts::LN_2 / beta).abs() < 1e-8);
+        assert!((value(&params, "VSS") - (v1 + v2)).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_half_life_ci_brackets_true_value() {
+        let (cl, v): (f64, f64) = (2.0, 25.0);
+        let true_half_life = std::f64::consts::LN_2 * v / cl;
+        let (sd_cl, sd_v) = (0.05, 0.08);
+        let covariance = vec![vec![sd_cl * sd_cl, 0.0], vec![0.0, sd_v * sd_v]];
+
+        // Delta-method SE of ln2*V/CL on the log scale
+        let estimates = secondary_parameter_estimates(
+            &ModelType::OneCompartment, &[cl.ln(), v.ln()], &covariance,
+        ).unwrap();
+        let half_life = estimates.iter().find(|p| p.name == "T_HALF").unwrap();
+        let expected_se = true_half_life * (sd_cl * sd_cl + sd_v * sd_v).sqrt();
+        assert!((half_life.standard_error - expected_se).abs() / expected_se < 1e-4);
+
+        // Coverage over estimates simulated from the sampling distribution
+        let mut rng = StdRng::seed_from_u64(11);
+        let noise_cl = Normal::new(0.0, sd_cl).unwrap();
+        let noise_v = Normal::new(0.0, sd_v).unwrap();
+        let n_draws = 400;
+        let covered = (0..n_draws).filter(|_| {
+            let theta = [cl.ln() + noise_cl.sample(&mut rng), v.ln() + noise_v.sample(&mut rng)];
+            let estimates = secondary_parameter_estimates(&ModelType::OneCompartment, &theta, &covariance).unwrap();
+            let half_life = estimates.iter().find(|p| p.name == "T_HALF").unwrap();
+            half_life.ci_lower <= true_half_life && true_half_life <= half_life.ci_upper
+        }).count();
+
+        let coverage = covered as f64 / n_draws as f64;
+        assert!((0.9..=0.99).contains(&coverage), "coverage {}", coverage);
+    }
+}
@@ -0,0 +1,114 @@
+use super::compartment::{CompartmentModelTrait, ModelParameters, ModelState};
+use super::ModelError;
+use nalgebra::DVector;
+
+#[derive(Default)]
+pub struct ZeroOrderAbsorptionModel {
+    // Model: dA/dt = R(t) - CL/V * A
+    // Where R(t) = Dose/D1 for D1 after an oral dose and 0 otherwise, so the
+    // dose enters the central compartment at a constant rate
+}
+
+impl ZeroOrderAbsorptionModel {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CompartmentModelTrait for ZeroOrderAbsorptionModel {
+    fn n_compartments(&self) -> usize {
+        1
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        vec!["CL".to_string(), "V".to_string(), "D1".to_string()]
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        let param_names = self.parameter_names();
+        let mut params = ModelParameters::new(3, param_names);
+
+        // Typical values for a one-compartment model with zero-order absorption
+        params.fixed_effects[0] = 1.0_f64.ln(); // ln(CL) = ln(1.0 L/h) = 0.0
+        params.fixed_effects[1] = 3.0_f64.ln(); // ln(V) = ln(3.0 L)
+        params.fixed_effects[2] = 1.0_f64.ln(); // ln(D1) = ln(1.0 h) = 0.0
+
+        // Inter-individual variability (diagonal omega matrix)
+        params.random_effects_variance[0][0] = 0.09; // 30% CV for CL
+        params.random_effects_variance[1][1] = 0.04; // 20% CV for V
+        params.random_effects_variance[2][2] = 0.04; // 20% CV for D1
+
+        // Residual error (proportional)
+        params.residual_variance = 0.01; // 10% CV
+
+        params
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+
+        // The zero-order input is added by the caller while a dose is running
+        let mut derivatives = DVector::<f64>::zeros(1);
+        derivatives[0] = -(cl / v) * state.compartments[0];
+
+        derivatives
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        if compartment != 1 {
+            return 0.0;
+        }
+
+        let v = params.fixed_effects[1].exp();
+        state.compartments[0] / v
+    }
+
+    fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError> {
+        if params.n_parameters() != 3 {
+            return Err(ModelError::InvalidParameter {
+                parameter: "n_parameters".to_string(),
+                value: params.n_parameters() as f64,
+            });
+        }
+
+        for (name, &theta) in params.parameter_names.iter().zip(&params.fixed_effects) {
+            let value = theta.exp();
+            if value <= 0.0 || !value.is_finite() {
+                return Err(ModelError::InvalidParameter {
+                    parameter: name.clone(),
+                    value,
+                });
+            }
+        }
+
+        if params.residual_variance <= 0.0 {
+            return Err(ModelError::InvalidParameter {
+                parameter: "residual_variance".to_string(),
+                value: params.residual_variance,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn absorption_duration(&self, params: &ModelParameters) -> Option<f64> {
+        Some(params.fixed_effects[2].exp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_order_absorption_model() {
+        let model = ZeroOrderAbsorptionModel::new();
+        assert_eq!(model.n_compartments(), 1);
+        assert_eq!(model.parameter_names(), vec!["CL", "V", "D1"]);
+
+        let params = model.default_parameters();
+        assert!(model.validate_parameters(&params).is_ok());
+        assert!((model.absorption_duration(&params).unwrap() - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::diagnostics;
+use crate::estimation::FoceResults;
+use crate::saem::SaemResults;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// The few facts an automated pipeline needs to decide whether a fit can be
+/// used, written to `converged.json` so it can gate on them without parsing
+/// the full results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvergenceReport {
+    pub estimation_method: String,
+    pub converged: bool,
+    pub final_iteration: usize,
+    pub objective_function_value: f64,
+    /// Norm of the OFV gradient at the estimates, when the estimator
+    /// computed a finite one; FOCE-family methods only.
+    pub gradient_norm: Option<f64>,
+    pub parameters_at_bounds: Vec<String>,
+    /// Parameters with %RSE above `diagnostics::HIGH_RSE_THRESHOLD`.
+    pub poorly_identified_parameters: Vec<String>,
+}
+
+impl ConvergenceReport {
+    pub fn from_saem(results: &SaemResults) -> Self {
+        Self {
+            estimation_method: "SAEM".to_string(),
+            converged: results.converged,
+            final_iteration: results.n_iterations,
+            objective_function_value: results.objective_function_value,
+            gradient_norm: None,
+            parameters_at_bounds: results.parameters_at_bounds.clone(),
+            poorly_identified_parameters: poorly_identified(&results.parameter_names, &results.rse_percent()),
+        }
+    }
+
+    pub fn from_foce(results: &FoceResults, estimation_method: &str) -> Self {
+        Self {
+            estimation_method: estimation_method.to_string(),
+            converged: results.converged,
+            final_iteration: results.n_iterations,
+            objective_function_value: results.objective_function_value,
+            gradient_norm: Some(results.gradient_norm).filter(|norm| norm.is_finite()),
+            parameters_at_bounds: results.parameters_at_bounds.clone(),
+            poorly_identified_parameters: poorly_identified(&results.parameter_names, &results.rse_percent()),
+        }
+    }
+}
+
+fn poorly_identified(parameter_names: &[String], rse_percent: &[f64]) -> Vec<String> {
+    diagnostics::poorly_identified_parameters(parameter_names, rse_percent)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Writes `converged.json` into `output_dir`.
+pub fn save_convergence_report(output_dir: &Path, report: &ConvergenceReport) -> Result<(), anyhow::Error> {
+    let json_content = serde_json::to_string_pretty(report)?;
+    fs::write(output_dir.join("converged.json"), json_content)?;
+    Ok(())
+}
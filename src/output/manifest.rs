@@ -0,0 +1,116 @@
+use crate::estimation::EstimationConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What produced a run's results, written to `manifest.json` in the output
+/// directory so an audit can tie the results to the exact data file,
+/// configuration and program version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub nmodes_version: String,
+    pub dataset_path: PathBuf,
+    /// SHA-256 of the dataset file's bytes, as lowercase hex.
+    pub dataset_sha256: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Subject identifiers validation removed before fitting.
+    #[serde(default)]
+    pub excluded_individuals: Vec<String>,
+    pub analyses: Vec<ManifestAnalysis>,
+}
+
+/// One model/method combination of the run, with the configuration it was
+/// estimated under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestAnalysis {
+    pub model_type: String,
+    pub estimation_method: String,
+    pub output_dir: PathBuf,
+    pub seed: Option<u64>,
+    pub config: EstimationConfig,
+}
+
+impl RunManifest {
+    /// A manifest for a run on the dataset at `dataset_path`, hashing the
+    /// file and starting the clock now.
+    pub fn new(dataset_path: &Path) -> Result<Self, anyhow::Error> {
+        let bytes = fs::read(dataset_path)?;
+        Ok(Self {
+            nmodes_version: env!("CARGO_PKG_VERSION").to_string(),
+            dataset_path: dataset_path.to_path_buf(),
+            dataset_sha256: sha256_hex(&bytes),
+            started_at: Utc::now(),
+            finished_at: None,
+            excluded_individuals: Vec::new(),
+            analyses: Vec::new(),
+        })
+    }
+
+    pub fn add_analysis(&mut self, model_type: &str, estimation_method: &str, output_dir: &Path, config: &EstimationConfig) {
+        self.analyses.push(ManifestAnalysis {
+            model_type: model_type.to_string(),
+            estimation_method: estimation_method.to_string(),
+            output_dir: output_dir.to_path_buf(),
+            seed: config.seed,
+            config: config.clone(),
+        });
+    }
+
+    /// Stamps the finish time and writes `manifest.json` into `output_dir`.
+    pub fn finish(&mut self, output_dir: &Path) -> Result<(), anyhow::Error> {
+        self.finished_at = Some(Utc::now());
+        let json_content = serde_json::to_string_pretty(self)?;
+        fs::write(output_dir.join("manifest.json"), json_content)?;
+        Ok(())
+    }
+}
+
+/// SHA-256 digest of `data` as lowercase hex.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_matches_reference_vectors() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // Two blocks once padded
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_manifest_hash_changes_with_dataset() {
+        let dir = std::env::temp_dir().join("nmodes_manifest_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.csv");
+        fs::write(&path, "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,8.2,,0\n").unwrap();
+        let mut manifest = RunManifest::new(&path).unwrap();
+        let config = EstimationConfig::default();
+        manifest.add_analysis("one-compartment", "SAEM", &dir.join("one-compartment_SAEM"), &config);
+        manifest.finish(&dir).unwrap();
+
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+        assert_eq!(written["dataset_sha256"], manifest.dataset_sha256.as_str());
+        assert_eq!(written["nmodes_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(written["analyses"][0]["seed"], 12345);
+        assert!(written["finished_at"].is_string());
+
+        // One changed observation changes the hash; identical content does not
+        fs::write(&path, "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,8.3,,0\n").unwrap();
+        let changed = RunManifest::new(&path).unwrap();
+        assert_ne!(changed.dataset_sha256, manifest.dataset_sha256);
+        fs::write(&path, "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,8.2,,0\n").unwrap();
+        assert_eq!(RunManifest::new(&path).unwrap().dataset_sha256, manifest.dataset_sha256);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,19 +1,52 @@
+pub mod convergence;
+pub mod manifest;
+pub mod pharmpy;
+
 use crate::saem::SaemResults;
-use crate::diagnostics::DiagnosticResults;
-use crate::data::Dataset;
-use crate::models::{CompartmentModel, ModelParameters, ModelState};
-use crate::solver::{RungeKuttaSolver, OdeSolver, SolverConfig, OdeSystem};
-use nalgebra::DVector;
+use crate::diagnostics::{self, CovariateForestEffect, DiagnosticResults, ParameterShrinkage, QqData, StratumGoodnessOfFit};
+use crate::data::{Dataset, Individual};
+use crate::estimation::{ErrorModel, IndividualObjective, LikelihoodProfile};
+use crate::models::{secondary_parameter_estimates, secondary_parameters, CompartmentModel};
+use crate::prediction;
+use crate::solver::{RungeKuttaSolver, SolverConfig};
+use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
 use log::info;
 
+/// How parameter values are written to CSV files and text reports. JSON
+/// outputs always hold every digit, so they reload exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    /// The shortest decimal that reads back as the same `f64`.
+    #[default]
+    Full,
+    /// Rounded to this many significant figures, so small values such as a
+    /// clearance of 1.23456e-4 keep their leading digits.
+    Significant(usize),
+}
+
+impl NumberFormat {
+    pub fn format(&self, value: f64) -> String {
+        match *self {
+            NumberFormat::Significant(digits) if value.is_finite() => {
+                // Round in scientific notation, then print the rounded value
+                // without trailing zeros
+                let rounded: f64 = format!("{:.*e}", digits.max(1) - 1, value).parse().unwrap_or(value);
+                rounded.to_string()
+            }
+            _ => value.to_string(),
+        }
+    }
+}
+
 pub fn save_results(
     output_dir: &Path,
     results: &SaemResults,
     diagnostics: &DiagnosticResults,
     dataset: &Dataset,
     model: &CompartmentModel,
+    format: NumberFormat,
 ) -> Result<(), anyhow::Error> {
     info!("Saving results to {:?}", output_dir);
     
@@ -27,14 +60,26 @@ pub fn save_results(
     save_diagnostics(output_dir, diagnostics)?;
     
     // Save parameter trajectory
-    save_parameter_trajectory(output_dir, results)?;
+    save_parameter_trajectory(output_dir, results, format)?;
     
     // Save summary report
-    save_summary_report(output_dir, results, diagnostics)?;
+    save_summary_report(output_dir, results, diagnostics, model, format)?;
+    
+    // Save derived secondary parameters
+    save_secondary_parameters(output_dir, model, &results.fixed_effects, None, format)?;
+    
+    // Save per-parameter shrinkage
+    save_shrinkage_csv(output_dir, &diagnostics::parameter_shrinkage(results, &diagnostics.residual_analysis.iwres))?;
+    
+    save_qq_data_csv(output_dir, &diagnostics::qq_data(&diagnostics.residual_analysis.standardized_residuals))?;
     
     // Save predictions CSV
     save_predictions_csv(output_dir, results, dataset, model)?;
     
+    if !results.mcmc_samples.is_empty() {
+        save_mcmc_samples_csv(output_dir, results, dataset)?;
+    }
+    
     info!("All results saved successfully");
     Ok(())
 }
@@ -62,6 +107,7 @@ fn save_diagnostics(
 fn save_parameter_trajectory(
     output_dir: &Path,
     results: &SaemResults,
+    format: NumberFormat,
 ) -> Result<(), anyhow::Error> {
     let trajectory_file = output_dir.join("parameter_trajectory.csv");
     let mut wtr = csv::Writer::from_path(trajectory_file)?;
@@ -81,9 +127,9 @@ fn save_parameter_trajectory(
     {
         let mut record = vec![iter.to_string()];
         for &param in params.iter() {
-            record.push(param.to_string());
+            record.push(format.format(param));
         }
-        record.push(log_like.to_string());
+        record.push(format.format(log_like));
         wtr.write_record(&record)?;
     }
     
@@ -91,10 +137,17 @@ fn save_parameter_trajectory(
     Ok(())
 }
 
+/// Unit of parameter `index` for the report tables, `-` when it has none.
+pub(crate) fn unit_label(units: &[Option<String>], index: usize) -> &str {
+    units.get(index).and_then(|unit| unit.as_deref()).unwrap_or("-")
+}
+
 fn save_summary_report(
     output_dir: &Path,
     results: &SaemResults,
     diagnostics: &DiagnosticResults,
+    model: &CompartmentModel,
+    format: NumberFormat,
 ) -> Result<(), anyhow::Error> {
     let report_file = output_dir.join("summary_report.txt");
     
@@ -103,6 +156,9 @@ fn save_summary_report(
     report.push_str("=================================\n\n");
     
     report.push_str(&format!("Model Convergence: {}\n", results.converged));
+    if results.time_budget_exceeded {
+        report.push_str("Stopped Early: time budget spent\n");
+    }
     report.push_str(&format!("Total Iterations: {}\n", results.n_iterations));
     report.push_str(&format!("Final Log-Likelihood: {:.6}\n", results.final_log_likelihood));
     report.push_str(&format!("Objective Function Value: {:.6}\n", results.objective_function_value));
@@ -113,22 +169,59 @@ fn save_summary_report(
     report.push_str(&format!("BIC: {:.6}\n", diagnostics.goodness_of_fit.bic));
     report.push_str(&format!("R-squared: {:.6}\n", diagnostics.goodness_of_fit.r_squared));
     report.push_str(&format!("RMSE: {:.6}\n", diagnostics.goodness_of_fit.rmse));
+    report.push_str(&format!("Standardized Residual Q-Q Correlation: {:.6}\n", diagnostics.residual_analysis.qq_correlation));
+    if let Some(summary) = &diagnostics.residual_analysis.iwres_autocorrelation_summary {
+        report.push_str(&format!("IWRES Lag-1 Autocorrelation: median {:.4} (5th-95th percentile {:.4} to {:.4}, {} individuals)\n",
+                                 summary.median, summary.percentile_5, summary.percentile_95, summary.n_individuals));
+    }
+    report.push_str(&format!("ODE Solves: {}\n", results.evaluation_counts.ode_solves));
+    report.push_str(&format!("Derivative Evaluations: {}\n", results.evaluation_counts.derivative_evaluations));
+    report.push_str(&format!("Negative State Corrections: {}\n", results.evaluation_counts.negative_state_corrections));
     
     report.push_str("\nFixed Effects Parameter Estimates:\n");
     report.push_str("----------------------------------\n");
-    report.push_str(&format!("{:<10} {:<12} {:<10}\n", "Parameter", "Estimate", "%RSE"));
-    report.push_str(&format!("{:<10} {:<12} {:<10}\n", "---------", "--------", "----"));
-    for param_stat in &results.parameter_statistics {
-        report.push_str(&format!("{:<10} {:<12.6} {:<10.2}\n", 
-            param_stat.name, param_stat.estimate, param_stat.rse_percent));
+    report.push_str(&format!("{:<10} {:<8} {:<12} {:<10}\n", "Parameter", "Unit", "Estimate", "%RSE"));
+    report.push_str(&format!("{:<10} {:<8} {:<12} {:<10}\n", "---------", "----", "--------", "----"));
+    // Estimates are reported in their units, exp of the log-scale THETA
+    for (i, param_stat) in results.parameter_statistics.iter().enumerate() {
+        let flag = if results.parameters_at_bounds.contains(&param_stat.name) { " (at bound)" } else { "" };
+        report.push_str(&format!("{:<10} {:<8} {:<12} {:<10.2}{}\n", 
+            param_stat.name, unit_label(&results.parameter_units, i), format.format(param_stat.estimate.exp()), param_stat.rse_percent, flag));
     }
     
-    report.push_str(&format!("\nResidual Error Variance: {:.6}\n", results.residual_variance));
+    let poorly_identified = diagnostics::poorly_identified_parameters(&results.parameter_names, &results.rse_percent());
+    if !poorly_identified.is_empty() {
+        report.push_str(&format!("\nWARNING: Poorly Identified Parameters (%RSE > {}):\n", diagnostics::HIGH_RSE_THRESHOLD));
+        for (name, rse) in &poorly_identified {
+            report.push_str(&format!("  {}: {:.1}%\n", name, rse));
+        }
+    }
+    
+    if let Ok(secondary) = secondary_parameters(model.model_type(), &results.fixed_effects) {
+        report.push_str("\nSecondary Parameters:\n");
+        report.push_str("---------------------\n");
+        for param in &secondary {
+            report.push_str(&format!("{:<10} {:<12}\n", param.name, format.format(param.value)));
+        }
+    }
+    
+    if results.error_model == ErrorModel::Exponential {
+        report.push_str(&format!("\nResidual Error Variance (exponential, log scale): {}\n", format.format(results.residual_variance)));
+    } else {
+        report.push_str(&format!("\nResidual Error Variance: {}\n", format.format(results.residual_variance)));
+    }
+    if let Some(variance) = results.effect_residual_variance {
+        report.push_str(&format!("Effect Residual Error Variance: {}\n", format.format(variance)));
+    }
+    for (compartment, &variance) in &results.output_residual_variances {
+        report.push_str(&format!("CMT {} Residual Error Variance: {}\n", compartment, format.format(variance)));
+    }
     
     report.push_str("\nRandom Effects Variance (Omega):\n");
     report.push_str("-------------------------------\n");
-    report.push_str(&format!("{:<15} {:<12} {:<12}\n", "Parameter", "Estimate", "Shrinkage%"));
-    report.push_str(&format!("{:<15} {:<12} {:<12}\n", "---------", "--------", "----------"));
+    report.push_str(&format!("{:<15} {:<12} {:<8} {:<12}\n", "Parameter", "Estimate", "%CV", "Shrinkage%"));
+    report.push_str(&format!("{:<15} {:<12} {:<8} {:<12}\n", "---------", "--------", "---", "----------"));
+    let cv_percent = diagnostics::iiv_cv_percent(&results.random_effects_variance);
     for omega_stat in &results.omega_statistics {
         if omega_stat.parameter_i == omega_stat.parameter_j {
             let shrinkage_text = if let Some(shrinkage) = omega_stat.shrinkage_percent {
@@ -136,13 +229,24 @@ fn save_summary_report(
             } else {
                 "N/A".to_string()
             };
-            report.push_str(&format!("{:<15} {:<12.6} {:<12}\n", 
+            let cv_text = results.parameter_names.iter()
+                .position(|name| name == &omega_stat.parameter_i)
+                .map_or("N/A".to_string(), |i| format!("{:.1}", cv_percent[i]));
+            report.push_str(&format!("{:<15} {:<12} {:<8} {:<12}\n", 
                 format!("{}({})", omega_stat.parameter_i, omega_stat.parameter_i),
-                omega_stat.estimate, shrinkage_text));
+                format.format(omega_stat.estimate), cv_text, shrinkage_text));
         } else if omega_stat.estimate.abs() > 1e-10 {
-            report.push_str(&format!("{:<15} {:<12.6} {:<12}\n", 
+            report.push_str(&format!("{:<15} {:<12} {:<8} {:<12}\n", 
                 format!("{}({})", omega_stat.parameter_i, omega_stat.parameter_j),
-                omega_stat.estimate, "N/A"));
+                format.format(omega_stat.estimate), "N/A", "N/A"));
+        }
+    }
+    
+    if !results.parameter_acceptance.is_empty() {
+        report.push_str("\nMCMC Acceptance Rate (post burn-in):\n");
+        report.push_str("-----------------------------------\n");
+        for (name, rate) in results.parameter_names.iter().zip(&results.parameter_acceptance) {
+            report.push_str(&format!("{:<10} {:.3}\n", name, rate));
         }
     }
     
@@ -150,138 +254,358 @@ fn save_summary_report(
     Ok(())
 }
 
-fn save_predictions_csv(
+/// Writes `secondary_parameters.csv`. With a covariance matrix of the fixed
+/// effects, delta-method SEs and 95% CIs are added.
+pub fn save_secondary_parameters(
     output_dir: &Path,
-    results: &SaemResults,
-    dataset: &Dataset,
     model: &CompartmentModel,
+    fixed_effects: &[f64],
+    covariance: Option<&[Vec<f64>]>,
+    format: NumberFormat,
 ) -> Result<(), anyhow::Error> {
-    let predictions_file = output_dir.join("predictions.csv");
-    let mut wtr = csv::Writer::from_path(predictions_file)?;
+    // Custom models have no known disposition structure; nothing to write
+    let secondary = match secondary_parameters(model.model_type(), fixed_effects) {
+        Ok(secondary) => secondary,
+        Err(_) => return Ok(()),
+    };
     
-    // Write header
-    wtr.write_record(&["ID", "TIME", "DV", "IPRED", "PRED"])?;
+    let secondary_file = output_dir.join("secondary_parameters.csv");
+    let mut wtr = csv::Writer::from_path(secondary_file)?;
     
-    let solver = RungeKuttaSolver::new();
-    let solver_config = SolverConfig::default();
+    match covariance.map(|cov| secondary_parameter_estimates(model.model_type(), fixed_effects, cov)) {
+        Some(Ok(estimates)) => {
+            wtr.write_record(["Parameter", "Value", "SE", "CI_Lower", "CI_Upper"])?;
+            for param in &estimates {
+                wtr.write_record([
+                    param.name.clone(),
+                    format.format(param.value),
+                    format.format(param.standard_error),
+                    format.format(param.ci_lower),
+                    format.format(param.ci_upper),
+                ])?;
+            }
+        }
+        _ => {
+            wtr.write_record(["Parameter", "Value"])?;
+            for param in &secondary {
+                wtr.write_record([param.name.clone(), format.format(param.value)])?;
+            }
+        }
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `shrinkage.csv`, one row per parameter. Undefined shrinkage is left blank.
+pub fn save_shrinkage_csv(output_dir: &Path, shrinkage: &[ParameterShrinkage]) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("shrinkage.csv"))?;
+    wtr.write_record(["Parameter", "Omega", "Eta_SD", "Eta_Shrinkage_Percent", "Epsilon_Shrinkage_Percent"])?;
     
-    // Calculate population predictions using population parameters
-    let pop_params = model.default_parameters();
-    let mut pop_params_final = pop_params.clone();
-    pop_params_final.fixed_effects = results.fixed_effects.clone();
+    let percent = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+    for row in shrinkage {
+        wtr.write_record([
+            row.parameter.clone(),
+            row.omega.to_string(),
+            row.eta_sd.to_string(),
+            percent(row.eta_shrinkage_percent),
+            percent(row.epsilon_shrinkage_percent),
+        ])?;
+    }
     
-    for (&id, individual) in dataset.individuals() {
-        // Get individual parameters
-        let ind_params = results.individual_parameters.get(&id)
-            .unwrap_or(&results.fixed_effects);
-        
-        // Calculate individual predictions (IPRED)
-        let ipred = calculate_predictions(individual, ind_params, model, &solver, &solver_config)?;
-        
-        // Calculate population predictions (PRED) 
-        let pred = calculate_predictions(individual, &results.fixed_effects, model, &solver, &solver_config)?;
-        
-        // Write data for each observation
-        for (obs_idx, obs) in individual.observations().iter().enumerate() {
-            let ipred_value = ipred.get(obs_idx).copied().unwrap_or(0.0);
-            let pred_value = pred.get(obs_idx).copied().unwrap_or(0.0);
-            
-            wtr.write_record(&[
-                id.to_string(),
-                obs.time.to_string(),
-                obs.value.to_string(),
-                ipred_value.to_string(),
-                pred_value.to_string(),
-            ])?;
-        }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `qq_data.csv`, one row per standardized residual in ascending
+/// order with its theoretical normal quantile.
+pub fn save_qq_data_csv(output_dir: &Path, qq: &QqData) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("qq_data.csv"))?;
+    wtr.write_record(["RANK", "THEORETICAL_QUANTILE", "STANDARDIZED_RESIDUAL"])?;
+    
+    for (rank, (quantile, residual)) in qq.theoretical_quantiles.iter().zip(&qq.sorted_residuals).enumerate() {
+        wtr.write_record([(rank + 1).to_string(), quantile.to_string(), residual.to_string()])?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `individual_ofv.csv`: each individual's joint objective split into
+/// the data-likelihood and eta-prior terms, which sum to `OFV`, under the
+/// dataset's own subject identifiers.
+pub fn save_individual_ofv_csv(
+    output_dir: &Path,
+    objectives: &[IndividualObjective],
+    dataset: &Dataset,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("individual_ofv.csv"))?;
+    wtr.write_record(["ID", "OFV_DATA", "OFV_PRIOR", "OFV"])?;
+    
+    for objective in objectives {
+        wtr.write_record([
+            dataset.id_label(objective.id),
+            objective.data.to_string(),
+            objective.prior.to_string(),
+            objective.total().to_string(),
+        ])?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `objective_trajectory.csv`: the FOCE objective after each outer
+/// iteration, the counterpart of SAEM's `parameter_trajectory.csv`.
+pub fn save_objective_trajectory_csv(
+    output_dir: &Path,
+    trajectory: &[f64],
+    format: NumberFormat,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("objective_trajectory.csv"))?;
+    wtr.write_record(["iteration", "objective"])?;
+    
+    for (iter, &objective) in trajectory.iter().enumerate() {
+        wtr.write_record([iter.to_string(), format.format(objective)])?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `mcmc_samples.csv`: one row per retained MCMC draw with the etas
+/// of each parameter.
+pub fn save_mcmc_samples_csv(
+    output_dir: &Path,
+    results: &SaemResults,
+    dataset: &Dataset,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("mcmc_samples.csv"))?;
+    let mut header = vec!["ITERATION".to_string(), "ID".to_string(), "SAMPLE".to_string()];
+    header.extend(results.parameter_names.iter().map(|name| format!("ETA_{}", name)));
+    wtr.write_record(&header)?;
+    for draw in &results.mcmc_samples {
+        let mut record = vec![draw.iteration.to_string(), dataset.id_label(draw.id), draw.sample.to_string()];
+        record.extend(draw.eta.iter().map(|eta| eta.to_string()));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `gof_by_stratum.csv` with one row of fit metrics per stratum.
+pub fn save_gof_by_stratum_csv(output_dir: &Path, strata: &[StratumGoodnessOfFit]) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("gof_by_stratum.csv"))?;
+    wtr.write_record(["Stratum", "N_Individuals", "N_Observations", "RMSE", "MAE", "R_Squared"])?;
+    for stratum in strata {
+        wtr.write_record([
+            stratum.stratum.clone(),
+            stratum.n_individuals.to_string(),
+            stratum.n_observations.to_string(),
+            stratum.rmse.to_string(),
+            stratum.mae.to_string(),
+            stratum.r_squared.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `covariate_effects.csv` with one forest-plot row per effect and
+/// covariate value.
+pub fn save_covariate_effects_csv(output_dir: &Path, effects: &[CovariateForestEffect]) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("covariate_effects.csv"))?;
+    wtr.write_record(["Parameter", "Covariate", "Label", "Value", "Ratio", "Lower_95", "Upper_95"])?;
+    for effect in effects {
+        wtr.write_record([
+            effect.parameter.clone(),
+            effect.covariate.clone(),
+            effect.label.clone(),
+            effect.value.to_string(),
+            effect.ratio.to_string(),
+            effect.lower.to_string(),
+            effect.upper.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `correlation_matrix.csv`: a header of parameter names and one row
+/// per parameter.
+pub fn save_correlation_matrix_csv(
+    output_dir: &Path,
+    parameter_names: &[String],
+    correlation: &[Vec<f64>],
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("correlation_matrix.csv"))?;
+    let mut header = vec!["Parameter".to_string()];
+    header.extend(parameter_names.iter().cloned());
+    wtr.write_record(&header)?;
+    
+    for (name, row) in parameter_names.iter().zip(correlation) {
+        let mut record = vec![name.clone()];
+        record.extend(row.iter().map(|value| value.to_string()));
+        wtr.write_record(&record)?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes `profile_<parameter>.csv`: one row per profiled value with its
+/// OFV, the rise above the profile's minimum and whether the refit
+/// converged.
+pub fn save_likelihood_profile_csv(
+    output_dir: &Path,
+    profile: &LikelihoodProfile,
+    format: NumberFormat,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join(format!("profile_{}.csv", profile.parameter)))?;
+    wtr.write_record([profile.parameter.as_str(), "OFV", "DELTA_OFV", "CONVERGED"])?;
+    
+    for (point, delta) in profile.points.iter().zip(profile.delta_objective()) {
+        wtr.write_record([
+            format.format(point.value),
+            point.objective_function_value.to_string(),
+            delta.to_string(),
+            point.converged.to_string(),
+        ])?;
     }
     
     wtr.flush()?;
     Ok(())
 }
 
-fn calculate_predictions(
-    individual: &crate::data::Individual,
-    params: &[f64],
+/// Writes `best_model_profiles.csv`: IPRED and PRED on a grid of `n_points`
+/// times from 0 to each individual's last dose or observation. PRED is the
+/// typical individual (population parameters) on the same regimen.
+pub fn save_prediction_profiles(
+    output_dir: &Path,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+    fixed_effects: &[f64],
+    individual_parameters: &HashMap<i32, Vec<f64>>,
+    solver_config: &SolverConfig,
+    n_points: usize,
+) -> Result<(), anyhow::Error> {
+    write_grid_predictions(
+        &output_dir.join("best_model_profiles.csv"),
+        dataset,
+        model,
+        fixed_effects,
+        individual_parameters,
+        solver_config,
+        |individual| {
+            let last_dose = individual.dosing_records().iter().map(|d| d.time).fold(0.0, f64::max);
+            let end_time = individual.last_observation_time().unwrap_or(0.0).max(last_dose);
+            prediction::time_grid(0.0, end_time, n_points)
+        },
+    )
+}
+
+/// Writes `best_model_grid_predictions.csv`: IPRED and PRED at exactly
+/// `times` (ascending) for every individual, whatever their observation
+/// times, for overlaying the fit on plots made elsewhere.
+pub fn save_grid_predictions(
+    output_dir: &Path,
+    dataset: &Dataset,
     model: &CompartmentModel,
-    solver: &dyn OdeSolver,
+    fixed_effects: &[f64],
+    individual_parameters: &HashMap<i32, Vec<f64>>,
     solver_config: &SolverConfig,
-) -> Result<Vec<f64>, anyhow::Error> {
-    use crate::models::{ModelState, ModelParameters};
-    use crate::solver::OdeSystem;
+    times: &[f64],
+) -> Result<(), anyhow::Error> {
+    write_grid_predictions(
+        &output_dir.join("best_model_grid_predictions.csv"),
+        dataset,
+        model,
+        fixed_effects,
+        individual_parameters,
+        solver_config,
+        |_| times.to_vec(),
+    )
+}
+
+/// ID, TIME, IPRED and PRED at the times `grid` picks for each individual,
+/// in ID order, solved with the fit's `solver_config`.
+fn write_grid_predictions(
+    path: &Path,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+    fixed_effects: &[f64],
+    individual_parameters: &HashMap<i32, Vec<f64>>,
+    solver_config: &SolverConfig,
+    grid: impl Fn(&Individual) -> Vec<f64>,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
     
-    // Create temporary parameters for this prediction
-    let mut temp_params = model.default_parameters();
-    temp_params.fixed_effects = params.to_vec();
+    wtr.write_record(["ID", "TIME", "IPRED", "PRED"])?;
     
-    let system = CompartmentSystem {
-        model,
-        params: &temp_params,
-    };
+    let solver = RungeKuttaSolver::new();
     
-    let mut predictions = Vec::new();
-    let mut current_state = ModelState::new(model.n_compartments());
-    let mut last_time = 0.0;
-    
-    // Apply dosing events
-    for dose in individual.dosing_records() {
-        if dose.time > last_time {
-            let final_state = solver.solve_to_time(
-                &system,
-                last_time,
-                dose.time,
-                &current_state.compartments,
-                solver_config,
-            )?;
-            current_state.compartments = final_state;
-            current_state.time = dose.time;
-        }
-        
-        current_state.add_dose(dose.compartment as usize, dose.amount);
-        last_time = dose.time;
-    }
+    let mut pop_params = model.default_parameters();
+    pop_params.fixed_effects = fixed_effects.to_vec();
+    
+    let mut ids: Vec<i32> = dataset.individuals().keys().copied().collect();
+    ids.sort_unstable();
     
-    // Predict concentrations at observation times
-    for obs in individual.observations() {
-        if obs.time > last_time {
-            let final_state = solver.solve_to_time(
-                &system,
-                last_time,
-                obs.time,
-                &current_state.compartments,
-                solver_config,
-            )?;
-            current_state.compartments = final_state;
-            current_state.time = obs.time;
-            last_time = obs.time;
+    for id in ids {
+        let individual = &dataset.individuals()[&id];
+        let times = grid(individual);
+        
+        let mut ind_params = pop_params.clone();
+        if let Some(values) = individual_parameters.get(&id) {
+            ind_params.fixed_effects = values.clone();
         }
         
-        let concentration = model.observation_function(
-            &current_state,
-            &temp_params,
-            obs.compartment as usize,
-        );
-        predictions.push(concentration);
+        let ipred = prediction::predict_at_times(model, &solver, individual, &ind_params, &times, solver_config)?;
+        let pred = prediction::predict_at_times(model, &solver, individual, &pop_params, &times, solver_config)?;
+        
+        for ((time, ipred_value), pred_value) in times.iter().zip(ipred).zip(pred) {
+            wtr.write_record([
+                dataset.id_label(id),
+                time.to_string(),
+                ipred_value.to_string(),
+                pred_value.to_string(),
+            ])?;
+        }
     }
     
-    Ok(predictions)
+    wtr.flush()?;
+    Ok(())
 }
 
-struct CompartmentSystem<'a> {
-    model: &'a CompartmentModel,
-    params: &'a ModelParameters,
+fn save_predictions_csv(
+    output_dir: &Path,
+    results: &SaemResults,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<(), anyhow::Error> {
+    write_predictions_csv(&output_dir.join("predictions.csv"), results, dataset, model)
 }
 
-impl<'a> OdeSystem for CompartmentSystem<'a> {
-    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
-        let state = ModelState {
-            compartments: y.clone(),
-            time: t,
-        };
-        self.model.derivatives(&state, self.params)
-    }
+/// Writes ID, TIME, DV, IPRED and PRED for every observation, in ID order.
+pub fn write_predictions_csv(
+    path: &Path,
+    results: &SaemResults,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record(["ID", "TIME", "DV", "IPRED", "PRED"])?;
 
-    fn dimension(&self) -> usize {
-        self.model.n_compartments()
+    let predictions = prediction::individual_predictions(results, dataset, model)?;
+    for (id, obs, &(time, ipred, pred)) in dataset.iter_observations_with(&predictions) {
+        wtr.write_record(&[
+            dataset.id_label(id),
+            time.to_string(),
+            obs.value.to_string(),
+            ipred.to_string(),
+            pred.to_string(),
+        ])?;
     }
-}
\ No newline at end of file
+
+    wtr.flush()?;
+    Ok(())
+}
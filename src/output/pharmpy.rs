@@ -0,0 +1,176 @@
+use crate::estimation::FoceResults;
+use crate::saem::SaemResults;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Identifies the layout so downstream readers can check compatibility.
+pub const PHARMPY_FORMAT: &str = "nmodes-pharmpy-1";
+
+/// Model fit results laid out after Pharmpy's `ModelfitResults`: NONMEM-style
+/// parameter names (`THETA(1)`, `OMEGA(1,1)`, `SIGMA(1,1)`) with estimates,
+/// SEs and RSEs, plus the objective function and condition number.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PharmpyResults {
+    pub format: String,
+    pub model: String,
+    pub estimation_method: String,
+    pub ofv: f64,
+    pub minimization_successful: bool,
+    pub parameter_estimates: Vec<PharmpyParameter>,
+    pub covariance_matrix: Option<Vec<Vec<f64>>>,
+    pub condition_number: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PharmpyParameter {
+    pub name: String,
+    /// Model parameter the entry refers to, e.g. `CL` for `THETA(1)`.
+    pub label: String,
+    pub estimate: f64,
+    pub standard_error: Option<f64>,
+    pub relative_standard_error: Option<f64>,
+}
+
+impl PharmpyResults {
+    pub fn from_saem(results: &SaemResults, model: &str) -> Self {
+        let theta_ses: Vec<Option<f64>> = results.fixed_effects.iter().enumerate()
+            .map(|(i, &estimate)| {
+                results.parameter_statistics.get(i)
+                    .map(|stat| stat.rse_percent / 100.0 * estimate.abs())
+            })
+            .collect();
+
+        Self {
+            format: PHARMPY_FORMAT.to_string(),
+            model: model.to_string(),
+            estimation_method: "SAEM".to_string(),
+            ofv: results.objective_function_value,
+            minimization_successful: results.converged,
+            parameter_estimates: parameter_entries(
+                &results.parameter_names,
+                &results.fixed_effects,
+                &theta_ses,
+                &results.random_effects_variance,
+                results.residual_variance,
+            ),
+            covariance_matrix: None,
+            condition_number: None,
+        }
+    }
+
+    pub fn from_foce(results: &FoceResults, model: &str, estimation_method: &str) -> Self {
+        let theta_ses: Vec<Option<f64>> = (0..results.fixed_effects.len())
+            .map(|i| results.standard_errors.get(i).copied())
+            .collect();
+
+        Self {
+            format: PHARMPY_FORMAT.to_string(),
+            model: model.to_string(),
+            estimation_method: estimation_method.to_string(),
+            ofv: results.objective_function_value,
+            minimization_successful: results.converged,
+            parameter_estimates: parameter_entries(
+                &results.parameter_names,
+                &results.fixed_effects,
+                &theta_ses,
+                &results.random_effects_variance,
+                results.residual_variance,
+            ),
+            covariance_matrix: Some(results.covariance_matrix.clone()),
+            condition_number: Some(results.hessian_condition_number),
+        }
+    }
+}
+
+fn parameter_entries(
+    names: &[String],
+    fixed_effects: &[f64],
+    theta_ses: &[Option<f64>],
+    omega: &[Vec<f64>],
+    sigma: f64,
+) -> Vec<PharmpyParameter> {
+    let mut entries = Vec::new();
+
+    for (i, (&estimate, &se)) in fixed_effects.iter().zip(theta_ses.iter()).enumerate() {
+        entries.push(PharmpyParameter {
+            name: format!("THETA({})", i + 1),
+            label: names.get(i).cloned().unwrap_or_default(),
+            estimate,
+            standard_error: se,
+            relative_standard_error: se.filter(|_| estimate.abs() > 1e-10).map(|se| se / estimate.abs()),
+        });
+    }
+
+    // Lower triangle of Omega, skipping zero off-diagonal elements
+    for (i, row) in omega.iter().enumerate() {
+        for (j, &estimate) in row.iter().enumerate().take(i + 1) {
+            if i != j && estimate == 0.0 {
+                continue;
+            }
+            let label_i = names.get(i).cloned().unwrap_or_default();
+            let label_j = names.get(j).cloned().unwrap_or_default();
+            entries.push(PharmpyParameter {
+                name: format!("OMEGA({},{})", i + 1, j + 1),
+                label: if i == j { label_i } else { format!("{}-{}", label_i, label_j) },
+                estimate,
+                standard_error: None,
+                relative_standard_error: None,
+            });
+        }
+    }
+
+    entries.push(PharmpyParameter {
+        name: "SIGMA(1,1)".to_string(),
+        label: "RUV".to_string(),
+        estimate: sigma,
+        standard_error: None,
+        relative_standard_error: None,
+    });
+
+    entries
+}
+
+/// Writes `pharmpy_results.json` into `output_dir`.
+pub fn save_pharmpy_results(output_dir: &Path, results: &PharmpyResults) -> Result<(), anyhow::Error> {
+    let json_content = serde_json::to_string_pretty(results)?;
+    fs::write(output_dir.join("pharmpy_results.json"), json_content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pharmpy_json_round_trip() {
+        let mut results = FoceResults::new(2, vec!["CL".to_string(), "V".to_string()]);
+        results.fixed_effects = vec![0.7, 3.0];
+        results.standard_errors = vec![0.07, 0.15];
+        results.random_effects_variance = vec![vec![0.09, 0.0], vec![0.0, 0.04]];
+        results.residual_variance = 0.01;
+        results.objective_function_value = 123.4;
+        results.hessian_condition_number = 42.0;
+
+        let output_dir = std::env::temp_dir().join("pharmpy_round_trip_test");
+        fs::create_dir_all(&output_dir).unwrap();
+        save_pharmpy_results(&output_dir, &PharmpyResults::from_foce(&results, "one-compartment", "FOCE")).unwrap();
+
+        let json = fs::read_to_string(output_dir.join("pharmpy_results.json")).unwrap();
+        fs::remove_dir_all(&output_dir).ok();
+        let parsed: PharmpyResults = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.format, PHARMPY_FORMAT);
+        assert_eq!(parsed.ofv, 123.4);
+        assert_eq!(parsed.condition_number, Some(42.0));
+
+        let names: Vec<&str> = parsed.parameter_estimates.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["THETA(1)", "THETA(2)", "OMEGA(1,1)", "OMEGA(2,2)", "SIGMA(1,1)"]);
+
+        let theta1 = &parsed.parameter_estimates[0];
+        assert_eq!(theta1.label, "CL");
+        assert_eq!(theta1.standard_error, Some(0.07));
+        assert!((theta1.relative_standard_error.unwrap() - 0.1).abs() < 1e-12);
+        assert_eq!(parsed.parameter_estimates[4].estimate, 0.01);
+    }
+}
@@ -0,0 +1,1119 @@
+use crate::data::{Dataset, DatasetSummary};
+use crate::diagnostics::{self, InformationCriterion};
+use crate::estimation::{self, ErrorModel, EstimationConfig, EstimationMethod, FoceEstimator, FoceResults, LikelihoodProfile};
+use crate::models::{self, CompartmentModel, FittedModel, ModelParameters, ModelType};
+use crate::output;
+use crate::saem::{SaemEstimator, SaemResults};
+use crate::solver::SolverConfig;
+use crate::validation;
+use crate::vpc;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Grid size for the dense profiles written when `profiles` is set.
+pub const PROFILE_GRID_POINTS: usize = 200;
+
+/// Refits per parameter in a likelihood profile.
+pub const PROFILE_LIKELIHOOD_POINTS: usize = 21;
+
+/// Log-scale distance either side of the estimate a likelihood profile
+/// spans, from about 0.37 to 2.7 times the estimate.
+pub const PROFILE_LIKELIHOOD_LOG_SPAN: f64 = 1.0;
+
+/// Settings for a full analysis run; the command-line options map onto
+/// these one to one.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub dataset_path: PathBuf,
+    pub model_types: Vec<ModelType>,
+    pub estimation_methods: Vec<EstimationMethod>,
+    pub output_dir: PathBuf,
+    pub iterations: usize,
+    pub burn_in: usize,
+    pub chains: usize,
+    /// Write the comparison report even when only one analysis was run.
+    pub compare_results: bool,
+    /// Write dense predicted profiles for the best model by `rank_by`.
+    pub profiles: bool,
+    /// Ascending times at which to write the best model's predictions for
+    /// every individual.
+    pub predict_grid: Option<Vec<f64>>,
+    /// Fixed effects to profile the likelihood of for every analysis,
+    /// writing `profile_<parameter>.csv`.
+    pub profile_parameters: Vec<String>,
+    /// Also write `pharmpy_results.json` for each analysis.
+    pub pharmpy_format: bool,
+    pub vpc: Option<vpc::VpcConfig>,
+    /// Covariate column (or `AMT` for the first dose) whose baseline value
+    /// splits individuals for `gof_by_stratum.csv`.
+    pub gof_strata: Option<String>,
+    /// Write every post-burn-in SAEM MCMC draw to `mcmc_samples.csv`.
+    pub mcmc_samples: bool,
+    /// Block or componentwise Metropolis updates in SAEM.
+    pub mcmc_update: estimation::McmcUpdate,
+    pub auto_init: bool,
+    pub error_model: ErrorModel,
+    /// Gauss–Hermite nodes per random effect for AGQ analyses.
+    pub agq_nodes: usize,
+    /// Log an estimated time to completion every this many iterations.
+    pub timing_interval: Option<usize>,
+    /// Wall-clock limit on each analysis's fit.
+    pub time_budget: Option<Duration>,
+    /// Subjects to trace each iteration at trace level, by their ID as
+    /// written in the dataset; all when empty.
+    pub trace_individuals: Vec<String>,
+    /// Report OFVs with the ln(2π) constants, as opposed to NONMEM's.
+    pub include_likelihood_constants: bool,
+    /// Whether repeated (ID, TIME, compartment) observations are an error or
+    /// reduced to the first.
+    pub duplicate_observations: validation::DuplicateObservations,
+    /// Plausibility bounds on observed values, warned about when exceeded.
+    pub validation: validation::ValidationConfig,
+    /// Compartment whose observations are effect (PD) responses with their
+    /// own residual variance.
+    pub effect_compartment: Option<i32>,
+    /// Precision of parameter values in CSV files and text reports.
+    pub number_format: output::NumberFormat,
+    /// Criterion for the comparison ranking and the best model.
+    pub rank_by: InformationCriterion,
+}
+
+impl PipelineConfig {
+    /// A one-compartment SAEM run with the command-line defaults.
+    pub fn new(dataset_path: impl Into<PathBuf>, output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dataset_path: dataset_path.into(),
+            model_types: vec![ModelType::OneCompartment],
+            estimation_methods: vec![EstimationMethod::Saem],
+            output_dir: output_dir.into(),
+            iterations: 1000,
+            burn_in: 200,
+            chains: 4,
+            compare_results: false,
+            profiles: false,
+            predict_grid: None,
+            profile_parameters: Vec::new(),
+            pharmpy_format: false,
+            vpc: None,
+            gof_strata: None,
+            mcmc_samples: false,
+            mcmc_update: estimation::McmcUpdate::Block,
+            auto_init: false,
+            error_model: ErrorModel::Additive,
+            agq_nodes: 5,
+            timing_interval: None,
+            time_budget: None,
+            trace_individuals: Vec::new(),
+            include_likelihood_constants: true,
+            duplicate_observations: validation::DuplicateObservations::Error,
+            validation: validation::ValidationConfig::default(),
+            effect_compartment: None,
+            number_format: output::NumberFormat::Full,
+            rank_by: InformationCriterion::Aic,
+        }
+    }
+
+    pub fn with_models(mut self, model_types: Vec<ModelType>) -> Self {
+        self.model_types = model_types;
+        self
+    }
+
+    pub fn with_methods(mut self, estimation_methods: Vec<EstimationMethod>) -> Self {
+        self.estimation_methods = estimation_methods;
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_burnin(mut self, burn_in: usize) -> Self {
+        self.burn_in = burn_in;
+        self
+    }
+}
+
+/// Summary of one model/method fit, as used for comparison and ranking.
+#[derive(Debug, Clone)]
+pub struct AnalysisResult {
+    pub model_type: ModelType,
+    pub estimation_method: EstimationMethod,
+    pub objective_function_value: f64,
+    pub final_log_likelihood: f64,
+    pub converged: bool,
+    pub n_iterations: usize,
+    pub fixed_effects: Vec<f64>,
+    pub individual_parameters: HashMap<i32, Vec<f64>>,
+    pub parameter_names: Vec<String>,
+    pub aic: f64,
+    pub bic: f64,
+    pub aicc: f64,
+    pub bicc: f64,
+    pub rmse: f64,
+    pub r_squared: f64,
+    /// Solver settings of the fit, reused for its grid predictions.
+    pub solver_config: SolverConfig,
+    /// Directory holding this analysis' output files.
+    pub output_dir: PathBuf,
+}
+
+/// What `run_pipeline` would do with a configuration: the validated
+/// dataset's summary and the analyses it would fit, in order.
+#[derive(Debug, Clone)]
+pub struct PipelinePlan {
+    pub dataset: DatasetSummary,
+    /// Subject identifiers excluded by validation.
+    pub excluded_individuals: Vec<String>,
+    pub analyses: Vec<(ModelType, EstimationMethod)>,
+}
+
+impl std::fmt::Display for PipelinePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Dataset:")?;
+        for line in self.dataset.to_string().lines() {
+            writeln!(f, "  {}", line)?;
+        }
+        if !self.excluded_individuals.is_empty() {
+            writeln!(f, "Excluded individuals: {}", self.excluded_individuals.join(", "))?;
+        }
+        writeln!(f, "Planned analyses: {}", self.analyses.len())?;
+        for (model_type, method) in &self.analyses {
+            writeln!(f, "  {} + {}", model_type, method)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads and validates the dataset and lists the model/method combinations
+/// `run_pipeline` would fit, without fitting or writing anything.
+pub fn plan_pipeline(config: &PipelineConfig) -> Result<PipelinePlan> {
+    let (dataset, excluded_individuals) = load_dataset(config)?;
+    let analyses = config.model_types.iter()
+        .flat_map(|model_type| config.estimation_methods.iter().map(move |method| (model_type.clone(), method.clone())))
+        .collect();
+    Ok(PipelinePlan { dataset: dataset.summary(), excluded_individuals, analyses })
+}
+
+/// Reads the dataset and prepares it as configured, then validates it
+/// against every model. Returns it with the subject identifiers of the
+/// individuals validation excluded.
+fn load_dataset(config: &PipelineConfig) -> Result<(Dataset, Vec<String>)> {
+    info!("Loading dataset...");
+    let mut dataset = Dataset::from_csv(&config.dataset_path)?;
+    info!("Loaded {} individuals with {} observations", 
+          dataset.n_individuals(), dataset.n_observations());
+
+    if let Some(compartment) = config.effect_compartment {
+        let retyped = dataset.set_effect_compartment(compartment);
+        info!("{} observation(s) in compartment {} are effect observations", retyped, compartment);
+    }
+
+    // Validate dataset
+    let dropped = validation::resolve_duplicate_observations(&mut dataset, config.duplicate_observations)?;
+    if dropped > 0 {
+        warn!("Dropped {} duplicate observation(s)", dropped);
+    }
+    let validated = validation::validate_and_prune(&dataset, &config.validation)?;
+    let excluded = validated.excluded_ids.iter().map(|&id| dataset.id_label(id)).collect();
+    let dataset = validated.dataset;
+    for model_type in &config.model_types {
+        validation::check_dose_units(&dataset, &CompartmentModel::new(model_type.clone())?);
+    }
+    Ok((dataset, excluded))
+}
+
+/// Loads and validates the dataset, fits every model/method combination,
+/// writes each analysis' outputs under `config.output_dir`, and writes the
+/// comparison report when requested or when more than one analysis ran.
+pub fn run_pipeline(config: PipelineConfig) -> Result<Vec<AnalysisResult>> {
+    info!("Starting NMODES analysis");
+    info!("Dataset: {:?}", config.dataset_path);
+    info!("Model types: {:?}", config.model_types);
+    info!("Estimation methods: {:?}", config.estimation_methods);
+    info!("Output directory: {:?}", config.output_dir);
+
+    // Create output directory
+    std::fs::create_dir_all(&config.output_dir)?;
+
+    let (dataset, excluded_individuals) = load_dataset(&config)?;
+    let trace_individuals = config.trace_individuals.iter()
+        .map(|label| dataset.id_for_label(label).ok_or_else(|| anyhow!("Traced subject {} is not in the dataset", label)))
+        .collect::<Result<Vec<i32>>>()?;
+    let mut manifest = output::manifest::RunManifest::new(&config.dataset_path)?;
+    manifest.excluded_individuals = excluded_individuals;
+
+    // Store all results for comparison
+    let mut all_results: Vec<AnalysisResult> = Vec::new();
+    
+    // Run analysis for each model and method combination
+    for model_type in &config.model_types {
+        for estimation_method in &config.estimation_methods {
+            info!("Running {} estimation with {} model", estimation_method, model_type);
+            
+            // Create model
+            let model = CompartmentModel::new(model_type.clone())?;
+            
+            // Configure estimation
+            let estimation_config = EstimationConfig {
+                method: estimation_method.clone(),
+                n_iterations: config.iterations,
+                n_burnin: config.burn_in,
+                n_chains: config.chains,
+                step_size: 0.1,
+                target_acceptance: 0.44,
+                mcmc_update: config.mcmc_update,
+                adaptation_interval: 50,
+                foce_max_iterations: if matches!(estimation_method, EstimationMethod::Foce | EstimationMethod::FoceI | EstimationMethod::Agq) {
+                    config.iterations
+                } else {
+                    100
+                },
+                foce_tolerance: 1e-6,
+                foce_step_size: 1e-4,
+                foce_interaction: matches!(estimation_method, EstimationMethod::FoceI),
+                error_model: config.error_model,
+                agq_nodes: config.agq_nodes,
+                timing_interval: config.timing_interval,
+                time_budget: config.time_budget,
+                trace_individuals: trace_individuals.clone(),
+                include_likelihood_constants: config.include_likelihood_constants,
+                save_mcmc_samples: config.mcmc_samples,
+                ..Default::default()
+            };
+            let estimation_config = if config.auto_init {
+                let initial = estimation::nca::initial_estimates(&dataset, &model);
+                if initial.is_none() {
+                    warn!("NCA failed for every individual; using default initial estimates");
+                }
+                estimation_config.with_initial_fixed_effects(initial)
+            } else {
+                estimation_config
+            };
+            
+            // Create method-specific output directory
+            let method_output_dir = config.output_dir.join(format!("{}_{}", model_type, estimation_method));
+            std::fs::create_dir_all(&method_output_dir)?;
+            manifest.add_analysis(&model_type.to_string(), &estimation_method.to_string(), &method_output_dir, &estimation_config);
+            
+            // Run estimation
+            let analysis_result = match estimation_method {
+                EstimationMethod::Saem => {
+                    let mut estimator = SaemEstimator::new(model, estimation_config);
+                    let results = estimator.fit(&dataset)?;
+                    let parameter_names = estimator.model().parameter_names();
+                    let estimate = FittedModel::from_saem(estimator.model(), &results).parameters;
+                    save_likelihood_profiles(&config, &parameter_names, &estimate, &method_output_dir, |index, values| {
+                        estimator.profile_likelihood(&dataset, &estimate, index, values)
+                    })?;
+                    write_saem_outputs(&config, &dataset, estimator.model(), model_type, &results, method_output_dir)?
+                }
+                EstimationMethod::Foce | EstimationMethod::FoceI | EstimationMethod::Agq => {
+                    let mut estimator = FoceEstimator::new(model, estimation_config);
+                    let results = estimator.fit(&dataset)?;
+                    let parameter_names = estimator.model().parameter_names();
+                    let estimate = FittedModel::from_foce(estimator.model(), &results).parameters;
+                    save_likelihood_profiles(&config, &parameter_names, &estimate, &method_output_dir, |index, values| {
+                        estimator.profile_likelihood(&dataset, &estimate, index, values)
+                    })?;
+                    write_foce_outputs(&config, &dataset, estimator.model(), model_type, estimation_method, &results, method_output_dir)?
+                }
+            };
+            
+            all_results.push(analysis_result);
+        }
+    }
+
+    // Generate comparison report if requested or if multiple analyses were run
+    if config.compare_results || all_results.len() > 1 {
+        generate_comparison_report(&config.output_dir, &all_results, config.rank_by)?;
+    }
+
+    for result in all_results.iter().filter(|r| !r.criterion(config.rank_by).is_finite()) {
+        warn!("{} with {} produced a non-finite {} ({}); ranking it last",
+              result.model_type, result.estimation_method, config.rank_by, result.criterion(config.rank_by));
+    }
+
+    if config.profiles {
+        if let Some(best_result) = best_by(&all_results, config.rank_by) {
+            let model = CompartmentModel::new(best_result.model_type.clone())?;
+            output::save_prediction_profiles(
+                &config.output_dir,
+                &dataset,
+                &model,
+                &best_result.fixed_effects,
+                &best_result.individual_parameters,
+                &best_result.solver_config,
+                PROFILE_GRID_POINTS,
+            )?;
+        }
+    }
+
+    if let Some(times) = &config.predict_grid {
+        if let Some(best_result) = best_by(&all_results, config.rank_by) {
+            let model = CompartmentModel::new(best_result.model_type.clone())?;
+            output::save_grid_predictions(
+                &config.output_dir,
+                &dataset,
+                &model,
+                &best_result.fixed_effects,
+                &best_result.individual_parameters,
+                &best_result.solver_config,
+                times,
+            )?;
+        }
+    }
+
+    manifest.finish(&config.output_dir)?;
+    info!("Analysis completed successfully!");
+    Ok(all_results)
+}
+
+/// Rewrites the diagnostics and output files of a fit saved by an earlier
+/// run, without refitting: `results_path` is that run's
+/// `parameter_estimates.json` (SAEM) or `foce_results.json` (FOCE, FOCE-I,
+/// AGQ), fitted with the single model and method of `config` to the dataset
+/// of `config`. The files go to the analysis' usual directory under
+/// `config.output_dir`.
+pub fn regenerate_outputs(config: &PipelineConfig, results_path: &Path) -> Result<AnalysisResult> {
+    let (model_type, estimation_method) = match (config.model_types.as_slice(), config.estimation_methods.as_slice()) {
+        ([model_type], [estimation_method]) => (model_type, estimation_method),
+        _ => return Err(anyhow!("Regenerating outputs from saved results needs exactly one model and one method")),
+    };
+    info!("Regenerating {} {} outputs from {:?}", model_type, estimation_method, results_path);
+
+    let (dataset, _) = load_dataset(config)?;
+    let model = CompartmentModel::new(model_type.clone())?;
+    let json_content = fs::read_to_string(results_path)?;
+    let method_output_dir = config.output_dir.join(format!("{}_{}", model_type, estimation_method));
+    fs::create_dir_all(&method_output_dir)?;
+
+    let check_parameters = |parameter_names: &[String]| {
+        if parameter_names != model.parameter_names().as_slice() {
+            return Err(anyhow!("Saved results have parameters {:?} but the {} model has {:?}",
+                               parameter_names, model_type, model.parameter_names()));
+        }
+        Ok(())
+    };
+    match estimation_method {
+        EstimationMethod::Saem => {
+            let results: SaemResults = serde_json::from_str(&json_content)?;
+            check_parameters(&results.parameter_names)?;
+            write_saem_outputs(config, &dataset, &model, model_type, &results, method_output_dir)
+        }
+        EstimationMethod::Foce | EstimationMethod::FoceI | EstimationMethod::Agq => {
+            let results: FoceResults = serde_json::from_str(&json_content)?;
+            check_parameters(&results.parameter_names)?;
+            write_foce_outputs(config, &dataset, &model, model_type, estimation_method, &results, method_output_dir)
+        }
+    }
+}
+
+/// Profiles each of `config.profile_parameters` over a grid around
+/// `estimate` with `profile` and writes `profile_<parameter>.csv` into
+/// `method_output_dir`. Parameters the model lacks are skipped.
+fn save_likelihood_profiles(
+    config: &PipelineConfig,
+    parameter_names: &[String],
+    estimate: &ModelParameters,
+    method_output_dir: &Path,
+    mut profile: impl FnMut(usize, &[f64]) -> Result<LikelihoodProfile>,
+) -> Result<()> {
+    for name in &config.profile_parameters {
+        let Some(index) = parameter_names.iter().position(|parameter| parameter == name) else {
+            warn!("Cannot profile {}: the model's parameters are {:?}", name, parameter_names);
+            continue;
+        };
+        info!("Profiling the likelihood of {} over {} values", name, PROFILE_LIKELIHOOD_POINTS);
+        let values = estimation::profile::profile_grid(
+            estimate.fixed_effects[index], PROFILE_LIKELIHOOD_LOG_SPAN, PROFILE_LIKELIHOOD_POINTS,
+        );
+        let profile = profile(index, &values)?;
+        let bound = |value: Option<f64>| value.map_or("beyond the profiled range".to_string(), |v| v.to_string());
+        info!("Profile-likelihood 95% CI for {}: {} to {}", name, bound(profile.lower), bound(profile.upper));
+        output::save_likelihood_profile_csv(method_output_dir, &profile, config.number_format)?;
+    }
+    Ok(())
+}
+
+/// Diagnoses a SAEM fit and writes its output files into
+/// `method_output_dir`.
+fn write_saem_outputs(
+    config: &PipelineConfig,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+    model_type: &ModelType,
+    results: &SaemResults,
+    method_output_dir: PathBuf,
+) -> Result<AnalysisResult> {
+    // Generate diagnostics
+    let diagnostics = diagnostics::generate_diagnostics(dataset, results, model)?;
+    
+    // Save SAEM results
+    output::save_results(&method_output_dir, results, &diagnostics, dataset, model, config.number_format)?;
+    FittedModel::from_saem(model, results)
+        .save(&method_output_dir.join("fitted_model.json"))?;
+    output::convergence::save_convergence_report(
+        &method_output_dir,
+        &output::convergence::ConvergenceReport::from_saem(results),
+    )?;
+    
+    if config.pharmpy_format {
+        let pharmpy = output::pharmpy::PharmpyResults::from_saem(results, &model_type.to_string());
+        output::pharmpy::save_pharmpy_results(&method_output_dir, &pharmpy)?;
+    }
+    
+    if let Some(stratify_by) = &config.gof_strata {
+        let strata = diagnostics::goodness_of_fit_by_stratum(dataset, results, model, stratify_by)?;
+        output::save_gof_by_stratum_csv(&method_output_dir, &strata)?;
+    }
+    
+    if let Some(vpc_config) = &config.vpc {
+        let bins = vpc::run_vpc(dataset, results, model, vpc_config)?;
+        vpc::save_vpc_csv(&method_output_dir, &bins)?;
+    }
+    
+    Ok(AnalysisResult {
+        model_type: model_type.clone(),
+        estimation_method: EstimationMethod::Saem,
+        objective_function_value: results.objective_function_value,
+        final_log_likelihood: results.final_log_likelihood,
+        converged: results.converged,
+        n_iterations: results.n_iterations,
+        fixed_effects: results.fixed_effects.clone(),
+        individual_parameters: results.individual_parameters.clone(),
+        parameter_names: results.parameter_names.clone(),
+        aic: diagnostics.goodness_of_fit.aic,
+        bic: diagnostics.goodness_of_fit.bic,
+        aicc: diagnostics.goodness_of_fit.aicc,
+        bicc: diagnostics.goodness_of_fit.bicc,
+        rmse: diagnostics.goodness_of_fit.rmse,
+        r_squared: diagnostics.goodness_of_fit.r_squared,
+        solver_config: results.solver_config.clone(),
+        output_dir: method_output_dir,
+    })
+}
+
+/// Diagnoses a FOCE, FOCE-I or AGQ fit and writes its output files into
+/// `method_output_dir`.
+fn write_foce_outputs(
+    config: &PipelineConfig,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+    model_type: &ModelType,
+    estimation_method: &EstimationMethod,
+    results: &FoceResults,
+    method_output_dir: PathBuf,
+) -> Result<AnalysisResult> {
+    // Convert FOCE results to SAEM format for diagnostics compatibility
+    let saem_results = convert_foce_to_saem_results(results);
+    let diagnostics = diagnostics::generate_diagnostics(dataset, &saem_results, model)?;
+    
+    // Save FOCE results
+    save_foce_results(&method_output_dir, results, &diagnostics, dataset, model, config.number_format)?;
+    FittedModel::from_foce(model, results)
+        .save(&method_output_dir.join("fitted_model.json"))?;
+    output::convergence::save_convergence_report(
+        &method_output_dir,
+        &output::convergence::ConvergenceReport::from_foce(results, &estimation_method.to_string()),
+    )?;
+    output::save_shrinkage_csv(
+        &method_output_dir,
+        &diagnostics::parameter_shrinkage(&saem_results, &diagnostics.residual_analysis.iwres),
+    )?;
+    
+    if config.pharmpy_format {
+        let pharmpy = output::pharmpy::PharmpyResults::from_foce(
+            results, &model_type.to_string(), &estimation_method.to_string(),
+        );
+        output::pharmpy::save_pharmpy_results(&method_output_dir, &pharmpy)?;
+    }
+    
+    if let Some(stratify_by) = &config.gof_strata {
+        let strata = diagnostics::goodness_of_fit_by_stratum(dataset, &saem_results, model, stratify_by)?;
+        output::save_gof_by_stratum_csv(&method_output_dir, &strata)?;
+    }
+    
+    if let Some(vpc_config) = &config.vpc {
+        let bins = vpc::run_vpc(dataset, &saem_results, model, vpc_config)?;
+        vpc::save_vpc_csv(&method_output_dir, &bins)?;
+    }
+    
+    Ok(AnalysisResult {
+        model_type: model_type.clone(),
+        estimation_method: estimation_method.clone(),
+        objective_function_value: results.objective_function_value,
+        final_log_likelihood: results.final_log_likelihood,
+        converged: results.converged,
+        n_iterations: results.n_iterations,
+        fixed_effects: results.fixed_effects.clone(),
+        individual_parameters: results.individual_parameters.clone(),
+        parameter_names: results.parameter_names.clone(),
+        aic: diagnostics.goodness_of_fit.aic,
+        bic: diagnostics.goodness_of_fit.bic,
+        aicc: diagnostics.goodness_of_fit.aicc,
+        bicc: diagnostics.goodness_of_fit.bicc,
+        rmse: diagnostics.goodness_of_fit.rmse,
+        r_squared: diagnostics.goodness_of_fit.r_squared,
+        solver_config: results.solver_config.clone(),
+        output_dir: method_output_dir,
+    })
+}
+
+impl AnalysisResult {
+    pub fn criterion(&self, criterion: InformationCriterion) -> f64 {
+        match criterion {
+            InformationCriterion::Aic => self.aic,
+            InformationCriterion::Aicc => self.aicc,
+            InformationCriterion::Bic => self.bic,
+            InformationCriterion::Bicc => self.bicc,
+        }
+    }
+}
+
+/// The result with the lowest value of `criterion`, ranking non-finite
+/// values last.
+pub fn best_by(results: &[AnalysisResult], criterion: InformationCriterion) -> Option<&AnalysisResult> {
+    results.iter()
+        .min_by(|a, b| diagnostics::compare_information_criteria(a.criterion(criterion), b.criterion(criterion)))
+}
+
+/// Message reported when none of `n_analyses` analyses converged.
+pub fn no_converged_model_message(n_analyses: usize) -> String {
+    format!("No reliable model was found: none of the {} analyses converged.", n_analyses)
+}
+
+/// Relative difference between methods above which a parameter is flagged
+/// in the comparison report.
+pub const PARAMETER_DISCREPANCY_THRESHOLD: f64 = 0.2;
+
+/// |estimate − reference| / |reference| on the natural scale.
+pub fn relative_difference(estimate: f64, reference: f64) -> f64 {
+    (estimate - reference).abs() / reference.abs()
+}
+
+fn generate_comparison_report(
+    output_dir: &Path,
+    results: &[AnalysisResult],
+    criterion: InformationCriterion,
+) -> Result<()> {
+    let comparison_file = output_dir.join("model_comparison_report.txt");
+    let mut report = String::new();
+    
+    report.push_str("NMODES Model and Method Comparison Report\n");
+    report.push_str("=========================================\n\n");
+    
+    report.push_str(&format!("Total analyses performed: {}\n", results.len()));
+    report.push_str(&format!("Analysis date: {}\n\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
+    
+    let none_converged = !results.iter().any(|r| r.converged);
+    if none_converged {
+        report.push_str(&format!("{}\n\n", no_converged_model_message(results.len())));
+    }
+    
+    // Summary table
+    report.push_str("Summary Table:\n");
+    report.push_str("--------------\n");
+    report.push_str(&format!("{:<15} {:<10} {:<12} {:<12} {:<10} {:<8} {:<8} {:<8} {:<8} {:<8} {:<8}\n", 
+                             "Model", "Method", "OFV", "LogLik", "Converged", "AIC", "AICc", "BIC", "BICc", "RMSE", "R²"));
+    report.push_str(&format!("{}\n", "-".repeat(118)));
+    
+    for result in results {
+        report.push_str(&format!("{:<15} {:<10} {:<12.2} {:<12.2} {:<10} {:<8.1} {:<8.1} {:<8.1} {:<8.1} {:<8.3} {:<8.3}\n", 
+                                 format!("{}", result.model_type),
+                                 format!("{}", result.estimation_method),
+                                 result.objective_function_value,
+                                 result.final_log_likelihood,
+                                 result.converged,
+                                 result.aic,
+                                 result.aicc,
+                                 result.bic,
+                                 result.bicc,
+                                 result.rmse,
+                                 result.r_squared));
+    }
+    
+    // Model ranking by the chosen criterion
+    let mut sorted_results = results.to_vec();
+    sorted_results.sort_by(|a, b| diagnostics::compare_information_criteria(a.criterion(criterion), b.criterion(criterion)));
+    
+    let heading = format!("Model Ranking by {} (lower is better):", criterion);
+    report.push_str(&format!("\n{}\n", heading));
+    report.push_str(&format!("{}\n", "-".repeat(heading.chars().count())));
+    for (rank, result) in sorted_results.iter().enumerate() {
+        let delta = result.criterion(criterion) - sorted_results[0].criterion(criterion);
+        report.push_str(&format!("{}. {} + {} ({}: {:.2}, Δ{}: {:.2})\n", 
+                                 rank + 1,
+                                 result.model_type,
+                                 result.estimation_method,
+                                 criterion,
+                                 result.criterion(criterion),
+                                 criterion,
+                                 delta));
+    }
+    
+    // Parameter comparison for converged models
+    let converged_results: Vec<&AnalysisResult> = results.iter()
+        .filter(|r| r.converged)
+        .collect();
+    
+    let mut discrepancies = Vec::new();
+    if !converged_results.is_empty() {
+        report.push_str("\nParameter Estimates (Converged Models Only):\n");
+        report.push_str("-------------------------------------------\n");
+        
+        // Group by model type for easier comparison
+        let mut models_by_type: HashMap<String, Vec<&AnalysisResult>> = HashMap::new();
+        for result in &converged_results {
+            models_by_type.entry(format!("{}", result.model_type))
+                .or_default()
+                .push(result);
+        }
+        
+        for (model_name, model_results) in models_by_type {
+            report.push_str(&format!("\n{} Model:\n", model_name));
+            
+            // Get parameter names (should be same for all results of same model type)
+            if let Some(first_result) = model_results.first() {
+                for (param_idx, param_name) in first_result.parameter_names.iter().enumerate() {
+                    report.push_str(&format!("  {}:\n", param_name));
+                    for result in &model_results {
+                        let param_value = result.fixed_effects[param_idx].exp(); // Transform back from log scale
+                        report.push_str(&format!("    {}: {:.4}\n", result.estimation_method, param_value));
+                    }
+
+                    // Agreement of SAEM with each FOCE-family method; large
+                    // disagreement points at the likelihood approximation
+                    // or at convergence
+                    let saem = model_results.iter()
+                        .find(|r| matches!(r.estimation_method, EstimationMethod::Saem));
+                    if let Some(saem) = saem {
+                        for reference in model_results.iter().filter(|r| !matches!(r.estimation_method, EstimationMethod::Saem)) {
+                            let difference = relative_difference(
+                                saem.fixed_effects[param_idx].exp(),
+                                reference.fixed_effects[param_idx].exp(),
+                            );
+                            let flagged = difference.is_nan() || difference > PARAMETER_DISCREPANCY_THRESHOLD;
+                            report.push_str(&format!("    |SAEM − {}|/{}: {:.1}%{}\n",
+                                                     reference.estimation_method,
+                                                     reference.estimation_method,
+                                                     difference * 100.0,
+                                                     if flagged { "  ** DISCREPANCY **" } else { "" }));
+                            if flagged {
+                                discrepancies.push(format!("{} {} (SAEM vs {}: {:.1}%)",
+                                                           model_name, param_name, reference.estimation_method, difference * 100.0));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    // Method comparison
+    report.push_str("\nMethod Performance Comparison:\n");
+    report.push_str("-----------------------------\n");
+    
+    let mut method_stats: HashMap<String, Vec<&AnalysisResult>> = HashMap::new();
+    for result in results {
+        method_stats.entry(format!("{}", result.estimation_method))
+            .or_default()
+            .push(result);
+    }
+    
+    for (method_name, method_results) in method_stats {
+        let converged_count = method_results.iter().filter(|r| r.converged).count();
+        let avg_iterations = method_results.iter()
+            .map(|r| r.n_iterations)
+            .sum::<usize>() as f64 / method_results.len() as f64;
+        
+        report.push_str(&format!("{}:\n", method_name));
+        report.push_str(&format!("  Convergence rate: {}/{} ({:.1}%)\n", 
+                                 converged_count, method_results.len(),
+                                 (converged_count as f64 / method_results.len() as f64) * 100.0));
+        report.push_str(&format!("  Average iterations: {:.0}\n", avg_iterations));
+        
+        if converged_count > 0 {
+            let avg_aic = method_results.iter()
+                .filter(|r| r.converged)
+                .map(|r| r.aic)
+                .sum::<f64>() / converged_count as f64;
+            report.push_str(&format!("  Average AIC (converged): {:.2}\n", avg_aic));
+        }
+        report.push('\n');
+    }
+    
+    // Recommendations
+    report.push_str("Recommendations:\n");
+    report.push_str("---------------\n");
+    
+    if none_converged {
+        // Ranking estimates that never converged would only mislead
+        report.push_str("• No reliable model was found. Consider:\n");
+        report.push_str("  - Increasing iterations\n");
+        report.push_str("  - Trying different estimation method\n");
+        report.push_str("  - Checking data quality and initial estimates\n");
+    } else {
+        if let Some(best_result) = sorted_results.first() {
+            if best_result.converged {
+                report.push_str(&format!("• Best fitting model: {} with {} ({}: {:.2})\n", 
+                                         best_result.model_type, best_result.estimation_method, criterion, best_result.criterion(criterion)));
+            } else {
+                report.push_str(&format!("• Warning: Best {} model did not converge. Consider:\n", criterion));
+                report.push_str("  - Increasing iterations\n");
+                report.push_str("  - Trying different estimation method\n");
+                report.push_str("  - Checking data quality\n");
+            }
+        }
+        
+        // Check for substantial model differences
+        if sorted_results.len() > 1 {
+            let delta = sorted_results[1].criterion(criterion) - sorted_results[0].criterion(criterion);
+            if delta < 2.0 {
+                report.push_str(&format!("• Models have similar fit (Δ{} < 2). Consider simpler model for parsimony.\n", criterion));
+            } else if delta > 10.0 {
+                report.push_str(&format!("• Strong evidence for best model (Δ{} > 10).\n", criterion));
+            } else {
+                report.push_str(&format!("• Moderate evidence for best model (2 < Δ{} < 10).\n", criterion));
+            }
+        }
+    }
+    
+    // Method-specific recommendations
+    let saem_results: Vec<&AnalysisResult> = results.iter()
+        .filter(|r| matches!(r.estimation_method, EstimationMethod::Saem))
+        .collect();
+    let foce_results: Vec<&AnalysisResult> = results.iter()
+        .filter(|r| matches!(r.estimation_method, EstimationMethod::Foce | EstimationMethod::FoceI | EstimationMethod::Agq))
+        .collect();
+    
+    if !saem_results.is_empty() && !foce_results.is_empty() {
+        report.push_str("• Method comparison available - check consistency between SAEM and FOCE results.\n");
+    }
+    if !discrepancies.is_empty() {
+        report.push_str(&format!("• Estimates differ between methods by more than {:.0}%, which usually signals a likelihood-approximation or convergence problem:\n",
+                                 PARAMETER_DISCREPANCY_THRESHOLD * 100.0));
+        for discrepancy in &discrepancies {
+            report.push_str(&format!("  - {}\n", discrepancy));
+        }
+    }
+    
+    fs::write(comparison_file, report)?;
+    
+    // Also generate CSV comparison for easy analysis
+    generate_comparison_csv(output_dir, results)?;
+    
+    info!("Comparison report saved to: {:?}", output_dir.join("model_comparison_report.txt"));
+    info!("Comparison CSV saved to: {:?}", output_dir.join("model_comparison.csv"));
+
+    Ok(())
+}
+
+fn generate_comparison_csv(
+    output_dir: &Path,
+    results: &[AnalysisResult],
+) -> Result<()> {
+    let csv_file = output_dir.join("model_comparison.csv");
+    let mut wtr = csv::Writer::from_path(csv_file)?;
+    
+    // Write header
+    wtr.write_record([
+        "Model", "Method", "OFV", "LogLikelihood", "Converged", 
+        "Iterations", "AIC", "AICc", "BIC", "BICc", "RMSE", "R_squared"
+    ])?;
+    
+    // Write data
+    for result in results {
+        wtr.write_record(&[
+            format!("{}", result.model_type),
+            format!("{}", result.estimation_method),
+            result.objective_function_value.to_string(),
+            result.final_log_likelihood.to_string(),
+            result.converged.to_string(),
+            result.n_iterations.to_string(),
+            result.aic.to_string(),
+            result.aicc.to_string(),
+            result.bic.to_string(),
+            result.bicc.to_string(),
+            result.rmse.to_string(),
+            result.r_squared.to_string(),
+        ])?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+/// FOCE estimates as `SaemResults`, for the diagnostics, outputs and
+/// `FittedModel` shared by both estimators.
+pub(crate) fn convert_foce_to_saem_results(foce_results: &FoceResults) -> SaemResults {
+    let mut saem_results = SaemResults::new(
+        foce_results.fixed_effects.len(),
+        foce_results.parameter_names.clone(),
+    );
+    
+    saem_results.fixed_effects = foce_results.fixed_effects.clone();
+    saem_results.random_effects_variance = foce_results.random_effects_variance.clone();
+    saem_results.residual_variance = foce_results.residual_variance;
+    saem_results.error_model = foce_results.error_model;
+    saem_results.proportional_variance = foce_results.proportional_variance;
+    saem_results.error_power = foce_results.error_power;
+    saem_results.effect_residual_variance = foce_results.effect_residual_variance;
+    saem_results.output_residual_variances = foce_results.output_residual_variances.clone();
+    saem_results.parameter_units = foce_results.parameter_units.clone();
+    saem_results.time_budget_exceeded = foce_results.time_budget_exceeded;
+    saem_results.final_log_likelihood = foce_results.final_log_likelihood;
+    saem_results.objective_function_value = foce_results.objective_function_value;
+    saem_results.converged = foce_results.converged;
+    saem_results.n_iterations = foce_results.n_iterations;
+    saem_results.iteration_times = foce_results.iteration_times.clone();
+    saem_results.solver_config = foce_results.solver_config.clone();
+    saem_results.individual_parameters = foce_results.individual_parameters.clone();
+    saem_results.evaluation_counts = foce_results.evaluation_counts;
+    
+    saem_results
+}
+
+fn save_foce_results(
+    output_dir: &std::path::Path,
+    results: &FoceResults,
+    diagnostics: &diagnostics::DiagnosticResults,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+    format: output::NumberFormat,
+) -> Result<()> {
+    use std::fs;
+    
+    // Ensure output directory exists
+    fs::create_dir_all(output_dir)?;
+    
+    // Save FOCE-specific results
+    let foce_file = output_dir.join("foce_results.json");
+    let json_content = serde_json::to_string_pretty(results)?;
+    fs::write(foce_file, json_content)?;
+    
+    // Save diagnostics
+    let diagnostics_file = output_dir.join("diagnostics.json");
+    let json_content = serde_json::to_string_pretty(diagnostics)?;
+    fs::write(diagnostics_file, json_content)?;
+    
+    // Save FOCE-specific summary report
+    save_foce_summary_report(output_dir, results, diagnostics, model, format)?;
+    
+    // Save derived secondary parameters
+    output::save_secondary_parameters(output_dir, model, &results.fixed_effects, Some(&results.covariance_matrix), format)?;
+    
+    output::save_correlation_matrix_csv(
+        output_dir,
+        &results.parameter_names,
+        &diagnostics::correlation_matrix(&results.covariance_matrix),
+    )?;
+    
+    // Save predictions using FOCE results
+    save_foce_predictions_csv(output_dir, results, dataset, model)?;
+    
+    output::save_individual_ofv_csv(output_dir, &results.individual_objectives, dataset)?;
+    output::save_objective_trajectory_csv(output_dir, &results.objective_trajectory, format)?;
+    output::save_qq_data_csv(output_dir, &diagnostics::qq_data(&diagnostics.residual_analysis.standardized_residuals))?;
+    
+    Ok(())
+}
+
+fn save_foce_summary_report(
+    output_dir: &std::path::Path,
+    results: &FoceResults,
+    diagnostics: &diagnostics::DiagnosticResults,
+    model: &CompartmentModel,
+    format: output::NumberFormat,
+) -> Result<()> {
+    use std::fs;
+    
+    let report_file = output_dir.join("foce_summary_report.txt");
+    
+    let mut report = String::new();
+    report.push_str("NMODES FOCE Analysis Summary Report\n");
+    report.push_str("=================================\n\n");
+    
+    report.push_str("Estimation Method: FOCE\n");
+    report.push_str(&format!("Model Convergence: {}\n", results.converged));
+    if results.time_budget_exceeded {
+        report.push_str("Stopped Early: time budget spent\n");
+    }
+    report.push_str(&format!("Total Iterations: {}\n", results.n_iterations));
+    report.push_str(&format!("Final Log-Likelihood: {:.6}\n", results.final_log_likelihood));
+    report.push_str(&format!("Objective Function Value: {:.6}\n", results.objective_function_value));
+    report.push_str(&format!("Gradient Norm: {:.6}\n", results.gradient_norm));
+    report.push_str(&format!("Hessian Condition Number: {:.6}\n", results.hessian_condition_number));
+    report.push_str(&format!("Fisher Information Rank: {} of {}\n", results.fisher_rank, results.fixed_effects.len()));
+    report.push_str(&format!("Mean Inner Iterations: {:.2}\n", results.mean_inner_iterations));
+    report.push_str(&format!("Number of Individuals: {}\n", results.individual_parameters.len()));
+    report.push_str(&format!("AIC: {:.6}\n", diagnostics.goodness_of_fit.aic));
+    report.push_str(&format!("AICc: {:.6}\n", diagnostics.goodness_of_fit.aicc));
+    report.push_str(&format!("BIC: {:.6}\n", diagnostics.goodness_of_fit.bic));
+    report.push_str(&format!("BICc: {:.6}\n", diagnostics.goodness_of_fit.bicc));
+    report.push_str(&format!("R-squared: {:.6}\n", diagnostics.goodness_of_fit.r_squared));
+    report.push_str(&format!("RMSE: {:.6}\n", diagnostics.goodness_of_fit.rmse));
+    report.push_str(&format!("Standardized Residual Q-Q Correlation: {:.6}\n", diagnostics.residual_analysis.qq_correlation));
+    if let Some(summary) = &diagnostics.residual_analysis.iwres_autocorrelation_summary {
+        report.push_str(&format!("IWRES Lag-1 Autocorrelation: median {:.4} (5th-95th percentile {:.4} to {:.4}, {} individuals)\n",
+                                 summary.median, summary.percentile_5, summary.percentile_95, summary.n_individuals));
+    }
+    report.push_str(&format!("ODE Solves: {}\n", results.evaluation_counts.ode_solves));
+    report.push_str(&format!("Derivative Evaluations: {}\n", results.evaluation_counts.derivative_evaluations));
+    report.push_str(&format!("Negative State Corrections: {}\n", results.evaluation_counts.negative_state_corrections));
+    
+    report.push_str("\nFixed Effects Parameter Estimates:\n");
+    report.push_str("----------------------------------\n");
+    report.push_str(&format!("{:<10} {:<8} {:<12} {:<10} {:<10}\n", "Parameter", "Unit", "Estimate", "SE", "%RSE"));
+    report.push_str(&format!("{:<10} {:<8} {:<12} {:<10} {:<10}\n", "---------", "----", "--------", "--", "----"));
+    
+    let rse_percent = results.rse_percent();
+    // Natural-scale estimates, exp(THETA), with the delta-method SE
+    // exp(THETA)·SE(THETA)
+    for (i, param_name) in results.parameter_names.iter().enumerate() {
+        let estimate = results.fixed_effects[i].exp();
+        let se = estimate * results.standard_errors.get(i).copied().unwrap_or(0.0);
+        let flag = if results.parameters_at_bounds.contains(param_name) { " (at bound)" } else { "" };
+        report.push_str(&format!("{:<10} {:<8} {:<12} {:<10} {:<10.2}{}\n",
+                                 param_name, output::unit_label(&results.parameter_units, i),
+                                 format.format(estimate), format.format(se), rse_percent[i], flag));
+    }
+    
+    let poorly_identified = diagnostics::poorly_identified_parameters(&results.parameter_names, &results.rse_percent());
+    if !poorly_identified.is_empty() {
+        report.push_str(&format!("\nWARNING: Poorly Identified Parameters (%RSE > {}):\n", diagnostics::HIGH_RSE_THRESHOLD));
+        for (name, rse) in &poorly_identified {
+            report.push_str(&format!("  {}: {:.1}%\n", name, rse));
+        }
+    }
+    
+    if let Ok(secondary) = models::secondary_parameter_estimates(
+        model.model_type(), &results.fixed_effects, &results.covariance_matrix,
+    ) {
+        report.push_str("\nSecondary Parameters:\n");
+        report.push_str("---------------------\n");
+        report.push_str(&format!("{:<10} {:<12} {:<10} {:<24}\n", "Parameter", "Estimate", "SE", "95% CI"));
+        for param in &secondary {
+            report.push_str(&format!("{:<10} {:<12} {:<10} [{}, {}]\n",
+                                     param.name, format.format(param.value), format.format(param.standard_error),
+                                     format.format(param.ci_lower), format.format(param.ci_upper)));
+        }
+    }
+    
+    let correlation = diagnostics::correlation_matrix(&results.covariance_matrix);
+    let high_correlations = diagnostics::high_correlations(&correlation, &results.parameter_names);
+    if !high_correlations.is_empty() {
+        report.push_str(&format!("\nHighly Correlated Estimates (|r| > {}):\n", diagnostics::HIGH_CORRELATION_THRESHOLD));
+        for (first, second, r) in &high_correlations {
+            report.push_str(&format!("  {} / {}: {:.3}\n", first, second, r));
+        }
+    }
+    
+    report.push_str(&format!("\nResidual Error ({}):\n", results.error_model));
+    report.push_str(&format!("{:<14} {:<12} {:<10}\n", "Component", "Estimate", "SE"));
+    for component in &results.residual_components {
+        report.push_str(&format!("{:<14} {:<12} {:<10}\n",
+                                 component.name, format.format(component.estimate()), format.format(component.standard_error)));
+    }
+    
+    report.push_str("\nRandom Effects Variance (Omega):\n");
+    report.push_str("-------------------------------\n");
+    let cv_percent = diagnostics::iiv_cv_percent(&results.random_effects_variance);
+    for (i, (param_name, cv)) in results.parameter_names.iter().zip(&cv_percent).enumerate() {
+        let variance = results.random_effects_variance[i][i];
+        report.push_str(&format!("{}({}): {} ({:.1}% CV)\n", param_name, param_name, format.format(variance), cv));
+    }
+    
+    fs::write(report_file, report)?;
+    Ok(())
+}
+
+fn save_foce_predictions_csv(
+    output_dir: &std::path::Path,
+    results: &FoceResults,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<()> {
+    output::write_predictions_csv(
+        &output_dir.join("foce_predictions.csv"),
+        &convert_foce_to_saem_results(results),
+        dataset,
+        model,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_result(model_type: ModelType, aic: f64) -> AnalysisResult {
+        AnalysisResult {
+            model_type,
+            estimation_method: EstimationMethod::Saem,
+            objective_function_value: f64::NAN,
+            final_log_likelihood: f64::NAN,
+            converged: false,
+            n_iterations: 10,
+            fixed_effects: vec![0.0, 0.0],
+            individual_parameters: HashMap::new(),
+            parameter_names: vec!["CL".to_string(), "V".to_string()],
+            aic,
+            bic: aic,
+            aicc: aic,
+            bicc: aic,
+            rmse: f64::NAN,
+            r_squared: f64::NAN,
+            solver_config: SolverConfig::default(),
+            output_dir: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn test_report_states_no_reliable_model_when_nothing_converged() {
+        let output_dir = std::env::temp_dir().join("nmodes_no_converged_report");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let results = vec![
+            failed_result(ModelType::OneCompartment, f64::NAN),
+            failed_result(ModelType::TwoCompartment, 120.0),
+        ];
+        generate_comparison_report(&output_dir, &results, InformationCriterion::Aic).unwrap();
+        let report = std::fs::read_to_string(output_dir.join("model_comparison_report.txt")).unwrap();
+        std::fs::remove_dir_all(&output_dir).ok();
+
+        assert!(report.contains(&no_converged_model_message(2)));
+        assert!(report.contains("No reliable model was found. Consider:"));
+        assert!(!report.contains("Best fitting model"));
+        assert!(!report.contains("Best AIC model"));
+        assert!(!report.contains("evidence for best model"));
+        assert!(!report.contains("similar fit"));
+    }
+
+    #[test]
+    fn test_report_flags_divergent_estimates_between_methods() {
+        let output_dir = std::env::temp_dir().join("nmodes_discrepancy_report");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let saem = AnalysisResult {
+            converged: true,
+            fixed_effects: vec![3.0_f64.ln(), 10.0_f64.ln()],
+            ..failed_result(ModelType::OneCompartment, 100.0)
+        };
+        let foce = AnalysisResult {
+            estimation_method: EstimationMethod::Foce,
+            fixed_effects: vec![2.0_f64.ln(), 10.5_f64.ln()],
+            ..saem.clone()
+        };
+        generate_comparison_report(&output_dir, &[saem, foce], InformationCriterion::Aic).unwrap();
+        let report = std::fs::read_to_string(output_dir.join("model_comparison_report.txt")).unwrap();
+        std::fs::remove_dir_all(&output_dir).ok();
+
+        // CL: |3 − 2|/2 = 50%, flagged; V: |10 − 10.5|/10.5 ≈ 4.8%, not
+        assert!(report.contains("|SAEM − FOCE|/FOCE: 50.0%  ** DISCREPANCY **"));
+        assert!(report.contains("|SAEM − FOCE|/FOCE: 4.8%\n"));
+        assert!(report.contains("one-compartment CL (SAEM vs FOCE: 50.0%)"));
+        assert!(!report.contains("one-compartment V (SAEM"));
+    }
+}
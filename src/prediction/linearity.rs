@@ -0,0 +1,136 @@
+use super::predict_individual;
+use crate::data::Individual;
+use crate::models::{CompartmentModel, ModelParameters};
+use crate::solver::{RungeKuttaSolver, SolverConfig};
+
+/// Largest relative deviation from proportionality put down to rounding and
+/// solver error; a linear model's predictions scale with the dose to within
+/// far less.
+pub const DOSE_PROPORTIONALITY_TOLERANCE: f64 = 1e-6;
+
+/// How far an individual's predictions at several dose levels depart from
+/// scaling with the dose.
+#[derive(Debug, Clone)]
+pub struct DoseProportionality {
+    /// Multipliers of the individual's own doses that were simulated.
+    pub dose_scales: Vec<f64>,
+    /// For each scale `s`, the largest `|C_s − s·C_1|` over the
+    /// observations, relative to `s` times the peak of the unscaled `C_1`.
+    pub deviations: Vec<f64>,
+}
+
+impl DoseProportionality {
+    pub fn max_deviation(&self) -> f64 {
+        self.deviations.iter().fold(0.0, |m, &d| m.max(d))
+    }
+
+    /// Whether every dose level scaled the predictions to within `tolerance`.
+    pub fn is_proportional(&self, tolerance: f64) -> bool {
+        self.deviations.iter().all(|&d| d <= tolerance)
+    }
+}
+
+/// Simulates the individual with every dose multiplied by each of
+/// `dose_scales` and compares the predictions with the unscaled ones times
+/// the scale. A linear model with empty initial compartments passes at any
+/// scale; saturable elimination or absorption, or a dose-dependent term
+/// slipped into a custom model, shows up as a deviation growing with the
+/// scale.
+pub fn check_dose_proportionality(
+    model: &CompartmentModel,
+    individual: &Individual,
+    params: &ModelParameters,
+    dose_scales: &[f64],
+    solver_config: &SolverConfig,
+) -> Result<DoseProportionality, anyhow::Error> {
+    let solver = RungeKuttaSolver::new();
+    let reference = predict_individual(model, &solver, individual, params, solver_config)?;
+    let peak = reference.iter().fold(0.0_f64, |m, c| m.max(c.abs()));
+    if peak == 0.0 {
+        return Err(anyhow::anyhow!("Individual {} has no predicted exposure to scale", individual.id));
+    }
+
+    let deviations = dose_scales.iter()
+        .map(|&scale| {
+            let scaled = predict_individual(model, &solver, &individual.with_dose_scale(scale), params, solver_config)?;
+            let deviation = scaled.iter().zip(&reference)
+                .fold(0.0_f64, |m, (c, c1)| m.max((c - scale * c1).abs()));
+            Ok(deviation / (scale * peak))
+        })
+        .collect::<Result<Vec<f64>, anyhow::Error>>()?;
+
+    Ok(DoseProportionality { dose_scales: dose_scales.to_vec(), deviations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingRecord, DosingType, Observation, ObservationType};
+    use crate::models::{CompartmentModelTrait, ModelError, ModelState, ModelType};
+    use nalgebra::DVector;
+    use std::collections::HashMap;
+
+    /// One compartment with Michaelis–Menten elimination: VMAX·C/(KM + C).
+    struct MichaelisMenten;
+
+    impl CompartmentModelTrait for MichaelisMenten {
+        fn n_compartments(&self) -> usize {
+            1
+        }
+
+        fn parameter_names(&self) -> Vec<String> {
+            vec!["VMAX".to_string(), "KM".to_string(), "V".to_string()]
+        }
+
+        fn default_parameters(&self) -> ModelParameters {
+            let mut params = ModelParameters::new(3, self.parameter_names());
+            params.fixed_effects = vec![10.0_f64.ln(), 2.0_f64.ln(), 10.0_f64.ln()];
+            params
+        }
+
+        fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+            let vmax = params.fixed_effects[0].exp();
+            let km = params.fixed_effects[1].exp();
+            let v = params.fixed_effects[2].exp();
+            let concentration = state.compartments[0] / v;
+            DVector::from_element(1, -vmax * concentration / (km + concentration))
+        }
+
+        fn observation_function(&self, state: &ModelState, params: &ModelParameters, _compartment: usize) -> f64 {
+            state.compartments[0] / params.fixed_effects[2].exp()
+        }
+
+        fn validate_parameters(&self, _params: &ModelParameters) -> Result<(), ModelError> {
+            Ok(())
+        }
+    }
+
+    fn individual() -> Individual {
+        let observations = [0.5, 1.0, 2.0, 4.0, 8.0, 12.0].iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let mut infusion = DosingRecord::new(6.0, 50.0, 1, DosingType::Infusion);
+        infusion.rate = Some(25.0);
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus), infusion];
+        Individual::new(1, observations, doses, HashMap::new())
+    }
+
+    #[test]
+    fn test_linear_model_passes_and_michaelis_menten_fails_proportionality() {
+        let scales = [0.1, 2.0, 10.0];
+        let config = SolverConfig::default();
+
+        let linear = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+        let check = check_dose_proportionality(&linear, &individual(), &linear.default_parameters(), &scales, &config).unwrap();
+        assert_eq!(check.deviations.len(), 3);
+        assert!(check.is_proportional(DOSE_PROPORTIONALITY_TOLERANCE), "{:?}", check.deviations);
+
+        // Elimination saturates at 10 mg/L against KM = 2 mg/L, so a tenfold
+        // dose gives far more than tenfold concentrations later on
+        let saturable = CompartmentModel::from_custom("michaelis-menten", Box::new(MichaelisMenten));
+        let params = saturable.default_parameters();
+        let check = check_dose_proportionality(&saturable, &individual(), &params, &scales, &config).unwrap();
+        assert!(!check.is_proportional(DOSE_PROPORTIONALITY_TOLERANCE));
+        assert!(check.deviations[2] > check.deviations[1] && check.max_deviation() > 0.1, "{:?}", check.deviations);
+    }
+}
@@ -0,0 +1,782 @@
+use crate::data::{Dataset, DosingRecord, DosingType, Individual, Observation, ObservationType, MODELED_DURATION_RATE};
+use crate::models::{weibull_fraction_absorbed, CompartmentModel, ModelParameters, ModelState};
+use crate::saem::SaemResults;
+use crate::solver::{OdeSolver, OdeSystem, RungeKuttaSolver, SolverConfig};
+use nalgebra::DVector;
+use std::collections::HashMap;
+
+pub mod linearity;
+pub mod step_size;
+
+pub use linearity::{check_dose_proportionality, DoseProportionality, DOSE_PROPORTIONALITY_TOLERANCE};
+pub use step_size::{step_size_advisory, StepSizeAdvisory, StepSizeCheck};
+
+pub struct CompartmentSystem<'a> {
+    pub model: &'a CompartmentModel,
+    pub params: &'a ModelParameters,
+    /// Zero-order input into each compartment (amount per unit time) from
+    /// the infusions running over the integration segment.
+    pub input_rates: &'a DVector<f64>,
+    /// Weibull-absorbed doses given so far, whose unabsorbed amounts the
+    /// integrated state includes.
+    pub weibull_inputs: &'a [WeibullInput],
+}
+
+impl<'a> OdeSystem for CompartmentSystem<'a> {
+    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
+        let state = ModelState {
+            compartments: absorbed_amounts(y, self.weibull_inputs, t),
+            time: t,
+        };
+        self.model.derivatives(&state, self.params) + self.input_rates
+    }
+
+    fn dimension(&self) -> usize {
+        self.model.n_compartments()
+    }
+}
+
+/// A dose entering its compartment along a Weibull curve from `start`.
+/// The amount not yet absorbed is known in closed form, so it is added to
+/// the integrated state when the dose is given and subtracted whenever the
+/// state is read, instead of integrating the input rate, which is unbounded
+/// at the dose time for β < 1.
+#[derive(Debug, Clone)]
+pub struct WeibullInput {
+    pub compartment: usize,
+    pub start: f64,
+    pub amount: f64,
+    pub tau: f64,
+    pub beta: f64,
+}
+
+impl WeibullInput {
+    fn unabsorbed(&self, t: f64) -> f64 {
+        self.amount * (1.0 - weibull_fraction_absorbed(t - self.start, self.tau, self.beta))
+    }
+}
+
+/// Compartment amounts at `t` from an integrated state that still holds
+/// the unabsorbed part of `inputs`.
+fn absorbed_amounts(y: &DVector<f64>, inputs: &[WeibullInput], t: f64) -> DVector<f64> {
+    let mut amounts = y.clone();
+    for input in inputs {
+        if input.compartment > 0 && input.compartment <= amounts.nrows() {
+            amounts[input.compartment - 1] -= input.unabsorbed(t);
+        }
+    }
+    amounts
+}
+
+/// How a dose enters its compartment: all at once, at a constant rate over
+/// a duration, or along a Weibull curve.
+enum DoseInput {
+    Bolus(f64),
+    ZeroOrder { rate: f64, duration: f64 },
+    Weibull { amount: f64, tau: f64, beta: f64 },
+}
+
+/// Something that changes the system at a point in time.
+enum Event {
+    Dose(usize, f64),
+    InputStart(usize, f64),
+    InputStop(usize, f64),
+    WeibullStart(WeibullInput),
+    CovariateChange,
+}
+
+/// Where and how a dose enters the system, and the delay before it does.
+/// Oral doses, and doses whose CMT names the depot, go to the depot scaled
+/// by F after ALAG. Without a depot, oral doses enter the central
+/// compartment along the Weibull curve, over D1, or at once. RATE=-2 doses
+/// run into their CMT over D1, infusions over AMT/RATE, others at once.
+fn dose_input(
+    model: &CompartmentModel,
+    params: &ModelParameters,
+    dose: &DosingRecord,
+) -> Result<(usize, DoseInput, f64), anyhow::Error> {
+    let depot = model.depot_compartment();
+    let oral = dose.dosing_type == DosingType::Oral || depot == Some(dose.compartment as usize);
+    let zero_order = |amount: f64, duration: f64| {
+        if duration > 0.0 {
+            DoseInput::ZeroOrder { rate: amount / duration, duration }
+        } else {
+            DoseInput::Bolus(amount)
+        }
+    };
+
+    if dose.has_modeled_duration() {
+        let duration = model.absorption_duration(params).ok_or_else(|| anyhow::anyhow!(
+            "Dose at time {} has RATE={} but the {} model does not estimate a duration (D1)",
+            dose.time, MODELED_DURATION_RATE, model.model_type()
+        ))?;
+        let amount = if oral { dose.amount * model.bioavailability(params) } else { dose.amount };
+        return Ok((dose.compartment as usize, zero_order(amount, duration), 0.0));
+    }
+
+    if oral && depot.is_none() {
+        if let Some((tau, beta)) = model.weibull_absorption(params) {
+            let amount = dose.amount * model.bioavailability(params);
+            return Ok((model.central_compartment(), DoseInput::Weibull { amount, tau, beta }, model.absorption_lag(params)));
+        }
+    }
+
+    Ok(match (oral, depot, model.absorption_duration(params)) {
+        (true, Some(depot), _) => (
+            depot,
+            DoseInput::Bolus(dose.amount * model.bioavailability(params)),
+            model.absorption_lag(params),
+        ),
+        (true, None, Some(duration)) => (
+            model.central_compartment(),
+            zero_order(dose.amount * model.bioavailability(params), duration),
+            model.absorption_lag(params),
+        ),
+        (true, None, None) => (model.central_compartment(), DoseInput::Bolus(dose.amount), 0.0),
+        (false, _, _) => match dose.infusion_duration() {
+            Some(duration) => (dose.compartment as usize, zero_order(dose.amount, duration), 0.0),
+            None => (dose.compartment as usize, DoseInput::Bolus(dose.amount), 0.0),
+        },
+    })
+}
+
+/// Predicts the model output at each of the individual's observations.
+///
+/// Between consecutive events (doses and covariate changes) the system is
+/// integrated in one continuous pass and the state is recorded at every
+/// observation time, so adding or removing observations never changes the
+/// predictions at the others. Observations at an event time see the
+/// post-event state and parameters, unless marked `pre_dose`, in which case
+/// they see the state just before any event at that time. Doses are routed
+/// as described for `dose_input`, with F, ALAG and D1 taken from the
+/// baseline individual parameters. An individual's prior regimen is given
+/// first, from the initial conditions at its first dose, so the state at
+/// time zero is what the regimen left.
+///
+/// When `solver_config.analytic_solutions` is set and the model has a closed
+/// form for this individual, the solver is not called at all.
+pub fn predict_individual(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    individual: &Individual,
+    params: &ModelParameters,
+    solver_config: &SolverConfig,
+) -> Result<Vec<f64>, anyhow::Error> {
+    if solver_config.analytic_solutions {
+        if let Some(predictions) = model.analytic_prediction(individual, params) {
+            return Ok(predictions);
+        }
+    }
+
+    let observations = individual.observations();
+    let mut active_params = model.individual_parameters(params, &individual.covariates_at(f64::NEG_INFINITY));
+
+    // Dose inputs and covariate changes in time order; a zero-order input
+    // starts and stops at separate events. Integration restarts at every
+    // event, so the kink where an infusion starts or stops falls on a step
+    // boundary instead of inside a step
+    let mut events: Vec<(f64, Event)> = individual.covariate_change_times().into_iter()
+        .map(|t| (t, Event::CovariateChange))
+        .collect();
+    let prior_doses = individual.prior_regimen().map(|regimen| regimen.doses()).unwrap_or_default();
+    for dose in prior_doses.iter().chain(individual.dosing_records()) {
+        let (compartment, input, lag) = dose_input(model, &active_params, dose)?;
+        let start = dose.time + lag;
+        match input {
+            DoseInput::Bolus(amount) => events.push((start, Event::Dose(compartment, amount))),
+            DoseInput::ZeroOrder { rate, duration } => {
+                events.push((start, Event::InputStart(compartment, rate)));
+                events.push((start + duration, Event::InputStop(compartment, rate)));
+            }
+            DoseInput::Weibull { amount, tau, beta } => {
+                let input = WeibullInput { compartment, start, amount, tau, beta };
+                events.push((start, Event::WeibullStart(input)));
+            }
+        }
+    }
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut predictions = Vec::with_capacity(observations.len());
+    let start_time = prior_doses.first().map_or(0.0, |dose| dose.time.min(0.0));
+    let mut current_state = ModelState::with_initial_conditions(model.n_compartments(), &active_params.initial_conditions)
+        .with_time(start_time);
+    let mut input_rates = DVector::<f64>::zeros(model.n_compartments());
+    let mut weibull_inputs: Vec<WeibullInput> = Vec::new();
+    let mut next_obs = 0;
+
+    for (event_time, event) in events {
+        // Observations before this event, followed by the event time itself
+        let segment_end = observations[next_obs..].iter()
+            .position(|obs| obs.time > event_time || (obs.time == event_time && !obs.pre_dose))
+            .map(|offset| next_obs + offset)
+            .unwrap_or(observations.len());
+
+        let (mut output_times, time_index) = distinct_times(&observations[next_obs..segment_end]);
+        if output_times.last() != Some(&event_time) {
+            output_times.push(event_time);
+        }
+
+        let system = CompartmentSystem {
+            model,
+            params: &active_params,
+            input_rates: &input_rates,
+            weibull_inputs: &weibull_inputs,
+        };
+        let states = solver.solve_at_times(
+            &system,
+            current_state.time,
+            &current_state.compartments,
+            &output_times,
+            solver_config,
+        )?;
+
+        for (obs, &k) in observations[next_obs..segment_end].iter().zip(&time_index) {
+            let obs_state = ModelState {
+                compartments: absorbed_amounts(&states[k], &weibull_inputs, obs.time),
+                time: obs.time,
+            };
+            predictions.push(model.observation_function(&obs_state, &active_params, obs.compartment as usize));
+        }
+        next_obs = segment_end;
+
+        if let Some(state_at_event) = states.into_iter().last() {
+            current_state.compartments = state_at_event;
+        }
+        current_state.time = event_time;
+
+        match event {
+            Event::Dose(compartment, amount) => current_state.add_dose(compartment, amount),
+            Event::InputStart(compartment, rate) => add_input_rate(&mut input_rates, compartment, rate),
+            Event::InputStop(compartment, rate) => add_input_rate(&mut input_rates, compartment, -rate),
+            Event::WeibullStart(input) => {
+                current_state.add_dose(input.compartment, input.amount);
+                weibull_inputs.push(input);
+            }
+            Event::CovariateChange => {
+                active_params = model.individual_parameters(params, &individual.covariates_at(event_time));
+            }
+        }
+    }
+
+    // Observations after the last event
+    let system = CompartmentSystem {
+        model,
+        params: &active_params,
+        input_rates: &input_rates,
+        weibull_inputs: &weibull_inputs,
+    };
+    let (output_times, time_index) = distinct_times(&observations[next_obs..]);
+    let states = solver.solve_at_times(
+        &system,
+        current_state.time,
+        &current_state.compartments,
+        &output_times,
+        solver_config,
+    )?;
+
+    for (obs, &k) in observations[next_obs..].iter().zip(&time_index) {
+        let obs_state = ModelState {
+            compartments: absorbed_amounts(&states[k], &weibull_inputs, obs.time),
+            time: obs.time,
+        };
+        predictions.push(model.observation_function(&obs_state, &active_params, obs.compartment as usize));
+    }
+
+    Ok(predictions)
+}
+
+/// The distinct times of time-ordered `observations` and the index of each
+/// observation's time among them, so observations of several compartments
+/// at one time are read from a single solver output.
+fn distinct_times(observations: &[Observation]) -> (Vec<f64>, Vec<usize>) {
+    let mut times: Vec<f64> = Vec::with_capacity(observations.len());
+    let index = observations.iter()
+        .map(|obs| {
+            if times.last() != Some(&obs.time) {
+                times.push(obs.time);
+            }
+            times.len() - 1
+        })
+        .collect();
+    (times, index)
+}
+
+fn add_input_rate(input_rates: &mut DVector<f64>, compartment: usize, rate: f64) {
+    if compartment > 0 && compartment <= input_rates.nrows() {
+        input_rates[compartment - 1] += rate;
+    }
+}
+
+/// Predicts the central-compartment concentration at arbitrary `times`
+/// (ascending) under the individual's dosing and covariates.
+pub fn predict_at_times(
+    model: &CompartmentModel,
+    solver: &dyn OdeSolver,
+    individual: &Individual,
+    params: &ModelParameters,
+    times: &[f64],
+    solver_config: &SolverConfig,
+) -> Result<Vec<f64>, anyhow::Error> {
+    let observations = times.iter()
+        .map(|&t| Observation::new(t, 0.0, 1, ObservationType::Concentration))
+        .collect();
+    let grid_individual = individual.with_observations(observations);
+
+    predict_individual(model, solver, &grid_individual, params, solver_config)
+}
+
+/// (time, IPRED, PRED) at each observation of every individual, keyed by ID.
+pub type IndividualPredictions = HashMap<i32, Vec<(f64, f64, f64)>>;
+
+/// Predictions at each observation of every individual, with the solver
+/// settings of the fit. IPRED uses the individual estimates, PRED the fixed
+/// effects; an individual without estimates gets PRED for both.
+pub fn individual_predictions(
+    results: &SaemResults,
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<IndividualPredictions, anyhow::Error> {
+    let solver = RungeKuttaSolver::new();
+
+    let mut pop_params = model.default_parameters();
+    pop_params.fixed_effects = results.fixed_effects.clone();
+
+    let mut predictions = HashMap::new();
+    for (&id, individual) in dataset.individuals() {
+        let mut ind_params = pop_params.clone();
+        if let Some(values) = results.individual_parameters.get(&id) {
+            ind_params.fixed_effects = values.clone();
+        }
+
+        let pred = predict_individual(model, &solver, individual, &pop_params, &results.solver_config)?;
+        let ipred = predict_individual(model, &solver, individual, &ind_params, &results.solver_config)?;
+        let rows = individual.observations().iter().zip(ipred).zip(pred)
+            .map(|((obs, ipred), pred)| (obs.time, ipred, pred))
+            .collect();
+        predictions.insert(id, rows);
+    }
+    Ok(predictions)
+}
+
+/// `n_points` evenly spaced times from `start` to `end` inclusive.
+pub fn time_grid(start: f64, end: f64, n_points: usize) -> Vec<f64> {
+    match n_points {
+        0 => Vec::new(),
+        1 => vec![start],
+        _ => {
+            let step = (end - start) / (n_points - 1) as f64;
+            (0..n_points).map(|i| start + i as f64 * step).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingType, Observation, ObservationType, PriorRegimen};
+    use crate::models::ModelType;
+    use crate::solver::RungeKuttaSolver;
+    use std::collections::HashMap;
+
+    fn individual_with_times(times: &[f64]) -> Individual {
+        let observations = times.iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        Individual::new(1, observations, doses, HashMap::new())
+    }
+
+    #[test]
+    fn test_predictions_invariant_to_extra_observation_times() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+
+        let sparse = individual_with_times(&[1.0, 5.0, 12.0]);
+        let dense = individual_with_times(&[0.3, 1.0, 2.7, 5.0, 7.9, 12.0]);
+
+        let sparse_pred = predict_individual(&model, &solver, &sparse, &params, &config).unwrap();
+        let dense_pred = predict_individual(&model, &solver, &dense, &params, &config).unwrap();
+
+        assert_eq!(sparse_pred[0], dense_pred[1]);
+        assert_eq!(sparse_pred[1], dense_pred[3]);
+        assert_eq!(sparse_pred[2], dense_pred[5]);
+
+        // And both agree with the analytic bolus solution
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+        for (&t, &pred) in [1.0, 5.0, 12.0].iter().zip(sparse_pred.iter()) {
+            let analytic = 100.0 / v * (-cl / v * t).exp();
+            assert!((pred - analytic).abs() / analytic < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_coincident_observations_of_two_compartments_share_one_integration() {
+        use crate::solver::{CountingSolver, EvaluationCounter};
+
+        let model = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+        let params = model.default_parameters();
+        let config = SolverConfig::default();
+        // Off the solver's step grid, so each output needs a partial step
+        let times = [0.5, 4.25, 12.5];
+        let individual = |compartments: &[i32]| {
+            let observations = times.iter()
+                .flat_map(|&t| compartments.iter().map(move |&cmt| Observation::new(t, 1.0, cmt, ObservationType::Concentration)))
+                .collect();
+            let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+            Individual::new(1, observations, doses, HashMap::new())
+        };
+        let predict = |compartments: &[i32]| {
+            let counter = EvaluationCounter::new();
+            let solver = RungeKuttaSolver::new();
+            let solver = CountingSolver::new(&solver, &counter);
+            let predictions = predict_individual(&model, &solver, &individual(compartments), &params, &config).unwrap();
+            (predictions, counter.counts().derivative_evaluations)
+        };
+
+        let (central, central_evaluations) = predict(&[1]);
+        let (peripheral, _) = predict(&[2]);
+        let (both, both_evaluations) = predict(&[1, 2]);
+
+        // Each compartment is read from the same state as when observed alone
+        for k in 0..times.len() {
+            assert_eq!(both[2 * k], central[k]);
+            assert_eq!(both[2 * k + 1], peripheral[k]);
+            assert!(peripheral[k] > 0.0 && peripheral[k] != central[k]);
+        }
+        // and the second compartment costs no extra integration
+        assert_eq!(both_evaluations, central_evaluations);
+    }
+
+    #[test]
+    fn test_initial_central_amount_without_dose() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut params = model.default_parameters();
+        params.initial_conditions = vec![50.0];
+        let (cl, v) = (params.fixed_effects[0].exp(), params.fixed_effects[1].exp());
+        let observations = [0.0, 2.0, 8.0].iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let individual = Individual::new(1, observations, Vec::new(), HashMap::new());
+
+        let config = SolverConfig { analytic_solutions: true, ..SolverConfig::default() };
+        let predictions = predict_individual(&model, &RungeKuttaSolver::new(), &individual, &params, &config).unwrap();
+
+        assert_eq!(predictions[0], 50.0 / v);
+        for (&t, &pred) in [2.0, 8.0].iter().zip(&predictions[1..]) {
+            let expected = 50.0 / v * (-cl / v * t).exp();
+            assert!((pred - expected).abs() / expected < 1e-3);
+        }
+
+        // Without initial conditions nothing is there to observe
+        params.initial_conditions.clear();
+        let predictions = predict_individual(&model, &RungeKuttaSolver::new(), &individual, &params, &config).unwrap();
+        assert!(predictions.iter().all(|&pred| pred == 0.0));
+    }
+
+    #[test]
+    fn test_pre_dose_trough_sees_state_before_dose() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+
+        let observations = vec![
+            Observation::new(12.0, 1.0, 1, ObservationType::Concentration).with_pre_dose(true),
+            Observation::new(12.0, 1.0, 1, ObservationType::Concentration),
+        ];
+        let doses = vec![
+            DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus),
+            DosingRecord::new(12.0, 100.0, 1, DosingType::Bolus),
+        ];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+        let pred = predict_individual(&model, &solver, &individual, &params, &config).unwrap();
+
+        let cl = params.fixed_effects[0].exp();
+        let v = params.fixed_effects[1].exp();
+        let trough = 100.0 / v * (-cl / v * 12.0).exp();
+        assert!((pred[0] - trough).abs() / trough < 1e-3);
+        // The unflagged sample at the same time sees the dose
+        assert!((pred[1] - pred[0] - 100.0 / v).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_analytic_one_compartment_matches_numerical() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut params = model.default_parameters();
+        params.fixed_effects = vec![1.7_f64.ln(), 12.0_f64.ln()];
+        let solver = RungeKuttaSolver::new();
+
+        let observations = [0.5, 3.0, 6.0, 6.0, 9.5, 12.0, 20.0].iter().enumerate()
+            .map(|(k, &t)| Observation::new(t, 1.0, 1, ObservationType::Concentration).with_pre_dose(k == 2))
+            .collect();
+        let doses = vec![
+            DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus),
+            DosingRecord::new(6.0, 50.0, 1, DosingType::Bolus),
+            DosingRecord::new(12.0, 80.0, 1, DosingType::Oral),
+        ];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+
+        let numerical_config = SolverConfig {
+            absolute_tolerance: 1e-12,
+            relative_tolerance: 1e-12,
+            max_step_size: 0.05,
+            analytic_solutions: false,
+            ..SolverConfig::default()
+        };
+        let analytic = model.analytic_prediction(&individual, &params).unwrap();
+        let numerical = predict_individual(&model, &solver, &individual, &params, &numerical_config).unwrap();
+        assert_eq!(analytic, predict_individual(&model, &solver, &individual, &params, &SolverConfig::default()).unwrap());
+        for (a, n) in analytic.iter().zip(&numerical) {
+            assert!((a - n).abs() < 1e-6, "analytic {} vs numerical {}", a, n);
+        }
+
+        // Infusions are left to the solver
+        let infusion = Individual::new(
+            1,
+            vec![Observation::new(1.0, 1.0, 1, ObservationType::Concentration)],
+            vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Infusion)],
+            HashMap::new(),
+        );
+        assert!(model.analytic_prediction(&infusion, &params).is_none());
+    }
+
+    /// One-compartment model with first-order absorption from a depot.
+    struct OralOneCompartment;
+
+    impl crate::models::CompartmentModelTrait for OralOneCompartment {
+        fn n_compartments(&self) -> usize {
+            2
+        }
+
+        fn parameter_names(&self) -> Vec<String> {
+            vec!["KA".to_string(), "CL".to_string(), "V".to_string()]
+        }
+
+        fn default_parameters(&self) -> ModelParameters {
+            let mut params = ModelParameters::new(3, self.parameter_names());
+            params.fixed_effects = vec![1.0_f64.ln(), 1.0_f64.ln(), 10.0_f64.ln()];
+            params
+        }
+
+        fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+            let ka = params.fixed_effects[0].exp();
+            let ke = params.fixed_effects[1].exp() / params.fixed_effects[2].exp();
+            let depot = state.compartments[0];
+            let central = state.compartments[1];
+            DVector::from_vec(vec![-ka * depot, ka * depot - ke * central])
+        }
+
+        fn observation_function(&self, state: &ModelState, params: &ModelParameters, _compartment: usize) -> f64 {
+            state.compartments[1] / params.fixed_effects[2].exp()
+        }
+
+        fn validate_parameters(&self, _params: &ModelParameters) -> Result<(), crate::models::ModelError> {
+            Ok(())
+        }
+
+        fn central_compartment(&self) -> usize {
+            2
+        }
+
+        fn depot_compartment(&self) -> Option<usize> {
+            Some(1)
+        }
+
+        fn bioavailability(&self, _params: &ModelParameters) -> f64 {
+            0.5
+        }
+
+        fn absorption_lag(&self, _params: &ModelParameters) -> f64 {
+            0.5
+        }
+    }
+
+    #[test]
+    fn test_oral_dose_absorbs_through_depot() {
+        let model = CompartmentModel::from_custom("oral", Box::new(OralOneCompartment));
+        let params = model.default_parameters();
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+
+        let times = [0.25, 0.75, 1.0, 2.0, 3.0, 6.0, 12.0];
+        let predict = |dosing_type: DosingType, compartment: i32| {
+            let observations = times.iter()
+                .map(|&t| Observation::new(t, 1.0, 2, ObservationType::Concentration))
+                .collect();
+            let doses = vec![DosingRecord::new(0.0, 100.0, compartment, dosing_type)];
+            let individual = Individual::new(1, observations, doses, HashMap::new());
+            predict_individual(&model, &solver, &individual, &params, &config).unwrap()
+        };
+
+        // IV bolus into central peaks immediately and only declines
+        let iv = predict(DosingType::Bolus, 2);
+        assert!(iv.windows(2).all(|w| w[1] < w[0]));
+        assert!((iv[0] - 10.0 * (-0.1_f64 * 0.25).exp()).abs() < 1e-3);
+
+        // Oral: nothing before ALAG, then a rise to a later peak, and F halves exposure
+        let oral = predict(DosingType::Oral, 2);
+        assert_eq!(oral[0], 0.0);
+        let peak = oral.iter().cloned().fold(f64::MIN, f64::max);
+        let peak_index = oral.iter().position(|&c| c == peak).unwrap();
+        assert!(peak_index > 1 && peak_index < oral.len() - 1);
+        assert!(peak < 0.5 * iv[0]);
+
+        // A bolus whose CMT is the depot is routed the same way
+        assert_eq!(predict(DosingType::Bolus, 1), oral);
+    }
+
+    #[test]
+    fn test_zero_order_absorption_is_linear_over_d1() {
+        let model = CompartmentModel::new(ModelType::OneCompartmentZeroOrder).unwrap();
+        let mut params = model.default_parameters();
+        // Negligible clearance, so the central amount is just what has been absorbed
+        params.fixed_effects = vec![1e-6_f64.ln(), 10.0_f64.ln(), 2.0_f64.ln()];
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+
+        let times = [0.5, 1.0, 1.5, 2.0, 3.0, 4.0];
+        let predict = |dose: DosingRecord| {
+            let observations = times.iter()
+                .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+                .collect();
+            let individual = Individual::new(1, observations, vec![dose], HashMap::new());
+            predict_individual(&model, &solver, &individual, &params, &config).unwrap()
+        };
+
+        // 100 mg over D1 = 2 h into V = 10 L: C(t) = 5·t up to D1, then flat
+        let oral = predict(DosingRecord::new(0.0, 100.0, 1, DosingType::Oral));
+        for (&t, &c) in times.iter().zip(&oral) {
+            let expected = 5.0 * t.min(2.0);
+            assert!((c - expected).abs() < 1e-3, "t {}: {} vs {}", t, c, expected);
+        }
+
+        // RATE=-2 asks for the same duration; an infusion at AMT/D1 matches it
+        let mut modeled = DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus);
+        modeled.rate = Some(crate::data::MODELED_DURATION_RATE);
+        let mut infusion = DosingRecord::new(0.0, 100.0, 1, DosingType::Infusion);
+        infusion.rate = Some(50.0);
+        let modeled = predict(modeled);
+        for ((a, b), c) in oral.iter().zip(&modeled).zip(&predict(infusion)) {
+            assert!((a - b).abs() < 1e-9 && (a - c).abs() < 1e-6);
+        }
+
+        // A model without D1 cannot honour RATE=-2
+        let one_compartment = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut dose = DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus);
+        dose.rate = Some(crate::data::MODELED_DURATION_RATE);
+        let individual = Individual::new(
+            1,
+            vec![Observation::new(1.0, 1.0, 1, ObservationType::Concentration)],
+            vec![dose],
+            HashMap::new(),
+        );
+        let params = one_compartment.default_parameters();
+        assert!(predict_individual(&one_compartment, &solver, &individual, &params, &config).is_err());
+    }
+
+    #[test]
+    fn test_infusion_ending_between_steps_matches_analytic() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut params = model.default_parameters();
+        params.fixed_effects = vec![2.0_f64.ln(), 10.0_f64.ln()];
+        let (cl, k) = (2.0, 0.2);
+        let solver = RungeKuttaSolver::new();
+        // Unit steps from the infusion start put its end at 2.5 h mid-step
+        let config = SolverConfig { max_step_size: 1.0, analytic_solutions: false, ..SolverConfig::default() };
+
+        // 100 mg at 40 mg/h: C = R/CL·(1 − e^(−kt)) until 2.5 h, then decays
+        let mut infusion = DosingRecord::new(0.0, 100.0, 1, DosingType::Infusion);
+        infusion.rate = Some(40.0);
+        let times = [1.7, 2.5, 2.51, 3.0, 6.0];
+        let observations = times.iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let individual = Individual::new(1, observations, vec![infusion], HashMap::new());
+        let predictions = predict_individual(&model, &solver, &individual, &params, &config).unwrap();
+
+        let at_end = 40.0 / cl * (1.0 - (-k * 2.5_f64).exp());
+        for (&t, &pred) in times.iter().zip(&predictions) {
+            let expected = if t <= 2.5 {
+                40.0 / cl * (1.0 - (-k * t).exp())
+            } else {
+                at_end * (-k * (t - 2.5)).exp()
+            };
+            assert!((pred - expected).abs() / expected < 1e-4, "t {}: {} vs {}", t, pred, expected);
+        }
+        // Just after the end the concentration has started to fall
+        assert!(predictions[2] < predictions[1]);
+    }
+
+    #[test]
+    fn test_prior_regimen_sets_steady_state_trough_at_zero() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let params = model.default_parameters();
+        let (cl, v) = (params.fixed_effects[0].exp(), params.fixed_effects[1].exp());
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+
+        // Trough sampled just before the first recorded dose, then 1 h after it
+        let mut trough = Observation::new(0.0, 1.0, 1, ObservationType::Concentration);
+        trough.pre_dose = true;
+        let observations = vec![trough, Observation::new(1.0, 1.0, 1, ObservationType::Concentration)];
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let naive = Individual::new(1, observations, doses, HashMap::new());
+        let (interval, k) = (12.0, cl / v);
+        let accumulation = 1.0 / (1.0 - (-k * interval).exp());
+
+        // Thirty doses of 100 every 12 h, about six 2.1 h half-lives apart;
+        // RK4 at the default step is accurate to about 1e-3
+        let chronic = naive.clone().with_prior_regimen(PriorRegimen::new(100.0, interval, 30, DosingType::Bolus));
+        let predictions = predict_individual(&model, &solver, &chronic, &params, &config).unwrap();
+        let expected_trough = 100.0 / v * (-k * interval).exp() * accumulation;
+        assert!((predictions[0] - expected_trough).abs() < 1e-3 * expected_trough, "{} vs {}", predictions[0], expected_trough);
+        let expected_peak = 100.0 / v * (-k).exp() * accumulation;
+        assert!((predictions[1] - expected_peak).abs() < 1e-3 * expected_peak);
+
+        // Without the regimen the trough is empty
+        let predictions = predict_individual(&model, &solver, &naive, &params, &config).unwrap();
+        assert_eq!(predictions[0], 0.0);
+        let one_dose = naive.with_prior_regimen(PriorRegimen::new(100.0, interval, 1, DosingType::Bolus));
+        let predictions = predict_individual(&model, &solver, &one_dose, &params, &config).unwrap();
+        let expected = 100.0 / v * (-k * interval).exp();
+        assert!((predictions[0] - expected).abs() < 1e-3 * expected);
+    }
+
+    #[test]
+    fn test_weibull_absorption_follows_fraction_absorbed() {
+        let model = CompartmentModel::new(ModelType::OneCompartmentWeibull).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let config = SolverConfig::default();
+        let times = [0.25, 0.5, 1.0, 2.0, 3.0, 6.0, 12.0];
+        let observations: Vec<Observation> = times.iter()
+            .map(|&t| Observation::new(t, 1.0, 1, ObservationType::Concentration))
+            .collect();
+        let individual = Individual::new(1, observations, vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Oral)], HashMap::new());
+
+        // Negligible clearance, so C(t) = Dose·F(t)/V; β < 1 has an
+        // unbounded absorption rate at the dose
+        for beta in [0.5, 1.0, 3.0] {
+            let mut params = model.default_parameters();
+            params.fixed_effects = vec![1e-6_f64.ln(), 10.0_f64.ln(), 2.0_f64.ln(), f64::ln(beta)];
+            let predictions = predict_individual(&model, &solver, &individual, &params, &config).unwrap();
+            for (&t, &c) in times.iter().zip(&predictions) {
+                let expected = 10.0 * weibull_fraction_absorbed(t, 2.0, beta);
+                assert!((c - expected).abs() < 1e-4, "beta {} t {}: {} vs {}", beta, t, c, expected);
+            }
+        }
+
+        // β = 1 is first-order absorption with KA = 1/τ:
+        // C = Dose·ka/(V(ka − k))·(e^(−kt) − e^(−ka·t))
+        let mut params = model.default_parameters();
+        params.fixed_effects = vec![1.0_f64.ln(), 10.0_f64.ln(), 2.0_f64.ln(), 0.0];
+        let predictions = predict_individual(&model, &solver, &individual, &params, &config).unwrap();
+        let (ka, k) = (0.5, 0.1);
+        for (&t, &c) in times.iter().zip(&predictions) {
+            let expected = 100.0 * ka / (10.0 * (ka - k)) * ((-k * t).exp() - (-ka * t).exp());
+            assert!((c - expected).abs() < 1e-4, "t {}: {} vs {}", t, c, expected);
+        }
+    }
+}
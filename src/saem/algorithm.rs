@@ -1,37 +1,30 @@
-use super::{SaemResults, McmcSampler, McmcConfig};
+use super::{mcmc, SaemResults, McmcSample, McmcSampler, McmcConfig, PosteriorAccumulator};
+use super::sufficient::{self, SufficientStatistics};
 use super::{ParameterStatistics, OmegaStatistics};
-use crate::data::Dataset;
-use crate::models::{CompartmentModel, ModelParameters, ModelState};
-use crate::estimation::EstimationConfig;
-use crate::solver::{OdeSolver, OdeSystem, RungeKuttaSolver, SolverConfig};
-use anyhow::{Context, Result};
-use log::{info, debug, warn};
-use std::collections::HashMap;
-use nalgebra::DVector;
-
-struct CompartmentSystem<'a> {
-    model: &'a CompartmentModel,
-    params: &'a ModelParameters,
-}
-
-impl<'a> OdeSystem for CompartmentSystem<'a> {
-    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
-        let state = ModelState {
-            compartments: y.clone(),
-            time: t,
-        };
-        self.model.derivatives(&state, self.params)
-    }
-
-    fn dimension(&self) -> usize {
-        self.model.n_compartments()
-    }
-}
+use crate::data::{Dataset, ObservationType};
+use crate::models::{CompartmentModel, ModelParameters};
+use crate::estimation::{bounds, objective, omega, ErrorModel, EstimationConfig, LikelihoodProfile, McmcUpdate};
+use crate::estimation::profile;
+use crate::estimation::timing::IterationTimer;
+use crate::estimation::trace::{self, IndividualTrace};
+use crate::diagnostics;
+use crate::prediction::{self, StepSizeCheck};
+use crate::validation;
+use crate::solver::{CountingSolver, EvaluationCounter, OdeSolver, RungeKuttaSolver};
+use anyhow::{anyhow, Context, Result};
+use log::{info, debug, trace, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+/// Draws per individual in `evaluate_objective`'s chains; the first half is
+/// discarded as warm-up and the rest averaged.
+const EVALUATION_MCMC_SAMPLES: usize = 200;
 
 pub struct SaemEstimator {
     model: CompartmentModel,
     config: EstimationConfig,
     solver: Box<dyn OdeSolver + Send + Sync>,
+    evaluations: EvaluationCounter,
 }
 
 impl SaemEstimator {
@@ -42,6 +35,7 @@ impl SaemEstimator {
             model,
             config,
             solver,
+            evaluations: EvaluationCounter::new(),
         }
     }
 
@@ -52,12 +46,65 @@ impl SaemEstimator {
     // CORRECTED: Removed duplicate function definition
     pub fn fit(&mut self, dataset: &Dataset) -> Result<SaemResults> {
         info!("Starting SAEM estimation for {} individuals", dataset.n_individuals());
+        let started = Instant::now();
+        self.evaluations.reset();
+        let negative_corrections_before = self.solver.negative_state_corrections();
+        validation::validate_dose_compartments(dataset, &self.model)?;
+        validation::validate_dosed_individuals(dataset, &self.model)?;
+        
+        let post_burnin = self.config.post_burnin_iterations();
+        if post_burnin < self.config.statistics_window {
+            warn!("Only {} post-burn-in iterations for a statistics window of {}; parameter statistics will use fewer iterations or be empty",
+                  post_burnin, self.config.statistics_window);
+        }
+        if post_burnin < self.config.convergence_window {
+            warn!("Only {} post-burn-in iterations for a convergence window of {}; convergence will not be checked",
+                  post_burnin, self.config.convergence_window);
+        }
         
         let n_params = self.model.parameter_names().len();
         let parameter_names = self.model.parameter_names();
         let mut results = SaemResults::new(n_params, parameter_names.clone());
+        results.parameter_units = self.model.parameter_units();
         
+        let log_bounds = bounds::log_bounds(&self.config.parameter_bounds, &parameter_names);
         let mut current_params = self.model.default_parameters();
+        if let Some(initial) = &self.config.initial_fixed_effects {
+            current_params.fixed_effects = initial.clone();
+        }
+        if let Some(initial) = &self.config.initial_omega {
+            omega::validate_omega(initial, current_params.n_parameters())
+                .map_err(|message| anyhow!("Invalid initial Omega: {}", message))?;
+            current_params.random_effects_variance = initial.clone();
+        }
+        if let Some(initial) = self.config.initial_residual_variance {
+            current_params.residual_variance = initial;
+        }
+        for (theta, &limits) in current_params.fixed_effects.iter_mut().zip(&log_bounds) {
+            *theta = theta.clamp(limits.0, limits.1);
+        }
+        if !self.config.eta_priors.is_empty() {
+            current_params.eta_priors = parameter_names.iter()
+                .map(|name| self.config.eta_priors.get(name).copied().unwrap_or_default())
+                .collect();
+        }
+        if dataset.has_effect_observations() && current_params.effect_residual_variance.is_none() {
+            current_params.effect_residual_variance = Some(current_params.residual_variance);
+        }
+        objective::initialize_output_variances(&self.model, dataset, &mut current_params);
+        match self.config.error_model {
+            ErrorModel::Additive => {}
+            ErrorModel::Exponential => current_params.log_scale_residuals = true,
+            error_model => warn!("SAEM fits an additive or exponential residual error; the {} error model is used by FOCE, FOCE-I and AGQ only",
+                                 error_model),
+        }
+        results.error_model = if current_params.log_scale_residuals { ErrorModel::Exponential } else { ErrorModel::Additive };
+        let mut step_size_check = StepSizeCheck::new();
+        step_size_check.check(&self.model, &current_params, &self.config.solver_config);
+        if !self.config.fix_omega && dataset.n_individuals() < omega::MIN_INDIVIDUALS_FOR_OMEGA {
+            warn!("SAEM fit to {} individual(s): Omega is not estimable and is held at its initial value",
+                  dataset.n_individuals());
+        }
         results.set_fixed_effects(&current_params.get_fixed_effects_vector());
         results.set_random_effects_variance(&current_params.get_random_effects_matrix());
         results.residual_variance = current_params.residual_variance;
@@ -67,41 +114,88 @@ impl SaemEstimator {
             individual_params.insert(id, current_params.fixed_effects.clone());
         }
 
-        let mut sa_sum_theta = vec![0.0; n_params];
-        let mut sa_sum_theta_sq = vec![vec![0.0; n_params]; n_params];
-        let mut sa_sum_sigma = 0.0;
+        let mut posterior = PosteriorAccumulator::new();
+        // Componentwise step sizes of each individual, tuned during burn-in
+        let mut step_sizes: HashMap<i32, Vec<f64>> = dataset.individuals().keys()
+            .map(|&id| (id, vec![self.config.step_size; n_params]))
+            .collect();
+        let mut acceptance_sums = vec![0.0; n_params];
+        let mut n_chains_sampled = 0usize;
+
+        let mut statistics = SufficientStatistics::new(n_params);
+        let n_exploratory = self.config.exploratory_iterations();
+        let mut timer = self.config.timing_interval
+            .map(|interval| IterationTimer::new(interval, self.config.n_iterations));
+        let tracer = IndividualTrace::new(&self.config.trace_individuals);
 
         for iteration in 0..self.config.n_iterations {
             debug!("SAEM iteration {}/{}", iteration + 1, self.config.n_iterations);
             
             let mut iteration_log_likelihood = 0.0;
 
-            let gamma = if iteration < self.config.n_burnin {
-                1.0
-            } else {
-                1.0 / ((iteration - self.config.n_burnin + 1) as f64).powf(0.7)
-            };
+            let gamma = sufficient::step_gain(iteration, n_exploratory);
 
             for (&id, individual) in dataset.individuals() {
                 let mcmc_config = McmcConfig {
                     n_samples: self.config.mcmc_samples_per_iteration,
                     step_size: self.config.step_size,
+                    step_sizes: match self.config.mcmc_update {
+                        McmcUpdate::Block => Vec::new(),
+                        McmcUpdate::Componentwise => step_sizes[&id].clone(),
+                    },
                     target_acceptance: self.config.target_acceptance,
+                    update: self.config.mcmc_update,
                     seed: self.config.seed.map(|s| s.wrapping_add(iteration as u64).wrapping_add(id as u64)),
+                    bounds: log_bounds.clone(),
+                    solver_config: self.config.solver_config.clone(),
                 };
 
+                let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
                 let mut sampler = McmcSampler::new(
                     &self.model,
-                    self.solver.as_ref(),
+                    &solver,
                     mcmc_config,
                 );
 
-                let (new_params, log_like) = sampler.sample_individual_parameters(
+                let initial = individual_params.get(&id).unwrap();
+                let (samples, log_like) = sampler.sample_chain(
                     individual,
                     &current_params,
-                    individual_params.get(&id).unwrap(),
+                    initial,
                 ).with_context(|| format!("MCMC sampling failed for individual {}", id))?;
+                if iteration < self.config.n_burnin {
+                    if self.config.mcmc_update == McmcUpdate::Componentwise {
+                        if let Some(steps) = step_sizes.get_mut(&id) {
+                            mcmc::adapt_step_sizes(steps, sampler.parameter_acceptance(), self.config.target_acceptance);
+                        }
+                    }
+                } else {
+                    for (sum, &rate) in acceptance_sums.iter_mut().zip(sampler.parameter_acceptance()) {
+                        *sum += rate;
+                    }
+                    n_chains_sampled += 1;
+                }
+
+                if iteration >= self.config.n_burnin && self.config.save_mcmc_samples {
+                    for (sample, params) in samples.iter().enumerate() {
+                        results.mcmc_samples.push(McmcSample {
+                            iteration,
+                            id,
+                            sample,
+                            eta: params.iter().zip(&current_params.fixed_effects).map(|(p, theta)| p - theta).collect(),
+                        });
+                    }
+                }
+                let new_params = samples.into_iter().last().unwrap_or_else(|| initial.clone());
+                if tracer.traces(id) {
+                    let eta: Vec<f64> = new_params.iter().zip(&current_params.fixed_effects).map(|(p, theta)| p - theta).collect();
+                    trace!(target: trace::TARGET, "SAEM iteration={} id={} eta={} log_density={} acceptance={:.3}",
+                           iteration + 1, id, trace::format_values(&eta), log_like, sampler.acceptance_rate());
+                }
 
+                if iteration >= self.config.n_burnin {
+                    posterior.add(id, &new_params);
+                }
                 individual_params.insert(id, new_params);
                 iteration_log_likelihood += log_like;
             }
@@ -109,17 +203,19 @@ impl SaemEstimator {
             self.update_population_parameters(
                 &individual_params,
                 &mut current_params,
-                &mut sa_sum_theta,
-                &mut sa_sum_theta_sq,
-                &mut sa_sum_sigma,
+                &mut statistics,
                 gamma,
+                iteration < n_exploratory,
                 dataset,
             );
 
             results.parameter_trajectory.push(current_params.fixed_effects.clone());
             results.log_likelihood_trajectory.push(iteration_log_likelihood);
+            if let Some(timer) = timer.as_mut() {
+                timer.lap("SAEM");
+            }
 
-            if iteration > self.config.n_burnin && iteration % 50 == 0 {
+            if iteration > self.config.n_burnin && iteration % self.config.convergence_window == 0 {
                 if self.check_convergence(&results) {
                     info!("Convergence achieved at iteration {}", iteration);
                     results.converged = true;
@@ -131,20 +227,59 @@ impl SaemEstimator {
                 info!("Iteration {}: Log-likelihood = {:.3}", 
                       iteration, iteration_log_likelihood);
             }
+
+            if self.config.time_budget_spent(started) {
+                warn!("SAEM stopped after {} iteration(s): time budget spent before convergence", iteration + 1);
+                results.time_budget_exceeded = true;
+                break;
+            }
         }
 
         results.set_fixed_effects(&current_params.get_fixed_effects_vector());
         results.set_random_effects_variance(&current_params.get_random_effects_matrix());
         results.residual_variance = current_params.residual_variance;
-        results.final_log_likelihood = results.log_likelihood_trajectory.last().copied()
-            .unwrap_or(f64::NEG_INFINITY);
-        results.objective_function_value = -2.0 * results.final_log_likelihood;
+        results.effect_residual_variance = current_params.effect_residual_variance;
+        results.output_residual_variances = current_params.output_residual_variances.clone();
+        if n_chains_sampled > 0 {
+            results.parameter_acceptance = acceptance_sums.iter().map(|sum| sum / n_chains_sampled as f64).collect();
+        }
+        // Individual estimates are posterior means over the post-burn-in
+        // samples rather than the noisy final sample
+        if !posterior.is_empty() {
+            individual_params = posterior.means();
+            results.individual_parameter_sd = posterior.sds();
+        }
+        // The trajectory holds the sampler's conditional log densities; the
+        // reported OFV is the marginal one shared with every method
+        let etas: HashMap<i32, Vec<f64>> = individual_params.iter()
+            .map(|(&id, params)| (id, params.iter().zip(&current_params.fixed_effects).map(|(p, theta)| p - theta).collect()))
+            .collect();
+        results.objective_function_value = self.reported_objective(dataset, &current_params, &etas)?;
+        step_size_check.check(&self.model, &current_params, &self.config.solver_config);
+        results.final_log_likelihood = -results.objective_function_value / 2.0;
         results.n_iterations = results.parameter_trajectory.len();
+        results.iteration_times = timer.map(IterationTimer::into_times).unwrap_or_default();
+        results.solver_config = self.config.solver_config.clone();
+        results.parameters_at_bounds = bounds::parameters_at_bounds(&results.fixed_effects, &log_bounds, &parameter_names);
+        for name in &results.parameters_at_bounds {
+            warn!("Parameter {} finished at a bound", name);
+        }
         results.individual_parameters = individual_params;
+        results.evaluation_counts = self.evaluations.counts();
+        results.evaluation_counts.negative_state_corrections =
+            self.solver.negative_state_corrections() - negative_corrections_before;
+        if results.evaluation_counts.negative_state_corrections > 0 {
+            warn!("ODE integration produced negative amounts in {} steps; {}",
+                  results.evaluation_counts.negative_state_corrections,
+                  self.config.solver_config.negative_states.outcome());
+        }
 
         // Calculate parameter statistics
         self.calculate_parameter_statistics(&mut results);
-        self.calculate_omega_statistics(&mut results, dataset);
+        self.calculate_omega_statistics(&mut results);
+        for (name, rse) in diagnostics::poorly_identified_parameters(&results.parameter_names, &results.rse_percent()) {
+            warn!("Parameter {} is poorly identified (%RSE {:.1})", name, rse);
+        }
 
         info!("SAEM estimation completed. Final log-likelihood: {:.3}, Objective function: {:.3}", 
               results.final_log_likelihood, results.objective_function_value);
@@ -152,56 +287,99 @@ impl SaemEstimator {
         Ok(results)
     }
 
+    /// -2·log-likelihood at the population parameters `params` on the
+    /// convention of `fit`'s OFV, without fitting. Each individual's etas are
+    /// the posterior means of an MCMC chain under `params`, around which the
+    /// marginal likelihood is Laplace-integrated; the residual error model is
+    /// the one `params` describes. For likelihood profiles and sensitivity
+    /// analyses; repeatable for a given seed.
+    pub fn evaluate_objective(&self, dataset: &Dataset, params: &ModelParameters) -> Result<f64> {
+        let parameter_names = self.model.parameter_names();
+        if params.n_parameters() != parameter_names.len() {
+            return Err(anyhow!("Expected {} parameters for the model, got {}", parameter_names.len(), params.n_parameters()));
+        }
+        let log_bounds = bounds::log_bounds(&self.config.parameter_bounds, &parameter_names);
+        let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
+        let mut etas = HashMap::new();
+        for (&id, individual) in dataset.individuals() {
+            let mcmc_config = McmcConfig {
+                n_samples: EVALUATION_MCMC_SAMPLES,
+                step_size: self.config.step_size,
+                step_sizes: Vec::new(),
+                target_acceptance: self.config.target_acceptance,
+                update: self.config.mcmc_update,
+                seed: self.config.seed.map(|s| s.wrapping_add(id as u64)),
+                bounds: log_bounds.clone(),
+                solver_config: self.config.solver_config.clone(),
+            };
+            let (samples, _) = McmcSampler::new(&self.model, &solver, mcmc_config)
+                .sample_chain(individual, params, &params.fixed_effects)
+                .with_context(|| format!("MCMC sampling failed for individual {}", id))?;
+            let kept = &samples[samples.len() / 2..];
+            let eta = params.fixed_effects.iter().enumerate()
+                .map(|(k, theta)| kept.iter().map(|sample| sample[k]).sum::<f64>() / kept.len() as f64 - theta)
+                .collect();
+            etas.insert(id, eta);
+        }
+        self.reported_objective(dataset, params, &etas)
+    }
+
+    /// Profile likelihood of fixed effect `param_index` over the
+    /// natural-scale `values`: at each value the parameter is held fixed and
+    /// the rest refitted from `estimate`, typically the fit's own
+    /// `FittedModel::parameters`. See `profile::LikelihoodProfile` for the
+    /// confidence interval read from it.
+    pub fn profile_likelihood(
+        &mut self,
+        dataset: &Dataset,
+        estimate: &ModelParameters,
+        param_index: usize,
+        values: &[f64],
+    ) -> Result<LikelihoodProfile> {
+        let parameter_names = self.model.parameter_names();
+        let original = self.config.clone();
+        let profile = profile::profile_likelihood(&parameter_names, &original, estimate, param_index, values, |config| {
+            self.config = config;
+            self.fit(dataset).map(|results| (results.objective_function_value, results.converged))
+        });
+        self.config = original;
+        profile
+    }
+
+    /// The marginal OFV at `etas`, less the likelihood constants when the
+    /// configuration leaves them out.
+    fn reported_objective(&self, dataset: &Dataset, params: &ModelParameters, etas: &HashMap<i32, Vec<f64>>) -> Result<f64> {
+        let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
+        let objective = objective::objective_function_value(
+            &self.model, &solver, &self.config.solver_config, dataset, params, etas,
+        )?;
+        Ok(if self.config.include_likelihood_constants {
+            objective
+        } else {
+            objective - objective::likelihood_constant(dataset)
+        })
+    }
+
+    /// Stochastic-approximation step on the sufficient statistics followed
+    /// by the M-step. During the exploratory phase each variance may shrink
+    /// by at most `ANNEALING_DECAY` per iteration.
     fn update_population_parameters(
         &self,
         individual_params: &HashMap<i32, Vec<f64>>,
         current_params: &mut ModelParameters,
-        sa_sum_theta: &mut Vec<f64>,
-        sa_sum_theta_sq: &mut Vec<Vec<f64>>,
-        sa_sum_sigma: &mut f64,
+        statistics: &mut SufficientStatistics,
         gamma: f64,
+        exploratory: bool,
         dataset: &Dataset,
     ) {
-        let n_individuals = individual_params.len() as f64;
-        
-        let mut mean_individual_params = vec![0.0; current_params.n_parameters()];
-        for params in individual_params.values() {
-            for (i, param) in params.iter().enumerate() {
-                mean_individual_params[i] += param;
-            }
-        }
-        for val in mean_individual_params.iter_mut() {
-            *val /= n_individuals;
-        }
-        
-        for i in 0..sa_sum_theta.len() {
-            sa_sum_theta[i] = (1.0 - gamma) * sa_sum_theta[i] + gamma * mean_individual_params[i];
-            // Apply bounds to prevent parameters from becoming too negative
-            sa_sum_theta[i] = sa_sum_theta[i].max(-10.0);
-        }
-        current_params.fixed_effects = sa_sum_theta.clone();
-        
-        let mut sum_outer_products = vec![vec![0.0; current_params.n_parameters()]; current_params.n_parameters()];
-        for params in individual_params.values() {
-            for i in 0..params.len() {
-                for j in 0..params.len() {
-                    let centered_i = params[i] - current_params.fixed_effects[i];
-                    let centered_j = params[j] - current_params.fixed_effects[j];
-                    sum_outer_products[i][j] += centered_i * centered_j;
-                }
-            }
-        }
-        
-        for i in 0..sum_outer_products.len() {
-            for j in 0..sum_outer_products[i].len() {
-                let mean_outer_product = sum_outer_products[i][j] / n_individuals;
-                sa_sum_theta_sq[i][j] = (1.0 - gamma) * sa_sum_theta_sq[i][j] + gamma * mean_outer_product;
-            }
-        }
-        current_params.random_effects_variance = sa_sum_theta_sq.clone();
-        
+        // Sums over observations, each counted as often as its individual's
+        // weight
         let mut residual_sum = 0.0;
-        let mut total_observations = 0;
+        let mut total_observations = 0.0;
+        let mut effect_residual_sum = 0.0;
+        let mut effect_observations = 0.0;
+        let mut output_sums: BTreeMap<usize, (f64, f64)> = BTreeMap::new();
+        let separate_effects = current_params.effect_residual_variance.is_some();
         
         for (&id, individual) in dataset.individuals() {
             if let Some(ind_params) = individual_params.get(&id) {
@@ -217,84 +395,134 @@ impl SaemEstimator {
                     }
                 };
                 
-                for (obs, pred) in individual.observations().iter().zip(predicted.iter()) {
-                    let residual = (obs.value - pred).powi(2);
-                    residual_sum += residual;
-                    total_observations += 1;
+                // Censored observations carry a limit, not a measured value
+                let weight = individual.weight();
+                for (obs, pred) in individual.observations().iter().zip(predicted.iter()).filter(|(obs, _)| !obs.is_censored()) {
+                    let residual = if objective::on_log_scale(current_params, obs) {
+                        weight * objective::log_scale_residual(obs, *pred).powi(2)
+                    } else {
+                        weight * (obs.value - pred).powi(2)
+                    };
+                    if separate_effects && obs.observation_type == ObservationType::Effect {
+                        effect_residual_sum += residual;
+                        effect_observations += weight;
+                    } else if let Some(compartment) = objective::separate_output(current_params, obs) {
+                        let (sum, observations) = output_sums.entry(compartment).or_default();
+                        *sum += residual;
+                        *observations += weight;
+                    } else {
+                        residual_sum += residual;
+                        total_observations += weight;
+                    }
                 }
             }
         }
         
         // CORRECTED: Add check to prevent division by zero
-        if total_observations > 0 {
-            let empirical_residual_var = residual_sum / total_observations as f64;
-            *sa_sum_sigma = (1.0 - gamma) * (*sa_sum_sigma) + gamma * empirical_residual_var;
-            current_params.residual_variance = *sa_sum_sigma;
+        let empirical_residual_var = (total_observations > 0.0).then(|| residual_sum / total_observations);
+        statistics.update(individual_params, &dataset.individual_weights(), empirical_residual_var, gamma);
+        if effect_observations > 0.0 {
+            statistics.update_effect_residual(effect_residual_sum / effect_observations, gamma);
+        }
+        for (&compartment, &(sum, observations)) in &output_sums {
+            statistics.update_output_residual(compartment, sum / observations, gamma);
         }
-    }
-
-    fn predict_individual(
-        &self,
-        individual: &crate::data::Individual,
-        params: &ModelParameters,
-    ) -> Result<Vec<f64>, anyhow::Error> {
-        let mut predictions = Vec::new();
-        let solver_config = SolverConfig::default();
-        
-        let system = CompartmentSystem {
-            model: &self.model,
-            params,
-        };
-        
-        let mut current_state = ModelState::new(self.model.n_compartments());
-        let mut last_time = 0.0;
         
-        // Apply dosing events
-        for dose in individual.dosing_records() {
-            if dose.time > last_time {
-                let final_state = self.solver.solve_to_time(
-                    &system,
-                    last_time,
-                    dose.time,
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = final_state;
-                current_state.time = dose.time;
+        if !self.config.fix_theta {
+            let parameter_names = self.model.parameter_names();
+            let log_bounds = bounds::log_bounds(&self.config.parameter_bounds, &parameter_names);
+            for (i, (theta, (&mean, &limits))) in current_params.fixed_effects.iter_mut().zip(statistics.mean.iter().zip(&log_bounds)).enumerate() {
+                if self.config.theta_fixed(&parameter_names[i]) {
+                    continue;
+                }
+                // A truncated eta has a nonzero mean, so the typical value
+                // is not the mean of the individual values
+                let prior = current_params.eta_priors.get(i).copied().unwrap_or_default();
+                let (typical, _) = prior.match_moments(mean, statistics.outer_product[i][i] - mean * mean);
+                *theta = bounds::clamp(typical, limits);
             }
-            
-            current_state.add_dose(dose.compartment as usize, dose.amount);
-            last_time = dose.time;
         }
         
-        // Predict concentrations at observation times
-        for obs in individual.observations() {
-            if obs.time > last_time {
-                let final_state = self.solver.solve_to_time(
-                    &system,
-                    last_time,
-                    obs.time,
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = final_state;
-                current_state.time = obs.time;
-                last_time = obs.time;
+        if !self.config.fix_omega && dataset.n_individuals() >= omega::MIN_INDIVIDUALS_FOR_OMEGA {
+            let previous = current_params.random_effects_variance.clone();
+            current_params.random_effects_variance = statistics.omega(&current_params.fixed_effects);
+            // A truncated eta is taken as independent of the others, with
+            // the ω² of the untruncated normal
+            let n_parameters = current_params.n_parameters();
+            for i in 0..n_parameters {
+                let prior = current_params.eta_prior(i);
+                if !prior.is_truncated() {
+                    continue;
+                }
+                let mean = statistics.mean[i];
+                let (_, variance) = prior.match_moments(mean, statistics.outer_product[i][i] - mean * mean);
+                for j in 0..n_parameters {
+                    current_params.random_effects_variance[i][j] = 0.0;
+                    current_params.random_effects_variance[j][i] = 0.0;
+                }
+                current_params.random_effects_variance[i][i] = variance;
             }
-            
-            let concentration = self.model.observation_function(
-                &current_state,
-                params,
-                obs.compartment as usize,
+            if exploratory {
+                for (i, row) in previous.iter().enumerate() {
+                    let floor = sufficient::ANNEALING_DECAY * row[i];
+                    let variance = &mut current_params.random_effects_variance[i][i];
+                    *variance = variance.max(floor);
+                }
+            }
+            let (projected, indefinite) = omega::nearest_positive_definite(
+                &current_params.random_effects_variance, omega::MIN_OMEGA_EIGENVALUE,
             );
-            predictions.push(concentration);
+            if indefinite {
+                warn!("SAEM Omega update was not positive definite; projected to the nearest positive-definite matrix");
+            }
+            current_params.random_effects_variance = projected;
         }
         
-        Ok(predictions)
+        if !self.config.fix_residual_variance && empirical_residual_var.is_some() {
+            current_params.residual_variance = if exploratory {
+                statistics.residual.max(sufficient::ANNEALING_DECAY * current_params.residual_variance)
+            } else {
+                statistics.residual
+            };
+        }
+        if !self.config.fix_residual_variance && effect_observations > 0.0 {
+            let previous = current_params.effect_residual_variance.unwrap_or(statistics.effect_residual);
+            current_params.effect_residual_variance = Some(if exploratory {
+                statistics.effect_residual.max(sufficient::ANNEALING_DECAY * previous)
+            } else {
+                statistics.effect_residual
+            });
+        }
+        if !self.config.fix_residual_variance {
+            for &compartment in output_sums.keys() {
+                let estimate = statistics.output_residuals[&compartment];
+                let variance = current_params.output_residual_variances.entry(compartment).or_insert(estimate);
+                *variance = if exploratory {
+                    estimate.max(sufficient::ANNEALING_DECAY * *variance)
+                } else {
+                    estimate
+                };
+            }
+        }
+    }
+
+    fn predict_individual(
+        &self,
+        individual: &crate::data::Individual,
+        params: &ModelParameters,
+    ) -> Result<Vec<f64>, anyhow::Error> {
+        let solver = CountingSolver::new(self.solver.as_ref(), &self.evaluations);
+        prediction::predict_individual(
+            &self.model,
+            &solver,
+            individual,
+            params,
+            &self.config.solver_config,
+        )
     }
 
     fn check_convergence(&self, results: &SaemResults) -> bool {
-        let window_size = 50;
+        let window_size = self.config.convergence_window;
         if results.log_likelihood_trajectory.len() < window_size {
             return false;
         }
@@ -320,7 +548,7 @@ impl SaemEstimator {
     }
 
     fn calculate_parameter_statistics(&self, results: &mut SaemResults) {
-        let n_recent = 100.min(results.parameter_trajectory.len());
+        let n_recent = self.config.statistics_window.min(results.parameter_trajectory.len());
         if n_recent < 10 {
             return; // Not enough data for reliable statistics
         }
@@ -358,41 +586,19 @@ impl SaemEstimator {
         }
     }
 
-    fn calculate_omega_statistics(&self, results: &mut SaemResults, dataset: &Dataset) {
+    fn calculate_omega_statistics(&self, results: &mut SaemResults) {
         let n_params = results.parameter_names.len();
-        
-        // Calculate empirical Bayes estimates (EBEs) for shrinkage calculation
-        let mut empirical_variances = vec![0.0; n_params];
-        let n_individuals = dataset.n_individuals() as f64;
-        
-        if n_individuals > 1.0 {
-            for param_idx in 0..n_params {
-                let individual_values: Vec<f64> = results.individual_parameters
-                    .values()
-                    .map(|params| params[param_idx])
-                    .collect();
-                
-                let mean = individual_values.iter().sum::<f64>() / n_individuals;
-                let empirical_var = individual_values.iter()
-                    .map(|&x| (x - mean).powi(2))
-                    .sum::<f64>() / (n_individuals - 1.0);
-                
-                empirical_variances[param_idx] = empirical_var;
-            }
-        }
+        let eta_shrinkage = diagnostics::eta_shrinkage(
+            &results.individual_parameters,
+            &results.fixed_effects,
+            &results.random_effects_variance,
+        );
 
         // Generate omega statistics
-        for i in 0..n_params {
+        for (i, &shrinkage) in eta_shrinkage.iter().enumerate().take(n_params) {
             for j in 0..n_params {
                 let omega_estimate = results.random_effects_variance[i][j];
-                
-                let shrinkage_percent = if i == j && empirical_variances[i] > 1e-10 {
-                    // Shrinkage = (1 - empirical_variance / omega) * 100%
-                    let shrinkage = (1.0 - empirical_variances[i] / omega_estimate.abs()) * 100.0;
-                    Some(shrinkage.max(0.0).min(100.0)) // Clamp between 0-100%
-                } else {
-                    None
-                };
+                let shrinkage_percent = if i == j { shrinkage } else { None };
 
                 results.omega_statistics.push(OmegaStatistics {
                     parameter_i: results.parameter_names[i].clone(),
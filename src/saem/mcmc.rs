@@ -1,17 +1,38 @@
-use crate::data::Individual;
+use crate::data::{Individual, Observation};
+use crate::estimation::bounds::{self, LOG_PARAMETER_FLOOR};
+use crate::estimation::objective;
+use crate::estimation::McmcUpdate;
 use crate::models::{CompartmentModel, ModelParameters};
+use crate::prediction;
 use crate::solver::{OdeSolver, SolverConfig};
-use nalgebra::{DVector, DMatrix, Dynamic};
+use nalgebra::{DMatrix, Dynamic};
 use rand::prelude::*;
 use rand_distr::StandardNormal;
 use rand::{SeedableRng, rngs::StdRng};
 
+/// Log-likelihood of one observation under the error model of `params`, the
+/// population's current estimates: the same density the FOCE objective
+/// uses, so effect observations and separately observed outputs are sampled
+/// under their own variances. Censored observations contribute the
+/// probability of lying beyond their limit.
+pub fn observation_log_likelihood(params: &ModelParameters, obs: &Observation, prediction: f64) -> f64 {
+    -0.5 * objective::observation_objective(params, obs, prediction)
+}
+
 #[derive(Debug, Clone)]
 pub struct McmcConfig {
     pub n_samples: usize,
     pub step_size: f64,
+    /// Step size of each parameter, in parameter order; parameters without
+    /// an entry use `step_size`.
+    pub step_sizes: Vec<f64>,
     pub target_acceptance: f64,
+    pub update: McmcUpdate,
     pub seed: Option<u64>,
+    /// Log-scale (lower, upper) per parameter that proposals are reflected
+    /// into. Parameters without an entry are floored at `LOG_PARAMETER_FLOOR`.
+    pub bounds: Vec<(f64, f64)>,
+    pub solver_config: SolverConfig,
 }
 
 impl Default for McmcConfig {
@@ -19,20 +40,39 @@ impl Default for McmcConfig {
         Self {
             n_samples: 100,
             step_size: 0.1,
+            step_sizes: Vec::new(),
             target_acceptance: 0.44,
+            update: McmcUpdate::Block,
             seed: None,
+            bounds: Vec::new(),
+            solver_config: SolverConfig::default(),
         }
     }
 }
 
-pub struct McmcSampler<'a> {
+/// Random-walk Metropolis sampler for one individual's parameters. Generic
+/// over the random number generator, `StdRng` by default.
+pub struct McmcSampler<'a, R: Rng = StdRng> {
     model: &'a CompartmentModel,
     solver: &'a dyn OdeSolver,
     config: McmcConfig,
-    rng: StdRng,
+    rng: R,
+    acceptance_rate: f64,
+    parameter_acceptance: Vec<f64>,
+}
+
+/// Scales each step size by `exp(acceptance − target)`, so parameters
+/// accepted more often than the target take longer steps and those accepted
+/// less often shorter ones. Applied between chains, it converges to the
+/// step sizes at which each parameter is accepted at the target rate.
+pub fn adapt_step_sizes(step_sizes: &mut [f64], acceptance: &[f64], target: f64) {
+    for (step, &rate) in step_sizes.iter_mut().zip(acceptance) {
+        *step *= (rate - target).exp();
+    }
 }
 
 impl<'a> McmcSampler<'a> {
+    /// A sampler using `StdRng`, seeded from `config.seed` or from entropy.
     pub fn new(
         model: &'a CompartmentModel,
         solver: &'a dyn OdeSolver,
@@ -44,55 +84,121 @@ impl<'a> McmcSampler<'a> {
             StdRng::from_entropy()
         };
         
+        Self::with_rng(model, solver, config, rng)
+    }
+}
+
+impl<'a, R: Rng> McmcSampler<'a, R> {
+    /// A sampler drawing from `rng`; `config.seed` is ignored.
+    pub fn with_rng(
+        model: &'a CompartmentModel,
+        solver: &'a dyn OdeSolver,
+        config: McmcConfig,
+        rng: R,
+    ) -> Self {
         Self {
             model,
             solver,
             config,
             rng,
+            acceptance_rate: 0.0,
+            parameter_acceptance: Vec::new(),
         }
     }
 
+    /// Fraction of proposals accepted in the last `sample_chain` call.
+    pub fn acceptance_rate(&self) -> f64 {
+        self.acceptance_rate
+    }
+
+    /// Fraction of samples in the last `sample_chain` call in which each
+    /// parameter moved. Under block updates every entry is the joint
+    /// acceptance rate.
+    pub fn parameter_acceptance(&self) -> &[f64] {
+        &self.parameter_acceptance
+    }
+
+    fn step_size(&self, index: usize) -> f64 {
+        self.config.step_sizes.get(index).copied().unwrap_or(self.config.step_size)
+    }
+
+    /// Random-walk proposal for parameter `index` from `value`, reflected
+    /// into its bounds.
+    fn propose(&mut self, index: usize, value: f64) -> f64 {
+        let step: f64 = self.rng.sample(StandardNormal);
+        let bounds = self.config.bounds.get(index).copied()
+            .unwrap_or((LOG_PARAMETER_FLOOR, f64::INFINITY));
+        bounds::reflect(value + self.step_size(index) * step, bounds)
+    }
+
+    /// Metropolis acceptance of a move from `current` to `proposed` log density.
+    fn accept(&mut self, current: f64, proposed: f64) -> bool {
+        let alpha = (proposed - current).exp().min(1.0);
+        self.rng.gen::<f64>() < alpha
+    }
+
     pub fn sample_individual_parameters(
         &mut self,
         individual: &Individual,
         population_params: &ModelParameters,
         initial_params: &Vec<f64>,
     ) -> Result<(Vec<f64>, f64), anyhow::Error> {
+        let (samples, log_likelihood) = self.sample_chain(individual, population_params, initial_params)?;
+        let last = samples.into_iter().last().unwrap_or_else(|| initial_params.clone());
+        Ok((last, log_likelihood))
+    }
+
+    /// Runs `n_samples` Metropolis steps and returns the state after each,
+    /// with the log density of the last. A componentwise step updates every
+    /// parameter in turn.
+    pub fn sample_chain(
+        &mut self,
+        individual: &Individual,
+        population_params: &ModelParameters,
+        initial_params: &Vec<f64>,
+    ) -> Result<(Vec<Vec<f64>>, f64), anyhow::Error> {
         let mut current_params = initial_params.clone();
         let mut current_log_likelihood = self.log_likelihood(individual, &current_params, population_params)?;
         
-        let mut n_accepted = 0;
         let n_params = current_params.len();
+        let mut n_accepted = vec![0usize; n_params];
+        let mut samples = Vec::with_capacity(self.config.n_samples);
         
         for _ in 0..self.config.n_samples {
-            // Propose new parameters
-            let mut proposed_params = current_params.clone();
-            
-            for i in 0..n_params {
-                let step: f64 = self.rng.sample(StandardNormal);
-                proposed_params[i] += self.config.step_size * step;
-                
-                // Apply bounds: ensure exp(param) > 0 by keeping param > -10
-                proposed_params[i] = proposed_params[i].max(-10.0);
-            }
-            
-            // Calculate log-likelihood for proposed parameters
-            let proposed_log_likelihood = self.log_likelihood(individual, &proposed_params, population_params)?;
-            
-            // Metropolis-Hastings acceptance
-            let log_alpha = proposed_log_likelihood - current_log_likelihood;
-            let alpha = log_alpha.exp().min(1.0);
-            
-            if self.rng.gen::<f64>() < alpha {
-                current_params = proposed_params;
-                current_log_likelihood = proposed_log_likelihood;
-                n_accepted += 1;
+            match self.config.update {
+                McmcUpdate::Block => {
+                    let mut proposed_params = current_params.clone();
+                    for (i, value) in proposed_params.iter_mut().enumerate() {
+                        *value = self.propose(i, *value);
+                    }
+                    let proposed_log_likelihood = self.log_likelihood(individual, &proposed_params, population_params)?;
+                    if self.accept(current_log_likelihood, proposed_log_likelihood) {
+                        current_params = proposed_params;
+                        current_log_likelihood = proposed_log_likelihood;
+                        n_accepted.iter_mut().for_each(|count| *count += 1);
+                    }
+                }
+                McmcUpdate::Componentwise => {
+                    for i in 0..n_params {
+                        let mut proposed_params = current_params.clone();
+                        proposed_params[i] = self.propose(i, current_params[i]);
+                        let proposed_log_likelihood = self.log_likelihood(individual, &proposed_params, population_params)?;
+                        if self.accept(current_log_likelihood, proposed_log_likelihood) {
+                            current_params = proposed_params;
+                            current_log_likelihood = proposed_log_likelihood;
+                            n_accepted[i] += 1;
+                        }
+                    }
+                }
             }
+            samples.push(current_params.clone());
         }
         
-        let _acceptance_rate = n_accepted as f64 / self.config.n_samples as f64;
+        let n_samples = self.config.n_samples.max(1) as f64;
+        self.parameter_acceptance = n_accepted.iter().map(|&count| count as f64 / n_samples).collect();
+        self.acceptance_rate = self.parameter_acceptance.iter().sum::<f64>() / n_params.max(1) as f64;
         
-        Ok((current_params, current_log_likelihood))
+        Ok((samples, current_log_likelihood))
     }
 
     fn log_likelihood(
@@ -104,7 +210,7 @@ impl<'a> McmcSampler<'a> {
         // Log-likelihood = log p(y|θ) + log p(θ|μ,Ω)
         // where y are observations, θ are individual parameters, μ are population means, Ω is covariance
         
-        let data_log_likelihood = self.data_log_likelihood(individual, individual_params)?;
+        let data_log_likelihood = self.data_log_likelihood(individual, individual_params, population_params)?;
         let prior_log_likelihood = self.prior_log_likelihood(individual_params, population_params);
         
         Ok(data_log_likelihood + prior_log_likelihood)
@@ -114,22 +220,13 @@ impl<'a> McmcSampler<'a> {
         &self,
         individual: &Individual,
         individual_params: &Vec<f64>,
+        population_params: &ModelParameters,
     ) -> Result<f64, anyhow::Error> {
         let predictions = self.predict_concentrations(individual, individual_params)?;
         let mut log_likelihood = 0.0;
         
         for (obs, pred) in individual.observations().iter().zip(predictions.iter()) {
-            if obs.value > 0.0 && *pred > 0.0 {
-                // Log-normal error model
-                let log_obs = obs.value.ln();
-                let log_pred = pred.ln();
-                let residual = log_obs - log_pred;
-                
-                // Assume proportional error model
-                let sigma = 0.1; // This should come from population_params.residual_variance
-                log_likelihood -= 0.5 * (residual / sigma).powi(2);
-                log_likelihood -= 0.5 * (2.0 * std::f64::consts::PI * sigma.powi(2)).ln();
-            }
+            log_likelihood += observation_log_likelihood(population_params, obs, *pred);
         }
         
         Ok(log_likelihood)
@@ -140,23 +237,14 @@ impl<'a> McmcSampler<'a> {
         individual_params: &Vec<f64>,
         population_params: &ModelParameters,
     ) -> f64 {
-        // Multivariate normal prior: θ ~ N(μ, Ω)
-        let mut diff = vec![0.0; individual_params.len()];
-        for i in 0..individual_params.len() {
-            diff[i] = individual_params[i] - population_params.fixed_effects[i];
-        }
-        
-        // Simplified calculation assuming diagonal covariance matrix
-        let mut quadratic_form = 0.0;
-        let mut det_omega = 1.0;
-        for i in 0..diff.len() {
-            let variance = population_params.random_effects_variance[i][i];
-            quadratic_form += diff[i] * diff[i] / variance;
-            det_omega *= variance;
-        }
-        
-        -0.5 * quadratic_form - 0.5 * det_omega.ln() - 
-        0.5 * (individual_params.len() as f64) * (2.0 * std::f64::consts::PI).ln()
+        // Prior θ = μ + η with each η from its parameter's prior, normal
+        // N(0, ω²) unless truncated; simplified to a diagonal Ω
+        individual_params.iter().zip(&population_params.fixed_effects).enumerate()
+            .map(|(i, (param, theta))| {
+                let variance = population_params.random_effects_variance[i][i];
+                population_params.eta_prior(i).log_density(param - theta, variance)
+            })
+            .sum()
     }
 
     fn predict_concentrations(
@@ -168,79 +256,183 @@ impl<'a> McmcSampler<'a> {
         let mut temp_params = self.model.default_parameters();
         temp_params.fixed_effects = individual_params.clone();
         
-        let mut predictions = Vec::new();
-        let solver_config = SolverConfig::default();
-        
-        // Simulate the PK profile
-        let system = CompartmentSystem {
-            model: &self.model,
-            params: &temp_params,
-        };
-        
-        let mut current_state = crate::models::ModelState::new(self.model.n_compartments());
-        let mut last_time = 0.0;
-        
-        // Apply dosing events
-        for dose in individual.dosing_records() {
-            if dose.time > last_time {
-                // Integrate from last_time to dose.time
-                let final_state = self.solver.solve_to_time(
-                    &system,
-                    last_time,
-                    dose.time,
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = final_state;
-                current_state.time = dose.time;
-            }
-            
-            // Apply dose
-            current_state.add_dose(dose.compartment as usize, dose.amount);
-            last_time = dose.time;
+        prediction::predict_individual(
+            self.model,
+            self.solver,
+            individual,
+            &temp_params,
+            &self.config.solver_config,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingRecord, DosingType, Observation, ObservationType};
+    use crate::models::{EtaPrior, ModelType};
+    use crate::solver::RungeKuttaSolver;
+    use std::collections::HashMap;
+
+    /// Minimal xorshift generator standing in for a user-supplied backend.
+    struct XorShift(u64);
+
+    impl RngCore for XorShift {
+        fn next_u32(&mut self) -> u32 {
+            (self.next_u64() >> 32) as u32
         }
-        
-        // Predict concentrations at observation times
-        for obs in individual.observations() {
-            if obs.time > last_time {
-                let (_, solutions) = self.solver.solve(
-                    &system,
-                    (last_time, obs.time),
-                    &current_state.compartments,
-                    &solver_config,
-                )?;
-                current_state.compartments = solutions.into_iter().last().unwrap_or(current_state.compartments.clone());
-                current_state.time = obs.time;
-                last_time = obs.time;
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for chunk in dest.chunks_mut(8) {
+                let bytes = self.next_u64().to_le_bytes();
+                chunk.copy_from_slice(&bytes[..chunk.len()]);
             }
-            
-            let concentration = self.model.observation_function(
-                &current_state,
-                &temp_params,
-                obs.compartment as usize,
-            );
-            predictions.push(concentration);
         }
-        
-        Ok(predictions)
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
     }
-}
 
-struct CompartmentSystem<'a> {
-    model: &'a CompartmentModel,
-    params: &'a ModelParameters,
-}
+    #[test]
+    fn test_injected_rng_is_reproducible() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let population = model.default_parameters();
+        let observations = [1.0_f64, 2.0, 4.0, 8.0].iter()
+            .map(|&t| Observation::new(t, 30.0 * (-0.3 * t).exp(), 1, ObservationType::Concentration))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+        let config = McmcConfig { n_samples: 20, ..McmcConfig::default() };
 
-impl<'a> crate::solver::OdeSystem for CompartmentSystem<'a> {
-    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
-        let state = crate::models::ModelState {
-            compartments: y.clone(),
-            time: t,
+        let run = |seed: u64| {
+            let mut sampler = McmcSampler::with_rng(&model, &solver, config.clone(), XorShift(seed));
+            sampler.sample_individual_parameters(&individual, &population, &population.fixed_effects).unwrap()
         };
-        self.model.derivatives(&state, self.params)
+
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42).0, run(7).0);
+
+        // The default constructor is the seeded StdRng case of the generic one
+        let seeded = McmcConfig { seed: Some(3), ..config.clone() };
+        let (default_params, _) = McmcSampler::new(&model, &solver, seeded.clone())
+            .sample_individual_parameters(&individual, &population, &population.fixed_effects).unwrap();
+        let (injected_params, _) = McmcSampler::with_rng(&model, &solver, seeded, StdRng::seed_from_u64(3))
+            .sample_individual_parameters(&individual, &population, &population.fixed_effects).unwrap();
+        assert_eq!(default_params, injected_params);
     }
 
-    fn dimension(&self) -> usize {
-        self.model.n_compartments()
+    #[test]
+    fn test_truncated_prior_keeps_parameter_on_allowed_side() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let mut population = model.default_parameters();
+        // Data from a clearance half the typical value pull CL's eta negative
+        let (cl, v) = (0.5 * population.fixed_effects[0].exp(), population.fixed_effects[1].exp());
+        let observations = [0.5_f64, 1.0, 2.0, 4.0, 8.0, 12.0].iter()
+            .map(|&t| Observation::new(t, 100.0 / v * (-cl / v * t).exp(), 1, ObservationType::Concentration))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+        let config = McmcConfig { n_samples: 300, seed: Some(11), ..McmcConfig::default() };
+
+        let min_cl_eta = |population: &ModelParameters| {
+            let (samples, _) = McmcSampler::new(&model, &solver, config.clone())
+                .sample_chain(&individual, population, &population.fixed_effects)
+                .unwrap();
+            samples.iter().map(|s| s[0] - population.fixed_effects[0]).fold(f64::INFINITY, f64::min)
+        };
+
+        assert!(min_cl_eta(&population) < 0.0);
+        population.eta_priors = vec![EtaPrior::half_normal()];
+        assert!(min_cl_eta(&population) >= 0.0);
+    }
+
+    #[test]
+    fn test_componentwise_updates_mix_better_on_disparate_scales() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        // Without observations the posterior is the prior: SDs of 0.01 for
+        // CL and 1 for V
+        let mut population = model.default_parameters();
+        population.random_effects_variance = vec![vec![1e-4, 0.0], vec![0.0, 1.0]];
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, Vec::new(), doses, HashMap::new());
+        let lag_one_autocorrelation = |samples: &[Vec<f64>], index: usize| {
+            let values: Vec<f64> = samples.iter().map(|s| s[index]).collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance: f64 = values.iter().map(|v| (v - mean).powi(2)).sum();
+            let covariance: f64 = values.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum();
+            covariance / variance
+        };
+        let chain = |config: McmcConfig| {
+            let mut sampler = McmcSampler::new(&model, &solver, config);
+            let (samples, _) = sampler.sample_chain(&individual, &population, &population.fixed_effects).unwrap();
+            (samples, sampler.parameter_acceptance().to_vec())
+        };
+
+        let block = McmcConfig { n_samples: 2000, seed: Some(5), ..McmcConfig::default() };
+        let (block_samples, block_acceptance) = chain(block.clone());
+        // One joint decision: both parameters move together
+        assert_eq!(block_acceptance[0], block_acceptance[1]);
+
+        // Tune the componentwise step sizes over short chains, as SAEM's
+        // burn-in does
+        let mut componentwise = McmcConfig {
+            n_samples: 20,
+            update: McmcUpdate::Componentwise,
+            step_sizes: vec![0.1, 0.1],
+            ..block
+        };
+        for seed in 0..100 {
+            componentwise.seed = Some(seed);
+            let (_, acceptance) = chain(componentwise.clone());
+            adapt_step_sizes(&mut componentwise.step_sizes, &acceptance, componentwise.target_acceptance);
+        }
+        assert!(componentwise.step_sizes[1] > 10.0 * componentwise.step_sizes[0], "{:?}", componentwise.step_sizes);
+        componentwise.n_samples = 2000;
+        componentwise.seed = Some(5);
+        let (componentwise_samples, acceptance) = chain(componentwise);
+        assert!(acceptance.iter().all(|&rate| (rate - 0.44).abs() < 0.15), "{:?}", acceptance);
+
+        // V barely moves under block steps sized by CL
+        let block_v = lag_one_autocorrelation(&block_samples, 1);
+        let componentwise_v = lag_one_autocorrelation(&componentwise_samples, 1);
+        assert!(block_v > 0.95 && componentwise_v < 0.8, "block {} componentwise {}", block_v, componentwise_v);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_likelihood_follows_population_error_model() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut params = model.default_parameters();
+        params.residual_variance = 0.25;
+        let concentration = Observation::new(1.0, 5.0, 1, ObservationType::Concentration);
+        let normal = |residual: f64, variance: f64| -0.5 * ((2.0 * std::f64::consts::PI * variance).ln() + residual * residual / variance);
+
+        // Additive error at the estimated variance, even as the prediction vanishes
+        assert!((observation_log_likelihood(&params, &concentration, 4.0) - normal(1.0, 0.25)).abs() < 1e-12);
+        assert!((observation_log_likelihood(&params, &concentration, 0.0) - normal(5.0, 0.25)).abs() < 1e-12);
+        params.residual_variance = 1.0;
+        assert!((observation_log_likelihood(&params, &concentration, 4.0) - normal(1.0, 1.0)).abs() < 1e-12);
+
+        // Effect observations use their own variance
+        params.effect_residual_variance = Some(4.0);
+        let effect = Observation::new(1.0, 5.0, 1, ObservationType::Effect);
+        assert!((observation_log_likelihood(&params, &effect, 4.0) - normal(1.0, 4.0)).abs() < 1e-12);
+
+        // The exponential model matches the FOCE objective on the log scale
+        params.log_scale_residuals = true;
+        let expected = -0.5 * objective::observation_objective(&params, &concentration, 4.0);
+        assert_eq!(observation_log_likelihood(&params, &concentration, 4.0), expected);
+        assert!((expected - (normal((5.0_f64 / 4.0).ln(), 1.0) - 5.0_f64.ln())).abs() < 1e-12);
+    }
+}
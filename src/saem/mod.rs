@@ -1,12 +1,19 @@
 pub mod algorithm;
 pub mod mcmc;
+pub mod posterior;
+pub mod sufficient;
 
 pub use algorithm::SaemEstimator;
 pub use mcmc::{McmcSampler, McmcConfig};
+pub use posterior::{McmcSample, PosteriorAccumulator};
+pub use sufficient::SufficientStatistics;
 
+use crate::estimation::ErrorModel;
+use crate::solver::{EvaluationCounts, SolverConfig};
 use nalgebra::{DVector, DMatrix};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParameterStatistics {
@@ -28,16 +35,67 @@ pub struct SaemResults {
     pub fixed_effects: Vec<f64>,
     pub random_effects_variance: Vec<Vec<f64>>,
     pub residual_variance: f64,
+    /// Additive, or exponential when `residual_variance` is the variance of
+    /// ln(DV) − ln(PRED).
+    #[serde(default)]
+    pub error_model: ErrorModel,
+    /// Proportional residual variance, with `residual_variance` the additive
+    /// part; zero for SAEM fits, set when FOCE results are converted.
+    #[serde(default)]
+    pub proportional_variance: f64,
+    /// Exponent on |PRED| of the proportional component.
+    #[serde(default = "crate::models::compartment::default_error_power")]
+    pub error_power: f64,
+    /// Residual variance of effect (PD) observations, estimated separately
+    /// when the dataset has any.
+    #[serde(default)]
+    pub effect_residual_variance: Option<f64>,
+    /// Residual variance of each output compartment the model observes
+    /// separately, by CMT.
+    #[serde(default)]
+    pub output_residual_variances: BTreeMap<usize, f64>,
     pub log_likelihood_trajectory: Vec<f64>,
     pub parameter_trajectory: Vec<Vec<f64>>,
     pub final_log_likelihood: f64,
     pub objective_function_value: f64,
     pub converged: bool,
     pub n_iterations: usize,
+    /// Posterior means of each individual's parameters over the post-burn-in
+    /// samples of all chains.
     pub individual_parameters: HashMap<i32, Vec<f64>>,
+    /// Posterior SDs matching `individual_parameters`.
+    #[serde(default)]
+    pub individual_parameter_sd: HashMap<i32, Vec<f64>>,
+    /// Fraction of post-burn-in MCMC samples in which each parameter moved,
+    /// averaged over individuals; empty when no iteration followed burn-in.
+    #[serde(default)]
+    pub parameter_acceptance: Vec<f64>,
     pub parameter_statistics: Vec<ParameterStatistics>,
     pub omega_statistics: Vec<OmegaStatistics>,
     pub parameter_names: Vec<String>,
+    /// Unit of each parameter, in `parameter_names` order; `None` when
+    /// dimensionless.
+    #[serde(default)]
+    pub parameter_units: Vec<Option<String>>,
+    pub evaluation_counts: EvaluationCounts,
+    /// Parameters whose final estimate lies on a bound.
+    #[serde(default)]
+    pub parameters_at_bounds: Vec<String>,
+    /// Every post-burn-in MCMC draw, kept only when
+    /// `EstimationConfig::save_mcmc_samples` is set.
+    #[serde(skip)]
+    pub mcmc_samples: Vec<McmcSample>,
+    /// Wall-clock time of each iteration, recorded only when
+    /// `EstimationConfig::timing_interval` is set.
+    #[serde(default)]
+    pub iteration_times: Vec<Duration>,
+    /// Whether the fit stopped because `EstimationConfig::time_budget` ran
+    /// out.
+    #[serde(default)]
+    pub time_budget_exceeded: bool,
+    /// Solver settings of the fit, reused for predictions from the results.
+    #[serde(default)]
+    pub solver_config: SolverConfig,
 }
 
 impl SaemResults {
@@ -46,6 +104,11 @@ impl SaemResults {
             fixed_effects: vec![0.0; n_params],
             random_effects_variance: vec![vec![0.0; n_params]; n_params],
             residual_variance: 1.0,
+            error_model: ErrorModel::Additive,
+            proportional_variance: 0.0,
+            error_power: 1.0,
+            effect_residual_variance: None,
+            output_residual_variances: BTreeMap::new(),
             log_likelihood_trajectory: Vec::new(),
             parameter_trajectory: Vec::new(),
             final_log_likelihood: f64::NEG_INFINITY,
@@ -53,12 +116,33 @@ impl SaemResults {
             converged: false,
             n_iterations: 0,
             individual_parameters: HashMap::new(),
+            individual_parameter_sd: HashMap::new(),
+            parameter_acceptance: Vec::new(),
             parameter_statistics: Vec::new(),
             omega_statistics: Vec::new(),
             parameter_names,
+            parameter_units: Vec::new(),
+            evaluation_counts: EvaluationCounts::default(),
+            parameters_at_bounds: Vec::new(),
+            mcmc_samples: Vec::new(),
+            iteration_times: Vec::new(),
+            time_budget_exceeded: false,
+            solver_config: SolverConfig::default(),
         }
     }
     
+    /// %RSE of each fixed effect from `parameter_statistics`, in
+    /// `parameter_names` order; NaN where no statistics were computed.
+    pub fn rse_percent(&self) -> Vec<f64> {
+        self.parameter_names.iter()
+            .map(|name| {
+                self.parameter_statistics.iter()
+                    .find(|stat| &stat.name == name)
+                    .map_or(f64::NAN, |stat| stat.rse_percent)
+            })
+            .collect()
+    }
+
     pub fn get_fixed_effects_vector(&self) -> DVector<f64> {
         DVector::from_vec(self.fixed_effects.clone())
     }
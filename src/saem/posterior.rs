@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+/// One retained post-burn-in MCMC draw of an individual's etas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct McmcSample {
+    pub iteration: usize,
+    pub id: i32,
+    /// Position of the draw within its iteration's chain, from 0.
+    pub sample: usize,
+    /// Deviations from the fixed effects the draw was made under.
+    pub eta: Vec<f64>,
+}
+
+/// Running sums of the post-burn-in MCMC samples of each individual's single
+/// chain, averaged into posterior means and SDs.
+#[derive(Debug, Clone, Default)]
+pub struct PosteriorAccumulator {
+    sums: HashMap<i32, SampleSums>,
+}
+
+#[derive(Debug, Clone)]
+struct SampleSums {
+    count: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl PosteriorAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, id: i32, sample: &[f64]) {
+        let sums = self.sums.entry(id).or_insert_with(|| SampleSums {
+            count: 0,
+            sum: vec![0.0; sample.len()],
+            sum_sq: vec![0.0; sample.len()],
+        });
+        sums.count += 1;
+        for (k, &value) in sample.iter().enumerate() {
+            sums.sum[k] += value;
+            sums.sum_sq[k] += value * value;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sums.is_empty()
+    }
+
+    /// Posterior mean of each individual's parameters.
+    pub fn means(&self) -> HashMap<i32, Vec<f64>> {
+        self.sums.iter()
+            .map(|(&id, sums)| (id, sums.sum.iter().map(|s| s / sums.count as f64).collect()))
+            .collect()
+    }
+
+    /// Posterior SD of each individual's parameters; zero from a single sample.
+    pub fn sds(&self) -> HashMap<i32, Vec<f64>> {
+        self.sums.iter()
+            .map(|(&id, sums)| {
+                let n = sums.count as f64;
+                let sd = sums.sum.iter().zip(&sums.sum_sq)
+                    .map(|(s, sq)| {
+                        if sums.count < 2 {
+                            0.0
+                        } else {
+                            ((sq - s * s / n) / (n - 1.0)).max(0.0).sqrt()
+                        }
+                    })
+                    .collect();
+                (id, sd)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DosingRecord, DosingType, Individual, Observation, ObservationType};
+    use crate::models::{CompartmentModel, ModelType};
+    use crate::saem::{McmcConfig, McmcSampler};
+    use crate::solver::RungeKuttaSolver;
+
+    #[test]
+    fn test_mean_and_sd_of_samples() {
+        let mut posterior = PosteriorAccumulator::new();
+        for value in [1.0, 3.0, 5.0] {
+            posterior.add(1, &[value]);
+        }
+        assert_eq!(posterior.means()[&1], vec![3.0]);
+        assert!((posterior.sds()[&1][0] - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pooled_estimates_vary_less_than_last_sample_across_seeds() {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let solver = RungeKuttaSolver::new();
+        let population = model.default_parameters();
+        let observations = [1.0_f64, 2.0, 4.0, 8.0].iter()
+            .map(|&t| Observation::new(t, 30.0 * (-0.3 * t).exp(), 1, ObservationType::Concentration))
+            .collect();
+        let doses = vec![DosingRecord::new(0.0, 100.0, 1, DosingType::Bolus)];
+        let individual = Individual::new(1, observations, doses, HashMap::new());
+
+        let (n_iterations, n_burnin) = (60, 20);
+        let mut last_samples = Vec::new();
+        let mut pooled = Vec::new();
+        for seed in 0..12_u64 {
+            let mut params = population.fixed_effects.clone();
+            let mut posterior = PosteriorAccumulator::new();
+            for iteration in 0..n_iterations {
+                let config = McmcConfig {
+                    n_samples: 2,
+                    step_size: 0.02,
+                    seed: Some(seed * 1000 + iteration),
+                    ..McmcConfig::default()
+                };
+                params = McmcSampler::new(&model, &solver, config)
+                    .sample_individual_parameters(&individual, &population, &params)
+                    .unwrap()
+                    .0;
+                if iteration >= n_burnin {
+                    posterior.add(1, &params);
+                }
+            }
+            last_samples.push(params[0]);
+            pooled.push(posterior.means()[&1][0]);
+        }
+
+        let variance = |values: &[f64]| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+        };
+        assert!(variance(&pooled) < variance(&last_samples),
+                "pooled {} vs last {}", variance(&pooled), variance(&last_samples));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Exponent of the decreasing gain 1/k^α in the smoothing phase; any α in
+/// (0.5, 1] gives almost-sure convergence.
+const GAIN_EXPONENT: f64 = 0.7;
+
+/// Largest fraction of its previous value a variance may fall to in one
+/// exploratory iteration, so the sampler keeps exploring while the
+/// population estimates are still far off.
+pub const ANNEALING_DECAY: f64 = 0.95;
+
+/// Gain of iteration `iteration` (0-based): 1 through the exploratory phase,
+/// so the statistics track the current samples, then 1/k^α for the k-th
+/// smoothing iteration, so they average over all samples since.
+pub fn step_gain(iteration: usize, n_exploratory: usize) -> f64 {
+    if iteration < n_exploratory {
+        1.0
+    } else {
+        1.0 / ((iteration - n_exploratory + 1) as f64).powf(GAIN_EXPONENT)
+    }
+}
+
+/// Stochastic-approximation averages of the SAEM sufficient statistics:
+/// the mean individual parameter vector (S1), the mean outer product of the
+/// individual parameters (S2) and the mean squared residual (S3), with a
+/// separate mean squared residual for effect observations. The M-step reads
+/// the population parameters off these.
+#[derive(Debug, Clone)]
+pub struct SufficientStatistics {
+    pub mean: Vec<f64>,
+    pub outer_product: Vec<Vec<f64>>,
+    pub residual: f64,
+    pub effect_residual: f64,
+    /// Residual statistic of each separately observed output, by CMT.
+    pub output_residuals: BTreeMap<usize, f64>,
+}
+
+impl SufficientStatistics {
+    pub fn new(n_parameters: usize) -> Self {
+        Self {
+            mean: vec![0.0; n_parameters],
+            outer_product: vec![vec![0.0; n_parameters]; n_parameters],
+            residual: 0.0,
+            effect_residual: 0.0,
+            output_residuals: BTreeMap::new(),
+        }
+    }
+
+    /// S ← (1 − γ)·S + γ·s with s the statistics of this iteration's
+    /// samples, each individual counted `weights[id]` times (once when
+    /// absent). The residual statistic is left alone when `residual` is
+    /// `None`.
+    pub fn update(
+        &mut self,
+        individual_params: &HashMap<i32, Vec<f64>>,
+        weights: &HashMap<i32, f64>,
+        residual: Option<f64>,
+        gamma: f64,
+    ) {
+        let weight = |id: &i32| weights.get(id).copied().unwrap_or(1.0);
+        let n: f64 = individual_params.keys().map(weight).sum();
+        if n == 0.0 {
+            return;
+        }
+        let n_parameters = self.mean.len();
+        let mut mean = vec![0.0; n_parameters];
+        let mut outer_product = vec![vec![0.0; n_parameters]; n_parameters];
+        // Summed in ID order so the result does not depend on hash order
+        let mut ids: Vec<i32> = individual_params.keys().copied().collect();
+        ids.sort();
+        for id in &ids {
+            let (params, share) = (&individual_params[id], weight(id) / n);
+            for i in 0..n_parameters {
+                mean[i] += share * params[i];
+                for j in 0..n_parameters {
+                    outer_product[i][j] += share * params[i] * params[j];
+                }
+            }
+        }
+
+        for (statistic, value) in self.mean.iter_mut().zip(&mean) {
+            *statistic = (1.0 - gamma) * *statistic + gamma * value;
+        }
+        for (statistics, values) in self.outer_product.iter_mut().zip(&outer_product) {
+            for (statistic, value) in statistics.iter_mut().zip(values) {
+                *statistic = (1.0 - gamma) * *statistic + gamma * value;
+            }
+        }
+        if let Some(residual) = residual {
+            self.residual = (1.0 - gamma) * self.residual + gamma * residual;
+        }
+    }
+
+    /// The stochastic-approximation step for the effect residual statistic.
+    pub fn update_effect_residual(&mut self, effect_residual: f64, gamma: f64) {
+        self.effect_residual = (1.0 - gamma) * self.effect_residual + gamma * effect_residual;
+    }
+
+    /// The stochastic-approximation step for an output's residual statistic.
+    pub fn update_output_residual(&mut self, compartment: usize, residual: f64, gamma: f64) {
+        let statistic = self.output_residuals.entry(compartment).or_insert(0.0);
+        *statistic = (1.0 - gamma) * *statistic + gamma * residual;
+    }
+
+    /// Omega about `theta`: S2 − S1·θ' − θ·S1' + θ·θ', which is S2 − θ·θ'
+    /// when θ is S1 itself.
+    pub fn omega(&self, theta: &[f64]) -> Vec<Vec<f64>> {
+        let n_parameters = self.mean.len();
+        (0..n_parameters)
+            .map(|i| {
+                (0..n_parameters)
+                    .map(|j| {
+                        self.outer_product[i][j] - self.mean[i] * theta[j] - theta[i] * self.mean[j]
+                            + theta[i] * theta[j]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+
+    #[test]
+    fn test_gain_is_one_then_decreasing() {
+        assert_eq!(step_gain(0, 10), 1.0);
+        assert_eq!(step_gain(9, 10), 1.0);
+        assert_eq!(step_gain(10, 10), 1.0);
+        assert!(step_gain(11, 10) < 1.0);
+        assert!(step_gain(50, 10) < step_gain(20, 10));
+    }
+
+    #[test]
+    fn test_omega_is_smoother_in_smoothing_phase() {
+        // Each iteration draws fresh individual parameters, as the MCMC
+        // step does, from a population with theta 1 and omega 0.09
+        let (n_iterations, n_exploratory) = (400, 200);
+        let mut rng = StdRng::seed_from_u64(11);
+        let draw = Normal::new(1.0, 0.3).unwrap();
+        let mut statistics = SufficientStatistics::new(1);
+        let mut omegas = Vec::new();
+        for iteration in 0..n_iterations {
+            let samples: HashMap<i32, Vec<f64>> = (0..30)
+                .map(|id| (id, vec![draw.sample(&mut rng)]))
+                .collect();
+            statistics.update(&samples, &HashMap::new(), None, step_gain(iteration, n_exploratory));
+            let theta = statistics.mean.clone();
+            omegas.push(statistics.omega(&theta)[0][0]);
+        }
+
+        let mean_abs_change = |values: &[f64]| {
+            values.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (values.len() - 1) as f64
+        };
+        let exploratory = mean_abs_change(&omegas[50..n_exploratory]);
+        let smoothing = mean_abs_change(&omegas[n_exploratory + 50..]);
+        assert!(smoothing < 0.2 * exploratory, "smoothing {} vs exploratory {}", smoothing, exploratory);
+        assert!((omegas[n_iterations - 1] - 0.09).abs() < 0.02, "omega {}", omegas[n_iterations - 1]);
+    }
+}
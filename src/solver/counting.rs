@@ -0,0 +1,134 @@
+use super::{OdeSolver, OdeSystem, SolverConfig, SolverError};
+use nalgebra::DVector;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Totals reported on estimation results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationCounts {
+    pub ode_solves: usize,
+    pub derivative_evaluations: usize,
+    /// Integration steps whose negative states were clamped or refined.
+    #[serde(default)]
+    pub negative_state_corrections: usize,
+}
+
+/// Thread-safe tally of ODE solve calls and right-hand-side evaluations.
+#[derive(Debug, Default)]
+pub struct EvaluationCounter {
+    ode_solves: AtomicUsize,
+    derivative_evaluations: AtomicUsize,
+}
+
+impl EvaluationCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&self) {
+        self.ode_solves.store(0, Ordering::Relaxed);
+        self.derivative_evaluations.store(0, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> EvaluationCounts {
+        EvaluationCounts {
+            ode_solves: self.ode_solves.load(Ordering::Relaxed),
+            derivative_evaluations: self.derivative_evaluations.load(Ordering::Relaxed),
+            negative_state_corrections: 0,
+        }
+    }
+}
+
+struct CountingSystem<'a> {
+    inner: &'a dyn OdeSystem,
+    counter: &'a EvaluationCounter,
+}
+
+impl<'a> OdeSystem for CountingSystem<'a> {
+    fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64> {
+        self.counter.derivative_evaluations.fetch_add(1, Ordering::Relaxed);
+        self.inner.derivatives(t, y)
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+}
+
+/// Wraps a solver and records every call into an `EvaluationCounter`.
+pub struct CountingSolver<'a> {
+    inner: &'a dyn OdeSolver,
+    counter: &'a EvaluationCounter,
+}
+
+impl<'a> CountingSolver<'a> {
+    pub fn new(inner: &'a dyn OdeSolver, counter: &'a EvaluationCounter) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<'a> OdeSolver for CountingSolver<'a> {
+    fn solve(
+        &self,
+        system: &dyn OdeSystem,
+        t_span: (f64, f64),
+        y0: &DVector<f64>,
+        config: &SolverConfig,
+    ) -> Result<(Vec<f64>, Vec<DVector<f64>>), SolverError> {
+        self.counter.ode_solves.fetch_add(1, Ordering::Relaxed);
+        let counted = CountingSystem { inner: system, counter: self.counter };
+        self.inner.solve(&counted, t_span, y0, config)
+    }
+
+    fn solve_at_times(
+        &self,
+        system: &dyn OdeSystem,
+        t_start: f64,
+        y0: &DVector<f64>,
+        output_times: &[f64],
+        config: &SolverConfig,
+    ) -> Result<Vec<DVector<f64>>, SolverError> {
+        self.counter.ode_solves.fetch_add(1, Ordering::Relaxed);
+        let counted = CountingSystem { inner: system, counter: self.counter };
+        self.inner.solve_at_times(&counted, t_start, y0, output_times, config)
+    }
+
+    fn negative_state_corrections(&self) -> usize {
+        self.inner.negative_state_corrections()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::RungeKuttaSolver;
+
+    struct Decay;
+
+    impl OdeSystem for Decay {
+        fn derivatives(&self, _t: f64, y: &DVector<f64>) -> DVector<f64> {
+            -y.clone()
+        }
+
+        fn dimension(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_counting_solver_tallies_calls() {
+        let rk4 = RungeKuttaSolver::new();
+        let counter = EvaluationCounter::new();
+        let solver = CountingSolver::new(&rk4, &counter);
+        let y0 = DVector::from_vec(vec![1.0]);
+        let config = SolverConfig::default();
+
+        solver.solve_at_times(&Decay, 0.0, &y0, &[2.0], &config).unwrap();
+        assert_eq!(counter.counts().ode_solves, 1);
+        // Two full RK4 steps of four stages each
+        assert_eq!(counter.counts().derivative_evaluations, 8);
+
+        counter.reset();
+        assert_eq!(counter.counts(), EvaluationCounts::default());
+    }
+}
@@ -1,7 +1,9 @@
+pub mod counting;
 pub mod ode;
 pub mod runge_kutta;
 
-pub use ode::{OdeSolver, OdeSystem, SolverConfig};
+pub use counting::{CountingSolver, EvaluationCounter, EvaluationCounts};
+pub use ode::{NegativeStateHandling, OdeSolver, OdeSystem, SolverConfig};
 pub use runge_kutta::RungeKuttaSolver;
 
 use thiserror::Error;
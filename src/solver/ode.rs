@@ -1,18 +1,48 @@
 use super::SolverError;
 use nalgebra::DVector;
+use serde::{Deserialize, Serialize};
 
 pub trait OdeSystem {
     fn derivatives(&self, t: f64, y: &DVector<f64>) -> DVector<f64>;
     fn dimension(&self) -> usize;
 }
 
-#[derive(Debug, Clone)]
+/// What a solver does when a step leaves a state more negative than
+/// `absolute_tolerance`, as an over-large step can for compartment amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NegativeStateHandling {
+    /// Keep the negative values; the default.
+    Allow,
+    /// Set negative values to zero.
+    Clamp,
+    /// Redo the step as halves until no value goes negative, clamping only
+    /// once the step would fall below `min_step_size`.
+    Refine,
+}
+
+impl NegativeStateHandling {
+    /// What became of the negative amounts, for the estimators' warnings.
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            NegativeStateHandling::Allow => "they were kept",
+            NegativeStateHandling::Clamp => "they were clamped to zero",
+            NegativeStateHandling::Refine => "the steps were halved, and clamped to zero where halving reached the minimum step",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SolverConfig {
     pub absolute_tolerance: f64,
     pub relative_tolerance: f64,
     pub max_step_size: f64,
     pub min_step_size: f64,
     pub max_iterations: usize,
+    pub negative_states: NegativeStateHandling,
+    /// Use a model's closed-form solution instead of integrating when it
+    /// has one.
+    pub analytic_solutions: bool,
 }
 
 impl Default for SolverConfig {
@@ -23,6 +53,8 @@ impl Default for SolverConfig {
             max_step_size: 1.0,
             min_step_size: 1e-12,
             max_iterations: 10000,
+            negative_states: NegativeStateHandling::Allow,
+            analytic_solutions: true,
         }
     }
 }
@@ -47,4 +79,34 @@ pub trait OdeSolver {
         let (_, solutions) = self.solve(system, (t_start, t_end), y0, config)?;
         Ok(solutions.into_iter().last().unwrap_or_else(|| y0.clone()))
     }
+
+    /// Steps so far that produced a negative state and were clamped or refined.
+    fn negative_state_corrections(&self) -> usize {
+        0
+    }
+
+    /// Returns the state at each of `output_times` (ascending, >= `t_start`).
+    ///
+    /// The numerical trajectory must not depend on how many output times are
+    /// requested. The default integrates independently from `t_start` to each
+    /// output time; solvers with a step grid should override this with a
+    /// single continuous pass.
+    fn solve_at_times(
+        &self,
+        system: &dyn OdeSystem,
+        t_start: f64,
+        y0: &DVector<f64>,
+        output_times: &[f64],
+        config: &SolverConfig,
+    ) -> Result<Vec<DVector<f64>>, SolverError> {
+        output_times.iter()
+            .map(|&t| {
+                if t > t_start {
+                    self.solve_to_time(system, t_start, t, y0, config)
+                } else {
+                    Ok(y0.clone())
+                }
+            })
+            .collect()
+    }
 }
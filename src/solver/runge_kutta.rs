@@ -1,11 +1,66 @@
-use super::{OdeSolver, OdeSystem, SolverConfig, SolverError};
+use super::{NegativeStateHandling, OdeSolver, OdeSystem, SolverConfig, SolverError};
 use nalgebra::DVector;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub struct RungeKuttaSolver;
+/// Deepest step halving tried by `NegativeStateHandling::Refine`.
+const MAX_REFINEMENT_DEPTH: u32 = 8;
+
+pub struct RungeKuttaSolver {
+    negative_corrections: AtomicUsize,
+}
 
 impl RungeKuttaSolver {
     pub fn new() -> Self {
-        Self
+        Self {
+            negative_corrections: AtomicUsize::new(0),
+        }
+    }
+
+    /// An RK4 step with the configured handling of negative states.
+    fn guarded_step(
+        &self,
+        system: &dyn OdeSystem,
+        t: f64,
+        y: &DVector<f64>,
+        h: f64,
+        config: &SolverConfig,
+    ) -> DVector<f64> {
+        let next = self.rk4_step(system, t, y, h);
+        if config.negative_states == NegativeStateHandling::Allow || !is_negative(&next, config) {
+            return next;
+        }
+
+        self.negative_corrections.fetch_add(1, Ordering::Relaxed);
+        match config.negative_states {
+            NegativeStateHandling::Refine => self.refined_step(system, t, y, h, config, 1),
+            _ => clamp_negative(next),
+        }
+    }
+
+    fn refined_step(
+        &self,
+        system: &dyn OdeSystem,
+        t: f64,
+        y: &DVector<f64>,
+        h: f64,
+        config: &SolverConfig,
+        depth: u32,
+    ) -> DVector<f64> {
+        let half = h / 2.0;
+        if depth > MAX_REFINEMENT_DEPTH || half < config.min_step_size {
+            return clamp_negative(self.rk4_step(system, t, y, h));
+        }
+
+        let mut state = y.clone();
+        for start in [t, t + half] {
+            let next = self.rk4_step(system, start, &state, half);
+            state = if is_negative(&next, config) {
+                self.refined_step(system, start, &state, half, config, depth + 1)
+            } else {
+                next
+            };
+        }
+        state
     }
 
     fn rk4_step(
@@ -24,6 +79,14 @@ impl RungeKuttaSolver {
     }
 }
 
+fn is_negative(y: &DVector<f64>, config: &SolverConfig) -> bool {
+    y.iter().any(|&val| val < -config.absolute_tolerance)
+}
+
+fn clamp_negative(y: DVector<f64>) -> DVector<f64> {
+    y.map(|val| val.max(0.0))
+}
+
 impl OdeSolver for RungeKuttaSolver {
     fn solve(
         &self,
@@ -51,7 +114,7 @@ impl OdeSolver for RungeKuttaSolver {
         solutions.push(y.clone());
         
         for _ in 0..n_steps {
-            y = self.rk4_step(system, t, &y, step_size);
+            y = self.guarded_step(system, t, &y, step_size, config);
             t += step_size;
             
             // Check for numerical issues
@@ -65,6 +128,65 @@ impl OdeSolver for RungeKuttaSolver {
         
         Ok((times, solutions))
     }
+
+    fn solve_at_times(
+        &self,
+        system: &dyn OdeSystem,
+        t_start: f64,
+        y0: &DVector<f64>,
+        output_times: &[f64],
+        config: &SolverConfig,
+    ) -> Result<Vec<DVector<f64>>, SolverError> {
+        let h = config.max_step_size;
+        if h <= 0.0 {
+            return Err(SolverError::InvalidTimeStep(h));
+        }
+
+        // Grid nodes sit at t_start + k*h regardless of the output times, so
+        // the trajectory is the same however many outputs fall in the span.
+        // Outputs between nodes are read off with a partial step from the
+        // preceding node that is not carried forward.
+        let mut outputs = Vec::with_capacity(output_times.len());
+        let mut k: usize = 0;
+        let mut node_time = t_start;
+        let mut node_state = y0.clone();
+
+        for &t in output_times {
+            if t < node_time - config.min_step_size {
+                return Err(SolverError::InvalidTimeStep(t - node_time));
+            }
+
+            loop {
+                let next_time = t_start + (k + 1) as f64 * h;
+                if next_time > t + config.min_step_size {
+                    break;
+                }
+                node_state = self.guarded_step(system, node_time, &node_state, next_time - node_time, config);
+                if node_state.as_slice().iter().any(|&val| !val.is_finite()) {
+                    return Err(SolverError::NumericalInstability);
+                }
+                node_time = next_time;
+                k += 1;
+            }
+
+            let remainder = t - node_time;
+            if remainder > config.min_step_size {
+                let state = self.guarded_step(system, node_time, &node_state, remainder, config);
+                if state.as_slice().iter().any(|&val| !val.is_finite()) {
+                    return Err(SolverError::NumericalInstability);
+                }
+                outputs.push(state);
+            } else {
+                outputs.push(node_state.clone());
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    fn negative_state_corrections(&self) -> usize {
+        self.negative_corrections.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -102,4 +224,46 @@ mod tests {
         let final_solution = solutions.last().unwrap()[0];
         assert!((final_solution - (-1.0_f64).exp()).abs() < 0.01);
     }
-}
\ No newline at end of file
+
+    /// Depot-to-central transfer; RK4 with a unit step overshoots the central
+    /// amount below zero once the absorption rate is large.
+    struct FastAbsorption;
+
+    impl OdeSystem for FastAbsorption {
+        fn derivatives(&self, _t: f64, y: &DVector<f64>) -> DVector<f64> {
+            DVector::from_vec(vec![-5.0 * y[0], 5.0 * y[0] - 0.1 * y[1]])
+        }
+
+        fn dimension(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn test_negative_states_clamped_or_refined() {
+        let y0 = DVector::from_vec(vec![100.0, 0.0]);
+        let central_at = |handling: NegativeStateHandling| {
+            let solver = RungeKuttaSolver::new();
+            let config = SolverConfig { negative_states: handling, ..SolverConfig::default() };
+            let states = solver.solve_at_times(&FastAbsorption, 0.0, &y0, &[1.0], &config).unwrap();
+            (states[0][1], solver.negative_state_corrections())
+        };
+
+        // Negative amounts are kept unless clamping or refinement is opted into
+        assert_eq!(SolverConfig::default().negative_states, NegativeStateHandling::Allow);
+        let (allowed, corrections) = central_at(NegativeStateHandling::Allow);
+        assert!(allowed < 0.0);
+        assert_eq!(corrections, 0);
+
+        let (clamped, corrections) = central_at(NegativeStateHandling::Clamp);
+        assert_eq!(clamped, 0.0);
+        assert_eq!(corrections, 1);
+
+        // Exact central amount at t = 1
+        let exact = 100.0 * 5.0 / 4.9 * ((-0.1_f64).exp() - (-5.0_f64).exp());
+        let (refined, corrections) = central_at(NegativeStateHandling::Refine);
+        assert!(refined > 0.0);
+        assert!((refined - exact).abs() < (clamped - exact).abs());
+        assert_eq!(corrections, 1);
+    }
+}
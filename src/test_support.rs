@@ -0,0 +1,54 @@
+//! Datasets shared by the unit tests.
+
+use crate::data::Dataset;
+
+/// Clearance and volume of the simulated one-compartment individuals.
+pub(crate) const SIMULATED_CL: f64 = 1.0;
+pub(crate) const SIMULATED_V: f64 = 3.0;
+
+/// Concentration `t` hours after a 100 mg bolus at `SIMULATED_CL` and
+/// `SIMULATED_V`.
+pub(crate) fn one_compartment_concentration(t: f64) -> f64 {
+    100.0 / SIMULATED_V * (-SIMULATED_CL / SIMULATED_V * t).exp()
+}
+
+/// CSV of individuals 1 to `n_individuals`, each given 100 mg into
+/// compartment 1 at time 0 and observed there at `times`. The DV of
+/// individual `id` at `times[k]` is `observe(id, k, pred)`, `pred` being
+/// `one_compartment_concentration` at that time.
+pub(crate) fn simulated_one_compartment_csv(
+    n_individuals: i32,
+    times: &[f64],
+    mut observe: impl FnMut(i32, usize, f64) -> f64,
+) -> String {
+    let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+    for id in 1..=n_individuals {
+        csv.push_str(&format!("{},0,,100,1,1\n", id));
+        for (k, &t) in times.iter().enumerate() {
+            let dv = observe(id, k, one_compartment_concentration(t));
+            csv.push_str(&format!("{},{},{},,0,1\n", id, t, dv));
+        }
+    }
+    csv
+}
+
+/// The dataset of `simulated_one_compartment_csv`, loaded through the
+/// temporary file `name`.
+pub(crate) fn simulated_one_compartment(
+    name: &str,
+    n_individuals: i32,
+    times: &[f64],
+    observe: impl FnMut(i32, usize, f64) -> f64,
+) -> Dataset {
+    dataset_from_csv(name, &simulated_one_compartment_csv(n_individuals, times, observe))
+}
+
+/// Loads `csv` through the file `name` in the temporary directory, which
+/// must differ between tests as they run in parallel.
+pub(crate) fn dataset_from_csv(name: &str, csv: &str) -> Dataset {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, csv).unwrap();
+    let dataset = Dataset::from_csv(&path);
+    std::fs::remove_file(&path).ok();
+    dataset.unwrap()
+}
@@ -1,7 +1,82 @@
-use crate::data::{Dataset, DataError};
+use crate::data::{Dataset, DataError, Observation};
+use crate::models::CompartmentModel;
 use log::{info, warn};
 
-pub fn validate_dataset(dataset: &Dataset) -> Result<(), DataError> {
+/// Plausibility bounds on observed values. Values outside
+/// `[min_concentration, max_concentration]` are warned about and counted,
+/// not rejected; set the bounds to suit the assay and units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValidationConfig {
+    pub min_concentration: f64,
+    pub max_concentration: f64,
+    /// Remove individuals that cannot be fitted, such as those without
+    /// observations, in `validate_and_prune` instead of rejecting the
+    /// dataset.
+    pub prune_invalid_individuals: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            min_concentration: 0.0,
+            max_concentration: 1e6,
+            prune_invalid_individuals: false,
+        }
+    }
+}
+
+/// A dataset after `validate_and_prune`, ready to fit.
+#[derive(Debug, Clone)]
+pub struct ValidatedDataset {
+    pub dataset: Dataset,
+    /// Individuals removed as invalid, in ID order; empty unless
+    /// `prune_invalid_individuals` was set.
+    pub excluded_ids: Vec<i32>,
+    /// Observations outside the plausibility bounds.
+    pub implausible: usize,
+}
+
+/// IDs of the individuals a fit cannot use, in ID order: those without
+/// observations, which contribute nothing to the likelihood but still get
+/// etas and appear in every per-individual output.
+pub fn invalid_individuals(dataset: &Dataset) -> Vec<i32> {
+    dataset.individuals().iter()
+        .filter(|(_, individual)| individual.observations().is_empty())
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+/// Validates `dataset` under `config`, first removing the individuals of
+/// `invalid_individuals` when `config.prune_invalid_individuals` is set, so
+/// the validation and the fit see the same data.
+pub fn validate_and_prune(dataset: &Dataset, config: &ValidationConfig) -> Result<ValidatedDataset, DataError> {
+    let excluded_ids = if config.prune_invalid_individuals { invalid_individuals(dataset) } else { Vec::new() };
+    let dataset = if excluded_ids.is_empty() {
+        dataset.clone()
+    } else {
+        for &id in &excluded_ids {
+            warn!("Excluding individual {}: no observations", dataset.id_label(id));
+        }
+        let kept: Vec<i32> = dataset.individuals().keys()
+            .copied()
+            .filter(|id| !excluded_ids.contains(id))
+            .collect();
+        dataset.subset(&kept)
+    };
+    let implausible = validate_dataset_with(&dataset, config)?;
+    Ok(ValidatedDataset { dataset, excluded_ids, implausible })
+}
+
+/// `validate_dataset_with` under the default bounds.
+pub fn validate_dataset(dataset: &Dataset) -> Result<usize, DataError> {
+    validate_dataset_with(dataset, &ValidationConfig::default())
+}
+
+/// Checks the dataset's structure and returns the number of observations
+/// outside the plausibility bounds of `config`. An individual without
+/// observations is an error; `validate_and_prune` can remove such
+/// individuals first.
+pub fn validate_dataset_with(dataset: &Dataset, config: &ValidationConfig) -> Result<usize, DataError> {
     info!("Validating dataset with {} individuals", dataset.n_individuals());
     
     // Check minimum requirements
@@ -16,12 +91,12 @@ pub fn validate_dataset(dataset: &Dataset) -> Result<(), DataError> {
     let mut total_dose_events = 0;
     let mut individuals_with_doses = 0;
     let mut individuals_with_observations = 0;
+    let mut implausible = 0;
     
     for (id, individual) in dataset.individuals() {
         // Validate individual data
         if individual.observations().is_empty() {
-            warn!("Individual {} has no observations", id);
-            continue;
+            return Err(DataError::NoObservations(*id));
         }
         individuals_with_observations += 1;
         
@@ -40,13 +115,14 @@ pub fn validate_dataset(dataset: &Dataset) -> Result<(), DataError> {
         
         // Check for reasonable concentration values
         for obs in individual.observations() {
-            if obs.value < 0.0 {
-                warn!("Individual {} has negative concentration at time {}", id, obs.time);
-            }
-            
-            if obs.value > 1e6 {
+            if obs.value < config.min_concentration {
+                warn!("Individual {} has concentration {} below {} at time {}",
+                      id, obs.value, config.min_concentration, obs.time);
+                implausible += 1;
+            } else if obs.value > config.max_concentration {
                 warn!("Individual {} has very high concentration ({}) at time {}", 
                       id, obs.value, obs.time);
+                implausible += 1;
             }
         }
     }
@@ -55,14 +131,164 @@ pub fn validate_dataset(dataset: &Dataset) -> Result<(), DataError> {
     info!("  - {} individuals with observations", individuals_with_observations);
     info!("  - {} individuals with dosing records", individuals_with_doses);
     info!("  - {} total dose events", total_dose_events);
+    if implausible > 0 {
+        warn!("{} observation(s) outside [{}, {}]", implausible, config.min_concentration, config.max_concentration);
+    }
     
     if individuals_with_doses == 0 {
         warn!("No dosing information found in dataset");
     }
     
+    Ok(implausible)
+}
+
+/// What to do with observations of one individual that share a TIME and
+/// compartment, usually a data-entry error that would double-count the
+/// information at that time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateObservations {
+    /// Reject the dataset.
+    #[default]
+    Error,
+    /// Warn and keep the first row of each duplicate group in file order.
+    KeepFirst,
+}
+
+/// Applies `policy` to observations sharing an (ID, TIME, compartment,
+/// PREDOSE): a trough taken just before a dose and a sample just after it
+/// are distinct observations.
+/// Returns the number of observations dropped, always 0 under `Error`.
+pub fn resolve_duplicate_observations(
+    dataset: &mut Dataset,
+    policy: DuplicateObservations,
+) -> Result<usize, DataError> {
+    let mut deduplicated = Vec::new();
+    
+    for (&id, individual) in dataset.individuals() {
+        let mut kept: Vec<Observation> = Vec::with_capacity(individual.n_observations());
+        for obs in individual.observations() {
+            let duplicate = kept.iter()
+                .any(|k| k.time == obs.time && k.compartment == obs.compartment && k.pre_dose == obs.pre_dose);
+            if !duplicate {
+                kept.push(obs.clone());
+                continue;
+            }
+            match policy {
+                DuplicateObservations::Error => {
+                    return Err(DataError::DuplicateObservation {
+                        id,
+                        time: obs.time,
+                        compartment: obs.compartment,
+                    });
+                }
+                DuplicateObservations::KeepFirst => {
+                    warn!("Individual {} has more than one observation at time {} in compartment {}; keeping the first",
+                          dataset.id_label(id), obs.time, obs.compartment);
+                }
+            }
+        }
+        if kept.len() < individual.n_observations() {
+            deduplicated.push((id, kept));
+        }
+    }
+    
+    let mut dropped = 0;
+    for (id, kept) in deduplicated {
+        dropped += dataset.individuals()[&id].n_observations() - kept.len();
+        dataset.replace_observations(id, kept);
+    }
+    Ok(dropped)
+}
+
+/// Checks that every dose targets a compartment the model actually has.
+///
+/// `ModelState::add_dose` ignores out-of-range compartments, so without this
+/// check such doses would silently vanish from the fit.
+pub fn validate_dose_compartments(
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<(), DataError> {
+    let n_compartments = model.n_compartments();
+    
+    for (&id, individual) in dataset.individuals() {
+        for dose in individual.dosing_records() {
+            if dose.compartment < 1 || dose.compartment as usize > n_compartments {
+                return Err(DataError::InvalidDoseCompartment {
+                    id,
+                    compartment: dose.compartment,
+                    n_compartments,
+                });
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Checks that every individual with observations has a dose, either a
+/// dosing record or a prior regimen, unless the model starts from nonzero
+/// initial conditions (an endogenous or baseline model). Without any of
+/// these, the individual's predictions are all zero, and its observations
+/// would be fitted against nothing.
+pub fn validate_dosed_individuals(
+    dataset: &Dataset,
+    model: &CompartmentModel,
+) -> Result<(), DataError> {
+    if model.default_parameters().initial_conditions.iter().any(|&amount| amount != 0.0) {
+        return Ok(());
+    }
+    
+    for (&id, individual) in dataset.individuals() {
+        if individual.dosing_records().is_empty()
+            && individual.prior_regimen().is_none()
+            && !individual.observations().is_empty()
+        {
+            return Err(DataError::NoDoses(id));
+        }
+    }
+    
     Ok(())
 }
 
+/// Factor by which observed concentrations may differ from `Dose / V` before
+/// the dose and concentration units are suspected to be inconsistent.
+pub const DOSE_SCALE_TOLERANCE: f64 = 1000.0;
+
+/// Heuristic unit check: compares each individual's peak concentration with
+/// its first dose over the model's typical central volume. Returns the median
+/// observed/expected ratio, with a warning, when it is off by more than
+/// `DOSE_SCALE_TOLERANCE` either way (e.g. mg doses with ng/mL samples).
+/// Models without a `V` or `V1` parameter are not checked.
+pub fn check_dose_units(dataset: &Dataset, model: &CompartmentModel) -> Option<f64> {
+    let names = model.parameter_names();
+    let v_index = names.iter().position(|name| name == "V" || name == "V1")?;
+    let volume = model.default_parameters().fixed_effects[v_index].exp();
+    
+    let mut ratios: Vec<f64> = dataset.individuals().values()
+        .filter_map(|individual| {
+            let dose = individual.dosing_records().first()?.amount;
+            let peak = individual.observations().iter()
+                .map(|obs| obs.value)
+                .filter(|&value| value > 0.0)
+                .fold(None, |max: Option<f64>, value| Some(max.map_or(value, |m| m.max(value))))?;
+            (dose > 0.0).then(|| peak / (dose / volume))
+        })
+        .collect();
+    if ratios.is_empty() {
+        return None;
+    }
+    ratios.sort_by(|a, b| a.total_cmp(b));
+    let median = ratios[ratios.len() / 2];
+    
+    if !(1.0 / DOSE_SCALE_TOLERANCE..=DOSE_SCALE_TOLERANCE).contains(&median) {
+        warn!("Observed concentrations are {:.3e} times Dose/V for the {} model; check that dose and concentration units match",
+              median, model.model_type());
+        Some(median)
+    } else {
+        None
+    }
+}
+
 pub fn validate_model_fit(
     predicted: &[f64],
     observed: &[f64],
@@ -93,6 +319,7 @@ pub fn validate_model_fit(
 mod tests {
     use super::*;
     use crate::data::*;
+    use crate::test_support::dataset_from_csv;
     use std::collections::HashMap;
 
     #[test]
@@ -107,4 +334,120 @@ mod tests {
         assert!(validate_dataset(&dataset).is_err());
         std::fs::remove_file(&temp_file).ok();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_custom_concentration_bounds_count_flagged_points() {
+        let dataset = dataset_from_csv("concentration_bounds_test.csv",
+            "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,850.0,,0\n1,2,420.0,,0\n1,4,0.02,,0\n\
+             2,0,,100,1\n2,1,1200.0,,0\n2,2,-0.5,,0\n");
+
+        // Only the negative value is implausible under the default bounds
+        assert_eq!(validate_dataset(&dataset).unwrap(), 1);
+        let config = ValidationConfig { min_concentration: 0.05, max_concentration: 500.0, ..ValidationConfig::default() };
+        assert_eq!(validate_dataset_with(&dataset, &config).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_individual_without_observations_pruned_and_reported() {
+        let dataset = dataset_from_csv("prune_individuals_test.csv",
+            "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,5.0,,0\n2,0,,100,1\n3,0,,100,1\n3,1,4.0,,0\n");
+        assert_eq!(invalid_individuals(&dataset), vec![2]);
+
+        // Rejected unless pruning is asked for
+        assert!(matches!(
+            validate_and_prune(&dataset, &ValidationConfig::default()),
+            Err(DataError::NoObservations(2))
+        ));
+
+        let config = ValidationConfig { prune_invalid_individuals: true, ..ValidationConfig::default() };
+        let validated = validate_and_prune(&dataset, &config).unwrap();
+        assert_eq!(validated.excluded_ids, vec![2]);
+        assert_eq!(validated.dataset.individuals().keys().copied().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(validated.dataset.n_observations(), 2);
+    }
+
+    #[test]
+    fn test_out_of_range_dose_compartment_rejected() {
+        let dataset = dataset_from_csv("dose_cmt_test.csv", "ID,TIME,DV,AMT,EVID,CMT\n1,0,,100,1,3\n1,1,5.0,,0,1\n");
+
+        let one_comp = CompartmentModel::new(crate::models::ModelType::OneCompartment).unwrap();
+        let three_comp = CompartmentModel::new(crate::models::ModelType::ThreeCompartment).unwrap();
+
+        assert!(matches!(
+            validate_dose_compartments(&dataset, &one_comp),
+            Err(DataError::InvalidDoseCompartment { id: 1, compartment: 3, n_compartments: 1 })
+        ));
+        assert!(validate_dose_compartments(&dataset, &three_comp).is_ok());
+    }
+
+    #[test]
+    fn test_dose_less_individual_in_pk_model_rejected() {
+        let dataset = dataset_from_csv("dose_less_test.csv", "ID,TIME,DV,AMT,EVID\n1,0,,100,1\n1,1,5.0,,0\n2,1,4.0,,0\n2,2,3.0,,0\n");
+
+        let model = CompartmentModel::new(crate::models::ModelType::OneCompartment).unwrap();
+        assert!(matches!(validate_dosed_individuals(&dataset, &model), Err(DataError::NoDoses(2))));
+        assert!(validate_dosed_individuals(&dataset.subset(&[1]), &model).is_ok());
+
+        // Fitting stops with the diagnostic instead of fitting zeros
+        let config = crate::estimation::EstimationConfig::default()
+            .with_method(crate::estimation::EstimationMethod::Foce);
+        let error = crate::estimation::FoceEstimator::new(model, config).fit(&dataset).unwrap_err();
+        assert!(error.to_string().contains("Individual 2 has observations but no doses"), "{}", error);
+    }
+
+    #[test]
+    fn test_individual_dosed_only_by_prior_regimen_accepted() {
+        let mut dataset = dataset_from_csv("prior_regimen_only_test.csv", "ID,TIME,DV,AMT,EVID\n1,0,9.0,,0\n1,4,6.5,,0\n1,8,5.0,,0\n1,12,3.8,,0\n");
+
+        let model = CompartmentModel::new(crate::models::ModelType::OneCompartment).unwrap();
+        assert!(matches!(validate_dosed_individuals(&dataset, &model), Err(DataError::NoDoses(1))));
+        dataset.set_prior_regimen(1, Some(crate::data::PriorRegimen::new(100.0, 12.0, 10, crate::data::DosingType::Bolus)));
+        assert!(validate_dosed_individuals(&dataset, &model).is_ok());
+
+        let config = crate::estimation::EstimationConfig::default()
+            .with_method(crate::estimation::EstimationMethod::Foce)
+            .with_foce_iterations(3);
+        let results = crate::estimation::FoceEstimator::new(model, config).fit(&dataset).unwrap();
+        assert!(results.objective_function_value.is_finite());
+    }
+
+    #[test]
+    fn test_duplicate_observation_rejected_or_first_kept() {
+        let dataset = dataset_from_csv("duplicate_obs_test.csv",
+            "ID,TIME,DV,AMT,EVID,CMT,PREDOSE\n1,0,,100,1,1,\n1,1,5.0,,0,1,\n1,1,5.5,,0,1,\n1,1,2.0,,0,2,\n1,4,3.0,,0,1,\n\
+             2,0,,100,1,1,\n2,1,6.0,,0,1,\n2,12,1.5,,0,1,1\n2,12,,100,1,1,\n2,12,9.0,,0,1,\n");
+
+        let mut rejected = dataset.clone();
+        assert!(matches!(
+            resolve_duplicate_observations(&mut rejected, DuplicateObservations::Error),
+            Err(DataError::DuplicateObservation { id: 1, time, compartment: 1 }) if time == 1.0
+        ));
+
+        let mut deduplicated = dataset.clone();
+        assert_eq!(resolve_duplicate_observations(&mut deduplicated, DuplicateObservations::KeepFirst).unwrap(), 1);
+        let kept: Vec<(f64, f64, i32)> = deduplicated.individuals()[&1].observations().iter()
+            .map(|obs| (obs.time, obs.value, obs.compartment))
+            .collect();
+        // The same time in another compartment is not a duplicate
+        assert_eq!(kept, vec![(1.0, 5.0, 1), (1.0, 2.0, 2), (4.0, 3.0, 1)]);
+        // A trough and a post-dose sample at the same dose time are not
+        // duplicates either
+        assert_eq!(deduplicated.individuals()[&2].n_observations(), 3);
+        assert_eq!(deduplicated.individuals()[&1].dosing_records().len(), 1);
+    }
+
+    #[test]
+    fn test_dose_unit_mismatch_flagged() {
+        let model = CompartmentModel::new(crate::models::ModelType::OneCompartment).unwrap();
+        let load = |dose: f64| dataset_from_csv("dose_units_test.csv", &format!(
+            "ID,TIME,DV,AMT,EVID,CMT\n1,0,,{dose},1,1\n1,1,20.0,,0,1\n1,4,12.0,,0,1\n\
+             2,0,,{dose},1,1\n2,1,25.0,,0,1\n2,4,15.0,,0,1\n"
+        ));
+
+        // 100 mg into a few litres gives concentrations of tens of mg/L
+        assert_eq!(check_dose_units(&load(100.0), &model), None);
+        // The same samples against a dose recorded in µg
+        let ratio = check_dose_units(&load(100_000.0), &model).unwrap();
+        assert!(ratio < 1.0 / DOSE_SCALE_TOLERANCE);
+    }
+}
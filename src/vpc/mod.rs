@@ -0,0 +1,420 @@
+use crate::data::{Dataset, Individual};
+use crate::estimation::objective;
+use crate::models::{CompartmentModel, FittedModel};
+use crate::prediction;
+use crate::saem::SaemResults;
+use crate::solver::RungeKuttaSolver;
+use nalgebra::DMatrix;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VpcConfig {
+    pub n_simulations: usize,
+    /// Number of time bins, each holding roughly the same number of observations.
+    pub n_bins: usize,
+    pub seed: Option<u64>,
+    /// Covariate column whose baseline value splits individuals into strata.
+    pub strata: Option<String>,
+    /// Scale observed and simulated values by the bin's median population
+    /// prediction over the population prediction at each design point (pcVPC).
+    pub prediction_corrected: bool,
+}
+
+impl Default for VpcConfig {
+    fn default() -> Self {
+        Self {
+            n_simulations: 200,
+            n_bins: 5,
+            seed: Some(12345),
+            strata: None,
+            prediction_corrected: false,
+        }
+    }
+}
+
+/// Observed and simulated 5th, 50th and 95th percentiles in one time bin.
+/// Simulated percentiles are medians over the simulation replicates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VpcBin {
+    pub stratum: Option<String>,
+    pub bin: usize,
+    pub time_lower: f64,
+    pub time_upper: f64,
+    pub n_observations: usize,
+    pub observed: [f64; 3],
+    pub simulated: [f64; 3],
+}
+
+const PERCENTILES: [f64; 3] = [0.05, 0.5, 0.95];
+
+/// Runs a visual predictive check: each replicate simulates every individual's
+/// design from the population parameters, Omega and the residual error model
+/// of the fit. With
+/// `prediction_corrected`, observed and simulated values are normalized by the
+/// population prediction at their design point before binning.
+pub fn run_vpc(
+    dataset: &Dataset,
+    results: &SaemResults,
+    model: &CompartmentModel,
+    config: &VpcConfig,
+) -> Result<Vec<VpcBin>, anyhow::Error> {
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut ids: Vec<i32> = dataset.individuals().keys().copied().collect();
+    ids.sort_unstable();
+
+    // Individuals grouped by stratum label, in first-seen order
+    let mut strata: Vec<(Option<String>, Vec<&Individual>)> = Vec::new();
+    for id in ids {
+        let individual = &dataset.individuals()[&id];
+        let label = config.strata.as_ref().map(|name| {
+            individual.get_covariate(name)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| "NA".to_string())
+        });
+        match strata.iter_mut().find(|(existing, _)| *existing == label) {
+            Some((_, members)) => members.push(individual),
+            None => strata.push((label, vec![individual])),
+        }
+    }
+
+    let omega_factor = omega_cholesky(&results.random_effects_variance)?;
+    let fitted = FittedModel::from_saem(model, results);
+    let mut bins = Vec::new();
+
+    for (label, members) in &strata {
+        // Design points as (time, observed value, population prediction), in member order
+        let mut design: Vec<(f64, f64, f64)> = Vec::new();
+        for individual in members {
+            let population = population_predictions(individual, results, model)?;
+            design.extend(individual.observations().iter().zip(population)
+                .map(|(obs, pred)| (obs.time, obs.value, pred)));
+        }
+        let edges = bin_edges(&design.iter().map(|&(t, _, _)| t).collect::<Vec<_>>(), config.n_bins);
+        let design_bins: Vec<usize> = design.iter().map(|&(t, _, _)| bin_index(&edges, t)).collect();
+        let corrections = if config.prediction_corrected {
+            prediction_corrections(&design, &design_bins, edges.len() - 1)
+        } else {
+            vec![1.0; design.len()]
+        };
+
+        // simulated[replicate][bin] holds that replicate's values in the bin
+        let mut simulated: Vec<Vec<Vec<f64>>> = Vec::with_capacity(config.n_simulations);
+        for _ in 0..config.n_simulations {
+            let mut values = Vec::with_capacity(design.len());
+            for individual in members {
+                values.extend(simulate_individual(individual, &fitted, model, &omega_factor, &mut rng)?);
+            }
+            let mut replicate = vec![Vec::new(); edges.len() - 1];
+            for ((value, &bin), correction) in values.into_iter().zip(&design_bins).zip(&corrections) {
+                replicate[bin].push(value * correction);
+            }
+            simulated.push(replicate);
+        }
+
+        for bin in 0..edges.len() - 1 {
+            let mut observed: Vec<f64> = design.iter().zip(&design_bins).zip(&corrections)
+                .filter(|((_, &b), _)| b == bin)
+                .map(|((&(_, value, _), _), correction)| value * correction)
+                .collect();
+            if observed.is_empty() {
+                continue;
+            }
+
+            let mut simulated_percentiles = [0.0; 3];
+            for (k, &p) in PERCENTILES.iter().enumerate() {
+                let mut per_replicate: Vec<f64> = simulated.iter()
+                    .filter_map(|replicate| percentile(&mut replicate[bin].clone(), p))
+                    .collect();
+                simulated_percentiles[k] = percentile(&mut per_replicate, 0.5).unwrap_or(f64::NAN);
+            }
+
+            let n_observations = observed.len();
+            let observed_percentiles = PERCENTILES.map(|p| percentile(&mut observed, p).unwrap_or(f64::NAN));
+
+            bins.push(VpcBin {
+                stratum: label.clone(),
+                bin,
+                time_lower: edges[bin],
+                time_upper: edges[bin + 1],
+                n_observations,
+                observed: observed_percentiles,
+                simulated: simulated_percentiles,
+            });
+        }
+    }
+
+    Ok(bins)
+}
+
+/// Writes `vpc.csv`; a `STRATUM` column is added when the VPC is stratified.
+pub fn save_vpc_csv(output_dir: &Path, bins: &[VpcBin]) -> Result<(), anyhow::Error> {
+    let mut wtr = csv::Writer::from_path(output_dir.join("vpc.csv"))?;
+    let stratified = bins.iter().any(|b| b.stratum.is_some());
+
+    let mut header = vec!["BIN", "TIME_LOWER", "TIME_UPPER", "N_OBS",
+                          "OBS_P05", "OBS_P50", "OBS_P95", "SIM_P05", "SIM_P50", "SIM_P95"];
+    if stratified {
+        header.insert(0, "STRATUM");
+    }
+    wtr.write_record(&header)?;
+
+    for bin in bins {
+        let mut record = vec![
+            bin.bin.to_string(),
+            bin.time_lower.to_string(),
+            bin.time_upper.to_string(),
+            bin.n_observations.to_string(),
+        ];
+        record.extend(bin.observed.iter().chain(bin.simulated.iter()).map(|v| v.to_string()));
+        if stratified {
+            record.insert(0, bin.stratum.clone().unwrap_or_default());
+        }
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Predictions at the individual's observations with every random effect at zero.
+fn population_predictions(
+    individual: &Individual,
+    results: &SaemResults,
+    model: &CompartmentModel,
+) -> Result<Vec<f64>, anyhow::Error> {
+    let mut params = model.default_parameters();
+    params.fixed_effects = results.fixed_effects.clone();
+    prediction::predict_individual(model, &RungeKuttaSolver::new(), individual, &params, &results.solver_config)
+}
+
+/// Per-design-point pcVPC factors: the median population prediction in the
+/// point's bin over its own population prediction. Points whose prediction is
+/// not positive are left uncorrected.
+fn prediction_corrections(design: &[(f64, f64, f64)], design_bins: &[usize], n_bins: usize) -> Vec<f64> {
+    let bin_medians: Vec<f64> = (0..n_bins)
+        .map(|bin| {
+            let mut preds: Vec<f64> = design.iter().zip(design_bins)
+                .filter(|(_, &b)| b == bin)
+                .map(|(&(_, _, pred), _)| pred)
+                .collect();
+            percentile(&mut preds, 0.5).unwrap_or(f64::NAN)
+        })
+        .collect();
+
+    design.iter().zip(design_bins)
+        .map(|(&(_, _, pred), &bin)| {
+            if pred > 0.0 && bin_medians[bin].is_finite() { bin_medians[bin] / pred } else { 1.0 }
+        })
+        .collect()
+}
+
+/// One replicate of the individual's observations: etas drawn through
+/// `omega_factor` around the fitted fixed effects, and residual noise with
+/// each observation's variance under the fit's error model, on the log
+/// scale where the error model takes it there.
+fn simulate_individual(
+    individual: &Individual,
+    fitted: &FittedModel,
+    model: &CompartmentModel,
+    omega_factor: &DMatrix<f64>,
+    rng: &mut StdRng,
+) -> Result<Vec<f64>, anyhow::Error> {
+    let params = &fitted.parameters;
+    let n = params.fixed_effects.len();
+    let z: Vec<f64> = (0..n).map(|_| rng.sample(StandardNormal)).collect();
+
+    let mut simulated = params.clone();
+    for i in 0..n {
+        let eta: f64 = (0..=i).map(|j| omega_factor[(i, j)] * z[j]).sum();
+        simulated.fixed_effects[i] = params.fixed_effects[i] + eta;
+    }
+
+    let predictions = prediction::predict_individual(
+        model, &RungeKuttaSolver::new(), individual, &simulated, &fitted.solver_config,
+    )?;
+
+    Ok(individual.observations().iter().zip(predictions)
+        .map(|(obs, pred)| {
+            let sd = objective::observation_variance(params, obs, pred).sqrt();
+            let noise: f64 = rng.sample(StandardNormal);
+            if objective::on_log_scale(params, obs) {
+                pred * (sd * noise).exp()
+            } else {
+                pred + sd * noise
+            }
+        })
+        .collect())
+}
+
+/// Lower Cholesky factor of Omega. Random effects with no variance, such as
+/// a row fixed at zero, are left out of the factorization and get no eta.
+fn omega_cholesky(omega: &[Vec<f64>]) -> Result<DMatrix<f64>, anyhow::Error> {
+    let n = omega.len();
+    let varying: Vec<usize> = (0..n).filter(|&i| omega[i][i] > 0.0).collect();
+    let matrix = DMatrix::from_fn(varying.len(), varying.len(), |a, b| omega[varying[a]][varying[b]]);
+    let factor = matrix.cholesky()
+        .map(|chol| chol.l())
+        .ok_or_else(|| anyhow::anyhow!("Omega is not positive definite"))?;
+    let mut full = DMatrix::zeros(n, n);
+    for (a, &i) in varying.iter().enumerate() {
+        for (b, &j) in varying.iter().enumerate() {
+            full[(i, j)] = factor[(a, b)];
+        }
+    }
+    Ok(full)
+}
+
+/// Bin edges at equal-count quantiles of the observation times.
+fn bin_edges(times: &[f64], n_bins: usize) -> Vec<f64> {
+    let mut sorted = times.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let (Some(&first), Some(&last)) = (sorted.first(), sorted.last()) else {
+        return vec![0.0, 0.0];
+    };
+
+    let mut edges = vec![first];
+    for k in 1..n_bins.max(1) {
+        let edge = sorted[k * sorted.len() / n_bins.max(1)];
+        if edge > *edges.last().unwrap() && edge < last {
+            edges.push(edge);
+        }
+    }
+    edges.push(last);
+    edges
+}
+
+/// Bins are half-open `[lower, upper)` except the last, which includes its upper edge.
+fn bin_index(edges: &[f64], time: f64) -> usize {
+    let n_bins = edges.len() - 1;
+    edges[1..n_bins].iter().take_while(|&&edge| time >= edge).count()
+}
+
+/// Linear-interpolation percentile; sorts `values` in place.
+pub(crate) fn percentile(values: &mut [f64], p: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let position = p * (values.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f64;
+    Some(values[lower] + (values[upper] - values[lower]) * fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelType;
+    use crate::test_support::dataset_from_csv;
+
+    fn test_results(model: &CompartmentModel) -> SaemResults {
+        let params = model.default_parameters();
+        let mut results = SaemResults::new(2, model.parameter_names());
+        results.fixed_effects = params.fixed_effects.clone();
+        results.random_effects_variance = params.random_effects_variance.clone();
+        results.residual_variance = params.residual_variance;
+        results
+    }
+
+    #[test]
+    fn test_stratified_vpc_has_rows_per_stratum() {
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT,SEX\n");
+        for id in 1..=6 {
+            let sex = id % 2;
+            csv.push_str(&format!("{},0,,100,1,1,{}\n", id, sex));
+            for t in [1.0_f64, 2.0, 4.0, 8.0, 12.0] {
+                csv.push_str(&format!("{},{},{},,0,1,{}\n", id, t, 5.0 * (-0.1 * t).exp(), sex));
+            }
+        }
+        let dataset = dataset_from_csv("vpc_strata_test.csv", &csv);
+
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let results = test_results(&model);
+
+        let config = VpcConfig {
+            n_simulations: 20,
+            n_bins: 3,
+            strata: Some("SEX".to_string()),
+            ..VpcConfig::default()
+        };
+        let bins = run_vpc(&dataset, &results, &model, &config).unwrap();
+
+        let strata: Vec<Option<String>> = bins.iter().map(|b| b.stratum.clone()).collect();
+        assert!(strata.contains(&Some("0".to_string())));
+        assert!(strata.contains(&Some("1".to_string())));
+        for label in ["0", "1"] {
+            let rows: Vec<&VpcBin> = bins.iter().filter(|b| b.stratum.as_deref() == Some(label)).collect();
+            assert_eq!(rows.len(), 3);
+            // Three individuals with five samples each per stratum
+            assert_eq!(rows.iter().map(|b| b.n_observations).sum::<usize>(), 15);
+            assert!(rows.iter().all(|b| b.simulated[0] <= b.simulated[2]));
+        }
+    }
+
+    #[test]
+    fn test_prediction_corrected_bands_are_tighter_with_varying_doses() {
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=8 {
+            let dose = 25.0 * 2f64.powi(id % 4);
+            csv.push_str(&format!("{},0,,{},1,1\n", id, dose));
+            for t in [1.0_f64, 2.0, 4.0, 8.0, 12.0, 24.0] {
+                csv.push_str(&format!("{},{},{},,0,1\n", id, t, 0.05 * dose * (-0.1 * t).exp()));
+            }
+        }
+        let dataset = dataset_from_csv("vpc_pc_test.csv", &csv);
+
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let results = test_results(&model);
+
+        let mean_band_width = |prediction_corrected: bool| {
+            let config = VpcConfig {
+                n_simulations: 50,
+                n_bins: 3,
+                prediction_corrected,
+                ..VpcConfig::default()
+            };
+            let bins = run_vpc(&dataset, &results, &model, &config).unwrap();
+            bins.iter().map(|b| b.simulated[2] - b.simulated[0]).sum::<f64>() / bins.len() as f64
+        };
+
+        let raw = mean_band_width(false);
+        let corrected = mean_band_width(true);
+        assert!(corrected.is_finite() && raw.is_finite());
+        assert!(corrected < raw, "pcVPC band {} should be tighter than raw band {}", corrected, raw);
+    }
+
+    #[test]
+    fn test_proportional_fit_simulates_proportional_noise_with_zero_omega() {
+        // Every sample at one time, so the band is residual noise alone
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+        for id in 1..=100 {
+            csv.push_str(&format!("{},0,,100,1,1\n{},2,20.0,,0,1\n", id, id));
+        }
+        let dataset = dataset_from_csv("vpc_proportional_test.csv", &csv);
+
+        // A FOCE proportional fit converted to SaemResults, with the
+        // random effects fixed at zero
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut results = test_results(&model);
+        results.random_effects_variance = vec![vec![0.0; 2]; 2];
+        results.error_model = crate::estimation::ErrorModel::Proportional;
+        results.residual_variance = 0.0;
+        results.proportional_variance = 0.04;
+
+        let config = VpcConfig { n_simulations: 200, n_bins: 1, ..VpcConfig::default() };
+        let bins = run_vpc(&dataset, &results, &model, &config).unwrap();
+        assert_eq!(bins.len(), 1);
+        let [low, median, high] = bins[0].simulated;
+        // 5th and 95th percentiles at PRED · (1 ∓ 1.645 · 0.2)
+        assert!((high / median - 1.329).abs() < 0.05, "{:?}", bins[0].simulated);
+        assert!((low / median - 0.671).abs() < 0.05, "{:?}", bins[0].simulated);
+    }
+}
@@ -1,9 +1,28 @@
-use nmodes::data::Dataset;
-use nmodes::models::{CompartmentModel, ModelType};
+use nmodes::data::{Censoring, CovariateInterpolation, Dataset, DvColumn, ObservationType};
+use nmodes::diagnostics::poorly_identified_parameters;
+use nmodes::models::{CompartmentModel, CompartmentModelTrait, ModelError, ModelParameters, ModelState, ModelType};
 use nmodes::saem::SaemEstimator;
-use nmodes::estimation::EstimationConfig;
+use nmodes::estimation::{EstimationConfig, EstimationMethod, FoceEstimator};
+use nmodes::estimation::objective::objective_function_value;
+use nmodes::models::FittedModel;
+use nmodes::output::NumberFormat;
+use nmodes::prediction::predict_individual;
+use nmodes::solver::{RungeKuttaSolver, SolverConfig};
+use nmodes::pipeline::{run_pipeline, PipelineConfig};
+use nalgebra::DVector;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Loads `csv` through the file `name` in the temporary directory, which
+/// must differ between tests as they run in parallel.
+fn dataset_from_csv(name: &str, csv: &str) -> Dataset {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, csv).unwrap();
+    let dataset = Dataset::from_csv(&path);
+    std::fs::remove_file(&path).ok();
+    dataset.expect("Failed to load dataset")
+}
+
 #[test]
 fn test_full_pipeline() {
     // Create test dataset
@@ -36,6 +55,8 @@ fn test_full_pipeline() {
     assert_eq!(results.fixed_effects.len(), 2); // CL and V for 1-compartment
     assert!(results.n_iterations > 0);
     assert!(!results.log_likelihood_trajectory.is_empty());
+    assert_eq!(results.parameter_acceptance.len(), 2);
+    assert!(results.parameter_acceptance.iter().all(|rate| (0.0..=1.0).contains(rate)));
 }
 
 #[test]
@@ -45,6 +66,9 @@ fn test_model_comparison() {
         ModelType::OneCompartment,
         ModelType::TwoCompartment,
         ModelType::ThreeCompartment,
+        ModelType::OneCompartmentZeroOrder,
+        ModelType::OneCompartmentWeibull,
+        ModelType::ParentMetabolite,
     ];
     
     for model_type in models {
@@ -55,6 +79,10 @@ fn test_model_comparison() {
             ModelType::OneCompartment => 1,
             ModelType::TwoCompartment => 2,
             ModelType::ThreeCompartment => 3,
+            ModelType::OneCompartmentZeroOrder => 1,
+            ModelType::OneCompartmentWeibull => 1,
+            ModelType::ParentMetabolite => 2,
+            ModelType::Custom(_) => unreachable!(),
         };
         
         assert_eq!(model.n_compartments(), expected_compartments);
@@ -62,4 +90,1448 @@ fn test_model_comparison() {
         let params = model.default_parameters();
         assert!(model.validate_parameters(&params).is_ok());
     }
-}
\ No newline at end of file
+}
+
+struct CustomOneCompartment;
+
+impl CompartmentModelTrait for CustomOneCompartment {
+    fn n_compartments(&self) -> usize {
+        1
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        vec!["KE".to_string(), "V".to_string()]
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        let mut params = ModelParameters::new(2, self.parameter_names());
+        params.fixed_effects[0] = 0.3_f64.ln();
+        params.fixed_effects[1] = 10.0_f64.ln();
+        params.random_effects_variance[0][0] = 0.09;
+        params.random_effects_variance[1][1] = 0.04;
+        params.residual_variance = 0.01;
+        params
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        let ke = params.fixed_effects[0].exp();
+        DVector::from_vec(vec![-ke * state.compartments[0]])
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        if compartment != 1 {
+            return 0.0;
+        }
+        state.compartments[0] / params.fixed_effects[1].exp()
+    }
+
+    fn validate_parameters(&self, _params: &ModelParameters) -> Result<(), ModelError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_model_fit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::from_custom("custom-ke", Box::new(CustomOneCompartment));
+    assert_eq!(model.model_type(), &ModelType::Custom("custom-ke".to_string()));
+    assert_eq!(format!("{}", model.model_type()), "custom-ke");
+
+    let config = EstimationConfig::default()
+        .with_iterations(10)
+        .with_burnin(2);
+
+    let mut estimator = SaemEstimator::new(model, config);
+    let results = estimator.fit(&dataset).expect("Estimation failed");
+
+    assert_eq!(results.parameter_names, vec!["KE", "V"]);
+    assert!(results.fixed_effects.iter().all(|p| p.is_finite()));
+}
+
+#[test]
+fn test_small_convergence_window_checks_earlier() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+
+    let run = |window: usize| {
+        let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+        let mut config = EstimationConfig::default()
+            .with_iterations(80)
+            .with_burnin(2)
+            .with_convergence_window(window);
+        // Loose enough that any completed check succeeds
+        config.convergence_tolerance = 10.0;
+        let mut estimator = SaemEstimator::new(model, config);
+        estimator.fit(&dataset).expect("Estimation failed")
+    };
+
+    let small = run(5);
+    let large = run(50);
+
+    assert!(small.converged);
+    assert!(large.converged);
+    assert!(small.n_iterations < large.n_iterations);
+}
+
+#[test]
+fn test_iteration_times_recorded_per_iteration() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+
+    // SAEM stopping early at its convergence check
+    let mut config = EstimationConfig::default()
+        .with_iterations(80)
+        .with_burnin(2)
+        .with_convergence_window(5)
+        .with_timing_interval(Some(3));
+    config.convergence_tolerance = 10.0;
+    let mut estimator = SaemEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    let saem = estimator.fit(&dataset).expect("Estimation failed");
+    assert!(saem.converged && saem.n_iterations < 80);
+    assert_eq!(saem.iteration_times.len(), saem.n_iterations);
+
+    // FOCE stopping early on a loose tolerance
+    let config = EstimationConfig::default()
+        .with_foce_iterations(20)
+        .with_foce_tolerance(0.5)
+        .with_timing_interval(Some(1));
+    let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    let foce = estimator.fit(&dataset).expect("Estimation failed");
+    assert!(foce.converged && foce.n_iterations < 20);
+    assert_eq!(foce.iteration_times.len(), foce.n_iterations);
+
+    // Nothing is recorded unless asked for
+    let config = EstimationConfig::default().with_foce_iterations(2);
+    let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    assert!(estimator.fit(&dataset).unwrap().iteration_times.is_empty());
+}
+
+#[test]
+fn test_excluding_likelihood_constants_shifts_ofv_by_n_ln_2pi() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let n_observations: usize = dataset.individuals().values().map(|i| i.observations().len()).sum();
+    let shift = n_observations as f64 * (2.0 * std::f64::consts::PI).ln();
+    assert!((nmodes::estimation::objective::likelihood_constant(&dataset) - shift).abs() < 1e-9);
+
+    let fit_foce = |include: bool| {
+        let config = EstimationConfig::default()
+            .with_foce_iterations(2)
+            .with_likelihood_constants(include);
+        FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config)
+            .fit(&dataset)
+            .expect("Estimation failed")
+    };
+    let (with, without) = (fit_foce(true), fit_foce(false));
+    assert_eq!(with.fixed_effects, without.fixed_effects);
+    assert!((with.objective_function_value - without.objective_function_value - shift).abs() < 1e-6);
+
+    let fit_saem = |include: bool| {
+        let config = EstimationConfig::default()
+            .with_iterations(10)
+            .with_burnin(2)
+            .with_likelihood_constants(include);
+        SaemEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config)
+            .fit(&dataset)
+            .expect("Estimation failed")
+    };
+    let (with, without) = (fit_saem(true), fit_saem(false));
+    assert!((with.objective_function_value - without.objective_function_value - shift).abs() < 1e-6);
+}
+
+#[test]
+fn test_evaluation_counts_grow_with_iterations() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+
+    // The one-compartment model is solved analytically, so count on two
+    let run_saem = |model_type: ModelType, iterations: usize| {
+        let model = CompartmentModel::new(model_type).unwrap();
+        let config = EstimationConfig::default()
+            .with_iterations(iterations)
+            .with_burnin(1);
+        SaemEstimator::new(model, config).fit(&dataset).expect("Estimation failed")
+    };
+
+    let short = run_saem(ModelType::TwoCompartment, 3).evaluation_counts;
+    let long = run_saem(ModelType::TwoCompartment, 6).evaluation_counts;
+    assert!(short.ode_solves > 0);
+    assert!(short.derivative_evaluations > short.ode_solves);
+    assert!(long.ode_solves > short.ode_solves);
+    assert!(long.derivative_evaluations > short.derivative_evaluations);
+    assert_eq!(run_saem(ModelType::OneCompartment, 3).evaluation_counts.ode_solves, 0);
+
+    let model = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+    let config = EstimationConfig::default().with_foce_iterations(2);
+    let foce = FoceEstimator::new(model, config).fit(&dataset).expect("Estimation failed");
+    assert!(foce.evaluation_counts.ode_solves > 0);
+}
+
+#[test]
+fn test_solver_max_step_size_changes_integration_steps_in_fit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+
+    let run_foce = |max_step_size: f64| {
+        let model = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+        let solver_config = SolverConfig { max_step_size, ..SolverConfig::default() };
+        let config = EstimationConfig::default()
+            .with_foce_iterations(1)
+            .with_solver_config(solver_config.clone());
+        let results = FoceEstimator::new(model, config).fit(&dataset).expect("Estimation failed");
+        assert_eq!(results.solver_config, solver_config);
+        results.evaluation_counts
+    };
+
+    // Same ODE solves either way; quarter steps take about four times the
+    // RK4 stages
+    let coarse = run_foce(1.0);
+    let fine = run_foce(0.25);
+    assert_eq!(fine.ode_solves, coarse.ode_solves);
+    let ratio = fine.derivative_evaluations as f64 / coarse.derivative_evaluations as f64;
+    assert!(ratio > 2.5 && ratio < 4.5, "{} vs {} derivative evaluations", fine.derivative_evaluations, coarse.derivative_evaluations);
+
+    let invalid = EstimationConfig::default().with_solver_config(SolverConfig { max_step_size: 0.0, ..SolverConfig::default() });
+    assert!(invalid.validate().is_err());
+}
+
+/// KE scales linearly with body weight relative to 70 kg.
+struct WeightScaledOneCompartment;
+
+impl CompartmentModelTrait for WeightScaledOneCompartment {
+    fn n_compartments(&self) -> usize {
+        CustomOneCompartment.n_compartments()
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        CustomOneCompartment.parameter_names()
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        CustomOneCompartment.default_parameters()
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        CustomOneCompartment.derivatives(state, params)
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        CustomOneCompartment.observation_function(state, params, compartment)
+    }
+
+    fn validate_parameters(&self, params: &ModelParameters) -> Result<(), ModelError> {
+        CustomOneCompartment.validate_parameters(params)
+    }
+
+    fn individual_parameters(&self, params: &ModelParameters, covariates: &HashMap<String, f64>) -> ModelParameters {
+        let mut individual = params.clone();
+        let wt = covariates.get("WT").copied().unwrap_or(70.0);
+        individual.fixed_effects[0] += (wt / 70.0).ln();
+        individual
+    }
+}
+
+#[test]
+fn test_evid2_covariate_change_applies_from_its_time() {
+    let dataset = dataset_from_csv(
+        "evid2_covariate_test.csv",
+        "ID,TIME,DV,AMT,EVID,CMT,WT\n\
+         1,0,,100,1,1,70\n\
+         1,2,,,2,1,140\n\
+         1,4,1.0,,0,1,140\n\
+         2,0,,100,1,1,70\n\
+         2,4,1.0,,0,1,70\n",
+    );
+
+    let changed = dataset.get_individual(1).unwrap();
+    assert_eq!(changed.observations().len(), 1);
+    assert_eq!(changed.covariate_at("WT", 1.0), Some(70.0));
+    assert_eq!(changed.covariate_at("WT", 3.0), Some(140.0));
+    assert!(!dataset.get_individual(2).unwrap().has_time_varying_covariates());
+
+    let model = CompartmentModel::from_custom("wt-ke", Box::new(WeightScaledOneCompartment));
+    let params = model.default_parameters();
+    let solver = RungeKuttaSolver::new();
+    let config = SolverConfig { max_step_size: 0.1, ..SolverConfig::default() };
+
+    let changed_pred = predict_individual(&model, &solver, changed, &params, &config).unwrap();
+    let constant_pred = predict_individual(
+        &model, &solver, dataset.get_individual(2).unwrap(), &params, &config,
+    ).unwrap();
+
+    // KE doubles from t = 2 onwards for the first individual only
+    let expected_changed = 10.0 * (-0.3_f64 * 2.0 - 0.6 * 2.0).exp();
+    let expected_constant = 10.0 * (-0.3_f64 * 4.0).exp();
+    assert!((changed_pred[0] - expected_changed).abs() / expected_changed < 1e-3);
+    assert!((constant_pred[0] - expected_constant).abs() / expected_constant < 1e-3);
+}
+
+#[test]
+fn test_linear_covariate_interpolated_between_loaded_measurements() {
+    // CRCL is flat at 100 until t = 24, then falls to 40 at t = 48
+    let mut dataset = dataset_from_csv(
+        "linear_covariate_test.csv",
+        "ID,TIME,DV,AMT,EVID,CMT,CRCL\n\
+         1,0,,100,1,1,100\n\
+         1,12,5.0,,0,1,\n\
+         1,24,,,2,1,100\n\
+         1,36,2.0,,0,1,\n\
+         1,48,,,2,1,40\n",
+    );
+
+    let individual = dataset.get_individual(1).unwrap();
+    assert_eq!(individual.covariate_at("CRCL", 36.0), Some(100.0));
+    assert_eq!(individual.covariate_change_times(), vec![48.0]);
+
+    dataset.set_covariate_interpolation("CRCL", CovariateInterpolation::Linear);
+    let individual = dataset.get_individual(1).unwrap();
+    assert_eq!(individual.covariate_at("CRCL", 12.0), Some(100.0));
+    assert_eq!(individual.covariate_at("CRCL", 36.0), Some(70.0));
+    assert_eq!(individual.covariate_change_times(), vec![36.0, 48.0]);
+}
+
+#[test]
+fn test_profiles_flag_writes_dense_grid() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_profiles_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_nmodes"))
+        .args(["-d", "examples/example_dataset.csv", "-i", "120", "-b", "10", "-c", "1", "--profiles", "-o"])
+        .arg(&output_dir)
+        .status()
+        .expect("Failed to run nmodes");
+    // A run this short may not converge, which exits with 2 but still
+    // writes every output
+    assert!(matches!(status.code(), Some(0) | Some(2)), "status {:?}", status);
+
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+    let mut reader = csv::Reader::from_path(output_dir.join("best_model_profiles.csv")).unwrap();
+    let n_rows = reader.records().count();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    // 200 grid points per individual
+    assert_eq!(n_rows, 200 * dataset.n_individuals());
+}
+
+#[test]
+fn test_predict_grid_writes_row_per_grid_time_per_individual() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_predict_grid_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_nmodes"))
+        .args(["-d", "examples/example_dataset.csv", "-i", "120", "-b", "10", "-c", "1"])
+        .args(["--predict-grid", "0,0.5,1,2,4,8,24", "-o"])
+        .arg(&output_dir)
+        .status()
+        .expect("Failed to run nmodes");
+    assert!(matches!(status.code(), Some(0) | Some(2)), "status {:?}", status);
+
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+    let mut reader = csv::Reader::from_path(output_dir.join("best_model_grid_predictions.csv")).unwrap();
+    let mut times_by_id: HashMap<String, Vec<f64>> = HashMap::new();
+    for record in reader.records() {
+        let record = record.unwrap();
+        assert!(record[2].parse::<f64>().unwrap().is_finite() && record[3].parse::<f64>().unwrap().is_finite());
+        times_by_id.entry(record[0].to_string()).or_default().push(record[1].parse().unwrap());
+    }
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    // Exactly the grid for each individual, not their observation times
+    assert_eq!(times_by_id.len(), dataset.n_individuals());
+    for times in times_by_id.values() {
+        assert_eq!(times, &vec![0.0, 0.5, 1.0, 2.0, 4.0, 8.0, 24.0]);
+    }
+}
+
+#[test]
+fn test_grid_predictions_use_the_fit_solver_config() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+    let model = CompartmentModel::new(ModelType::TwoCompartment).unwrap();
+    let fixed_effects = model.default_parameters().fixed_effects;
+    let times = [0.5, 1.0, 3.0, 7.0];
+
+    // A coarse numerical solve differs visibly from the default one
+    let coarse = SolverConfig { max_step_size: 3.0, analytic_solutions: false, ..SolverConfig::default() };
+    let output_dir = std::env::temp_dir().join("nmodes_grid_solver_config_test");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    nmodes::output::save_grid_predictions(&output_dir, &dataset, &model, &fixed_effects, &HashMap::new(), &coarse, &times).unwrap();
+    let mut reader = csv::Reader::from_path(output_dir.join("best_model_grid_predictions.csv")).unwrap();
+    let written: Vec<f64> = reader.records().take(times.len()).map(|record| record.unwrap()[2].parse().unwrap()).collect();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    let mut ids: Vec<i32> = dataset.individuals().keys().copied().collect();
+    ids.sort_unstable();
+    let individual = &dataset.individuals()[&ids[0]];
+    let params = model.default_parameters();
+    let solver = RungeKuttaSolver::new();
+    let expected = nmodes::prediction::predict_at_times(&model, &solver, individual, &params, &times, &coarse).unwrap();
+    let default = nmodes::prediction::predict_at_times(&model, &solver, individual, &params, &times, &SolverConfig::default()).unwrap();
+    assert_eq!(written, expected);
+    assert_ne!(written, default);
+}
+
+#[test]
+fn test_shrinkage_csv_has_row_per_parameter() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let config = EstimationConfig::default()
+        .with_iterations(120)
+        .with_burnin(10)
+        .with_statistics_window(50);
+    let mut estimator = SaemEstimator::new(model, config);
+    let results = estimator.fit(&dataset).expect("Estimation failed");
+    let diagnostics = nmodes::diagnostics::generate_diagnostics(&dataset, &results, estimator.model()).unwrap();
+
+    let output_dir = std::env::temp_dir().join("nmodes_shrinkage_test");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    nmodes::output::save_results(&output_dir, &results, &diagnostics, &dataset, estimator.model(), NumberFormat::Full).unwrap();
+
+    let mut reader = csv::Reader::from_path(output_dir.join("shrinkage.csv")).unwrap();
+    let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    assert_eq!(rows.len(), results.parameter_names.len());
+    for (row, name) in rows.iter().zip(&results.parameter_names) {
+        assert_eq!(&row[0], name);
+        for column in [3, 4] {
+            let value: f64 = row[column].parse().expect("shrinkage should be defined");
+            assert!((0.0..=100.0).contains(&value), "{} out of range", value);
+        }
+    }
+}
+
+#[test]
+fn test_run_pipeline_end_to_end() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_pipeline_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    let config = PipelineConfig {
+        chains: 1,
+        ..PipelineConfig::new(&dataset_path, &output_dir)
+            .with_models(vec![ModelType::OneCompartment, ModelType::TwoCompartment])
+            .with_iterations(120)
+            .with_burnin(10)
+    };
+    let results = run_pipeline(config).expect("Pipeline failed");
+
+    assert_eq!(results.len(), 2);
+    for result in &results {
+        assert_eq!(result.fixed_effects.len(), result.parameter_names.len());
+        assert!(result.output_dir.join("parameter_estimates.json").exists());
+    }
+    // Two analyses always produce the comparison outputs
+    assert!(output_dir.join("model_comparison_report.txt").exists());
+    let mut reader = csv::Reader::from_path(output_dir.join("model_comparison.csv")).unwrap();
+    assert_eq!(reader.records().count(), 2);
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_pipeline_excludes_and_reports_individual_without_observations() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    // The example data plus a subject who was dosed but never sampled
+    let output_dir = std::env::temp_dir().join("nmodes_exclude_invalid_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let data_path = output_dir.join("data.csv");
+    let content = std::fs::read_to_string(&dataset_path).unwrap();
+    std::fs::write(&data_path, format!("{}\n999,0.0,,100.0,1,1,\n", content.trim_end())).unwrap();
+
+    let mut config = PipelineConfig::new(&data_path, &output_dir)
+        .with_iterations(60)
+        .with_burnin(10);
+    config.chains = 1;
+    config.validation.prune_invalid_individuals = true;
+    let results = run_pipeline(config).expect("Pipeline failed");
+
+    let original = Dataset::from_csv(&dataset_path).unwrap();
+    assert_eq!(results[0].individual_parameters.len(), original.n_individuals());
+    assert!(!results[0].individual_parameters.contains_key(&999));
+    let manifest: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(output_dir.join("manifest.json")).unwrap()
+    ).unwrap();
+    assert_eq!(manifest["excluded_individuals"], serde_json::json!(["999"]));
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_fixed_omega_unchanged_after_fit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let initial = model.default_parameters();
+    let config = EstimationConfig::default()
+        .with_iterations(30)
+        .with_burnin(5)
+        .with_statistics_window(10)
+        .with_fixed_omega(true);
+    let mut estimator = SaemEstimator::new(model, config);
+    let results = estimator.fit(&dataset).expect("Estimation failed");
+
+    assert_eq!(results.random_effects_variance, initial.random_effects_variance);
+    // Thetas are still estimated
+    assert_ne!(results.fixed_effects, initial.fixed_effects);
+}
+
+#[test]
+fn test_fixed_residual_variance_unchanged_by_fit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let known_variance = 0.25;
+
+    let config = EstimationConfig::default()
+        .with_iterations(30)
+        .with_burnin(5)
+        .with_statistics_window(10)
+        .with_initial_residual_variance(Some(known_variance))
+        .with_fixed_residual_variance(true);
+    let mut estimator = SaemEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    let saem = estimator.fit(&dataset).expect("Estimation failed");
+    assert_eq!(saem.residual_variance, known_variance);
+
+    let config = EstimationConfig::default()
+        .with_foce_iterations(3)
+        .with_initial_residual_variance(Some(known_variance))
+        .with_fixed_residual_variance(true);
+    let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    let foce = estimator.fit(&dataset).expect("Estimation failed");
+    assert_eq!(foce.residual_variance, known_variance);
+    // Thetas are still estimated
+    let initial = CompartmentModel::new(ModelType::OneCompartment).unwrap().default_parameters();
+    assert_ne!(foce.fixed_effects, initial.fixed_effects);
+
+    // Without the flag the variance moves away from its starting value
+    let config = EstimationConfig::default()
+        .with_foce_iterations(3)
+        .with_initial_residual_variance(Some(known_variance));
+    let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    assert_ne!(estimator.fit(&dataset).unwrap().residual_variance, known_variance);
+}
+
+#[test]
+fn test_saem_and_foce_report_ofv_on_same_scale() {
+    // Noise-free one-compartment data for a handful of similar individuals
+    let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+    for id in 1..=5 {
+        let (cl, v) = (1.0 + 0.05 * id as f64, 3.0);
+        csv.push_str(&format!("{},0,,100,1,1\n", id));
+        for t in [0.5_f64, 1.0, 2.0, 4.0, 8.0] {
+            csv.push_str(&format!("{},{},{},,0,1\n", id, t, 100.0 / v * (-cl / v * t).exp()));
+        }
+    }
+    let dataset = dataset_from_csv("nmodes_ofv_scale_test.csv", &csv);
+
+    let config = EstimationConfig::default()
+        .with_iterations(40)
+        .with_burnin(10)
+        .with_statistics_window(20)
+        .with_foce_iterations(3);
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let saem = SaemEstimator::new(model, config.clone()).fit(&dataset).unwrap();
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let foce = FoceEstimator::new(model, config).fit(&dataset).unwrap();
+
+    // Both report -2 x the marginal log-likelihood on the shared convention,
+    // evaluated at their own estimates
+    let solver = RungeKuttaSolver::new();
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let canonical = |parameters: &ModelParameters, individual_parameters: &HashMap<i32, Vec<f64>>| {
+        let etas = individual_parameters.iter()
+            .map(|(&id, params)| (id, params.iter().zip(&parameters.fixed_effects).map(|(p, theta)| p - theta).collect()))
+            .collect();
+        objective_function_value(&model, &solver, &SolverConfig::default(), &dataset, parameters, &etas).unwrap()
+    };
+    let saem_parameters = FittedModel::from_saem(&model, &saem).parameters;
+    let foce_parameters = FittedModel::from_foce(&model, &foce).parameters;
+    for (ofv, log_likelihood, expected) in [
+        (saem.objective_function_value, saem.final_log_likelihood, canonical(&saem_parameters, &saem.individual_parameters)),
+        (foce.objective_function_value, foce.final_log_likelihood, canonical(&foce_parameters, &foce.individual_parameters)),
+    ] {
+        assert!(ofv.is_finite());
+        assert_eq!(ofv, -2.0 * log_likelihood);
+        assert!((ofv - expected).abs() < 1e-6 * expected.abs().max(1.0), "{} vs {}", ofv, expected);
+    }
+}
+
+#[test]
+fn test_bounded_parameter_never_exceeds_upper_limit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let upper = 0.05_f64;
+    let config = EstimationConfig::default()
+        .with_iterations(40)
+        .with_burnin(10)
+        .with_statistics_window(20)
+        .with_foce_iterations(3)
+        .with_parameter_bounds("CL", 0.01, upper);
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let saem = SaemEstimator::new(model, config.clone()).fit(&dataset).unwrap();
+    assert!(saem.parameter_trajectory.iter().all(|thetas| thetas[0] <= upper.ln()));
+    assert!(saem.individual_parameters.values().all(|params| params[0] <= upper.ln()));
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let foce = FoceEstimator::new(model, config).fit(&dataset).unwrap();
+    assert!(foce.fixed_effects[0] <= upper.ln());
+    assert!(foce.individual_parameters.values().all(|params| params[0] <= upper.ln()));
+    // FOCE's M-step is clamped, so its estimate finishes on the bound. SAEM's
+    // typical value averages individual values held inside it and stays below
+    assert!(foce.parameters_at_bounds.contains(&"CL".to_string()), "{:?}", foce.parameters_at_bounds);
+}
+
+#[test]
+fn test_string_ids_preserved_in_output_files() {
+    let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+    for (label, cl) in [("SUBJ-001", 1.0_f64), ("SUBJ-002", 1.5), ("SUBJ-003", 0.8)] {
+        csv.push_str(&format!("{},0,,100,1,1\n", label));
+        for t in [0.5_f64, 1.0, 2.0, 4.0, 8.0] {
+            csv.push_str(&format!("{},{},{},,0,1\n", label, t, 10.0 * (-cl / 10.0 * t).exp()));
+        }
+    }
+    let dataset_path = std::env::temp_dir().join("nmodes_string_ids.csv");
+    std::fs::write(&dataset_path, csv).unwrap();
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    assert_eq!(dataset.n_individuals(), 3);
+    let mut labels: Vec<String> = dataset.individuals().keys().map(|&id| dataset.id_label(id)).collect();
+    labels.sort();
+    assert_eq!(labels, vec!["SUBJ-001", "SUBJ-002", "SUBJ-003"]);
+
+    let output_dir = std::env::temp_dir().join("nmodes_string_ids_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    let config = PipelineConfig {
+        chains: 1,
+        ..PipelineConfig::new(&dataset_path, &output_dir)
+            .with_methods(vec![EstimationMethod::Saem, EstimationMethod::Foce])
+            .with_iterations(40)
+            .with_burnin(5)
+    };
+    let results = run_pipeline(config).expect("Pipeline failed");
+
+    for result in &results {
+        let file = match result.estimation_method {
+            EstimationMethod::Foce => "individual_ofv.csv",
+            _ => "predictions.csv",
+        };
+        let mut reader = csv::Reader::from_path(result.output_dir.join(file)).unwrap();
+        let mut ids: Vec<String> = reader.records().map(|r| r.unwrap()[0].to_string()).collect();
+        ids.dedup();
+        ids.sort();
+        assert_eq!(ids, vec!["SUBJ-001", "SUBJ-002", "SUBJ-003"], "{}", file);
+    }
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_ids_parsing_to_the_same_integer_kept_apart() {
+    let dataset = dataset_from_csv(
+        "nmodes_colliding_ids.csv",
+        "ID,TIME,DV,AMT,EVID\n\
+         01,0,,100,1\n\
+         01,1,5.0,,0\n\
+         1,0,,200,1\n\
+         1,1,9.0,,0\n\
+         2,0,,100,1\n\
+         2,1,4.0,,0\n",
+    );
+    assert_eq!(dataset.n_individuals(), 3);
+    let first = dataset.id_for_label("01").unwrap();
+    let second = dataset.id_for_label("1").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(dataset.id_label(first), "01");
+    assert_eq!(dataset.get_individual(second).unwrap().dosing_records()[0].amount, 200.0);
+    assert_eq!(dataset.id_for_label("3"), None);
+
+    // Integer IDs without collisions are used as written
+    let dataset = dataset_from_csv("nmodes_integer_ids.csv", "ID,TIME,DV,AMT,EVID\n7,0,,100,1\n7,1,5.0,,0\n");
+    assert_eq!(dataset.id_for_label("7"), Some(7));
+}
+
+#[test]
+fn test_mcmc_samples_csv_has_row_per_post_burnin_draw() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let (iterations, burnin, samples_per_iteration) = (12, 4, 3);
+    let config = EstimationConfig {
+        mcmc_samples_per_iteration: samples_per_iteration,
+        ..EstimationConfig::default()
+            .with_iterations(iterations)
+            .with_burnin(burnin)
+            .with_saved_mcmc_samples(true)
+    };
+    let results = SaemEstimator::new(model, config).fit(&dataset).expect("Estimation failed");
+    assert_eq!(results.n_iterations, iterations);
+
+    let output_dir = std::env::temp_dir().join("nmodes_mcmc_samples_test");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    nmodes::output::save_mcmc_samples_csv(&output_dir, &results, &dataset).unwrap();
+
+    let mut reader = csv::Reader::from_path(output_dir.join("mcmc_samples.csv")).unwrap();
+    assert_eq!(reader.headers().unwrap(), vec!["ITERATION", "ID", "SAMPLE", "ETA_CL", "ETA_V"]);
+    assert_eq!(
+        reader.records().count(),
+        (iterations - burnin) * dataset.n_individuals() * samples_per_iteration,
+    );
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_fit_restricted_to_subset_of_individuals() {
+    let dataset_path = PathBuf::from("examples/one_compartment_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let mut ids: Vec<i32> = dataset.individuals().keys().copied().collect();
+    ids.sort_unstable();
+    let subset = dataset.subset(&ids[..2]);
+    assert_eq!(subset.n_individuals(), 2);
+    assert!(subset.covariate_names().contains(&"WEIGHT".to_string()));
+    assert_eq!(subset.covariate_names(), dataset.covariate_names());
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let config = EstimationConfig::default().with_iterations(10).with_burnin(2);
+    let saem = SaemEstimator::new(model, config).fit(&subset).expect("Estimation failed");
+    let mut fitted: Vec<i32> = saem.individual_parameters.keys().copied().collect();
+    fitted.sort_unstable();
+    assert_eq!(fitted, ids[..2]);
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let config = EstimationConfig::default().with_foce_iterations(2);
+    let foce = FoceEstimator::new(model, config).fit(&subset).expect("Estimation failed");
+    assert_eq!(foce.individual_parameters.len(), 2);
+    assert!(ids[..2].iter().all(|id| foce.individual_parameters.contains_key(id)));
+}
+
+/// `CustomOneCompartment` with an extra parameter the predictions ignore.
+struct OverParameterizedOneCompartment;
+
+impl CompartmentModelTrait for OverParameterizedOneCompartment {
+    fn n_compartments(&self) -> usize {
+        CustomOneCompartment.n_compartments()
+    }
+
+    fn parameter_names(&self) -> Vec<String> {
+        vec!["KE".to_string(), "V".to_string(), "KX".to_string()]
+    }
+
+    fn default_parameters(&self) -> ModelParameters {
+        let base = CustomOneCompartment.default_parameters();
+        let mut params = ModelParameters::new(3, self.parameter_names());
+        params.fixed_effects = vec![base.fixed_effects[0], base.fixed_effects[1], 0.0];
+        params.random_effects_variance = vec![
+            vec![0.09, 0.0, 0.0],
+            vec![0.0, 0.04, 0.0],
+            vec![0.0, 0.0, 0.04],
+        ];
+        params.residual_variance = base.residual_variance;
+        params
+    }
+
+    fn derivatives(&self, state: &ModelState, params: &ModelParameters) -> DVector<f64> {
+        CustomOneCompartment.derivatives(state, params)
+    }
+
+    fn observation_function(&self, state: &ModelState, params: &ModelParameters, compartment: usize) -> f64 {
+        CustomOneCompartment.observation_function(state, params, compartment)
+    }
+
+    fn validate_parameters(&self, _params: &ModelParameters) -> Result<(), ModelError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_over_parameterized_model_flags_weak_parameter() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::from_custom("over-parameterized", Box::new(OverParameterizedOneCompartment));
+    let config = EstimationConfig::default().with_foce_iterations(5);
+    let results = FoceEstimator::new(model, config).fit(&dataset).expect("Estimation failed");
+
+    let flagged: Vec<String> = poorly_identified_parameters(&results.parameter_names, &results.rse_percent())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(flagged, vec!["KX".to_string()], "%RSE {:?}", results.rse_percent());
+}
+
+#[test]
+fn test_wide_format_dv_columns_expand_to_typed_observations() {
+    let csv = "\
+ID,TIME,DV1,DV2,AMT,EVID,CMT,WEIGHT
+1,0,,,100,1,1,70
+1,1,8.5,12.0,,0,,
+1,2,7.1,,,0,,
+1,4,5.0,9.5,,0,,
+2,0,,,100,1,1,80
+2,1,9.0,14.0,,0,,
+2,4,,10.0,,0,,
+";
+    let dataset_path = std::env::temp_dir().join("nmodes_wide_dv.csv");
+    std::fs::write(&dataset_path, csv).unwrap();
+
+    let dv_columns = [
+        DvColumn::new("DV1", 1, ObservationType::Concentration),
+        DvColumn::new("DV2", 2, ObservationType::Effect),
+    ];
+    let dataset = Dataset::from_wide_csv(&dataset_path, &dv_columns).expect("Failed to load wide dataset");
+    assert_eq!(dataset.covariate_names(), &["WEIGHT".to_string()]);
+
+    let count = |id: i32, concentration: bool| {
+        dataset.get_individual(id).unwrap().observations().iter()
+            .filter(|obs| if concentration {
+                matches!(obs.observation_type, ObservationType::Concentration) && obs.compartment == 1
+            } else {
+                matches!(obs.observation_type, ObservationType::Effect) && obs.compartment == 2
+            })
+            .count()
+    };
+    assert_eq!((count(1, true), count(1, false)), (3, 2));
+    assert_eq!((count(2, true), count(2, false)), (1, 2));
+
+    assert!(Dataset::from_wide_csv(&dataset_path, &[]).is_err());
+    assert!(Dataset::from_csv(&dataset_path).is_err(), "a wide file has no DV column");
+}
+
+#[test]
+fn test_repeated_runs_write_identical_predictions() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let run = |name: &str| {
+        let output_dir = std::env::temp_dir().join(name);
+        std::fs::remove_dir_all(&output_dir).ok();
+        let config = PipelineConfig {
+            chains: 1,
+            ..PipelineConfig::new(&dataset_path, &output_dir)
+                .with_iterations(30)
+                .with_burnin(5)
+        };
+        let results = run_pipeline(config).expect("Pipeline failed");
+        let predictions = std::fs::read(results[0].output_dir.join("predictions.csv")).unwrap();
+        std::fs::remove_dir_all(&output_dir).ok();
+        predictions
+    };
+
+    assert_eq!(run("nmodes_reproducible_a"), run("nmodes_reproducible_b"));
+}
+
+#[test]
+fn test_individual_predictions_match_predictions_csv() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let config = EstimationConfig::default().with_iterations(30).with_burnin(5).with_statistics_window(10);
+    let mut estimator = SaemEstimator::new(model, config);
+    let results = estimator.fit(&dataset).expect("SAEM failed");
+
+    let output_dir = std::env::temp_dir().join("nmodes_individual_predictions_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let diagnostics = nmodes::diagnostics::generate_diagnostics(&dataset, &results, estimator.model()).unwrap();
+    nmodes::output::save_results(&output_dir, &results, &diagnostics, &dataset, estimator.model(), NumberFormat::Full).unwrap();
+
+    let predictions = nmodes::prediction::individual_predictions(&results, &dataset, estimator.model()).unwrap();
+    assert_eq!(predictions.len(), dataset.n_individuals());
+
+    let mut reader = csv::Reader::from_path(output_dir.join("predictions.csv")).unwrap();
+    let mut rows_by_id: HashMap<i32, Vec<(f64, f64, f64)>> = HashMap::new();
+    for record in reader.records() {
+        let record = record.unwrap();
+        let value = |k: usize| record[k].parse::<f64>().unwrap();
+        rows_by_id.entry(record[0].parse().unwrap()).or_default().push((value(1), value(3), value(4)));
+    }
+    assert_eq!(rows_by_id, predictions);
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_above_uloq_observations_censored_in_fit() {
+    // Early concentrations above a ULOQ of 20 are reported at the limit
+    let uloq = 20.0;
+    let write = |censored: bool| {
+        let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT,CENS\n");
+        for id in 1..=6 {
+            let (cl, v) = (1.0 + 0.04 * (id as f64 - 3.5), 3.0);
+            csv.push_str(&format!("{},0,,100,1,1,\n", id));
+            for t in [0.25_f64, 0.5, 1.0, 2.0, 4.0, 8.0, 12.0] {
+                let conc = 100.0 / v * (-cl / v * t).exp();
+                if conc > uloq {
+                    csv.push_str(&format!("{},{},{},,0,1,{}\n", id, t, uloq, if censored { "-1" } else { "" }));
+                } else {
+                    csv.push_str(&format!("{},{},{},,0,1,\n", id, t, conc));
+                }
+            }
+        }
+        let temp_file = std::env::temp_dir().join(format!("uloq_test_{}.csv", censored));
+        std::fs::write(&temp_file, csv).unwrap();
+        let dataset = Dataset::from_csv(&temp_file).expect("Failed to load dataset");
+        std::fs::remove_file(&temp_file).ok();
+        dataset
+    };
+    let censored = write(true);
+    let naive = write(false);
+
+    let high = &censored.get_individual(1).unwrap().observations()[0];
+    assert_eq!(high.censoring, Censoring::AboveLimit);
+    assert_eq!(high.value, uloq);
+    assert!(!censored.get_individual(1).unwrap().observations()[3].is_censored());
+
+    let fit_v = |dataset: &Dataset| {
+        // Started away from the true CL = 1, V = 3
+        let config = EstimationConfig::default()
+            .with_foce_iterations(30)
+            .with_initial_fixed_effects(Some(vec![0.5_f64.ln(), 6.0_f64.ln()]));
+        let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+        let results = estimator.fit(dataset).expect("Estimation failed");
+        assert!(results.objective_function_value.is_finite());
+        results.fixed_effects[1].exp()
+    };
+    // Taking the limit at face value flattens the early profile and inflates
+    // V; the censored likelihood only asks the prediction to exceed it
+    let (v_censored, v_naive) = (fit_v(&censored), fit_v(&naive));
+    assert!((v_censored - 3.0).abs() < (v_naive - 3.0).abs(), "censored V {} vs naive V {}", v_censored, v_naive);
+}
+
+#[test]
+fn test_small_parameter_round_trips_through_output() {
+    let output_dir = std::env::temp_dir().join("nmodes_precision_test");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    // CL of about 1e-4 with V = 1, so KE = CL
+    let fixed_effects = vec![1.2345678901234e-4_f64.ln(), 0.0];
+    let ke = nmodes::models::secondary_parameters(model.model_type(), &fixed_effects).unwrap()[0].value;
+
+    let read_ke = |format: NumberFormat| {
+        nmodes::output::save_secondary_parameters(&output_dir, &model, &fixed_effects, None, format).unwrap();
+        let mut reader = csv::Reader::from_path(output_dir.join("secondary_parameters.csv")).unwrap();
+        let row = reader.records().next().unwrap().unwrap();
+        assert_eq!(&row[0], "KE");
+        row[1].to_string()
+    };
+    assert_eq!(read_ke(NumberFormat::Full).parse::<f64>().unwrap(), ke);
+    assert_eq!(read_ke(NumberFormat::Significant(3)), "0.000123");
+
+    // JSON keeps every digit whatever the CSV format
+    let mut parameters = model.default_parameters();
+    parameters.fixed_effects = fixed_effects.clone();
+    let path = output_dir.join("fitted_model.json");
+    FittedModel::new(ModelType::OneCompartment, parameters, nmodes::estimation::ErrorModel::Additive)
+        .save(&path)
+        .unwrap();
+    assert_eq!(FittedModel::load(&path).unwrap().parameters.fixed_effects, fixed_effects);
+
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_convergence_report_written_after_fit() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let dataset = Dataset::from_csv(&dataset_path).expect("Failed to load dataset");
+    let config = EstimationConfig::default().with_foce_iterations(3);
+    let mut estimator = FoceEstimator::new(CompartmentModel::new(ModelType::OneCompartment).unwrap(), config);
+    let results = estimator.fit(&dataset).expect("Estimation failed");
+
+    let output_dir = std::env::temp_dir().join("nmodes_convergence_report_test");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let report = nmodes::output::convergence::ConvergenceReport::from_foce(&results, "FOCE");
+    nmodes::output::convergence::save_convergence_report(&output_dir, &report).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(output_dir.join("converged.json")).unwrap()).unwrap();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    assert_eq!(json["estimation_method"], "FOCE");
+    assert_eq!(json["converged"], results.converged);
+    assert_eq!(json["final_iteration"], results.n_iterations);
+    assert_eq!(json["objective_function_value"].as_f64().unwrap(), results.objective_function_value);
+    assert_eq!(json["gradient_norm"].as_f64(), Some(results.gradient_norm).filter(|norm| norm.is_finite()));
+    assert!(json["parameters_at_bounds"].is_array());
+    assert!(json["poorly_identified_parameters"].is_array());
+}
+
+#[test]
+fn test_dry_run_plans_without_writing_outputs() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_dry_run_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    let config = PipelineConfig::new(&dataset_path, &output_dir)
+        .with_models(vec![ModelType::OneCompartment, ModelType::TwoCompartment])
+        .with_methods(vec![EstimationMethod::Saem, EstimationMethod::Foce]);
+
+    let plan = nmodes::pipeline::plan_pipeline(&config).expect("Dry run failed");
+
+    assert!(!output_dir.exists());
+    assert_eq!(plan.analyses.len(), 4);
+    assert_eq!(plan.analyses[1], (ModelType::OneCompartment, EstimationMethod::Foce));
+    assert!(plan.dataset.n_individuals > 0 && plan.dataset.n_observations > 0);
+    assert!(plan.to_string().contains("Planned analyses: 4"));
+}
+
+#[test]
+fn test_observation_iterators_cover_every_observation() {
+    let dataset = Dataset::from_csv("examples/example_dataset.csv").unwrap();
+    let observations: Vec<(i32, f64)> = dataset.iter_observations().map(|(id, obs)| (id, obs.time)).collect();
+    assert_eq!(observations.len(), dataset.n_observations());
+    // Ascending ID, then time within an individual
+    assert!(observations.windows(2).all(|pair| pair[0].0 < pair[1].0 || (pair[0].0 == pair[1].0 && pair[0].1 <= pair[1].1)));
+
+    // Paired with one prediction per observation; individuals without
+    // predictions are skipped
+    let first_id = *dataset.individuals().keys().next().unwrap();
+    let predictions: HashMap<i32, Vec<f64>> = dataset.individuals().iter()
+        .filter(|(&id, _)| id != first_id)
+        .map(|(&id, individual)| (id, individual.observations().iter().map(|obs| 2.0 * obs.value).collect()))
+        .collect();
+    let paired: Vec<_> = dataset.iter_observations_with(&predictions).collect();
+    assert_eq!(paired.len(), dataset.n_observations() - dataset.individuals()[&first_id].n_observations());
+    assert!(paired.iter().all(|&(id, obs, &prediction)| id != first_id && prediction == 2.0 * obs.value));
+}
+
+#[test]
+fn test_outputs_regenerated_from_saved_results_match_original_run() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_from_results_test");
+    let regenerated_dir = std::env::temp_dir().join("nmodes_from_results_regenerated");
+    std::fs::remove_dir_all(&output_dir).ok();
+    std::fs::remove_dir_all(&regenerated_dir).ok();
+
+    let analyses = [
+        (EstimationMethod::Saem, "parameter_estimates.json", "predictions.csv"),
+        (EstimationMethod::Foce, "foce_results.json", "foce_predictions.csv"),
+    ];
+    for (method, results_file, predictions_file) in analyses {
+        let config = PipelineConfig {
+            chains: 1,
+            ..PipelineConfig::new(&dataset_path, &output_dir)
+                .with_methods(vec![method.clone()])
+                .with_iterations(30)
+                .with_burnin(5)
+        };
+        let original = run_pipeline(config).expect("Pipeline failed").remove(0);
+
+        let config = PipelineConfig::new(&dataset_path, &regenerated_dir).with_methods(vec![method.clone()]);
+        let regenerated = nmodes::pipeline::regenerate_outputs(&config, &original.output_dir.join(results_file))
+            .expect("Regeneration failed");
+
+        assert_eq!(regenerated.objective_function_value, original.objective_function_value);
+        assert_eq!(regenerated.aic, original.aic);
+        for file in ["diagnostics.json", predictions_file] {
+            assert_eq!(
+                std::fs::read_to_string(regenerated.output_dir.join(file)).unwrap(),
+                std::fs::read_to_string(original.output_dir.join(file)).unwrap(),
+                "{} differs for {}", file, method
+            );
+        }
+    }
+
+    // The saved results must belong to the configured model
+    let config = PipelineConfig::new(&dataset_path, &regenerated_dir).with_models(vec![ModelType::TwoCompartment]);
+    let saem_results = output_dir.join("one-compartment_SAEM").join("parameter_estimates.json");
+    assert!(nmodes::pipeline::regenerate_outputs(&config, &saem_results).is_err());
+
+    std::fs::remove_dir_all(&output_dir).ok();
+    std::fs::remove_dir_all(&regenerated_dir).ok();
+}
+
+#[test]
+fn test_summary_reports_and_json_carry_parameter_units() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+
+    let output_dir = std::env::temp_dir().join("nmodes_parameter_units_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    let config = PipelineConfig {
+        chains: 1,
+        ..PipelineConfig::new(&dataset_path, &output_dir)
+            .with_methods(vec![EstimationMethod::Saem, EstimationMethod::Foce])
+            .with_iterations(30)
+            .with_burnin(5)
+    };
+    let results = run_pipeline(config).expect("Pipeline failed");
+    assert_eq!(results.len(), 2);
+
+    let saem_dir = output_dir.join("one-compartment_SAEM");
+    let report = std::fs::read_to_string(saem_dir.join("summary_report.txt")).unwrap();
+    let row = |report: &str, name: &str| report.lines().find(|line| line.starts_with(&format!("{:<10} ", name))).unwrap().to_string();
+    assert!(report.contains("Unit"));
+    assert!(row(&report, "CL").contains(" L/h "), "{}", report);
+    assert!(row(&report, "V").contains(" L "), "{}", report);
+
+    let estimates: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(saem_dir.join("parameter_estimates.json")).unwrap(),
+    ).unwrap();
+    assert_eq!(estimates["parameter_units"], serde_json::json!(["L/h", "L"]));
+
+    // Estimates in those units, not log-scale THETAs
+    let estimate = |report: &str| row(report, "CL").split_whitespace().nth(2).unwrap().parse::<f64>().unwrap();
+    let saem = results.iter().find(|result| result.estimation_method == EstimationMethod::Saem).unwrap();
+    assert!((estimate(&report) / saem.fixed_effects[0].exp() - 1.0).abs() < 1e-3, "{}", report);
+
+    let report = std::fs::read_to_string(output_dir.join("one-compartment_FOCE").join("foce_summary_report.txt")).unwrap();
+    assert!(row(&report, "CL").contains(" L/h "), "{}", report);
+    let foce = results.iter().find(|result| result.estimation_method == EstimationMethod::Foce).unwrap();
+    assert!((estimate(&report) / foce.fixed_effects[0].exp() - 1.0).abs() < 1e-3, "{}", report);
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_spent_time_budget_returns_current_estimates_unconverged() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+
+    // Spent before the first iteration ends, so each fit stops after one
+    let config = EstimationConfig::default()
+        .with_iterations(500)
+        .with_burnin(100)
+        .with_foce_iterations(50)
+        .with_time_budget(Some(std::time::Duration::from_nanos(1)));
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let saem = SaemEstimator::new(model, config.clone()).fit(&dataset).unwrap();
+    assert!(saem.time_budget_exceeded && !saem.converged);
+    assert_eq!(saem.n_iterations, 1);
+    assert!(saem.fixed_effects.iter().all(|theta| theta.is_finite()));
+    assert!(saem.objective_function_value.is_finite());
+    assert_eq!(saem.individual_parameters.len(), dataset.n_individuals());
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let foce = FoceEstimator::new(model, config).fit(&dataset).unwrap();
+    assert!(foce.time_budget_exceeded && !foce.converged);
+    assert_eq!(foce.n_iterations, 1);
+    assert!(foce.fixed_effects.iter().all(|theta| theta.is_finite()));
+    assert!(foce.objective_function_value.is_finite());
+}
+
+#[test]
+fn test_single_individual_fit_keeps_initial_omega_and_finite_estimates() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).unwrap().subset(&[1]);
+    assert_eq!(dataset.n_individuals(), 1);
+
+    let config = EstimationConfig::default()
+        .with_iterations(60)
+        .with_burnin(20)
+        .with_foce_iterations(10);
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let initial_omega = model.default_parameters().random_effects_variance;
+
+    // One subject says nothing about the spread between subjects, so Omega
+    // stays where it started in both estimators
+    let saem = SaemEstimator::new(model, config.clone()).fit(&dataset).unwrap();
+    assert_eq!(saem.random_effects_variance, initial_omega);
+    assert!(saem.fixed_effects.iter().all(|theta| theta.is_finite()));
+    assert!(saem.residual_variance.is_finite() && saem.objective_function_value.is_finite());
+    assert!(saem.omega_statistics.iter().all(|stat| stat.estimate.is_finite() && stat.shrinkage_percent.is_none()));
+    assert!(saem.individual_parameters[&1].iter().all(|p| p.is_finite()));
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let foce = FoceEstimator::new(model, config).fit(&dataset).unwrap();
+    assert_eq!(foce.random_effects_variance, initial_omega);
+    assert!(foce.fixed_effects.iter().all(|theta| theta.is_finite()));
+    assert!(foce.objective_function_value.is_finite());
+    assert!(foce.individual_parameters[&1].iter().all(|p| p.is_finite()));
+}
+
+#[test]
+fn test_exponential_error_recovers_injected_log_scale_sd() {
+    use nmodes::estimation::ErrorModel;
+    use rand::{SeedableRng, rngs::StdRng};
+    use rand_distr::{Distribution, Normal};
+
+    // ln(DV) = ln(PRED) + ε with SD(ε) = 0.2, around individual CL and V
+    let log_sd = 0.2_f64;
+    let mut rng = StdRng::seed_from_u64(203);
+    let noise = Normal::new(0.0_f64, 1.0).unwrap();
+    let mut csv = String::from("ID,TIME,DV,AMT,EVID,CMT\n");
+    for id in 1..=20 {
+        let cl = (0.3 * noise.sample(&mut rng)).exp();
+        let v = 3.0 * (0.2 * noise.sample(&mut rng)).exp();
+        csv.push_str(&format!("{},0,,100,1,1\n", id));
+        for t in [0.5_f64, 1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 16.0] {
+            let pred = 100.0 / v * (-cl / v * t).exp();
+            csv.push_str(&format!("{},{},{},,0,1\n", id, t, pred * (log_sd * noise.sample(&mut rng)).exp()));
+        }
+    }
+    let dataset = dataset_from_csv("nmodes_exponential_error_test.csv", &csv);
+
+    let config = EstimationConfig::default()
+        .with_iterations(300)
+        .with_burnin(100)
+        .with_foce_iterations(10)
+        .with_error_model(ErrorModel::Exponential);
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let foce = FoceEstimator::new(model, config.clone()).fit(&dataset).unwrap();
+    assert_eq!(foce.error_model, ErrorModel::Exponential);
+    assert!((foce.residual_variance.sqrt() - log_sd).abs() < 0.04, "FOCE log-scale SD {}", foce.residual_variance.sqrt());
+    assert_eq!(foce.residual_components.len(), 1);
+    assert_eq!(foce.residual_components[0].name, "Exponential");
+    assert!(foce.objective_function_value.is_finite());
+
+    // SAEM samples and updates under the same log-scale variance
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let saem = SaemEstimator::new(model, config).fit(&dataset).unwrap();
+    assert_eq!(saem.error_model, ErrorModel::Exponential);
+    assert!((saem.residual_variance.sqrt() - log_sd).abs() < 0.04, "SAEM log-scale SD {}", saem.residual_variance.sqrt());
+    assert!(saem.objective_function_value.is_finite());
+}
+
+#[test]
+fn test_evaluate_objective_lower_at_estimate_than_perturbed() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+    let config = EstimationConfig::default()
+        .with_iterations(200)
+        .with_burnin(50)
+        .with_foce_iterations(20);
+    let perturbed = |params: &ModelParameters| {
+        let mut perturbed = params.clone();
+        perturbed.fixed_effects[0] += 0.5;
+        perturbed
+    };
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let mut foce = FoceEstimator::new(model, config.clone());
+    let results = foce.fit(&dataset).unwrap();
+    let estimate = FittedModel::from_foce(foce.model(), &results).parameters;
+    let at_estimate = foce.evaluate_objective(&dataset, &estimate).unwrap();
+    assert!(at_estimate.is_finite());
+    assert!(at_estimate < foce.evaluate_objective(&dataset, &perturbed(&estimate)).unwrap());
+
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let mut saem = SaemEstimator::new(model, config);
+    let results = saem.fit(&dataset).unwrap();
+    let estimate = FittedModel::from_saem(saem.model(), &results).parameters;
+    let at_estimate = saem.evaluate_objective(&dataset, &estimate).unwrap();
+    assert!(at_estimate.is_finite());
+    assert!(at_estimate < saem.evaluate_objective(&dataset, &perturbed(&estimate)).unwrap());
+
+    // Same seed, same chains
+    assert_eq!(at_estimate, saem.evaluate_objective(&dataset, &estimate).unwrap());
+    assert!(saem.evaluate_objective(&dataset, &ModelParameters::new(1, vec!["CL".to_string()])).is_err());
+}
+
+#[test]
+fn test_likelihood_profile_minimum_at_estimate() {
+    let dataset_path = PathBuf::from("examples/example_dataset.csv");
+    if !dataset_path.exists() {
+        println!("Skipping integration test - example dataset not found");
+        return;
+    }
+    let dataset = Dataset::from_csv(&dataset_path).unwrap();
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let mut foce = FoceEstimator::new(model, EstimationConfig::default().with_foce_iterations(20));
+    let results = foce.fit(&dataset).unwrap();
+    let estimate = FittedModel::from_foce(foce.model(), &results).parameters;
+
+    let cl = estimate.fixed_effects[0];
+    let values: Vec<f64> = [-0.3, -0.15, 0.0, 0.15, 0.3].iter().map(|offset| (cl + offset).exp()).collect();
+    let profile = foce.profile_likelihood(&dataset, &estimate, 0, &values).unwrap();
+    assert_eq!(profile.parameter, "CL");
+    assert_eq!(profile.points.len(), 5);
+    assert_eq!(profile.minimum_index(), Some(2), "{:?}", profile.points);
+    // Held at the estimate, the refit returns to the fit's OFV
+    let at_estimate = profile.points[2].objective_function_value;
+    assert!((at_estimate - results.objective_function_value).abs() < 0.5,
+            "{} vs {}", at_estimate, results.objective_function_value);
+    assert!(profile.delta_objective().iter().all(|&delta| delta >= 0.0));
+    // The estimator's own configuration is untouched afterwards
+    let refit = foce.fit(&dataset).unwrap();
+    assert_eq!(refit.fixed_effects, results.fixed_effects);
+
+    let output_dir = std::env::temp_dir().join("nmodes_profile_likelihood_test");
+    std::fs::remove_dir_all(&output_dir).ok();
+    let mut config = PipelineConfig::new(&dataset_path, &output_dir)
+        .with_methods(vec![EstimationMethod::Foce])
+        .with_iterations(20);
+    config.profile_parameters = vec!["V".to_string()];
+    let results = run_pipeline(config).unwrap();
+    let mut reader = csv::Reader::from_path(results[0].output_dir.join("profile_V.csv")).unwrap();
+    assert_eq!(reader.headers().unwrap(), vec!["V", "OFV", "DELTA_OFV", "CONVERGED"]);
+    assert_eq!(reader.records().count(), nmodes::pipeline::PROFILE_LIKELIHOOD_POINTS);
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+#[test]
+fn test_sex_categories_get_distinct_typical_clearances() {
+    use nmodes::estimation::ErrorModel;
+    use nmodes::models::{CategoricalCovariateEffect, CovariateModel};
+
+    let temp_file = std::env::temp_dir().join("categorical_covariate_test.csv");
+    std::fs::write(
+        &temp_file,
+        "ID,TIME,DV,AMT,EVID,CMT,SEX,GENO,WT\n\
+         1,0,,100,1,1,M,10,70\n\
+         1,4,1.0,,0,1,M,10,.\n\
+         2,0,,100,1,1,F,2,80\n\
+         2,4,1.0,,0,1,F,2,80\n",
+    ).unwrap();
+    let undeclared = Dataset::from_csv(&temp_file).expect("Failed to load dataset");
+    let dataset = Dataset::from_csv_with_categorical(&temp_file, &["SEX"]).expect("Failed to load dataset");
+    let coded_genotype = Dataset::from_csv_with_categorical(&temp_file, &["GENO"]).expect("Failed to load dataset");
+    assert!(Dataset::from_csv_with_categorical(&temp_file, &["RACE"]).is_err());
+    std::fs::remove_file(&temp_file).ok();
+
+    // Only declared columns are coded, by sorted position; text elsewhere,
+    // like the missing-value dot in WT, carries no value
+    assert_eq!(undeclared.covariate_levels("SEX"), None);
+    assert_eq!(undeclared.get_individual(1).unwrap().get_covariate("SEX"), None);
+    assert_eq!(dataset.covariate_levels("SEX").unwrap(), ["F", "M"]);
+    assert_eq!(dataset.get_individual(1).unwrap().get_covariate("SEX"), Some(1.0));
+    assert_eq!(dataset.get_individual(1).unwrap().get_covariate("WT"), Some(70.0));
+    assert_eq!(dataset.covariate_levels("GENO"), None);
+    assert_eq!(dataset.covariate_category_value("GENO", "2"), Some(2.0));
+    // Numeric categories sort by value
+    assert_eq!(coded_genotype.covariate_levels("GENO").unwrap(), ["2", "10"]);
+    assert_eq!(coded_genotype.get_individual(1).unwrap().get_covariate("GENO"), Some(1.0));
+
+    let female = CategoricalCovariateEffect::from_dataset(&dataset, "CL", "SEX", "M", &[("F", -0.25)]).unwrap();
+    let covariate_model = CovariateModel::new().with_categorical_effect(female);
+    let model = CompartmentModel::new(ModelType::OneCompartment).unwrap();
+    let params = model.default_parameters();
+    let typical_cl = |id: i32| {
+        covariate_model.apply(&params, dataset.get_individual(id).unwrap().covariates()).fixed_effects[0].exp()
+    };
+    assert!((typical_cl(1) - params.fixed_effects[0].exp()).abs() < 1e-12);
+    assert!((typical_cl(2) - 0.75 * params.fixed_effects[0].exp()).abs() < 1e-12);
+
+    // Lower clearance, higher concentrations for the same dose
+    let fitted = FittedModel::new(ModelType::OneCompartment, params, ErrorModel::Additive)
+        .with_covariate_model(covariate_model);
+    let male = fitted.predict(dataset.get_individual(1).unwrap()).unwrap();
+    let female = fitted.predict(dataset.get_individual(2).unwrap()).unwrap();
+    assert!(female[0] > male[0], "{} vs {}", female[0], male[0]);
+
+    assert!(CategoricalCovariateEffect::from_dataset(&dataset, "CL", "SEX", "M", &[("X", 0.1)]).is_err());
+    assert!(CategoricalCovariateEffect::from_dataset(&dataset, "CL", "SEX", "M", &[("F", -1.0)]).is_err());
+}